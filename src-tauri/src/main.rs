@@ -6,25 +6,37 @@ use crc32fast::Hasher;
 use fs2::available_space;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tauri::{AppHandle, Manager, State};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, RunEvent, State};
 use walkdir::WalkDir;
 
 #[cfg(target_os = "windows")]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
+const STDERR_TAIL_LINES: usize = 10;
+/// External-audio source meaning "take audio track(s) out of another video file".
+const VIDEO_AUDIO_SOURCE: &str = "video-audio";
 static MEDIAINFO_AVAILABLE: OnceLock<bool> = OnceLock::new();
 static MKVMERGE_AVAILABLE: OnceLock<bool> = OnceLock::new();
 static FILE_INFO_CACHE: OnceLock<Mutex<HashMap<String, serde_json::Value>>> = OnceLock::new();
+static SEEDED_IDS: OnceLock<Mutex<Option<SeededIds>>> = OnceLock::new();
+/// Seeds deterministic ids at startup, e.g. for reproducible logs across a dry run and a real run.
+const ID_SEED_ENV: &str = "MKVBATCHMUX_ID_SEED";
+/// Points settings and logs at a custom folder, for portable installs and tests.
+const DATA_DIR_ENV: &str = "MKVBATCHMUX_DATA_DIR";
+/// Probe processes allowed at once while scanning, independent of `max_parallel_jobs`.
+const DEFAULT_MAX_CONCURRENT_PROBES: usize = 4;
+const DEFAULT_CRC_BUFFER_BYTES: usize = 1024 * 1024;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Preset {
@@ -58,6 +70,9 @@ struct Preset {
     default_favorite_subtitle_languages: Vec<String>,
     #[serde(rename = "Default_Favorite_Audio_Languages")]
     default_favorite_audio_languages: Vec<String>,
+    /// Mux options applied when the preset is chosen; `None` keeps the current options.
+    #[serde(rename = "Default_Mux_Settings", default)]
+    default_mux_settings: Option<MuxSettings>,
 }
 
 impl Default for Preset {
@@ -78,14 +93,61 @@ impl Default for Preset {
             default_destination_directory: String::new(),
             default_favorite_subtitle_languages: vec!["English".to_string(), "Arabic".to_string()],
             default_favorite_audio_languages: vec!["English".to_string(), "Arabic".to_string()],
+            default_mux_settings: None,
         }
     }
 }
 
+impl Preset {
+    /// The folder fields, keyed by their name in the options file.
+    fn directories_mut(&mut self) -> [(&'static str, &mut String); 6] {
+        [
+            ("Default_Video_Directory", &mut self.default_video_directory),
+            (
+                "Default_Subtitle_Directory",
+                &mut self.default_subtitle_directory,
+            ),
+            ("Default_Audio_Directory", &mut self.default_audio_directory),
+            (
+                "Default_Chapter_Directory",
+                &mut self.default_chapter_directory,
+            ),
+            (
+                "Default_Attachment_Directory",
+                &mut self.default_attachment_directory,
+            ),
+            (
+                "Default_Destination_Directory",
+                &mut self.default_destination_directory,
+            ),
+        ]
+    }
+}
+
 fn default_true() -> bool {
     true
 }
 
+fn default_crc_buffer_bytes() -> usize {
+    DEFAULT_CRC_BUFFER_BYTES
+}
+
+fn default_sanitize_filenames() -> bool {
+    cfg!(target_os = "windows")
+}
+
+fn default_sanitize_substitute() -> String {
+    "_".to_string()
+}
+
+fn default_temp_separator() -> String {
+    "#".to_string()
+}
+
+fn default_max_concurrent_probes() -> usize {
+    DEFAULT_MAX_CONCURRENT_PROBES
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct OptionsData {
     #[serde(rename = "Presets")]
@@ -100,6 +162,17 @@ struct OptionsData {
     choose_preset_on_startup: bool,
     #[serde(rename = "Show_Session_Recovery_Dialog", default = "default_true")]
     show_session_recovery_dialog: bool,
+    /// Named extension lists (`"subs"` -> `["ass", "srt", "sup"]`) that presets and
+    /// scans can use in place of an extension.
+    #[serde(rename = "Extension_Groups", default)]
+    extension_groups: HashMap<String, Vec<String>>,
+    /// Why the options file couldn't be loaded; only ever sent to the UI, never saved.
+    #[serde(
+        rename = "Load_Error",
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none"
+    )]
+    load_error: Option<String>,
 }
 
 impl Default for OptionsData {
@@ -111,6 +184,8 @@ impl Default for OptionsData {
             attachment_expert_mode_info_message_show: true,
             choose_preset_on_startup: false,
             show_session_recovery_dialog: true,
+            extension_groups: HashMap::new(),
+            load_error: None,
         }
     }
 }
@@ -121,6 +196,9 @@ struct TrackInfo {
     #[serde(rename = "type")]
     track_type: String,
     codec: Option<String>,
+    /// Matroska codec id (e.g. `A_FLAC`), when the probe reports one.
+    #[serde(rename = "codecId", default, skip_serializing_if = "Option::is_none")]
+    codec_id: Option<String>,
     language: Option<String>,
     name: Option<String>,
     #[serde(rename = "isDefault")]
@@ -129,6 +207,34 @@ struct TrackInfo {
     is_forced: Option<bool>,
     bitrate: Option<u64>, // Bitrate in bits per second
     action: Option<String>,
+    /// Name the track had in the source, set by the UI once the track is edited.
+    #[serde(
+        rename = "originalName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    original_name: Option<String>,
+    /// Default flag the track had in the source, set by the UI like `original_name`.
+    #[serde(
+        rename = "originalDefault",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    original_default: Option<bool>,
+    /// Shift in seconds for this source track, like `delay` on external files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delay: Option<f64>,
+}
+
+impl TrackInfo {
+    /// Whether `name` is still the one read from the source rather than one the user set.
+    fn has_imported_name(&self) -> bool {
+        self.action.as_deref() != Some("modify")
+            && self
+                .original_name
+                .as_ref()
+                .is_none_or(|original| self.name.as_ref() == Some(original))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -141,6 +247,42 @@ struct VideoFileInfo {
     fps: Option<f64>,
     status: String,
     tracks: Vec<TrackInfo>,
+    #[serde(
+        rename = "probeError",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    probe_error: Option<String>,
+    /// Set when the file was probed but has no video track (e.g. a renamed text file).
+    #[serde(default)]
+    warning: Option<String>,
+    /// Which tool supplied each probed field; diagnostic only.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sources: HashMap<String, String>,
+    /// Dolby Vision profile of the video track, from mediainfo.
+    #[serde(
+        rename = "dolbyVisionProfile",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    dolby_vision_profile: Option<u8>,
+    /// `duration` as seconds with the fraction kept, for arithmetic.
+    #[serde(rename = "durationSeconds", default)]
+    duration_seconds: Option<f64>,
+    /// Set when mkvmerge and mediainfo disagree on the file's tracks.
+    #[serde(rename = "probeWarning", default)]
+    probe_warning: Option<String>,
+}
+
+/// What an external audio file's `delay` shifts when several of its tracks are muxed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum DelayScope {
+    /// The whole file: every track taken from it, as `--sync -1:<ms>`.
+    #[default]
+    File,
+    /// Only the file's chosen `trackId` (else its lowest muxed track).
+    Track,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -156,6 +298,10 @@ struct ExternalFileInfo {
     #[serde(rename = "trackName")]
     track_name: Option<String>,
     delay: Option<f64>,
+    /// For external audio: whether `delay` applies to the whole file or one track.
+    /// Per-track overrides always win for their own track.
+    #[serde(rename = "delayScope", default)]
+    delay_scope: DelayScope,
     #[serde(rename = "isDefault")]
     is_default: Option<bool>,
     #[serde(rename = "isForced")]
@@ -167,22 +313,58 @@ struct ExternalFileInfo {
     size: Option<u64>,
     bitrate: Option<u64>,
     duration: Option<String>,
+    #[serde(rename = "durationSeconds", default)]
+    duration_seconds: Option<f64>,
     #[serde(rename = "trackId")]
     track_id: Option<u64>,
     #[serde(default)]
     tracks: Vec<TrackInfo>,
     #[serde(rename = "includedTrackIds", default)]
     included_track_ids: Option<Vec<u64>>,
+    /// Take only the lowest resolved track id instead of every track in the file.
+    #[serde(rename = "firstTrackOnly", default)]
+    first_track_only: bool,
     #[serde(rename = "includeSubtitles")]
     include_subtitles: Option<bool>,
     #[serde(rename = "includedSubtitleTrackIds", default)]
     included_subtitle_track_ids: Option<Vec<u64>>,
+    /// Flags for the subtitles taken along via `include_subtitles`; without it they get none.
+    #[serde(rename = "subtitleFlags", default)]
+    subtitle_flags: Option<ExtractedSubtitleFlags>,
     #[serde(rename = "trackOverrides", default)]
     track_overrides: HashMap<String, TrackOverride>,
+    /// MIME type for an attachment, used instead of the one guessed from its extension.
+    #[serde(rename = "mimeType", default)]
+    mime_type: Option<String>,
+    /// For a chapter file: mark its (first) edition as the default one.
+    #[serde(rename = "defaultEdition", default)]
+    default_edition: Option<bool>,
+    /// For a chapter file: mark its editions as ordered.
+    #[serde(default)]
+    ordered: Option<bool>,
+    #[serde(
+        rename = "probeError",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    probe_error: Option<String>,
+    /// Set when mkvmerge and mediainfo disagree on the file's tracks.
+    #[serde(rename = "probeWarning", default)]
+    probe_warning: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    sources: HashMap<String, String>,
     #[serde(skip)]
     apply_language: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ExtractedSubtitleFlags {
+    is_default: Option<bool>,
+    is_forced: Option<bool>,
+    language: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct TrackOverride {
     language: Option<String>,
@@ -199,6 +381,74 @@ struct ScanRequest {
     #[serde(rename = "type")]
     file_type: String,
     include_tracks: bool,
+    /// Unix seconds; files last modified before this are skipped before probing.
+    #[serde(default)]
+    modified_since: Option<u64>,
+    #[serde(default)]
+    duration_source: DurationSource,
+    /// Drop video files whose probe found no video track (needs `include_tracks` at the
+    /// full metadata level; a lighter probe lists no tracks to check).
+    #[serde(default)]
+    skip_trackless: bool,
+    #[serde(default)]
+    metadata_level: MetadataLevel,
+    /// Check that files named like Matroska actually start with an EBML header.
+    #[serde(default)]
+    verify_magic: bool,
+    /// Upper bound on mkvmerge/mediainfo processes running at once during the scan.
+    #[serde(default = "default_max_concurrent_probes")]
+    max_concurrent_probes: usize,
+    /// Keep only files with a track in one of these languages; probes tracks even
+    /// without `include_tracks`.
+    #[serde(default)]
+    require_languages: Vec<String>,
+    /// Track types `require_languages` looks at; empty means any type.
+    #[serde(default)]
+    require_language_types: Vec<String>,
+}
+
+impl ScanRequest {
+    /// A language requirement needs the track list, so it turns full track probing on.
+    fn probes_tracks(&self) -> (bool, MetadataLevel) {
+        if self.require_languages.is_empty() {
+            (self.include_tracks, self.metadata_level)
+        } else {
+            (true, MetadataLevel::Full)
+        }
+    }
+}
+
+/// How much a scan probes each file.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum MetadataLevel {
+    /// Name and size from the file system only; no external tools are run.
+    NameSizeOnly,
+    /// Probe for duration/bitrate but never list tracks.
+    Basic,
+    /// Probe as `include_tracks` asks.
+    #[default]
+    Full,
+}
+
+/// Which probe a file's duration is taken from; the other one is used as a fallback.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum DurationSource {
+    #[default]
+    Auto,
+    Mkvmerge,
+    Mediainfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanSummary {
+    items: Vec<serde_json::Value>,
+    matched: usize,
+    skipped_older: usize,
+    skipped_trackless: usize,
+    skipped_missing_language: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -207,6 +457,10 @@ struct InspectRequest {
     #[serde(rename = "type")]
     file_type: String,
     include_tracks: bool,
+    #[serde(default)]
+    duration_source: DurationSource,
+    #[serde(default)]
+    metadata_level: MetadataLevel,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -217,6 +471,10 @@ struct InspectStreamRequest {
     file_type: String,
     include_tracks: bool,
     batch_size: Option<usize>,
+    #[serde(default)]
+    duration_source: DurationSource,
+    #[serde(default)]
+    metadata_level: MetadataLevel,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -256,14 +514,385 @@ struct MuxSettings {
     only_keep_subtitles_enabled: bool,
     only_keep_audio_languages: Vec<String>,
     only_keep_subtitle_languages: Vec<String>,
+    /// Keep only source audio tracks with one of these codecs or codec ids; empty keeps all.
+    #[serde(default)]
+    keep_audio_codecs: Vec<String>,
+    /// Keep only source subtitle tracks with one of these codecs or codec ids; empty keeps all.
+    #[serde(default)]
+    keep_subtitle_codecs: Vec<String>,
     discard_old_chapters: bool,
     discard_old_attachments: bool,
+    /// With `discard_old_attachments`, drop only the source attachments that a new
+    /// attachment of the same name replaces, keeping the rest (e.g. embedded fonts).
+    #[serde(default)]
+    discard_replaced_attachments_only: bool,
     allow_duplicate_attachments: bool,
     attachments_expert_mode: bool,
     remove_global_tags: bool,
     make_audio_default_language: Option<String>,
     make_subtitle_default_language: Option<String>,
+    /// Languages tried in order when `make_audio_default_language` isn't set; the first
+    /// one a file has an audio track in becomes its default.
+    #[serde(default)]
+    audio_default_language_priority: Vec<String>,
+    /// The subtitle counterpart of `audio_default_language_priority`.
+    #[serde(default)]
+    subtitle_default_language_priority: Vec<String>,
+    /// 1-based position among the kept audio tracks to make default, clearing the rest.
+    /// Works for untagged files where the language-based option can't match.
+    #[serde(default)]
+    make_audio_default_position: Option<usize>,
+    /// 1-based position among the kept subtitle tracks to make default, clearing the rest.
+    #[serde(default)]
+    make_subtitle_default_position: Option<usize>,
     use_mkvpropedit: bool,
+    /// Pass `--gui-mode` to mkvmerge. Some old or patched builds reject it.
+    #[serde(default = "default_true")]
+    gui_mode: bool,
+    #[serde(default)]
+    split: Option<SplitMode>,
+    /// Clear the default flag on every subtitle track, overriding per-track and language defaults.
+    #[serde(default)]
+    no_default_subtitles: bool,
+    /// Clear the default flag on every audio track, overriding per-track and language defaults.
+    #[serde(default)]
+    no_default_audio: bool,
+    /// Folder the session log is copied to after every batch, independent of the destination.
+    #[serde(default)]
+    keep_log_destination: Option<String>,
+    /// Detected language -> replacement, applied before the keep/default language filters.
+    #[serde(default)]
+    language_remap: HashMap<String, String>,
+    /// Check every input against a `<file>.sha256` sidecar before muxing, when one exists.
+    #[serde(default)]
+    verify_input_checksums: bool,
+    /// Blank the source's track names: `true` for every track, or a list of track types.
+    /// Emits an empty `--track-name`, so outputs have unnamed tracks.
+    #[serde(default)]
+    strip_track_names: StripTrackNames,
+    /// Drop the names read from the source; only names set in the app (edits, batch
+    /// overrides) end up in the output. Unlike `strip_track_names`, those are kept.
+    #[serde(default)]
+    clear_imported_track_names: bool,
+    /// Drop the source's per-track tags; mkvmerge still writes fresh track statistics.
+    #[serde(default)]
+    remove_track_tags: bool,
+    /// Skip jobs whose output already exists instead of overwriting it.
+    #[serde(default)]
+    skip_existing: bool,
+    #[serde(default)]
+    batch_track_overrides: Vec<BatchTrackOverride>,
+    /// Extension of output files, casing kept as given; `mkv` when unset.
+    #[serde(default)]
+    output_extension: Option<String>,
+    /// Probe each finished output and warn when it has fewer tracks than the job asked for.
+    #[serde(default)]
+    verify_output: bool,
+    /// Error kinds that pause the queue when `abort_on_errors` is on; other failures
+    /// only fail their own job.
+    #[serde(default = "default_abort_on")]
+    abort_on: Vec<JobErrorKind>,
+    /// Program run after each successful job, e.g. `rclone copy {output} remote:`.
+    /// `{output}`, `{input}` and `{crc}` are filled in per argument, never through a shell.
+    #[serde(default)]
+    post_mux_command: Option<String>,
+    /// Fail the job when the post-mux command exits non-zero instead of only logging it.
+    #[serde(default)]
+    post_mux_command_must_succeed: bool,
+    /// Stop the batch once the finished outputs add up to more than this many bytes.
+    #[serde(default)]
+    max_total_output_bytes: Option<u64>,
+    /// Attach files sorted by name instead of in the order they were added.
+    #[serde(default)]
+    sort_attachments: bool,
+    /// Log each job's command and report it completed without running mkvmerge or
+    /// touching any file.
+    #[serde(default)]
+    dry_run: bool,
+    /// Read size for the CRC pass; larger reads keep fast drives busy.
+    #[serde(default = "default_crc_buffer_bytes")]
+    crc_buffer_bytes: usize,
+    /// Tell the OS the CRC pass reads the file once from start to end.
+    #[serde(default)]
+    crc_sequential_read: bool,
+    /// In overwrite mode, read the CRC from the temp output before it replaces the source,
+    /// then move it straight to its tagged name.
+    #[serde(default)]
+    crc_on_temp_output: bool,
+    /// Output name template filled from the show, season and episode parsed out of the
+    /// source name, e.g. `{show} - S{season:02}E{episode:02}`. A job's own output name wins.
+    #[serde(default)]
+    title_parser: Option<String>,
+    /// Pause the batch while a destination has less free space than this.
+    #[serde(default)]
+    low_space_threshold_bytes: Option<u64>,
+    /// Resume a batch paused for low space once the space is back.
+    #[serde(default)]
+    resume_on_space_recovered: bool,
+    /// Container of the outputs; MP4 is written by ffmpeg instead of mkvmerge.
+    #[serde(default)]
+    output_container: OutputContainer,
+    /// Replaces mkvmerge's "muxing application" string in Matroska outputs.
+    #[serde(default)]
+    muxing_app: Option<String>,
+    /// Write `{output}.json` next to each output describing what it contains.
+    #[serde(default)]
+    write_nfo_sidecar: bool,
+    /// What happens to source audio/subtitle tracks tagged `und` or untagged.
+    #[serde(default)]
+    undefined_language_policy: UndefinedPolicy,
+    /// Shift external chapters by the video's first-frame timestamp, for trimmed
+    /// sources whose timestamps don't start at zero.
+    #[serde(default)]
+    chapter_auto_offset: bool,
+    /// Free-text COMMENT added to each output's global tags. `{version}`, `{date}`
+    /// (UTC, `YYYY-MM-DD`), `{source}` and `{preset}` are expanded per job.
+    #[serde(default)]
+    comment_tag: Option<String>,
+    /// Replace characters Windows forbids in the output name, whatever the name came
+    /// from. Names from templates and titles are always cleaned.
+    #[serde(default = "default_sanitize_filenames")]
+    sanitize_filenames: bool,
+    /// What replaces each forbidden character; may be empty to drop them.
+    #[serde(default = "default_sanitize_substitute")]
+    sanitize_substitute: String,
+    /// Source track ids dropped from every file of the batch, whatever their type. On
+    /// top of the language and codec filters.
+    #[serde(default)]
+    block_source_track_ids: Vec<usize>,
+    /// Fill the gaps between mkvmerge's progress reports from the output's growth, so
+    /// fast jobs that only report 0% and 100% still move the bar.
+    #[serde(default)]
+    smooth_progress: bool,
+    /// Keep only the source tracks whose name matches one of these (case-insensitive,
+    /// `*` matches anything) in each type that has a match. Empty keeps every name.
+    #[serde(default)]
+    keep_track_name_patterns: Vec<String>,
+    /// Run only the first job of the queue, then end the batch: a quick check of the
+    /// settings against real files.
+    #[serde(default)]
+    stop_after_first: bool,
+    /// Longest output file name in bytes, extension and CRC tag included. Longer names
+    /// have their stem shortened; `None` leaves names alone.
+    #[serde(default)]
+    max_filename_bytes: Option<usize>,
+    /// Outputs larger than this many bytes get no CRC tag, sparing the read of huge files.
+    #[serde(default)]
+    crc_max_size_bytes: Option<u64>,
+    /// Goes between the stem and the timestamp of an overwrite-mode temp output,
+    /// `{stem}{separator}{secs}.mkv`, for tools that trip over `#`.
+    #[serde(default = "default_temp_separator")]
+    temp_separator: String,
+    /// Start every log line with its UTC time, to line up the events of parallel jobs.
+    #[serde(default = "default_true")]
+    timestamped_logs: bool,
+}
+
+impl MuxSettings {
+    /// `sanitize_substitute` with any forbidden characters of its own removed.
+    fn sanitize_substitute(&self) -> String {
+        clean_substitute(&self.sanitize_substitute)
+    }
+
+    /// `temp_separator` without forbidden characters, falling back to `#` when nothing
+    /// is left, since the timestamp alone couldn't be told apart from the stem.
+    fn temp_separator(&self) -> String {
+        let separator: String = self
+            .temp_separator
+            .chars()
+            .filter(|c| !is_forbidden_file_name_char(*c))
+            .collect();
+        if separator.is_empty() {
+            default_temp_separator()
+        } else {
+            separator
+        }
+    }
+}
+
+/// The options a fresh workspace starts with.
+impl Default for MuxSettings {
+    fn default() -> Self {
+        Self {
+            destination_dir: String::new(),
+            overwrite_source: false,
+            add_crc: false,
+            remove_old_crc: false,
+            keep_log_file: false,
+            abort_on_errors: false,
+            max_parallel_jobs: Some(2),
+            only_keep_audios_enabled: false,
+            only_keep_subtitles_enabled: false,
+            only_keep_audio_languages: Vec::new(),
+            only_keep_subtitle_languages: Vec::new(),
+            keep_audio_codecs: Vec::new(),
+            keep_subtitle_codecs: Vec::new(),
+            discard_old_chapters: false,
+            discard_old_attachments: true,
+            discard_replaced_attachments_only: false,
+            allow_duplicate_attachments: false,
+            attachments_expert_mode: false,
+            remove_global_tags: true,
+            make_audio_default_language: None,
+            make_subtitle_default_language: None,
+            audio_default_language_priority: Vec::new(),
+            subtitle_default_language_priority: Vec::new(),
+            make_audio_default_position: None,
+            make_subtitle_default_position: None,
+            use_mkvpropedit: false,
+            gui_mode: true,
+            split: None,
+            no_default_subtitles: false,
+            no_default_audio: false,
+            keep_log_destination: None,
+            language_remap: HashMap::new(),
+            verify_input_checksums: false,
+            strip_track_names: StripTrackNames::default(),
+            clear_imported_track_names: false,
+            remove_track_tags: false,
+            skip_existing: false,
+            batch_track_overrides: Vec::new(),
+            output_extension: None,
+            verify_output: false,
+            abort_on: default_abort_on(),
+            post_mux_command: None,
+            post_mux_command_must_succeed: false,
+            max_total_output_bytes: None,
+            sort_attachments: false,
+            dry_run: false,
+            crc_buffer_bytes: DEFAULT_CRC_BUFFER_BYTES,
+            crc_sequential_read: false,
+            crc_on_temp_output: false,
+            title_parser: None,
+            low_space_threshold_bytes: None,
+            resume_on_space_recovered: false,
+            output_container: OutputContainer::Mkv,
+            muxing_app: None,
+            write_nfo_sidecar: false,
+            undefined_language_policy: UndefinedPolicy::Leave,
+            chapter_auto_offset: false,
+            comment_tag: None,
+            sanitize_filenames: default_sanitize_filenames(),
+            sanitize_substitute: default_sanitize_substitute(),
+            block_source_track_ids: Vec::new(),
+            smooth_progress: false,
+            keep_track_name_patterns: Vec::new(),
+            stop_after_first: false,
+            max_filename_bytes: None,
+            crc_max_size_bytes: None,
+            temp_separator: default_temp_separator(),
+            timestamped_logs: true,
+        }
+    }
+}
+
+/// Track properties applied to every source track of a type and language across the batch.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BatchTrackOverride {
+    match_language: String,
+    track_type: String,
+    #[serde(default)]
+    set_name: Option<String>,
+    #[serde(default)]
+    set_default: Option<bool>,
+    #[serde(default)]
+    set_forced: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+enum StripTrackNames {
+    All(bool),
+    Types(Vec<String>),
+}
+
+impl Default for StripTrackNames {
+    fn default() -> Self {
+        StripTrackNames::All(false)
+    }
+}
+
+impl StripTrackNames {
+    fn applies_to(&self, track_type: &str) -> bool {
+        match self {
+            StripTrackNames::All(enabled) => *enabled,
+            StripTrackNames::Types(types) => types
+                .iter()
+                .any(|value| value.trim().eq_ignore_ascii_case(track_type)),
+        }
+    }
+}
+
+/// Why a job failed before producing an output.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum JobErrorKind {
+    /// The destination folder is unset or can't be created.
+    Destination,
+    DiskSpace,
+    OutputCollision,
+    SourceCorrupt,
+    /// mkvmerge is missing or won't start.
+    ToolMissing,
+    MuxFailed,
+}
+
+/// Problems that will fail every remaining job too, so they are worth stopping for,
+/// plus failed mux runs, which paused the batch before `abort_on` existed.
+fn default_abort_on() -> Vec<JobErrorKind> {
+    vec![
+        JobErrorKind::DiskSpace,
+        JobErrorKind::ToolMissing,
+        JobErrorKind::MuxFailed,
+    ]
+}
+
+/// Output container, which also picks the tool that writes it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum OutputContainer {
+    /// Matroska, written by mkvmerge.
+    #[default]
+    Mkv,
+    /// MP4, remuxed by ffmpeg with stream copy.
+    Mp4,
+}
+
+/// Handling of audio and subtitle tracks with an undetermined language. Applied after
+/// `language_remap` and before the keep/default language filters.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum UndefinedPolicy {
+    #[default]
+    Leave,
+    /// Tag them with this language, so the filters treat them like any other track.
+    SetTo(String),
+    /// Leave them out of the output.
+    Drop,
+}
+
+/// How mkvmerge should split each output into parts.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum SplitMode {
+    /// Split before the given chapter numbers (e.g. "5,10") or at every chapter ("all").
+    Chapters(String),
+}
+
+impl SplitMode {
+    fn to_mkvmerge_arg(&self) -> Option<String> {
+        match self {
+            SplitMode::Chapters(value) => {
+                let value = value.trim();
+                if value.is_empty() {
+                    None
+                } else {
+                    Some(format!("chapters:{value}"))
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -274,6 +903,42 @@ struct MuxJobRequest {
     subtitles: Vec<ExternalFileInfo>,
     chapters: Vec<ExternalFileInfo>,
     attachments: Vec<ExternalFileInfo>,
+    /// Output file name (without extension) used instead of the video's stem.
+    #[serde(default)]
+    output_name: Option<String>,
+    /// Name of the preset the job was built from; also fills the `{preset}` output name token.
+    #[serde(default)]
+    preset_name: Option<String>,
+    /// Destination folder for this job only; empty or missing uses the batch setting.
+    #[serde(default)]
+    destination_dir: Option<String>,
+    /// Later parts of a split source (`part2`, `CD2`, ...) appended to the video in order.
+    /// Overwriting only replaces the first part; the appended files are left in place.
+    #[serde(default)]
+    append_sources: Vec<String>,
+    /// Overrides `abort_on_errors` for this job only; `None` follows the batch setting.
+    #[serde(default)]
+    abort_on_errors: Option<bool>,
+    /// JPEG or PNG attached first as `cover.jpg`/`cover.png`, the name players use as poster art.
+    #[serde(default)]
+    poster_image: Option<String>,
+    /// Overrides `keep_track_name_patterns` for this job only; `None` follows the batch
+    /// setting.
+    #[serde(default)]
+    keep_track_name_patterns: Option<Vec<String>>,
+    /// Filled in by `probe_job_inputs` just before the command is built.
+    #[serde(skip)]
+    probed: ProbedInputs,
+}
+
+/// What the command needs from the inputs themselves, probed once per job so building
+/// it never runs a tool.
+#[derive(Debug, Clone, Default)]
+struct ProbedInputs {
+    /// Applied to every external chapter file, see `chapter_offset_seconds`.
+    chapter_offset: f64,
+    /// The source's attachments, listed only for `discard_replaced_attachments_only`.
+    embedded_attachments: Option<Vec<EmbeddedAttachment>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -291,6 +956,12 @@ struct MuxPreviewPlan {
     subtitles: Vec<ExternalFileInfo>,
     chapters: Vec<ExternalFileInfo>,
     attachments: Vec<ExternalFileInfo>,
+    /// The source video is replaced by the output once the job finishes.
+    #[serde(default)]
+    will_overwrite_source: bool,
+    /// Where mkvmerge writes before the output replaces the source, in overwrite mode.
+    #[serde(default)]
+    temp_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -300,16 +971,165 @@ struct MuxPreviewResult {
     command: String,
     warnings: Vec<String>,
     plan: MuxPreviewPlan,
+    #[serde(default)]
+    track_actions: Vec<TrackActionSummary>,
+    /// Whether the destination has room for `required_bytes`; also true when its free
+    /// space can't be read.
+    #[serde(default)]
+    space_ok: bool,
+    #[serde(default)]
+    required_bytes: u64,
+    #[serde(default)]
+    available_bytes: Option<u64>,
+}
+
+/// How many source tracks of a type make it into the output.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct TrackActionSummary {
+    #[serde(rename = "type")]
+    track_type: String,
+    kept: usize,
+    /// Tracks the user set to remove.
+    removed: usize,
+    /// Tracks dropped by the only-keep language or codec filters.
+    disabled: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct OutputAttachment {
+    name: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RenameItem {
+    path: String,
+    new_name: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct RenameResult {
+    path: String,
+    new_path: Option<String>,
+    error: Option<String>,
+}
+
+/// What a finished output actually contains, for checking it against the preview plan.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct OutputDescription {
+    tracks: Vec<TrackInfo>,
+    attachments: Vec<OutputAttachment>,
+    chapter_count: u64,
+}
+
+/// One track's estimated share of a file, from its bitrate and the file's duration.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct TrackSizeEstimate {
+    track_id: String,
+    #[serde(rename = "type")]
+    track_type: String,
+    language: Option<String>,
+    bitrate: Option<u64>,
+    /// `None` when the track has no bitrate or the file has no duration.
+    estimated_bytes: Option<u64>,
+    /// `estimated_bytes` as a fraction of the file size.
+    fraction: Option<f64>,
+}
+
+/// Per-track size estimates for a file, plus whatever they don't account for.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct TrackSizeBreakdown {
+    file_size: u64,
+    duration_seconds: Option<f64>,
+    tracks: Vec<TrackSizeEstimate>,
+    /// Container overhead, attachments and tracks without a bitrate. Zero when the
+    /// estimates overshoot the file size.
+    remainder_bytes: u64,
+}
+
+/// The sidecar written next to an output: its description plus where it came from.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputSidecar {
+    source: String,
+    output: String,
+    /// The CRC from the output's name; `None` when CRC naming is off.
+    crc: Option<String>,
+    #[serde(flatten)]
+    description: OutputDescription,
+}
+
+/// Where a job stands in a `mux-progress` event. Serialized as the lowercase strings
+/// the frontend switches on.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum JobPhase {
+    Processing,
+    Finalizing,
+    Completed,
+    Error,
+    Stopped,
+    Skipped,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct MuxProgressEvent {
     job_id: String,
-    status: String,
+    status: JobPhase,
     progress: u8,
     message: Option<String>,
     size_after: Option<u64>,
     error_message: Option<String>,
+    /// The last few stderr/diagnostic lines the tool printed, for failed jobs.
+    error_detail: Option<String>,
+    /// Wall-clock time the job took, set on `completed`/`error`/`stopped` events.
+    elapsed_ms: Option<u64>,
+}
+
+/// Sent when the batch stops itself, e.g. after hitting the output size cap.
+#[derive(Debug, Serialize, Clone)]
+struct MuxAbortedEvent {
+    reason: String,
+}
+
+/// Sent when the batch's workers have finished, however it ended.
+#[derive(Debug, Serialize, Clone)]
+struct MuxCompleteEvent {
+    /// Jobs handed to the workers.
+    processed: usize,
+    /// Queued jobs left unrun because of `stop_after_first`.
+    not_run: usize,
+}
+
+/// Which jobs `resume_batch` found already done and which it queued again.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ResumeSummary {
+    skipped: Vec<String>,
+    run: Vec<String>,
+}
+
+/// Sent when the low-space watchdog pauses the batch, and again if it resumes it.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct MuxLowSpaceEvent {
+    available_bytes: u64,
+    threshold_bytes: u64,
+    paused: bool,
+}
+
+/// Sent right before a job's mkvmerge is spawned, so the UI shows what actually runs.
+#[derive(Debug, Serialize, Clone)]
+struct MuxPlanEvent {
+    job_id: String,
+    args: Vec<String>,
+    output_path: String,
+    track_order: Vec<TrackOrderEntry>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -317,6 +1137,42 @@ struct AppPaths {
     app_data_dir: PathBuf,
     options_path: PathBuf,
     log_path: PathBuf,
+    stats_path: PathBuf,
+    /// Outputs that finished muxing, with their sizes, for `resume_batch`.
+    finished_outputs_path: PathBuf,
+    /// `timestamped_logs` as set by the last `start_muxing` or `run_single_job`, read by
+    /// `write_log_line`. Shared app-wide, so it applies to every log line from then on,
+    /// not only to that batch's jobs.
+    #[serde(skip)]
+    log_timestamps: Arc<AtomicBool>,
+}
+
+impl AppPaths {
+    fn in_dir(app_data_dir: PathBuf) -> Self {
+        Self {
+            options_path: app_data_dir.join("setting.json"),
+            log_path: app_data_dir.join("muxing_log_file.txt"),
+            stats_path: app_data_dir.join("stats.json"),
+            finished_outputs_path: app_data_dir.join("finished_outputs.json"),
+            app_data_dir,
+            log_timestamps: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Switches log timestamps for every later `write_log_line`, whichever batch writes it.
+    fn use_settings_for_log(&self, settings: &MuxSettings) {
+        self.log_timestamps
+            .store(settings.timestamped_logs, Ordering::Relaxed);
+    }
+}
+
+/// Prefers a non-empty `MKVBATCHMUX_DATA_DIR` over the OS app data directory.
+fn resolve_app_paths(default_dir: Option<PathBuf>) -> Option<AppPaths> {
+    std::env::var_os(DATA_DIR_ENV)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or(default_dir)
+        .map(AppPaths::in_dir)
 }
 
 #[derive(Debug)]
@@ -326,15 +1182,37 @@ struct MuxState {
     stop: bool,
     queue: Vec<MuxJobRequest>,
     settings: Option<MuxSettings>,
+    /// Number of jobs the most recent `start_muxing` was given.
+    batch_job_count: usize,
+    /// The command line each job last ran with, kept across batches for `diff_commands`.
+    last_commands: HashMap<String, String>,
     children: HashMap<String, Arc<Mutex<Child>>>,
+    stderr_tails: HashMap<String, VecDeque<String>>,
+    /// Last progress value emitted per job, so repeated lines don't re-emit it.
+    last_progress: HashMap<String, u8>,
+    /// Set by `stop_keep_outputs`: settle temp files of stopped jobs instead of orphaning them.
+    keep_outputs: bool,
+    /// Completed jobs of the running batch, added to `stats.json` when it ends.
+    batch_stats: UsageStats,
+    /// Bytes written by the running batch's completed jobs, checked against
+    /// `max_total_output_bytes`.
+    batch_output_bytes: u64,
 }
 
 #[derive(Clone)]
 struct AppState {
     paths: AppPaths,
     mux_state: Arc<Mutex<MuxState>>,
+    /// Held while `finished_outputs.json` is read and rewritten, so parallel workers
+    /// don't overwrite each other's entries.
+    finished_outputs_lock: Arc<Mutex<()>>,
+    /// Major version of the installed mkvmerge, read once on first use.
+    mkvmerge_version: Arc<OnceLock<Option<u32>>>,
+    /// Languages the installed mkvmerge lists, kept once a listing succeeds.
+    mkvmerge_languages: Arc<OnceLock<Vec<LanguageEntry>>>,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for MuxState {
     fn default() -> Self {
         Self {
@@ -343,7 +1221,14 @@ impl Default for MuxState {
             stop: false,
             queue: Vec::new(),
             settings: None,
+            batch_job_count: 0,
+            last_commands: HashMap::new(),
             children: HashMap::new(),
+            stderr_tails: HashMap::new(),
+            last_progress: HashMap::new(),
+            keep_outputs: false,
+            batch_stats: UsageStats::default(),
+            batch_output_bytes: 0,
         }
     }
 }
@@ -352,28 +1237,219 @@ fn ensure_dir(path: &Path) -> Result<(), String> {
     fs::create_dir_all(path).map_err(|e| format!("Failed to create directory: {e}"))
 }
 
-fn read_options(path: &Path) -> Result<OptionsData, String> {
-    if path.exists() {
-        let content =
-            fs::read_to_string(path).map_err(|e| format!("Failed to read options: {e}"))?;
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse options JSON: {e}"))
-    } else {
-        Ok(OptionsData::default())
+/// Reads a string that holds a number or boolean as that value, and 0/1 as a boolean.
+fn coerce_option_value(value: &serde_json::Value) -> Option<serde_json::Value> {
+    match value {
+        serde_json::Value::String(text) => {
+            let text = text.trim();
+            match text.to_ascii_lowercase().as_str() {
+                "true" => Some(true.into()),
+                "false" => Some(false.into()),
+                _ => serde_json::from_str::<serde_json::Number>(text)
+                    .ok()
+                    .map(serde_json::Value::Number),
+            }
+        }
+        serde_json::Value::Number(number) => match number.as_u64() {
+            Some(0) => Some(false.into()),
+            Some(1) => Some(true.into()),
+            _ => None,
+        },
+        _ => None,
     }
 }
 
-fn write_options(path: &Path, options: &OptionsData) -> Result<(), String> {
-    let content = serde_json::to_string_pretty(options)
-        .map_err(|e| format!("Failed to encode options: {e}"))?;
-    fs::write(path, content).map_err(|e| format!("Failed to write options: {e}"))
+/// Builds `T` from `value` one field at a time on top of `defaults`. A field that doesn't
+/// fit, even after coercion, keeps its default and is reported as `<context><field>: <why>`.
+fn merge_lenient<T>(
+    defaults: &T,
+    value: &serde_json::Map<String, serde_json::Value>,
+    context: &str,
+    errors: &mut Vec<String>,
+) -> T
+where
+    T: Serialize + serde::de::DeserializeOwned + Clone,
+{
+    let Ok(serde_json::Value::Object(mut merged)) = serde_json::to_value(defaults) else {
+        return defaults.clone();
+    };
+    for (key, field) in value {
+        let previous = merged.insert(key.clone(), field.clone());
+        let error = match serde_json::from_value::<T>(serde_json::Value::Object(merged.clone())) {
+            Ok(_) => continue,
+            Err(error) => error,
+        };
+        if let Some(coerced) = coerce_option_value(field) {
+            merged.insert(key.clone(), coerced);
+            if serde_json::from_value::<T>(serde_json::Value::Object(merged.clone())).is_ok() {
+                continue;
+            }
+        }
+        match previous {
+            Some(previous) => merged.insert(key.clone(), previous),
+            None => merged.remove(key),
+        };
+        errors.push(format!("{context}{key}: {error}"));
+    }
+    serde_json::from_value(serde_json::Value::Object(merged)).unwrap_or_else(|_| defaults.clone())
 }
 
-fn normalize_extension_list(extensions: &[String]) -> HashSet<String> {
-    extensions
-        .iter()
-        .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
-        .filter(|ext| !ext.is_empty())
-        .collect()
+/// Keeps every option that parses. Presets are repaired one by one so a bad field only
+/// costs that field; the errors list what was reset.
+fn options_from_value(value: &serde_json::Value) -> Result<(OptionsData, Vec<String>), String> {
+    let Some(object) = value.as_object() else {
+        return Err("Failed to parse options JSON: expected an object".to_string());
+    };
+    let mut errors = Vec::new();
+    let mut top_level = object.clone();
+    let presets = top_level.remove("Presets");
+    let mut options = merge_lenient(&OptionsData::default(), &top_level, "", &mut errors);
+    match presets {
+        Some(serde_json::Value::Array(items)) => {
+            let mut repaired = Vec::new();
+            for (index, item) in items.iter().enumerate() {
+                let context = format!("Presets[{index}].");
+                match item.as_object() {
+                    Some(preset) => {
+                        repaired.push(merge_lenient(
+                            &Preset::default(),
+                            preset,
+                            &context,
+                            &mut errors,
+                        ));
+                    }
+                    None => errors.push(format!("Presets[{index}]: not an object, dropped")),
+                }
+            }
+            if !repaired.is_empty() {
+                options.presets = repaired;
+            }
+        }
+        Some(other) => errors.push(format!("Presets: expected a list, found {other}")),
+        None => {}
+    }
+    Ok((options, errors))
+}
+
+/// Reads the options file; a missing file yields the defaults, anything unreadable is an error.
+/// Fields that don't parse fall back to their defaults and are listed in `load_error`.
+fn read_options(path: &Path) -> Result<OptionsData, String> {
+    if path.is_dir() {
+        return Err(format!(
+            "Options file {} is a directory",
+            path.to_string_lossy()
+        ));
+    }
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(OptionsData::default())
+        }
+        Err(error) => {
+            return Err(format!(
+                "Failed to read options from {}: {error}",
+                path.to_string_lossy()
+            ))
+        }
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse options JSON: {e}"))?;
+    let (mut options, errors) = options_from_value(&value)?;
+    if !errors.is_empty() {
+        options.load_error = Some(format!(
+            "Some options were invalid and use their defaults: {}",
+            errors.join("; ")
+        ));
+    }
+    Ok(options)
+}
+
+/// Loads options for the UI. An unreadable file is left untouched and the defaults are
+/// returned with `load_error` set, so the app still starts. A partly invalid file is
+/// not rewritten either; it's only replaced when the user saves.
+fn load_options_or_default(path: &Path) -> Result<OptionsData, String> {
+    let options = read_options_or_default(path);
+    if options.load_error.is_none() {
+        write_options(path, &options)?;
+    }
+    Ok(options)
+}
+
+/// Like `load_options_or_default` but never writes the file back, for read-only media
+/// or hand-edited shared configs.
+fn read_options_or_default(path: &Path) -> OptionsData {
+    read_options(path).unwrap_or_else(|error| OptionsData {
+        load_error: Some(error),
+        ..OptionsData::default()
+    })
+}
+
+/// `path` with its leading `old_root` replaced by `new_root`. Only whole path components
+/// match, so `/media/tv` doesn't move `/media/tv2`.
+fn relocate_path(path: &str, old_root: &str, new_root: &str) -> Option<String> {
+    let old_root = old_root.trim_end_matches(['/', '\\']);
+    if old_root.is_empty() {
+        return None;
+    }
+    let rest = path.strip_prefix(old_root)?;
+    if !rest.is_empty() && !rest.starts_with(['/', '\\']) {
+        return None;
+    }
+    Some(format!("{}{rest}", new_root.trim_end_matches(['/', '\\'])))
+}
+
+/// Moves a preset's folders from `old_root` to `new_root`, saving the options when
+/// anything changed. Returns the names of the changed fields.
+fn relocate_preset_in_options(
+    options_path: &Path,
+    preset_id: usize,
+    old_root: &str,
+    new_root: &str,
+) -> Result<Vec<String>, String> {
+    let mut options = read_options(options_path)?;
+    let preset = options
+        .presets
+        .get_mut(preset_id)
+        .ok_or_else(|| format!("No preset with id {preset_id}"))?;
+    let mut changed = Vec::new();
+    for (field, directory) in preset.directories_mut() {
+        if let Some(relocated) = relocate_path(directory, old_root, new_root) {
+            *directory = relocated;
+            changed.push(field.to_string());
+        }
+    }
+    if !changed.is_empty() {
+        write_options(options_path, &options)?;
+    }
+    Ok(changed)
+}
+
+fn write_options(path: &Path, options: &OptionsData) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(options)
+        .map_err(|e| format!("Failed to encode options: {e}"))?;
+    fs::write(path, content).map_err(|e| format!("Failed to write options: {e}"))
+}
+
+/// Lowercased extensions without dots. A token naming one of `groups` (case-insensitive)
+/// stands for that group's extensions; groups don't nest.
+fn normalize_extension_list(
+    extensions: &[String],
+    groups: &HashMap<String, Vec<String>>,
+) -> HashSet<String> {
+    let normalize = |ext: &String| ext.trim_start_matches('.').to_ascii_lowercase();
+    extensions
+        .iter()
+        .flat_map(|token| {
+            match groups
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(token.trim()))
+            {
+                Some((_, members)) => members.iter().map(normalize).collect(),
+                None => vec![normalize(token)],
+            }
+        })
+        .filter(|ext| !ext.is_empty())
+        .collect()
 }
 
 fn should_include_file(path: &Path, allowed_extensions: &HashSet<String>) -> bool {
@@ -386,6 +1462,55 @@ fn should_include_file(path: &Path, allowed_extensions: &HashSet<String>) -> boo
         .unwrap_or(false)
 }
 
+fn is_matroska_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "mkv" | "mka" | "mks" | "mk3d"
+            )
+        })
+        .unwrap_or(false)
+}
+
+const EBML_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+
+fn has_ebml_header(path: &Path) -> bool {
+    let mut header = [0u8; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| header == EBML_MAGIC)
+        .unwrap_or(false)
+}
+
+fn magic_mismatch_warning(path: &Path) -> Option<String> {
+    if is_matroska_path(path) && !has_ebml_header(path) {
+        Some("File is named like Matroska but has no EBML header".to_string())
+    } else {
+        None
+    }
+}
+
+/// Applies `verify_magic` to scanned items; mismatches keep their place but carry a warning.
+fn flag_magic_mismatches(request: &ScanRequest, items: &mut [serde_json::Value]) {
+    if !request.verify_magic {
+        return;
+    }
+    for item in items.iter_mut() {
+        let warning = item
+            .get("path")
+            .and_then(|path| path.as_str())
+            .and_then(|path| magic_mismatch_warning(Path::new(path)));
+        if let (Some(warning), Some(object)) = (warning, item.as_object_mut()) {
+            let has_warning = object.get("warning").is_some_and(|w| !w.is_null());
+            if !has_warning {
+                object.insert("warning".to_string(), serde_json::Value::String(warning));
+            }
+        }
+    }
+}
+
 fn hidden_command(program: &str) -> Command {
     #[cfg(target_os = "windows")]
     {
@@ -400,6 +1525,44 @@ fn hidden_command(program: &str) -> Command {
     }
 }
 
+/// Ties a spawned tool to a kill-on-close job object, so Windows ends it with the app
+/// even when the app dies without running its exit handler.
+#[cfg(target_os = "windows")]
+fn bind_child_to_app(child: &Child) {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    static JOB: OnceLock<Option<HANDLE>> = OnceLock::new();
+    let job = JOB.get_or_init(|| unsafe {
+        let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+        if job == 0 {
+            return None;
+        }
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let configured = SetInformationJobObject(
+            job,
+            JobObjectExtendedLimitInformation,
+            &info as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const _,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        );
+        (configured != 0).then_some(job)
+    });
+    if let Some(job) = job {
+        unsafe {
+            AssignProcessToJobObject(*job, child.as_raw_handle() as HANDLE);
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn bind_child_to_app(_child: &Child) {}
+
 fn tool_available(tool: &str, version_arg: &str) -> bool {
     hidden_command(tool)
         .arg(version_arg)
@@ -410,6 +1573,98 @@ fn tool_available(tool: &str, version_arg: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// The external tools the app shells out to, with the argument that prints their version.
+const TOOL_DEPENDENCIES: [(&str, &str); 3] = [
+    ("mkvmerge", "-V"),
+    ("mkvpropedit", "-V"),
+    ("mediainfo", "--Version"),
+];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DependencyStatus {
+    tool: String,
+    found: bool,
+    version: Option<String>,
+    path: Option<String>,
+    install_hint: Option<String>,
+}
+
+fn tool_version(tool: &str, version_arg: &str) -> Option<String> {
+    let output = hidden_command(tool)
+        .arg(version_arg)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // mediainfo prints a banner line before the version, so prefer the first line with a number.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines
+        .iter()
+        .find(|line| line.chars().any(|c| c.is_ascii_digit()))
+        .or(lines.first())
+        .map(|line| line.to_string())
+}
+
+fn find_in_path(tool: &str) -> Option<PathBuf> {
+    let direct = Path::new(tool);
+    if direct.is_absolute() {
+        return direct.is_file().then(|| direct.to_path_buf());
+    }
+    let file_name = if cfg!(target_os = "windows") && direct.extension().is_none() {
+        format!("{tool}.exe")
+    } else {
+        tool.to_string()
+    };
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(target_os = "windows")]
+fn install_hint(tool: &str) -> String {
+    match tool {
+        "mediainfo" => "choco install mediainfo-cli".to_string(),
+        _ => "choco install mkvtoolnix".to_string(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn install_hint(tool: &str) -> String {
+    match tool {
+        "mediainfo" => "brew install media-info".to_string(),
+        _ => "brew install mkvtoolnix".to_string(),
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn install_hint(tool: &str) -> String {
+    match tool {
+        "mediainfo" => "sudo apt install mediainfo".to_string(),
+        _ => "sudo apt install mkvtoolnix".to_string(),
+    }
+}
+
+fn dependency_status(tool: &str, version_arg: &str) -> DependencyStatus {
+    let version = tool_version(tool, version_arg);
+    let found = version.is_some() || tool_available(tool, version_arg);
+    DependencyStatus {
+        tool: tool.to_string(),
+        found,
+        version,
+        path: find_in_path(tool).map(|path| path.to_string_lossy().to_string()),
+        install_hint: (!found).then(|| install_hint(tool)),
+    }
+}
+
 fn mediainfo_available() -> bool {
     *MEDIAINFO_AVAILABLE.get_or_init(|| tool_available("mediainfo", "--Version"))
 }
@@ -418,6 +1673,74 @@ fn mkvmerge_available() -> bool {
     *MKVMERGE_AVAILABLE.get_or_init(|| tool_available("mkvmerge", "-V"))
 }
 
+/// First mkvmerge release with `--default-track-flag` and `--forced-display-flag`; older
+/// ones only know `--default-track` and `--forced-track`.
+const TRACK_FLAG_NAMES_VERSION: u32 = 57;
+
+/// Major version from `mkvmerge -V` output such as "mkvmerge v81.0 ('Milliontown') 64-bit".
+fn parse_mkvmerge_major_version(text: &str) -> Option<u32> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix('v'))
+        .find_map(|version| version.split('.').next()?.parse().ok())
+}
+
+/// The installed mkvmerge's major version, or `None` when it can't be read, in which
+/// case the current flag names are used.
+fn mkvmerge_major_version(state: &AppState) -> Option<u32> {
+    *state.mkvmerge_version.get_or_init(|| {
+        tool_version("mkvmerge", "-V").and_then(|text| parse_mkvmerge_major_version(&text))
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TrackFlag {
+    Default,
+    Forced,
+}
+
+/// The option setting `flag`, spelled the way the installed mkvmerge understands it.
+fn track_flag_arg(state: &AppState, flag: TrackFlag) -> &'static str {
+    let legacy = mkvmerge_major_version(state).is_some_and(|v| v < TRACK_FLAG_NAMES_VERSION);
+    match (flag, legacy) {
+        (TrackFlag::Default, false) => "--default-track-flag",
+        (TrackFlag::Default, true) => "--default-track",
+        (TrackFlag::Forced, false) => "--forced-display-flag",
+        (TrackFlag::Forced, true) => "--forced-track",
+    }
+}
+
+/// Flags swapped for their older spelling because the installed mkvmerge predates them.
+fn legacy_mkvmerge_warning(state: &AppState, args: &[String]) -> Option<String> {
+    let version = mkvmerge_major_version(state)?;
+    let legacy = args
+        .iter()
+        .any(|arg| arg == "--default-track" || arg == "--forced-track");
+    (version < TRACK_FLAG_NAMES_VERSION && legacy).then(|| {
+        format!(
+            "mkvmerge v{version} predates v{TRACK_FLAG_NAMES_VERSION}; using --default-track \
+             and --forced-track instead of --default-track-flag and --forced-display-flag"
+        )
+    })
+}
+
+/// External audio whose forced flag is left out because the installed mkvmerge predates
+/// setting it on audio tracks.
+fn skipped_audio_forced_warnings(job: &MuxJobRequest, state: &AppState) -> Vec<String> {
+    if mkvmerge_major_version(state).is_none_or(|v| v >= TRACK_FLAG_NAMES_VERSION) {
+        return Vec::new();
+    }
+    job.audios
+        .iter()
+        .filter(|audio| audio.is_forced.is_some())
+        .map(|audio| {
+            format!(
+                "Skipping the forced flag of {}: mkvmerge v{} or newer is needed",
+                audio.path, TRACK_FLAG_NAMES_VERSION
+            )
+        })
+        .collect()
+}
+
 fn file_info_cache() -> &'static Mutex<HashMap<String, serde_json::Value>> {
     FILE_INFO_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
 }
@@ -427,6 +1750,8 @@ fn build_file_cache_key(
     metadata: &fs::Metadata,
     file_type: &str,
     include_tracks: bool,
+    duration_source: DurationSource,
+    metadata_level: MetadataLevel,
 ) -> String {
     let modified = metadata
         .modified()
@@ -435,12 +1760,14 @@ fn build_file_cache_key(
         .map(|duration| duration.as_secs())
         .unwrap_or(0);
     format!(
-        "{}|{}|{}|{}|{}",
+        "{}|{}|{}|{}|{}|{:?}|{:?}",
         path.to_string_lossy(),
         metadata.len(),
         modified,
         file_type,
-        include_tracks
+        include_tracks,
+        duration_source,
+        metadata_level
     )
 }
 
@@ -457,22 +1784,135 @@ fn put_cached_file_info(cache_key: String, value: &serde_json::Value) {
     }
 }
 
-fn get_mkvmerge_info(path: &Path) -> Option<serde_json::Value> {
+/// Drops every cached probe of the given paths, whatever type or level they were
+/// probed with. Returns how many entries were removed.
+fn remove_cached_file_info(paths: &[String]) -> usize {
+    let prefixes: Vec<String> = paths.iter().map(|path| format!("{path}|")).collect();
+    let Ok(mut cache) = file_info_cache().lock() else {
+        return 0;
+    };
+    let before = cache.len();
+    cache.retain(|key, _| !prefixes.iter().any(|prefix| key.starts_with(prefix)));
+    before - cache.len()
+}
+
+/// Interprets a probe tool's output, keeping the reason when it failed or emitted non-JSON.
+fn parse_probe_output(
+    tool: &str,
+    success: bool,
+    stdout: &[u8],
+    stderr: &[u8],
+) -> Result<serde_json::Value, String> {
+    let stderr_text = String::from_utf8_lossy(stderr);
+    let last_stderr_line = stderr_text
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty());
+    match serde_json::from_slice::<serde_json::Value>(stdout) {
+        Ok(value) if success => Ok(value),
+        Ok(value) => {
+            // mkvmerge -J still reports its errors as JSON when it rejects a file
+            let reported = value
+                .get("errors")
+                .and_then(|errors| errors.as_array())
+                .and_then(|errors| errors.first())
+                .and_then(|error| error.as_str())
+                .map(|error| error.to_string());
+            Err(format!(
+                "{tool}: {}",
+                reported
+                    .or_else(|| last_stderr_line.map(|line| line.to_string()))
+                    .unwrap_or_else(|| "exited with an error".to_string())
+            ))
+        }
+        Err(_) => Err(format!(
+            "{tool}: {}",
+            last_stderr_line.unwrap_or("returned non-JSON output")
+        )),
+    }
+}
+
+/// Counting semaphore that caps how many probe processes run at once, so a scan
+/// doesn't flood a spinning disk or network share with dozens of readers.
+struct ProbeLimiter {
+    limit: usize,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ProbeLimiter {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    fn run<T>(&self, probe: impl FnOnce() -> T) -> T {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active >= self.limit {
+            active = self
+                .released
+                .wait(active)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *active += 1;
+        drop(active);
+        let _slot = ProbeSlot(self);
+        probe()
+    }
+}
+
+/// Gives the slot back even if the probe panics.
+struct ProbeSlot<'a>(&'a ProbeLimiter);
+
+impl Drop for ProbeSlot<'_> {
+    fn drop(&mut self) {
+        let mut active = self.0.active.lock().unwrap_or_else(|e| e.into_inner());
+        *active -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Runs `mkvmerge -J`. `Ok(None)` means mkvmerge isn't installed.
+fn probe_mkvmerge(path: &Path) -> Result<Option<serde_json::Value>, String> {
     if !mkvmerge_available() {
-        return None;
+        return Ok(None);
     }
     let output = hidden_command("mkvmerge")
         .arg("-J")
         .arg(path)
         .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
-    }
-    serde_json::from_slice(&output.stdout).ok()
+        .map_err(|e| format!("mkvmerge: failed to start: {e}"))?;
+    parse_probe_output(
+        "mkvmerge",
+        output.status.success(),
+        &output.stdout,
+        &output.stderr,
+    )
+    .map(Some)
+}
+
+fn get_mkvmerge_info(path: &Path) -> Option<serde_json::Value> {
+    probe_mkvmerge(path).ok().flatten()
 }
 
 fn parse_mkvmerge_duration(mkvmerge: &serde_json::Value) -> Option<String> {
+    parse_mkvmerge_duration_seconds(mkvmerge).map(format_duration)
+}
+
+/// `HH:MM:SS`, rounded to the nearest second, for display.
+fn format_duration(seconds: f64) -> String {
+    let total_seconds = seconds.round() as u64;
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let secs = total_seconds % 60;
+    format!("{:02}:{:02}:{:02}", hours, minutes, secs)
+}
+
+fn parse_mkvmerge_duration_seconds(mkvmerge: &serde_json::Value) -> Option<f64> {
     let duration = mkvmerge
         .get("container")?
         .get("properties")?
@@ -505,12 +1945,7 @@ fn parse_mkvmerge_duration(mkvmerge: &serde_json::Value) -> Option<String> {
     if seconds < 0.0 || !seconds.is_finite() || seconds > 86400.0 * 365.0 {
         return None;
     }
-
-    let total_seconds = seconds.round() as u64;
-    let hours = total_seconds / 3600;
-    let minutes = (total_seconds % 3600) / 60;
-    let secs = total_seconds % 60;
-    Some(format!("{:02}:{:02}:{:02}", hours, minutes, secs))
+    Some(seconds)
 }
 
 fn parse_mkvmerge_tracks(mkvmerge: &serde_json::Value) -> Vec<TrackInfo> {
@@ -541,6 +1976,10 @@ fn parse_mkvmerge_tracks(mkvmerge: &serde_json::Value) -> Vec<TrackInfo> {
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
         let properties = track.get("properties");
+        let codec_id = properties
+            .and_then(|p| p.get("codec_id"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
         let language = properties
             .and_then(|p| p.get("language"))
             .and_then(|v| v.as_str())
@@ -611,33 +2050,71 @@ fn parse_mkvmerge_tracks(mkvmerge: &serde_json::Value) -> Vec<TrackInfo> {
             id: track_id,
             track_type: mapped_type.to_string(),
             codec,
+            codec_id,
             language,
             name,
             is_default,
             is_forced,
             bitrate,
             action: Some("keep".to_string()),
+            original_name: None,
+            original_default: None,
+            delay: None,
         });
     }
     tracks
 }
 
-fn get_mediainfo(path: &Path) -> Option<serde_json::Value> {
+/// Runs `mediainfo --Output=JSON`. `Ok(None)` means mediainfo isn't installed.
+fn probe_mediainfo(path: &Path) -> Result<Option<serde_json::Value>, String> {
     if !mediainfo_available() {
-        return None;
+        return Ok(None);
     }
     let output = hidden_command("mediainfo")
         .arg("--Output=JSON")
         .arg(path)
         .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
-    }
-    serde_json::from_slice(&output.stdout).ok()
+        .map_err(|e| format!("mediainfo: failed to start: {e}"))?;
+    parse_probe_output(
+        "mediainfo",
+        output.status.success(),
+        &output.stdout,
+        &output.stderr,
+    )
+    .map(Some)
+}
+
+/// Splits probe results into the parsed values and a combined error message.
+fn split_probe_results(
+    mkvmerge: Result<Option<serde_json::Value>, String>,
+    mediainfo: Result<Option<serde_json::Value>, String>,
+) -> (
+    Option<serde_json::Value>,
+    Option<serde_json::Value>,
+    Option<String>,
+) {
+    let mut errors = Vec::new();
+    let mkvmerge = mkvmerge.unwrap_or_else(|error| {
+        errors.push(error);
+        None
+    });
+    let mediainfo = mediainfo.unwrap_or_else(|error| {
+        errors.push(error);
+        None
+    });
+    let probe_error = if errors.is_empty() {
+        None
+    } else {
+        Some(errors.join("; "))
+    };
+    (mkvmerge, mediainfo, probe_error)
 }
 
 fn parse_duration(mediainfo: &serde_json::Value) -> Option<String> {
+    parse_duration_seconds(mediainfo).map(format_duration)
+}
+
+fn parse_duration_seconds(mediainfo: &serde_json::Value) -> Option<f64> {
     let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
     for track in tracks {
         if track.get("@type")?.as_str()? == "General" {
@@ -647,11 +2124,7 @@ fn parse_duration(mediainfo: &serde_json::Value) -> Option<String> {
 
                 // First try parsing as seconds (most common for mediainfo JSON)
                 if let Ok(seconds) = duration_str.trim().parse::<f64>() {
-                    let total_seconds = seconds.round() as u64;
-                    let hours = total_seconds / 3600;
-                    let minutes = (total_seconds % 3600) / 60;
-                    let secs = total_seconds % 60;
-                    return Some(format!("{:02}:{:02}:{:02}", hours, minutes, secs));
+                    return Some(seconds);
                 }
 
                 // Try parsing as HH:MM:SS.mmm format
@@ -661,9 +2134,9 @@ fn parse_duration(mediainfo: &serde_json::Value) -> Option<String> {
                         if let (Ok(h), Ok(m), Ok(s)) = (
                             parts[0].parse::<u64>(),
                             parts[1].parse::<u64>(),
-                            parts[2].split('.').next().unwrap_or("0").parse::<u64>(),
+                            parts[2].trim().parse::<f64>(),
                         ) {
-                            return Some(format!("{:02}:{:02}:{:02}", h, m, s));
+                            return Some((h * 3600 + m * 60) as f64 + s);
                         }
                     }
                 }
@@ -673,6 +2146,33 @@ fn parse_duration(mediainfo: &serde_json::Value) -> Option<String> {
     None
 }
 
+/// Reads the Dolby Vision profile from mediainfo's `HDR_Format_Profile`, e.g. `dvhe.07.06`.
+fn parse_dolby_vision_profile(mediainfo: &serde_json::Value) -> Option<u8> {
+    let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
+    let video = tracks
+        .iter()
+        .find(|track| track.get("@type").and_then(|t| t.as_str()) == Some("Video"))?;
+    let profile = video.get("HDR_Format_Profile")?.as_str()?;
+    // Hybrid streams list one profile per layer ("dvhe.07.06 / dvhe.07.06")
+    profile.split('/').find_map(|entry| {
+        let (codec, rest) = entry.trim().split_once('.')?;
+        if !codec.starts_with("dv") {
+            return None;
+        }
+        rest.split('.').next()?.parse().ok()
+    })
+}
+
+/// Profile 7 carries an enhancement layer most players mishandle once remuxed.
+fn dolby_vision_warning(job: &MuxJobRequest) -> Option<String> {
+    (job.video.dolby_vision_profile == Some(7)).then(|| {
+        format!(
+            "{} is Dolby Vision profile 7; remuxes of it often play wrong, and it is kept as is",
+            job.video.name
+        )
+    })
+}
+
 fn parse_video_fps(mediainfo: &serde_json::Value) -> Option<f64> {
     let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
     for track in tracks {
@@ -699,6 +2199,49 @@ fn parse_video_fps(mediainfo: &serde_json::Value) -> Option<f64> {
     None
 }
 
+/// Timestamp of the first video frame in seconds, from mediainfo's video `Delay`.
+fn parse_video_start_seconds(mediainfo: &serde_json::Value) -> Option<f64> {
+    let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
+    let video = tracks
+        .iter()
+        .find(|track| track.get("@type").and_then(|t| t.as_str()) == Some("Video"))?;
+    let delay = video.get("Delay")?;
+    delay
+        .as_str()
+        .and_then(|delay| delay.trim().parse().ok())
+        .or_else(|| delay.as_f64())
+}
+
+/// Offset applied to every external chapter file: the video's first-frame timestamp
+/// with `chapter_auto_offset`, otherwise zero.
+fn chapter_offset_seconds(job: &MuxJobRequest, settings: &MuxSettings) -> f64 {
+    if !settings.chapter_auto_offset || job.chapters.is_empty() {
+        return 0.0;
+    }
+    probe_mediainfo(Path::new(&job.video.path))
+        .ok()
+        .flatten()
+        .and_then(|info| parse_video_start_seconds(&info))
+        .unwrap_or(0.0)
+}
+
+fn probe_job_inputs(job: &mut MuxJobRequest, settings: &MuxSettings) {
+    job.probed = ProbedInputs {
+        chapter_offset: chapter_offset_seconds(job, settings),
+        embedded_attachments: (settings.discard_old_attachments
+            && settings.discard_replaced_attachments_only)
+            .then(|| get_mkvmerge_info(Path::new(&job.video.path)))
+            .flatten()
+            .map(|info| embedded_attachments(&info)),
+    };
+}
+
+/// The chapter `--sync` in milliseconds: the file's own delay plus the global offset.
+fn chapter_sync_ms(delay: Option<f64>, offset: f64) -> Option<i64> {
+    let ms = ((delay.unwrap_or(0.0) + offset) * 1000.0).round() as i64;
+    (ms != 0).then_some(ms)
+}
+
 fn parse_bitrate_value(value: &serde_json::Value) -> Option<u64> {
     if let Some(v) = value.as_u64() {
         return Some(v);
@@ -752,6 +2295,10 @@ fn parse_tracks(mediainfo: &serde_json::Value) -> Vec<TrackInfo> {
             .get("Format")
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
+        let codec_id = track
+            .get("CodecID")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
         let language = track
             .get("Language")
             .and_then(|v| v.as_str())
@@ -780,55 +2327,204 @@ fn parse_tracks(mediainfo: &serde_json::Value) -> Vec<TrackInfo> {
             id: (index + 1).to_string(),
             track_type: mapped_type.to_string(),
             codec,
+            codec_id,
             language,
             name,
             is_default,
             is_forced,
             bitrate,
             action: Some("keep".to_string()),
+            original_name: None,
+            original_default: None,
+            delay: None,
         });
     }
 
     tracks
 }
 
-fn parse_external_track_id(mediainfo: &serde_json::Value, track_type: &str) -> Option<u64> {
-    let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
-    for track in tracks {
-        if track.get("@type")?.as_str()? != track_type {
+/// ISO 639-1 codes by ISO 639-2 and 639-3 code, read from mkvmerge's language list.
+static ISO_639_1_CODES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// The ISO 639-1 column of `mkvmerge --list-languages`, keyed by both of the languages'
+/// three-letter codes, so `ger` and `deu` both map to `de`.
+fn parse_iso_639_1_codes(text: &str) -> HashMap<String, String> {
+    let mut codes = HashMap::new();
+    for line in text.lines().filter(|line| !line.contains("ISO 639")) {
+        let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [_, iso_639_3, iso_639_2, iso_639_1] = columns[..] else {
+            continue;
+        };
+        if iso_639_1.len() != 2 || !iso_639_1.chars().all(|c| c.is_ascii_lowercase()) {
             continue;
         }
-        if let Some(id_val) = track.get("ID") {
-            if let Some(v) = id_val.as_u64() {
-                if v > 0 {
-                    return Some(v);
-                }
-            }
-            if let Some(v) = id_val.as_f64() {
-                let parsed = v as u64;
-                if parsed > 0 {
-                    return Some(parsed);
-                }
-            }
-            if let Some(v) = id_val.as_str() {
-                let digits: String = v.chars().filter(|c| c.is_ascii_digit()).collect();
-                if let Ok(parsed) = digits.parse::<u64>() {
-                    if parsed > 0 {
-                        return Some(parsed);
-                    }
-                }
+        for code in [iso_639_3, iso_639_2] {
+            if code.len() == 3 && code.chars().all(|c| c.is_ascii_lowercase()) {
+                codes.insert(code.to_string(), iso_639_1.to_string());
             }
         }
     }
-    None
+    codes
 }
 
-fn parse_external_track_id_mkvmerge(mkvmerge: &serde_json::Value, track_type: &str) -> Option<u64> {
-    let tracks = mkvmerge.get("tracks")?.as_array()?;
-    for track in tracks {
-        let mkv_type = track.get("type")?.as_str()?;
-        let mapped = match mkv_type {
-            "audio" => "Audio",
+/// The ISO 639-1 table of the installed mkvmerge. Only a successful listing is kept,
+/// so a later call tries again.
+fn iso_639_1_codes() -> Option<&'static HashMap<String, String>> {
+    if let Some(codes) = ISO_639_1_CODES.get() {
+        return Some(codes);
+    }
+    let codes = parse_iso_639_1_codes(&list_mkvmerge_languages()?);
+    (!codes.is_empty()).then(|| ISO_639_1_CODES.get_or_init(|| codes))
+}
+
+/// Whether mkvmerge's language (ISO 639-2, `jpn`) and mediainfo's (ISO 639-1, `ja`, or
+/// a tag like `ja-JP`) name the same language, looked up in `iso_639_1`. Codes missing
+/// from it match on their first two letters. Two undetermined languages match.
+fn same_language(a: Option<&str>, b: Option<&str>, iso_639_1: &HashMap<String, String>) -> bool {
+    if is_undefined_language(a) || is_undefined_language(b) {
+        return is_undefined_language(a) && is_undefined_language(b);
+    }
+    let primary = |language: Option<&str>| {
+        let language = language.unwrap_or_default().trim().to_ascii_lowercase();
+        language.split('-').next().unwrap_or_default().to_string()
+    };
+    let (a, b) = (primary(a), primary(b));
+    let (long, short) = match (a.len(), b.len()) {
+        (3, 2) => (a, b),
+        (2, 3) => (b, a),
+        _ => return a == b,
+    };
+    match iso_639_1.get(&long) {
+        Some(code) => *code == short,
+        None => long.starts_with(&short),
+    }
+}
+
+/// Whether mkvmerge's codec name covers mediainfo's format, e.g. `DTS-HD Master Audio`
+/// and `DTS`, while `E-AC-3` and `AC-3` stay apart.
+fn same_codec(mkvmerge: Option<&str>, mediainfo: Option<&str>) -> bool {
+    let normalize = |codec: Option<&str>| -> String {
+        codec
+            .unwrap_or_default()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase()
+    };
+    let (mkvmerge, mediainfo) = (normalize(mkvmerge), normalize(mediainfo));
+    if mediainfo.is_empty() {
+        mkvmerge.is_empty()
+    } else {
+        mkvmerge.starts_with(&mediainfo)
+    }
+}
+
+/// Warns when mkvmerge and mediainfo list a different number of tracks, since values
+/// copied from mediainfo can then land on the wrong track.
+fn track_count_warning(
+    mkvmerge: &serde_json::Value,
+    mediainfo: &serde_json::Value,
+) -> Option<String> {
+    let mkvmerge_count = parse_mkvmerge_tracks(mkvmerge).len();
+    let mediainfo_count = parse_tracks(mediainfo)
+        .iter()
+        .filter(|track| track.track_type != "chapter")
+        .count();
+    (mkvmerge_count != mediainfo_count).then(|| {
+        format!(
+            "mkvmerge found {mkvmerge_count} tracks but mediainfo found {mediainfo_count}; \
+             mediainfo bitrates were matched by language and codec"
+        )
+    })
+}
+
+/// Copies mediainfo audio bitrates onto `tracks`; mediainfo is preferred because it is
+/// more accurate for VBR streams. When both tools list the same number of audio tracks
+/// they pair up by position; otherwise each track takes the first unused mediainfo
+/// track with the same language and codec, or nothing.
+/// Returns the indices of the tracks whose bitrate came from mediainfo.
+fn apply_mediainfo_audio_bitrates(
+    tracks: &mut [TrackInfo],
+    mediainfo: &serde_json::Value,
+) -> Vec<usize> {
+    let mi_audios: Vec<TrackInfo> = parse_tracks(mediainfo)
+        .into_iter()
+        .filter(|t| t.track_type == "audio")
+        .collect();
+    let by_position = tracks.iter().filter(|t| t.track_type == "audio").count() == mi_audios.len();
+    let no_codes = HashMap::new();
+    let iso_639_1 = if by_position {
+        &no_codes
+    } else {
+        iso_639_1_codes().unwrap_or(&no_codes)
+    };
+    let mut used = vec![false; mi_audios.len()];
+    let mut applied = Vec::new();
+    for (position, (index, track)) in tracks
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, t)| t.track_type == "audio")
+        .enumerate()
+    {
+        let matched = if by_position {
+            Some(position)
+        } else {
+            (0..mi_audios.len()).find(|&candidate| {
+                let mi = &mi_audios[candidate];
+                !used[candidate]
+                    && same_language(track.language.as_deref(), mi.language.as_deref(), iso_639_1)
+                    && same_codec(track.codec.as_deref(), mi.codec.as_deref())
+            })
+        };
+        let Some(matched) = matched else {
+            continue;
+        };
+        used[matched] = true;
+        if let Some(bitrate) = mi_audios[matched].bitrate {
+            track.bitrate = Some(bitrate);
+            applied.push(index);
+        }
+    }
+    applied
+}
+
+fn parse_external_track_id(mediainfo: &serde_json::Value, track_type: &str) -> Option<u64> {
+    let tracks = mediainfo.get("media")?.get("track")?.as_array()?;
+    for track in tracks {
+        if track.get("@type")?.as_str()? != track_type {
+            continue;
+        }
+        if let Some(id_val) = track.get("ID") {
+            if let Some(v) = id_val.as_u64() {
+                if v > 0 {
+                    return Some(v);
+                }
+            }
+            if let Some(v) = id_val.as_f64() {
+                let parsed = v as u64;
+                if parsed > 0 {
+                    return Some(parsed);
+                }
+            }
+            if let Some(v) = id_val.as_str() {
+                let digits: String = v.chars().filter(|c| c.is_ascii_digit()).collect();
+                if let Ok(parsed) = digits.parse::<u64>() {
+                    if parsed > 0 {
+                        return Some(parsed);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+fn parse_external_track_id_mkvmerge(mkvmerge: &serde_json::Value, track_type: &str) -> Option<u64> {
+    let tracks = mkvmerge.get("tracks")?.as_array()?;
+    for track in tracks {
+        let mkv_type = track.get("type")?.as_str()?;
+        let mapped = match mkv_type {
+            "audio" => "Audio",
             "subtitles" => "Text",
             "video" => "Video",
             _ => "Unknown",
@@ -895,8 +2591,43 @@ fn parse_external_track_ids_mkvmerge(mkvmerge: &serde_json::Value, track_type: &
     ids
 }
 
+/// Sequential ids used instead of timestamped ones once a seed is set.
+struct SeededIds {
+    seed: u64,
+    next: u64,
+}
+
+impl SeededIds {
+    fn new(seed: u64) -> Self {
+        Self { seed, next: 0 }
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        let id = format!("{}-{}-{}", prefix, self.seed, self.next);
+        self.next += 1;
+        id
+    }
+}
+
+fn seeded_ids() -> &'static Mutex<Option<SeededIds>> {
+    SEEDED_IDS.get_or_init(|| Mutex::new(None))
+}
+
+/// Switches id generation to deterministic `prefix-seed-n` ids, or back to timestamps with `None`.
+fn set_seeded_ids(seed: Option<u64>) {
+    *seeded_ids().lock().unwrap() = seed.map(SeededIds::new);
+}
+
 fn generate_id(prefix: &str) -> String {
+    id_from(seeded_ids().lock().unwrap().as_mut(), prefix)
+}
+
+/// The next id from `seeded` when set, else a timestamped one.
+fn id_from(seeded: Option<&mut SeededIds>, prefix: &str) -> String {
     static COUNTER: AtomicU64 = AtomicU64::new(0);
+    if let Some(ids) = seeded {
+        return ids.next_id(prefix);
+    }
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or(Duration::from_secs(0))
@@ -905,9 +2636,23 @@ fn generate_id(prefix: &str) -> String {
     format!("{}-{}-{}", prefix, timestamp, counter)
 }
 
-fn scan_files(request: &ScanRequest) -> Result<Vec<PathBuf>, String> {
+fn modified_before(metadata: &fs::Metadata, cutoff: u64) -> bool {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|timestamp| timestamp.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() < cutoff)
+        .unwrap_or(false)
+}
+
+/// Returns the matching files and how many were skipped by `modified_since`.
+fn scan_files_counting(
+    request: &ScanRequest,
+    groups: &HashMap<String, Vec<String>>,
+) -> Result<(Vec<PathBuf>, usize), String> {
     let mut results = Vec::new();
-    let allowed_extensions = normalize_extension_list(&request.extensions);
+    let mut skipped_older = 0usize;
+    let allowed_extensions = normalize_extension_list(&request.extensions, groups);
     let walker = WalkDir::new(&request.folder)
         .follow_links(true)
         .max_depth(if request.recursive { usize::MAX } else { 1 });
@@ -915,21 +2660,194 @@ fn scan_files(request: &ScanRequest) -> Result<Vec<PathBuf>, String> {
     for entry in walker.into_iter().filter_map(|e| e.ok()) {
         let path = entry.path();
         if path.is_file() && should_include_file(path, &allowed_extensions) {
+            if let Some(cutoff) = request.modified_since {
+                if entry
+                    .metadata()
+                    .map(|metadata| modified_before(&metadata, cutoff))
+                    .unwrap_or(false)
+                {
+                    skipped_older += 1;
+                    continue;
+                }
+            }
             results.push(path.to_path_buf());
         }
     }
 
-    Ok(results)
+    Ok((results, skipped_older))
+}
+
+/// Duration in seconds from the tool `select_duration` picked, so both forms agree.
+fn tool_duration_seconds(
+    tool: &str,
+    mkvmerge: Option<&serde_json::Value>,
+    mediainfo: Option<&serde_json::Value>,
+) -> Option<f64> {
+    match tool {
+        "mkvmerge" => mkvmerge.and_then(parse_mkvmerge_duration_seconds),
+        _ => mediainfo.and_then(parse_duration_seconds),
+    }
+}
+
+/// Picks the duration and names the tool it came from.
+fn select_duration(
+    source: DurationSource,
+    mkvmerge: Option<&serde_json::Value>,
+    mediainfo: Option<&serde_json::Value>,
+) -> Option<(String, &'static str)> {
+    let from_mkvmerge = || {
+        mkvmerge
+            .and_then(parse_mkvmerge_duration)
+            .map(|duration| (duration, "mkvmerge"))
+    };
+    let from_mediainfo = || {
+        mediainfo
+            .and_then(parse_duration)
+            .map(|duration| (duration, "mediainfo"))
+    };
+    match source {
+        DurationSource::Auto | DurationSource::Mkvmerge => from_mkvmerge().or_else(from_mediainfo),
+        DurationSource::Mediainfo => from_mediainfo().or_else(from_mkvmerge),
+    }
+}
+
+/// Builds the `sources` map: `duration`, `fps`, `bitrate` and per-track
+/// `track:<id>:language` / `track:<id>:bitrate` entries, each naming the tool that supplied it.
+fn field_sources(
+    duration_tool: Option<&str>,
+    fps_tool: Option<&str>,
+    bitrate_tool: Option<&str>,
+    tracks: &[TrackInfo],
+    tracks_tool: &str,
+    mediainfo_bitrates: &[usize],
+) -> HashMap<String, String> {
+    let mut sources = HashMap::new();
+    for (field, tool) in [
+        ("duration", duration_tool),
+        ("fps", fps_tool),
+        ("bitrate", bitrate_tool),
+    ] {
+        if let Some(tool) = tool {
+            sources.insert(field.to_string(), tool.to_string());
+        }
+    }
+    for (index, track) in tracks.iter().enumerate() {
+        if track.language.is_some() {
+            let key = format!("track:{}:language", track.id);
+            sources.insert(key, tracks_tool.to_string());
+        }
+        if track.bitrate.is_some() {
+            let tool = if mediainfo_bitrates.contains(&index) {
+                "mediainfo"
+            } else {
+                tracks_tool
+            };
+            sources.insert(format!("track:{}:bitrate", track.id), tool.to_string());
+        }
+    }
+    sources
+}
+
+fn has_video_track(item: &serde_json::Value) -> bool {
+    item.get("tracks")
+        .and_then(|tracks| tracks.as_array())
+        .map(|tracks| {
+            tracks
+                .iter()
+                .any(|track| track.get("type").and_then(|t| t.as_str()) == Some("video"))
+        })
+        .unwrap_or(false)
+}
+
+/// Applies `skip_trackless` to scanned items, returning the kept items and how many were dropped.
+fn drop_trackless(
+    request: &ScanRequest,
+    items: Vec<serde_json::Value>,
+) -> (Vec<serde_json::Value>, usize) {
+    let (include_tracks, metadata_level) = request.probes_tracks();
+    if !request.skip_trackless
+        || !include_tracks
+        || metadata_level != MetadataLevel::Full
+        || request.file_type != "video"
+    {
+        return (items, 0);
+    }
+    let total = items.len();
+    let kept: Vec<serde_json::Value> = items.into_iter().filter(has_video_track).collect();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
+/// Applies `require_languages` to scanned items, returning the kept items and how many were dropped.
+fn drop_missing_languages(
+    request: &ScanRequest,
+    items: Vec<serde_json::Value>,
+) -> (Vec<serde_json::Value>, usize) {
+    if request.require_languages.is_empty() {
+        return (items, 0);
+    }
+    let has_required_track = |item: &serde_json::Value| {
+        item.get("tracks")
+            .and_then(|tracks| tracks.as_array())
+            .is_some_and(|tracks| {
+                tracks.iter().any(|track| {
+                    let track_type = track.get("type").and_then(|t| t.as_str()).unwrap_or("");
+                    let language = track.get("language").and_then(|l| l.as_str()).unwrap_or("");
+                    (request.require_language_types.is_empty()
+                        || request
+                            .require_language_types
+                            .iter()
+                            .any(|wanted| wanted == track_type))
+                        && request
+                            .require_languages
+                            .iter()
+                            .any(|wanted| wanted.eq_ignore_ascii_case(language))
+                })
+            })
+    };
+    let total = items.len();
+    let kept: Vec<serde_json::Value> = items.into_iter().filter(has_required_track).collect();
+    let dropped = total - kept.len();
+    (kept, dropped)
+}
+
+fn scan_files(
+    request: &ScanRequest,
+    groups: &HashMap<String, Vec<String>>,
+) -> Result<Vec<PathBuf>, String> {
+    scan_files_counting(request, groups).map(|(files, _)| files)
+}
+
+/// Whether a file gets probed with mkvmerge/mediainfo, and whether its tracks are listed.
+fn probe_plan(file_type: &str, include_tracks: bool, level: MetadataLevel) -> (bool, bool) {
+    let probed_type = matches!(file_type, "video" | "audio" | "subtitle");
+    match level {
+        MetadataLevel::NameSizeOnly => (false, false),
+        MetadataLevel::Basic => (probed_type, false),
+        MetadataLevel::Full if file_type == "video" => (include_tracks, include_tracks),
+        MetadataLevel::Full => (probed_type, include_tracks),
+    }
 }
 
 fn build_file_info(
     path: &Path,
     file_type: &str,
     include_tracks: bool,
+    duration_source: DurationSource,
+    metadata_level: MetadataLevel,
+    probes: &ProbeLimiter,
 ) -> Result<serde_json::Value, String> {
     let metadata =
         fs::metadata(path).map_err(|e| format!("Failed to read metadata for {:?}: {e}", path))?;
-    let cache_key = build_file_cache_key(path, &metadata, file_type, include_tracks);
+    let cache_key = build_file_cache_key(
+        path,
+        &metadata,
+        file_type,
+        include_tracks,
+        duration_source,
+        metadata_level,
+    );
+    let (probe, include_tracks) = probe_plan(file_type, include_tracks, metadata_level);
     if let Some(cached) = get_cached_file_info(&cache_key) {
         return Ok(cached);
     }
@@ -942,7 +2860,7 @@ fn build_file_info(
     let full_path = path.to_string_lossy().to_string();
     let id = generate_id(file_type);
 
-    let value = if file_type == "video" && !include_tracks {
+    let value = if file_type == "video" && !probe {
         let video = VideoFileInfo {
             id,
             name,
@@ -952,18 +2870,32 @@ fn build_file_info(
             fps: None,
             status: "pending".to_string(),
             tracks: Vec::new(),
+            probe_error: None,
+            warning: None,
+            sources: HashMap::new(),
+            dolby_vision_profile: None,
+            duration_seconds: None,
+            probe_warning: None,
         };
         serde_json::to_value(video).map_err(|e| format!("Serialize error: {e}"))?
     } else if file_type == "video" {
-        let (mkvmerge_info, mediainfo) = rayon::join(
-            || get_mkvmerge_info(path),
-            || get_mediainfo(path),
+        let (mkvmerge_result, mediainfo_result) = rayon::join(
+            || probes.run(|| probe_mkvmerge(path)),
+            || probes.run(|| probe_mediainfo(path)),
         );
-        let duration = mkvmerge_info
-            .as_ref()
-            .and_then(parse_mkvmerge_duration)
-            .or_else(|| mediainfo.as_ref().and_then(parse_duration));
+        let (mkvmerge_info, mediainfo, probe_error) =
+            split_probe_results(mkvmerge_result, mediainfo_result);
+        let (duration, duration_tool) =
+            select_duration(duration_source, mkvmerge_info.as_ref(), mediainfo.as_ref()).unzip();
+        let duration_seconds = duration_tool.and_then(|tool| {
+            tool_duration_seconds(tool, mkvmerge_info.as_ref(), mediainfo.as_ref())
+        });
         let fps = mediainfo.as_ref().and_then(parse_video_fps);
+        let tracks_tool = if mkvmerge_info.is_some() {
+            "mkvmerge"
+        } else {
+            "mediainfo"
+        };
         let mut tracks = if include_tracks {
             if let Some(info) = mkvmerge_info.as_ref() {
                 parse_mkvmerge_tracks(info)
@@ -975,34 +2907,25 @@ fn build_file_info(
         };
 
         // If we have mediainfo, supplement missing bitrate data for audio tracks
-        if let Some(mi) = mediainfo.as_ref() {
-            let mi_tracks = parse_tracks(mi);
-            let mi_audio_tracks: Vec<_> = mi_tracks
-                .iter()
-                .filter(|t| t.track_type == "audio")
-                .collect();
-
-            // Build a list of audio track IDs first to avoid borrow conflicts
-            let audio_track_ids: Vec<String> = tracks
-                .iter()
-                .filter(|t| t.track_type == "audio")
-                .map(|t| t.id.clone())
-                .collect();
-
-            // Prefer mediainfo bitrate for audio tracks (more accurate for VBR)
-            for track in tracks.iter_mut() {
-                if track.track_type == "audio" {
-                    if let Some(idx) = audio_track_ids.iter().position(|id| id == &track.id) {
-                        if let Some(mi_track) = mi_audio_tracks.get(idx) {
-                            if mi_track.bitrate.is_some() {
-                                track.bitrate = mi_track.bitrate;
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let mediainfo_bitrates = mediainfo
+            .as_ref()
+            .map(|mi| apply_mediainfo_audio_bitrates(&mut tracks, mi))
+            .unwrap_or_default();
+        let sources = field_sources(
+            duration_tool,
+            fps.map(|_| "mediainfo"),
+            None,
+            &tracks,
+            tracks_tool,
+            &mediainfo_bitrates,
+        );
 
+        let probe_warning = mkvmerge_info
+            .as_ref()
+            .zip(mediainfo.as_ref())
+            .and_then(|(mkvmerge, mediainfo)| track_count_warning(mkvmerge, mediainfo));
+        let warning = (include_tracks && !tracks.iter().any(|track| track.track_type == "video"))
+            .then(|| "No video track found".to_string());
         let video = VideoFileInfo {
             id,
             name,
@@ -1012,6 +2935,12 @@ fn build_file_info(
             fps,
             status: "pending".to_string(),
             tracks,
+            probe_error,
+            warning,
+            sources,
+            dolby_vision_profile: mediainfo.as_ref().and_then(parse_dolby_vision_profile),
+            duration_seconds,
+            probe_warning,
         };
         serde_json::to_value(video).map_err(|e| format!("Serialize error: {e}"))?
     } else {
@@ -1026,19 +2955,21 @@ fn build_file_info(
             }
         };
 
-        let (mkvmerge_info, mediainfo) = if normalized_file_type == "audio" || normalized_file_type == "subtitle" {
-            rayon::join(
-                || get_mkvmerge_info(path),
-                || get_mediainfo(path),
-            )
+        let (mkvmerge_info, mediainfo, probe_error) = if probe {
+            let (mkvmerge_result, mediainfo_result) = rayon::join(
+                || probes.run(|| probe_mkvmerge(path)),
+                || probes.run(|| probe_mediainfo(path)),
+            );
+            split_probe_results(mkvmerge_result, mediainfo_result)
         } else {
-            (None, None)
+            (None, None, None)
         };
+        let duration_seconds = mediainfo.as_ref().and_then(parse_duration_seconds);
         let (bitrate, duration, track_id) = if let Some(mi) = mediainfo.as_ref() {
             let tracks = parse_tracks(mi);
             let audio_track = tracks.iter().find(|t| t.track_type == "audio");
             let bitrate = audio_track.and_then(|t| t.bitrate);
-            let duration = parse_duration(mi);
+            let duration = duration_seconds.map(format_duration);
             let track_id = if normalized_file_type == "audio" {
                 mkvmerge_info
                     .as_ref()
@@ -1078,6 +3009,25 @@ fn build_file_info(
             Vec::new()
         };
 
+        // Multi-track externals show per-track bitrates in the selection UI
+        let mediainfo_bitrates = mediainfo
+            .as_ref()
+            .map(|mi| apply_mediainfo_audio_bitrates(&mut tracks, mi))
+            .unwrap_or_default();
+        let tracks_tool = if mkvmerge_info.is_some() {
+            "mkvmerge"
+        } else {
+            "mediainfo"
+        };
+        let sources = field_sources(
+            duration.as_ref().map(|_| "mediainfo"),
+            None,
+            bitrate.map(|_| "mediainfo"),
+            &tracks,
+            tracks_tool,
+            &mediainfo_bitrates,
+        );
+
         if normalized_file_type == "audio" {
             tracks.retain(|t| t.track_type == "audio" || t.track_type == "subtitle");
         } else if normalized_file_type == "subtitle" {
@@ -1093,6 +3043,7 @@ fn build_file_info(
             language: None,
             track_name: None,
             delay: None,
+            delay_scope: DelayScope::File,
             is_default: None,
             is_forced: None,
             mux_after: None,
@@ -1100,12 +3051,24 @@ fn build_file_info(
             size: Some(size),
             bitrate,
             duration,
+            duration_seconds,
             track_id,
             tracks,
             included_track_ids: None,
+            first_track_only: false,
             include_subtitles: None,
             included_subtitle_track_ids: None,
+            subtitle_flags: None,
             track_overrides: HashMap::new(),
+            mime_type: None,
+            default_edition: None,
+            ordered: None,
+            probe_error,
+            probe_warning: mkvmerge_info
+                .as_ref()
+                .zip(mediainfo.as_ref())
+                .and_then(|(mkvmerge, mediainfo)| track_count_warning(mkvmerge, mediainfo)),
+            sources,
             apply_language: true,
         };
         serde_json::to_value(external)
@@ -1116,6 +3079,25 @@ fn build_file_info(
     Ok(value)
 }
 
+/// Forgets cached probes for these files so the next scan reads them again, e.g. after
+/// a file was replaced by one with the same size and modification time.
+#[tauri::command]
+fn invalidate_probe_cache(paths: Vec<String>) -> usize {
+    remove_cached_file_info(&paths)
+}
+
+#[tauri::command]
+fn clear_probe_cache() -> usize {
+    file_info_cache()
+        .lock()
+        .map(|mut cache| {
+            let removed = cache.len();
+            cache.clear();
+            removed
+        })
+        .unwrap_or(0)
+}
+
 #[tauri::command]
 fn get_app_paths(state: State<AppState>) -> Result<AppPaths, String> {
     Ok(state.paths.clone())
@@ -1123,9 +3105,23 @@ fn get_app_paths(state: State<AppState>) -> Result<AppPaths, String> {
 
 #[tauri::command]
 fn load_options(state: State<AppState>) -> Result<OptionsData, String> {
-    let options = read_options(&state.paths.options_path)?;
-    write_options(&state.paths.options_path, &options)?;
-    Ok(options)
+    load_options_or_default(&state.paths.options_path)
+}
+
+#[tauri::command]
+fn read_options_only(state: State<AppState>) -> OptionsData {
+    read_options_or_default(&state.paths.options_path)
+}
+
+/// Points a preset's folders at a moved library, e.g. from `D:\Media` to `E:\Media`.
+#[tauri::command]
+fn relocate_preset_paths(
+    state: State<AppState>,
+    preset_id: usize,
+    old_root: String,
+    new_root: String,
+) -> Result<Vec<String>, String> {
+    relocate_preset_in_options(&state.paths.options_path, preset_id, &old_root, &new_root)
 }
 
 #[tauri::command]
@@ -1134,31 +3130,70 @@ fn save_options(state: State<AppState>, options: OptionsData) -> Result<(), Stri
 }
 
 #[tauri::command]
-fn scan_media(request: ScanRequest) -> Result<Vec<serde_json::Value>, String> {
-    let files = scan_files(&request)?;
-    let results = files
+fn scan_media(
+    state: State<AppState>,
+    request: ScanRequest,
+) -> Result<Vec<serde_json::Value>, String> {
+    Ok(scan_with_summary(&state, &request)?.items)
+}
+
+#[tauri::command]
+fn scan_media_with_summary(
+    state: State<AppState>,
+    request: ScanRequest,
+) -> Result<ScanSummary, String> {
+    scan_with_summary(&state, &request)
+}
+
+/// Scans and probes the folder, then applies the request's filters, counting what each
+/// one dropped. Files that fail to probe are left out.
+fn scan_with_summary(state: &AppState, request: &ScanRequest) -> Result<ScanSummary, String> {
+    let groups = read_options_or_default(&state.paths.options_path).extension_groups;
+    let (files, skipped_older) = scan_files_counting(request, &groups)?;
+    let probes = ProbeLimiter::new(request.max_concurrent_probes);
+    let (include_tracks, metadata_level) = request.probes_tracks();
+    let mut items: Vec<serde_json::Value> = files
         .par_iter()
         .filter_map(|path| {
-            match build_file_info(path, &request.file_type, request.include_tracks) {
-                Ok(file_info) => Some(file_info),
-                Err(error) => {
-                    eprintln!("Failed to process file {:?}: {}", path, error);
-                    None
-                }
-            }
+            build_file_info(
+                path,
+                &request.file_type,
+                include_tracks,
+                request.duration_source,
+                metadata_level,
+                &probes,
+            )
+            .ok()
         })
         .collect();
-    Ok(results)
+    flag_magic_mismatches(request, &mut items);
+    let (items, skipped_trackless) = drop_trackless(request, items);
+    let (items, skipped_missing_language) = drop_missing_languages(request, items);
+    Ok(ScanSummary {
+        matched: items.len(),
+        items,
+        skipped_older,
+        skipped_trackless,
+        skipped_missing_language,
+    })
 }
 
 #[tauri::command]
 fn inspect_paths(request: InspectRequest) -> Result<Vec<serde_json::Value>, String> {
     let paths: Vec<PathBuf> = request.paths.into_iter().map(PathBuf::from).collect();
+    let probes = ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES);
     let results = paths
         .par_iter()
         .filter(|path| path.is_file())
         .filter_map(|path| {
-            match build_file_info(path, &request.file_type, request.include_tracks) {
+            match build_file_info(
+                path,
+                &request.file_type,
+                request.include_tracks,
+                request.duration_source,
+                request.metadata_level,
+                &probes,
+            ) {
                 Ok(file_info) => Some(file_info),
                 Err(error) => {
                     eprintln!("Failed to inspect file {:?}: {}", path, error);
@@ -1178,6 +3213,8 @@ fn inspect_paths_stream(
     let scan_id = request.scan_id.clone();
     let file_type = request.file_type.clone();
     let include_tracks = request.include_tracks;
+    let duration_source = request.duration_source;
+    let metadata_level = request.metadata_level;
     let total = request.paths.len();
     if total == 0 {
         let payload = InspectStreamChunkEvent {
@@ -1197,20 +3234,28 @@ fn inspect_paths_stream(
     let batch_size = request.batch_size.unwrap_or(8).max(1);
     let all_paths: Vec<PathBuf> = request.paths.into_iter().map(PathBuf::from).collect();
     let mut processed = 0usize;
+    let probes = ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES);
 
     for chunk in all_paths.chunks(batch_size) {
         let items: Vec<serde_json::Value> = chunk
             .par_iter()
             .filter(|path| path.is_file())
-            .filter_map(
-                |path| match build_file_info(path, &file_type, include_tracks) {
+            .filter_map(|path| {
+                match build_file_info(
+                    path,
+                    &file_type,
+                    include_tracks,
+                    duration_source,
+                    metadata_level,
+                    &probes,
+                ) {
                     Ok(file_info) => Some(file_info),
                     Err(error) => {
                         eprintln!("Failed to inspect file {:?}: {}", path, error);
                         None
                     }
-                },
-            )
+                }
+            })
             .collect();
 
         processed = (processed + chunk.len()).min(total);
@@ -1247,7 +3292,15 @@ fn write_log_line(paths: &AppPaths, line: &str) -> Result<(), String> {
         .append(true)
         .open(&paths.log_path)
         .map_err(|e| format!("Failed to open log file: {e}"))?;
-    writeln!(file, "{line}").map_err(|e| format!("Failed to write log: {e}"))
+    let written = if paths.log_timestamps.load(Ordering::Relaxed) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(file, "{} {line}", utc_timestamp(now))
+    } else {
+        writeln!(file, "{line}")
+    };
+    written.map_err(|e| format!("Failed to write log: {e}"))
 }
 
 fn clear_log(paths: &AppPaths) -> Result<(), String> {
@@ -1255,1512 +3308,12001 @@ fn clear_log(paths: &AppPaths) -> Result<(), String> {
     Ok(())
 }
 
-fn get_output_paths(job: &MuxJobRequest, settings: &MuxSettings) -> (PathBuf, PathBuf, bool) {
-    let video_path = PathBuf::from(&job.video.path);
-    let source_dir = video_path.parent().unwrap_or(Path::new(".")).to_path_buf();
-    let output_dir = if settings.destination_dir.trim().is_empty() {
-        source_dir.clone()
-    } else {
-        PathBuf::from(&settings.destination_dir)
+/// Reads the last `lines` lines of a file, scanning backwards in chunks from the end.
+fn read_log_tail(path: &Path, lines: usize) -> Result<Vec<String>, String> {
+    const CHUNK_SIZE: u64 = 8 * 1024;
+    if lines == 0 {
+        return Ok(Vec::new());
+    }
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(format!("Failed to open log file: {error}")),
     };
-    let file_stem = video_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-    let overwrite_mode = settings.destination_dir.trim().is_empty() || settings.overwrite_source;
-
-    if overwrite_mode {
-        let suffix = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::from_secs(0))
-            .as_secs();
-        let temp_name = format!("{}#{}{}.mkv", file_stem, suffix, "");
-        let output_path = output_dir.join(temp_name);
-        let final_path = output_dir.join(format!("{}.mkv", file_stem));
-        (output_path, final_path, true)
-    } else {
-        let output_path = output_dir.join(format!("{}.mkv", file_stem));
-        (output_path.clone(), output_path, false)
+    let mut position = file
+        .metadata()
+        .map_err(|e| format!("Failed to read log file: {e}"))?
+        .len();
+    let mut buffer: Vec<u8> = Vec::new();
+    // One newline more than requested means the first wanted line is complete
+    while position > 0 && buffer.iter().filter(|byte| **byte == b'\n').count() <= lines {
+        let read = CHUNK_SIZE.min(position);
+        position -= read;
+        let mut chunk = vec![0u8; read as usize];
+        file.seek(SeekFrom::Start(position))
+            .and_then(|_| file.read_exact(&mut chunk))
+            .map_err(|e| format!("Failed to read log file: {e}"))?;
+        chunk.extend_from_slice(&buffer);
+        buffer = chunk;
     }
+    let text = String::from_utf8_lossy(&buffer);
+    let all: Vec<&str> = text.lines().collect();
+    let start = all.len().saturating_sub(lines);
+    Ok(all[start..].iter().map(|line| line.to_string()).collect())
 }
 
-fn compute_crc(path: &Path) -> Result<String, String> {
-    let mut file = File::open(path).map_err(|e| format!("Failed to open file for CRC: {e}"))?;
-    let mut hasher = Hasher::new();
-    let mut buffer = [0u8; 8192];
-    loop {
-        let read = file
-            .read(&mut buffer)
-            .map_err(|e| format!("Failed to read file: {e}"))?;
-        if read == 0 {
-            break;
-        }
-        hasher.update(&buffer[..read]);
+/// Copies the session log into `destination`, returning the copy's path.
+fn copy_log_to(paths: &AppPaths, destination: &str) -> Result<Option<PathBuf>, String> {
+    let destination = destination.trim();
+    if destination.is_empty() {
+        return Ok(None);
     }
-    Ok(format!("{:08X}", hasher.finalize()))
+    let dir = PathBuf::from(destination);
+    ensure_dir(&dir)?;
+    let target = dir.join("muxing_log_file.txt");
+    fs::copy(&paths.log_path, &target).map_err(|e| format!("Failed to copy log file: {e}"))?;
+    Ok(Some(target))
 }
 
-fn file_name_with_crc(path: &Path, crc: &str) -> PathBuf {
-    let file_name = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("output.mkv");
-    let file_stem = file_name.trim_end_matches(".mkv");
-    path.with_file_name(format!("{} [{}].mkv", file_stem, crc))
+/// Cumulative totals across every batch, kept in `stats.json`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct UsageStats {
+    jobs_completed: u64,
+    /// Size of the source videos of completed jobs.
+    bytes_processed: u64,
+    /// How much smaller outputs came out than their sources; larger outputs count as zero.
+    bytes_saved: u64,
+    /// Input bytes of jobs remuxed with mkvmerge, and the time mkvmerge took on them, for
+    /// estimating how long a batch will run.
+    remuxed_bytes: u64,
+    remux_ms: u64,
 }
 
-fn file_name_without_crc(path: &Path) -> PathBuf {
-    let file_name = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("output.mkv");
-    let cleaned = file_name.replace(".mkv", "");
-    let sanitized = if let Some(index) = cleaned.rfind('[') {
-        cleaned[..index].trim().to_string()
-    } else {
-        cleaned
-    };
-    path.with_file_name(format!("{}.mkv", sanitized))
+impl UsageStats {
+    fn add_job(&mut self, size_before: u64, size_after: Option<u64>) {
+        self.jobs_completed += 1;
+        self.bytes_processed += size_before;
+        self.bytes_saved += size_after.map_or(0, |after| size_before.saturating_sub(after));
+    }
+
+    fn add(&mut self, other: &UsageStats) {
+        self.jobs_completed += other.jobs_completed;
+        self.bytes_processed += other.bytes_processed;
+        self.bytes_saved += other.bytes_saved;
+        self.remuxed_bytes += other.remuxed_bytes;
+        self.remux_ms += other.remux_ms;
+    }
+
+    fn remux_bytes_per_second(&self) -> Option<f64> {
+        (self.remux_ms > 0).then(|| self.remuxed_bytes as f64 * 1000.0 / self.remux_ms as f64)
+    }
 }
 
-fn check_free_space(path: &Path, required_bytes: u64) -> Result<(), String> {
-    let available = available_space(path).map_err(|e| format!("Failed to read free space: {e}"))?;
-    if available < required_bytes {
-        return Err(format!(
-            "Not enough free space. Required: {} bytes",
-            required_bytes
-        ));
+/// `remux` is the job's input size (as `estimate_batch` counts it) and how long mkvmerge
+/// ran, when mkvmerge remuxed it; `None` for fast muxes.
+fn record_completed_job(
+    state: &AppState,
+    size_before: u64,
+    size_after: Option<u64>,
+    remux: Option<(u64, u64)>,
+) {
+    let mut mux_state = state.mux_state.lock().unwrap();
+    mux_state.batch_stats.add_job(size_before, size_after);
+    if let Some((input_bytes, remux_ms)) = remux {
+        mux_state.batch_stats.remuxed_bytes += input_bytes;
+        mux_state.batch_stats.remux_ms += remux_ms;
     }
-    Ok(())
+    mux_state.batch_output_bytes += size_after.unwrap_or(0);
 }
 
-fn collect_track_ids_by_language(
-    tracks: &[TrackInfo],
-    track_type: &str,
-    languages: &[String],
-) -> Vec<usize> {
-    let mut ids = Vec::new();
-    for (index, track) in tracks.iter().enumerate() {
-        if track.track_type != track_type {
-            continue;
-        }
-        if let Some(language) = &track.language {
-            if languages
-                .iter()
-                .any(|lang| lang.eq_ignore_ascii_case(language))
-            {
-                if let Ok(parsed) = track.id.parse::<usize>() {
-                    ids.push(parsed);
-                } else {
-                    ids.push(index);
-                }
-            }
-        }
+/// Stops the batch once its outputs pass `max_total_output_bytes`. Returns the reason
+/// the first time it does, so it's reported once.
+fn enforce_output_cap(state: &AppState, settings: &MuxSettings) -> Option<String> {
+    let cap = settings.max_total_output_bytes?;
+    let mut mux_state = state.mux_state.lock().unwrap();
+    if mux_state.stop || mux_state.batch_output_bytes <= cap {
+        return None;
     }
-    ids
+    mux_state.stop = true;
+    Some(format!(
+        "Stopped: outputs reached {}, over the {} limit",
+        format_bytes(mux_state.batch_output_bytes),
+        format_bytes(cap)
+    ))
 }
 
-fn parse_track_id(track: &TrackInfo, index: usize) -> usize {
-    track.id.parse::<usize>().unwrap_or(index)
+fn read_stats(path: &Path) -> Result<UsageStats, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            serde_json::from_str(&content).map_err(|e| format!("Failed to parse stats JSON: {e}"))
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(UsageStats::default()),
+        Err(error) => Err(format!("Failed to read stats: {error}")),
+    }
 }
 
-fn is_track_removed(track: &TrackInfo) -> bool {
-    matches!(track.action.as_deref(), Some("remove"))
+/// Writes next to the target and renames over it, so a crash never leaves half a file.
+fn write_stats(path: &Path, stats: &UsageStats) -> Result<(), String> {
+    let content =
+        serde_json::to_string_pretty(stats).map_err(|e| format!("Failed to encode stats: {e}"))?;
+    let temp_path = path.with_extension("tmp.json");
+    fs::write(&temp_path, content).map_err(|e| format!("Failed to write stats: {e}"))?;
+    fs::rename(&temp_path, path).map_err(|e| format!("Failed to write stats: {e}"))
 }
 
-fn collect_track_ids_by_action(tracks: &[TrackInfo], track_type: &str) -> (Vec<usize>, bool) {
-    let mut ids = Vec::new();
-    let mut has_removed = false;
-    for (index, track) in tracks.iter().enumerate() {
-        if track.track_type != track_type {
-            continue;
-        }
-        if is_track_removed(track) {
-            has_removed = true;
-            continue;
-        }
-        ids.push(parse_track_id(track, index));
+fn record_batch_stats(paths: &AppPaths, batch: &UsageStats) -> Result<(), String> {
+    if batch.jobs_completed == 0 {
+        return Ok(());
     }
-    (ids, has_removed)
+    // A corrupt file restarts the totals rather than blocking every later batch
+    let mut stats = read_stats(&paths.stats_path).unwrap_or_default();
+    stats.add(batch);
+    write_stats(&paths.stats_path, &stats)
 }
 
-fn intersect_ids(left: Vec<usize>, right: Vec<usize>) -> Vec<usize> {
-    left.into_iter().filter(|id| right.contains(id)).collect()
-}
+/// Output path (as `comparable_path` has it) to size, for every output a job finished.
+type FinishedOutputs = HashMap<String, u64>;
 
-fn apply_track_selection(
-    args: &mut Vec<String>,
-    tracks: &[TrackInfo],
-    track_type: &str,
-    only_keep_ids: Option<Vec<usize>>,
-) {
-    let (action_ids, has_removed) = collect_track_ids_by_action(tracks, track_type);
-    let type_ids: Vec<usize> = tracks
-        .iter()
-        .enumerate()
-        .filter(|(_, track)| track.track_type == track_type)
-        .map(|(index, track)| parse_track_id(track, index))
-        .collect();
+fn read_finished_outputs(path: &Path) -> FinishedOutputs {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
 
-    if type_ids.is_empty() {
-        return;
+/// Adds a job's outputs to `finished_outputs.json`, dropping entries whose file is gone.
+fn record_finished_outputs(state: &AppState, outputs: &[PathBuf]) {
+    let _guard = state.finished_outputs_lock.lock().unwrap();
+    let path = &state.paths.finished_outputs_path;
+    let mut finished = read_finished_outputs(path);
+    finished.retain(|output, _| Path::new(output).exists());
+    for output in outputs {
+        if let Ok(metadata) = fs::metadata(output) {
+            finished.insert(
+                comparable_path(output).to_string_lossy().to_string(),
+                metadata.len(),
+            );
+        }
     }
-
-    let mut selected = if has_removed {
-        action_ids
-    } else {
-        type_ids.clone()
+    let Ok(content) = serde_json::to_string_pretty(&finished) else {
+        return;
     };
-
-    if let Some(ref keep) = only_keep_ids {
-        selected = intersect_ids(selected, keep.clone());
+    let temp_path = path.with_extension("tmp.json");
+    if fs::write(&temp_path, content).is_err() || fs::rename(&temp_path, path).is_err() {
+        let _ = write_log_line(&state.paths, "Failed to record finished outputs");
     }
+}
 
-    if selected.len() == type_ids.len() && !has_removed && only_keep_ids.is_none() {
-        return;
-    }
+fn job_destination_dir<'a>(job: &'a MuxJobRequest, settings: &'a MuxSettings) -> &'a str {
+    job.destination_dir
+        .as_deref()
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or(settings.destination_dir.trim())
+}
 
-    if selected.is_empty() {
-        match track_type {
-            "audio" => args.push("--no-audio".to_string()),
-            "subtitle" => args.push("--no-subtitles".to_string()),
-            "video" => args.push("--no-video".to_string()),
-            _ => {}
-        }
-        return;
+fn resolve_output_dir(job: &MuxJobRequest, settings: &MuxSettings) -> PathBuf {
+    let destination_dir = job_destination_dir(job, settings);
+    if destination_dir.is_empty() {
+        PathBuf::from(&job.video.path)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf()
+    } else {
+        PathBuf::from(destination_dir)
     }
+}
 
-    let flag = match track_type {
-        "audio" => "--audio-tracks",
-        "subtitle" => "--subtitle-tracks",
-        "video" => "--video-tracks",
-        _ => return,
-    };
-    args.push(flag.to_string());
-    args.push(
-        selected
-            .iter()
-            .map(|id| id.to_string())
-            .collect::<Vec<_>>()
-            .join(","),
-    );
+/// Resolves the job's output directory and creates it (and any missing parents).
+fn prepare_output_dir(job: &MuxJobRequest, settings: &MuxSettings) -> Result<PathBuf, String> {
+    let output_dir = resolve_output_dir(job, settings);
+    ensure_dir(&output_dir).map_err(|err| format!("{err} ({})", output_dir.display()))?;
+    Ok(output_dir)
 }
 
-fn build_mkvpropedit_args(job: &MuxJobRequest) -> Vec<String> {
-    let mut args = Vec::new();
+fn is_forbidden_file_name_char(c: char) -> bool {
+    matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') || c.is_control()
+}
 
-    // Apply track modifications: name, language, default, forced flags
-    // For Fast Mux, we apply edits for all tracks that have any properties set
-    for (index, track) in job.video.tracks.iter().enumerate() {
-        if is_track_removed(track) {
-            continue;
-        }
+/// A substitute for forbidden characters, with any forbidden characters of its own removed.
+fn clean_substitute(substitute: &str) -> String {
+    substitute
+        .chars()
+        .filter(|c| !is_forbidden_file_name_char(*c))
+        .collect()
+}
 
-        // mkvpropedit uses 1-based track IDs, so add 1 to the parsed track ID
-        let track_id = parse_track_id(track, index) + 1;
+/// Whether and how `sanitize_filename` cleans a name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SanitizePolicy<'a> {
+    /// Leave the name as it is.
+    Keep,
+    /// Replace each forbidden character with this text.
+    Replace(&'a str),
+}
 
-        // Track name - apply if set (even if empty, to clear it)
-        if let Some(name) = &track.name {
-            args.push("--edit".to_string());
-            args.push(format!("track:{}", track_id));
-            args.push("--set".to_string());
-            args.push(format!("name={}", name.trim()));
+/// Replaces characters that are illegal in Windows/Unix file names and trims
+/// the trailing dots and spaces Windows refuses.
+fn sanitize_filename(name: &str, policy: SanitizePolicy) -> String {
+    let SanitizePolicy::Replace(substitute) = policy else {
+        return name.to_string();
+    };
+    let mut replaced = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_forbidden_file_name_char(c) {
+            replaced.push_str(substitute);
+        } else {
+            replaced.push(c);
         }
+    }
+    replaced.trim().trim_end_matches(['.', ' ']).to_string()
+}
 
-        // Language - apply if set
-        if let Some(language) = &track.language {
-            args.push("--edit".to_string());
-            args.push(format!("track:{}", track_id));
-            args.push("--set".to_string());
-            args.push(format!("language={}", language));
+/// Show, season and episode read from a release name.
+#[derive(Debug, PartialEq)]
+struct ParsedTitle {
+    show: String,
+    season: u32,
+    episode: u32,
+}
+
+/// `S01E05`/`s1e5` (anything after the episode number is ignored) or `1x05`.
+fn parse_season_episode(word: &str) -> Option<(u32, u32)> {
+    let word = word.to_ascii_lowercase();
+    let (season, rest) = match word.strip_prefix('s') {
+        Some(rest) => {
+            let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+            let episode = rest[digits..].strip_prefix('e')?;
+            (&rest[..digits], episode)
         }
+        None => word.split_once('x')?,
+    };
+    let episode_end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    Some((season.parse().ok()?, rest[..episode_end].parse().ok()?))
+}
 
-        // Default flag - apply if explicitly set (Some(true) or Some(false))
-        if let Some(is_default) = track.is_default {
-            args.push("--edit".to_string());
-            args.push(format!("track:{}", track_id));
-            args.push("--set".to_string());
-            args.push(format!(
-                "flag-default={}",
-                if is_default { "1" } else { "0" }
-            ));
+/// Reads show, season and episode from names like `[Group] Show - S01E05 (1080p)`,
+/// `Show.Name.S01E05.1080p.WEB-DL` or `[Group] Show - 05 [1080p]` (season 1).
+/// Bracketed tags are dropped and dots and underscores count as spaces.
+fn parse_release_title(stem: &str) -> Option<ParsedTitle> {
+    let mut cleaned = String::new();
+    let mut depth = 0usize;
+    for c in stem.chars() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = depth.saturating_sub(1),
+            '.' | '_' if depth == 0 => cleaned.push(' '),
+            c if depth == 0 => cleaned.push(c),
+            _ => {}
         }
+    }
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    let (index, season, episode) = words
+        .iter()
+        .enumerate()
+        .find_map(|(index, word)| {
+            parse_season_episode(word).map(|(season, episode)| (index, season, episode))
+        })
+        .or_else(|| {
+            // Absolute numbering: the first short number after a lone dash
+            words.windows(2).enumerate().find_map(|(index, pair)| {
+                let is_number =
+                    (1..=4).contains(&pair[1].len()) && pair[1].chars().all(|c| c.is_ascii_digit());
+                (pair[0] == "-" && is_number).then(|| (index + 1, 1, pair[1].parse().unwrap_or(0)))
+            })
+        })?;
+    let show = words[..index].join(" ");
+    let show = show.trim_matches(|c: char| c == '-' || c.is_whitespace());
+    (!show.is_empty()).then(|| ParsedTitle {
+        show: show.to_string(),
+        season,
+        episode,
+    })
+}
 
-        // Forced flag (for subtitles, use flag-forced-display)
-        if track.track_type == "subtitle" {
-            if let Some(is_forced) = track.is_forced {
-                args.push("--edit".to_string());
-                args.push(format!("track:{}", track_id));
-                args.push("--set".to_string());
-                args.push(format!(
-                    "flag-forced-display={}",
-                    if is_forced { "1" } else { "0" }
-                ));
-            }
-        } else if track.track_type == "audio" || track.track_type == "video" {
-            if let Some(is_forced) = track.is_forced {
-                args.push("--edit".to_string());
-                args.push(format!("track:{}", track_id));
-                args.push("--set".to_string());
-                args.push(format!("flag-forced={}", if is_forced { "1" } else { "0" }));
-            }
+/// Fills `{show}`, `{season}` and `{episode}` in `template`; `{season:02}` pads with zeros.
+fn render_title_template(template: &str, title: &ParsedTitle) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let token = &rest[start + 1..start + end];
+        let (name, width) = match token.split_once(':') {
+            Some((name, width)) => (name, width.parse::<usize>().ok()),
+            None => (token, None),
+        };
+        let width = width.unwrap_or(0);
+        match name {
+            "show" => rendered.push_str(&title.show),
+            "season" => rendered.push_str(&format!("{:0width$}", title.season)),
+            "episode" => rendered.push_str(&format!("{:0width$}", title.episode)),
+            _ => rendered.push_str(&rest[start..=start + end]),
         }
+        rest = &rest[start + end + 1..];
     }
+    rendered.push_str(rest);
+    rendered
+}
 
-    args
+/// The clean name `title_parser` gives a source file, with forbidden characters replaced
+/// by `substitute`, or `None` when nothing parses.
+fn clean_title_name(path: &Path, template: &str, substitute: &str) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let title = parse_release_title(stem)?;
+    let name = render_title_template(template, &title);
+    Some(sanitize_filename(
+        &name,
+        SanitizePolicy::Replace(substitute),
+    ))
+    .filter(|n| !n.is_empty())
 }
 
-fn quote_arg(arg: &str) -> String {
-    if arg.contains(' ') || arg.contains('"') || arg.contains('\'') {
-        format!("\"{}\"", arg.replace('"', "\\\""))
+#[tauri::command]
+fn preview_clean_title(
+    path: String,
+    template: String,
+    substitute: Option<String>,
+) -> Option<String> {
+    let substitute = clean_substitute(&substitute.unwrap_or_else(default_sanitize_substitute));
+    clean_title_name(Path::new(&path), &template, &substitute)
+}
+
+/// Container extensions an output name may be typed with; the configured one is added.
+const OUTPUT_NAME_EXTENSIONS: [&str; 7] = ["mkv", "mka", "mks", "mk3d", "webm", "mp4", "m4v"];
+
+/// `name` without a trailing container extension, e.g. `show.mp4` gives `show`.
+fn strip_container_extension<'a>(name: &'a str, settings: &MuxSettings) -> &'a str {
+    let Some((stem, extension)) = name.rsplit_once('.') else {
+        return name;
+    };
+    let known = OUTPUT_NAME_EXTENSIONS
+        .iter()
+        .chain([&output_extension(settings)])
+        .any(|known| known.eq_ignore_ascii_case(extension));
+    if known {
+        stem
     } else {
-        arg.to_string()
+        name
     }
 }
 
-fn join_mkvmerge_command(args: &[String]) -> String {
-    let mut parts = Vec::with_capacity(args.len() + 1);
-    parts.push("mkvmerge".to_string());
-    for arg in args {
-        parts.push(quote_arg(arg));
+fn output_file_stem(job: &MuxJobRequest, settings: &MuxSettings) -> String {
+    if let Some(name) = job.output_name.as_deref() {
+        let name = name.replace("{preset}", job.preset_name.as_deref().unwrap_or(""));
+        let name = strip_container_extension(name.trim(), settings);
+        let sanitized = sanitize_filename(
+            name,
+            SanitizePolicy::Replace(&settings.sanitize_substitute()),
+        );
+        if !sanitized.is_empty() {
+            return sanitized;
+        }
     }
-    parts.join(" ")
+    let template = settings
+        .title_parser
+        .as_deref()
+        .filter(|t| !t.trim().is_empty());
+    let substitute = settings.sanitize_substitute();
+    if let Some(name) =
+        template.and_then(|t| clean_title_name(Path::new(&job.video.path), t, &substitute))
+    {
+        return name;
+    }
+    Path::new(&job.video.path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output")
+        .to_string()
 }
 
-fn log_job_plan(state: &AppState, job: &MuxJobRequest, output_path: &Path) {
-    let audio_list = if job.audios.is_empty() {
-        "[]".to_string()
+fn output_extension(settings: &MuxSettings) -> &str {
+    settings
+        .output_extension
+        .as_deref()
+        .map(|ext| ext.trim().trim_start_matches('.'))
+        .filter(|ext| !ext.is_empty())
+        .unwrap_or(match settings.output_container {
+            OutputContainer::Mkv => "mkv",
+            OutputContainer::Mp4 => "mp4",
+        })
+}
+
+/// Bytes of a `[XXXXXXXX]` CRC tag with its leading space.
+const CRC_TAG_BYTES: usize = 11;
+/// Bytes of the unix seconds after the separator in a temp output's name.
+const TEMP_SECONDS_BYTES: usize = 10;
+/// Bytes of the `-001` suffix mkvmerge gives split parts.
+const SPLIT_SUFFIX_BYTES: usize = 4;
+
+/// The longest prefix of `value` that fits in `max_bytes` without splitting a character.
+fn truncate_to_bytes(value: &str, max_bytes: usize) -> &str {
+    if value.len() <= max_bytes {
+        return value;
+    }
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// The output stem, shortened when `max_filename_bytes` can't fit it together with the
+/// extension and whatever CRC tag, temp marker or split suffix the name may get. The
+/// second value is the full stem when it was shortened.
+fn output_stem(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    overwrite_mode: bool,
+) -> (String, Option<String>) {
+    let substitute = settings.sanitize_substitute();
+    let policy = if settings.sanitize_filenames {
+        SanitizePolicy::Replace(&substitute)
     } else {
-        let items: Vec<String> = job
-            .audios
-            .iter()
-            .map(|audio| {
-                format!(
-                    "{{path={}, lang={:?}, default={:?}, name={:?}}}",
-                    quote_arg(&audio.path),
-                    audio.language,
-                    audio.is_default,
-                    audio.track_name
-                )
-            })
-            .collect();
-        format!("[{}]", items.join(", "))
+        SanitizePolicy::Keep
     };
-    let subtitle_list = if job.subtitles.is_empty() {
-        "[]".to_string()
+    let stem = match sanitize_filename(&output_file_stem(job, settings), policy) {
+        stem if stem.is_empty() => "output".to_string(),
+        stem => stem,
+    };
+    let Some(max_bytes) = settings.max_filename_bytes else {
+        return (stem, None);
+    };
+    let crc_tag = if settings.add_crc { CRC_TAG_BYTES } else { 0 };
+    let temp_marker = if overwrite_mode {
+        settings.temp_separator().len() + TEMP_SECONDS_BYTES
     } else {
-        let items: Vec<String> = job
-            .subtitles
-            .iter()
-            .map(|subtitle| {
-                format!(
-                    "{{path={}, lang={:?}, default={:?}, forced={:?}, name={:?}}}",
-                    quote_arg(&subtitle.path),
-                    subtitle.language,
-                    subtitle.is_default,
-                    subtitle.is_forced,
-                    subtitle.track_name
-                )
-            })
-            .collect();
-        format!("[{}]", items.join(", "))
+        0
     };
-    let chapter_list = if job.chapters.is_empty() {
-        "[]".to_string()
+    let split_suffix = if settings.split.is_some() {
+        SPLIT_SUFFIX_BYTES
     } else {
-        let items: Vec<String> = job
-            .chapters
-            .iter()
-            .map(|chapter| quote_arg(&chapter.path))
-            .collect();
-        format!("[{}]", items.join(", "))
+        0
+    };
+    let reserved = crc_tag.max(temp_marker) + split_suffix + 1 + output_extension(settings).len();
+    let budget = max_bytes.saturating_sub(reserved).max(1);
+    if stem.len() <= budget {
+        return (stem, None);
+    }
+    let truncated = truncate_to_bytes(&stem, budget);
+    let trimmed = truncated.trim_end_matches(|c: char| c.is_whitespace() || c == '.');
+    let shortened = if trimmed.is_empty() {
+        truncated
+    } else {
+        trimmed
     };
+    (shortened.to_string(), Some(stem))
+}
 
-    let _ = write_log_line(
-        &state.paths,
-        &format!(
-            "JOB PLAN: video={} output={} audios={} subtitles={} chapters={}",
-            quote_arg(&job.video.path),
-            quote_arg(&output_path.to_string_lossy()),
-            audio_list,
-            subtitle_list,
-            chapter_list
-        ),
-    );
+/// Log line for a job whose output name had to be shortened to fit `max_filename_bytes`.
+fn filename_truncation_note(job: &MuxJobRequest, settings: &MuxSettings) -> Option<String> {
+    let overwrite_mode = job_destination_dir(job, settings).is_empty() || settings.overwrite_source;
+    let (stem, original) = output_stem(job, settings, overwrite_mode);
+    original.map(|original| {
+        format!(
+            "Job {}: output name shortened to fit {} bytes: \"{original}\" -> \"{stem}\"",
+            job.id,
+            settings.max_filename_bytes.unwrap_or_default()
+        )
+    })
 }
 
-fn build_mkvmerge_command(
-    job: &MuxJobRequest,
-    settings: &MuxSettings,
-    output_path: &Path,
-    _state: &AppState,
-) -> Vec<String> {
-    let mut args = vec![
-        "--gui-mode".to_string(),
-        "--output".to_string(),
-        output_path.to_string_lossy().to_string(),
-    ];
+fn get_output_paths(job: &MuxJobRequest, settings: &MuxSettings) -> (PathBuf, PathBuf, bool) {
+    let output_dir = resolve_output_dir(job, settings);
+    let extension = output_extension(settings);
+    let overwrite_mode = job_destination_dir(job, settings).is_empty() || settings.overwrite_source;
+    let (file_stem, _) = output_stem(job, settings, overwrite_mode);
 
-    let mut resolved_external_audios: Vec<(ExternalFileInfo, u64)> = Vec::new();
-    for audio in &job.audios {
-        let mut resolved_ids: Vec<u64> = Vec::new();
-        if let Some(ids) = &audio.included_track_ids {
-            if ids.is_empty() {
-                continue;
-            }
-            resolved_ids = ids.clone();
-        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&audio.path)) {
-            let ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Audio");
-            if ids.len() > 1 {
-                resolved_ids = ids;
-            } else if let Some(id) = audio.track_id {
-                resolved_ids.push(id);
-            } else {
-                resolved_ids = ids;
-            }
-        } else if let Some(id) = audio.track_id {
-            resolved_ids.push(id);
-        }
+    if overwrite_mode {
+        let suffix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::from_secs(0))
+            .as_secs();
+        let temp_name = format!(
+            "{}{}{}.{}",
+            file_stem,
+            settings.temp_separator(),
+            suffix,
+            extension
+        );
+        let output_path = output_dir.join(temp_name);
+        let final_path = output_dir.join(format!("{}.{}", file_stem, extension));
+        (output_path, final_path, true)
+    } else {
+        let output_path = output_dir.join(format!("{}.{}", file_stem, extension));
+        (output_path.clone(), output_path, false)
+    }
+}
 
-        if resolved_ids.is_empty() {
-            resolved_ids.push(0);
-        }
+/// The final path a temp output named `{stem}{separator}{secs}.{ext}` belongs to, or
+/// `None` when `path` isn't named like a temp output. Only a full ten-digit timestamp
+/// counts, so split parts (`show-001.mkv`) and numbered names aren't taken for temps.
+fn temp_output_final_path(path: &Path, separator: &str) -> Option<PathBuf> {
+    let (stem, extension) = output_name_parts(path);
+    let (base, seconds) = stem.rsplit_once(separator)?;
+    (!base.is_empty()
+        && seconds.len() == TEMP_SECONDS_BYTES
+        && seconds.chars().all(|c| c.is_ascii_digit()))
+    .then(|| path.with_file_name(format!("{base}.{extension}")))
+}
 
-        let set_default_on_first = audio.is_default.unwrap_or(false);
-        for (index, track_id) in resolved_ids.iter().enumerate() {
-            let mut cloned = audio.clone();
-            cloned.track_id = Some(*track_id);
-            if set_default_on_first {
-                cloned.is_default = Some(index == 0);
-            }
-            cloned.apply_language = index == 0;
-            resolved_external_audios.push((cloned, *track_id));
-        }
-    }
+/// Temp outputs for `final_path` that an interrupted earlier run left next to it. They
+/// are reported, never deleted: only the user can tell whether one is worth keeping.
+fn orphan_temp_outputs(final_path: &Path, settings: &MuxSettings) -> Vec<PathBuf> {
+    let separator = settings.temp_separator();
+    let dir = final_path.parent().unwrap_or(Path::new("."));
+    let mut orphans: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file()
+                        && temp_output_final_path(path, &separator).as_deref() == Some(final_path)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    orphans.sort();
+    orphans
+}
 
-    let mut resolved_external_subtitles: Vec<(ExternalFileInfo, u64)> = Vec::new();
-    let mut resolved_external_subtitles_from_audio: Vec<(ExternalFileInfo, u64)> = Vec::new();
-    for subtitle in &job.subtitles {
-        let mut resolved_ids: Vec<u64> = Vec::new();
-        if let Some(ids) = &subtitle.included_track_ids {
-            if ids.is_empty() {
-                continue;
-            }
-            resolved_ids = ids.clone();
-        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&subtitle.path)) {
-            let ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Text");
-            if ids.len() > 1 {
-                resolved_ids = ids;
-            } else if let Some(id) = subtitle.track_id {
-                resolved_ids.push(id);
-            } else {
-                resolved_ids = ids;
-            }
-        } else if let Some(id) = subtitle.track_id {
-            resolved_ids.push(id);
-        }
+/// Attempts made on a finished output that another process briefly holds open.
+const FILE_IN_USE_ATTEMPTS: u32 = 5;
+const FILE_IN_USE_BACKOFF: Duration = Duration::from_millis(100);
 
-        if resolved_ids.is_empty() {
-            resolved_ids.push(0);
-        }
+/// Antivirus scanners and Explorer thumbnailers open fresh files on Windows for a moment,
+/// failing renames and opens with a sharing violation (or access denied) until they let go.
+#[cfg(target_os = "windows")]
+fn is_file_in_use(error: &std::io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    matches!(
+        error.raw_os_error(),
+        Some(ERROR_ACCESS_DENIED | ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    )
+}
 
-        let set_default_on_first = subtitle.is_default.unwrap_or(false);
-        for (index, track_id) in resolved_ids.iter().enumerate() {
-            let mut cloned = subtitle.clone();
-            cloned.track_id = Some(*track_id);
-            if set_default_on_first {
-                cloned.is_default = Some(index == 0);
+#[cfg(not(target_os = "windows"))]
+fn is_file_in_use(_error: &std::io::Error) -> bool {
+    false
+}
+
+/// Runs `operation` again with a doubling delay while the file is in use.
+fn retry_file_in_use<T>(mut operation: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut delay = FILE_IN_USE_BACKOFF;
+    for _ in 1..FILE_IN_USE_ATTEMPTS {
+        match operation() {
+            Err(error) if is_file_in_use(&error) => {
+                thread::sleep(delay);
+                delay *= 2;
             }
-            cloned.apply_language = index == 0;
-            resolved_external_subtitles.push((cloned, *track_id));
+            result => return result,
         }
     }
+    operation()
+}
 
-    for audio in &job.audios {
-        if audio.include_subtitles != Some(true) {
-            continue;
-        }
-        let mut resolved_ids: Vec<u64> = Vec::new();
-        if let Some(ids) = &audio.included_subtitle_track_ids {
-            if ids.is_empty() {
-                continue;
-            }
-            resolved_ids = ids.clone();
-        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&audio.path)) {
-            resolved_ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Text");
-        }
-        if resolved_ids.is_empty() {
-            continue;
-        }
-        for track_id in resolved_ids.iter() {
-            let mut cloned = audio.clone();
-            cloned.track_id = Some(*track_id);
-            cloned.apply_language = false;
-            cloned.is_default = None;
-            cloned.is_forced = None;
-            resolved_external_subtitles_from_audio.push((cloned, *track_id));
-        }
+/// Replaces the source with the finished temp output (overwrite mode).
+fn promote_temp_output(output_path: &Path, final_path: &Path, source_path: &Path) {
+    let _ = retry_file_in_use(|| fs::remove_file(source_path));
+    let _ = retry_file_in_use(|| fs::rename(output_path, final_path));
+}
+
+/// Whether the mux tool wrote its whole output before a stop: it exited on its own
+/// (`exit_status` is `None` once it was killed) with success, or with mkvmerge's
+/// warnings-only code.
+fn finished_writing(exit_status: Option<i32>, settings: &MuxSettings) -> bool {
+    match exit_status {
+        Some(0) => true,
+        Some(1) => settings.output_container == OutputContainer::Mkv,
+        _ => false,
     }
+}
 
-    if settings.discard_old_chapters {
-        args.push("--no-chapters".to_string());
+/// Settles the output of a job that was stopped. A complete temp output replaces the
+/// source; an incomplete one is discarded so the untouched source stays in place.
+/// Outside overwrite mode the (possibly partial) output is kept as-is.
+fn settle_stopped_output(
+    output_path: &Path,
+    final_path: &Path,
+    source_path: &Path,
+    overwrite_mode: bool,
+    complete: bool,
+) -> PathBuf {
+    if !overwrite_mode {
+        return output_path.to_path_buf();
     }
-    if settings.discard_old_attachments {
-        args.push("--no-attachments".to_string());
+    if complete && output_path.exists() {
+        promote_temp_output(output_path, final_path, source_path);
+        final_path.to_path_buf()
+    } else {
+        let _ = fs::remove_file(output_path);
+        source_path.to_path_buf()
     }
-    if settings.remove_global_tags {
-        args.push("--no-global-tags".to_string());
+}
+
+/// Canonical form of a path that may not exist yet (canonicalizes the parent instead).
+fn comparable_path(path: &Path) -> PathBuf {
+    let resolved = fs::canonicalize(path).unwrap_or_else(|_| {
+        match (path.parent().map(fs::canonicalize), path.file_name()) {
+            (Some(Ok(parent)), Some(name)) => parent.join(name),
+            _ => path.to_path_buf(),
+        }
+    });
+    if cfg!(target_os = "windows") {
+        PathBuf::from(resolved.to_string_lossy().to_lowercase())
+    } else {
+        resolved
     }
+}
 
-    let external_audio_present = !resolved_external_audios.is_empty();
-    let external_subtitle_present = !resolved_external_subtitles.is_empty()
-        || !resolved_external_subtitles_from_audio.is_empty();
+fn job_input_paths(job: &MuxJobRequest) -> impl Iterator<Item = &String> {
+    std::iter::once(&job.video.path)
+        .chain(&job.append_sources)
+        .chain(
+            job.audios
+                .iter()
+                .chain(&job.subtitles)
+                .chain(&job.chapters)
+                .chain(&job.attachments)
+                .map(|external| &external.path),
+        )
+}
 
-    let external_audio_default = resolved_external_audios
-        .iter()
-        .any(|(audio, _)| audio.is_default.unwrap_or(false));
-    if external_audio_default {
-        for (index, track) in job.video.tracks.iter().enumerate() {
-            if track.track_type != "audio" {
-                continue;
-            }
-            let id = parse_track_id(track, index);
-            args.push("--default-track-flag".to_string());
-            args.push(format!("{id}:no"));
+/// Errors when mkvmerge would write to a file it is also reading from.
+fn check_output_collision(job: &MuxJobRequest, output_path: &Path) -> Result<(), String> {
+    let output = comparable_path(output_path);
+    for input in job_input_paths(job) {
+        if comparable_path(Path::new(input)) == output {
+            return Err(format!(
+                "Output path {} is also an input file; choose a different destination or output name",
+                output_path.to_string_lossy()
+            ));
         }
     }
+    Ok(())
+}
 
-    let external_subtitle_default = resolved_external_subtitles
-        .iter()
-        .any(|(subtitle, _)| subtitle.is_default.unwrap_or(false));
-    if external_subtitle_default {
-        for (index, track) in job.video.tracks.iter().enumerate() {
-            if track.track_type != "subtitle" {
-                continue;
-            }
-            let id = parse_track_id(track, index);
-            args.push("--default-track-flag".to_string());
-            args.push(format!("{id}:no"));
-        }
+/// Warns when an audio source taken from another video has no audio tracks to offer.
+fn video_audio_source_warning(audio: &ExternalFileInfo) -> Option<String> {
+    if audio.source.as_deref() != Some(VIDEO_AUDIO_SOURCE) {
+        return None;
+    }
+    let has_audio = if audio.tracks.is_empty() {
+        get_mkvmerge_info(Path::new(&audio.path))
+            .map(|info| !parse_external_track_ids_mkvmerge(&info, "Audio").is_empty())
+            .unwrap_or(true)
+    } else {
+        audio.tracks.iter().any(|track| track.track_type == "audio")
+    };
+    if has_audio {
+        None
+    } else {
+        Some(format!(
+            "Audio source video has no audio tracks: {}",
+            audio.path
+        ))
     }
+}
 
+/// Warns about default/keep language settings that match none of the job's source tracks.
+fn language_setting_warnings(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if job.video.tracks.is_empty() {
+        return warnings;
+    }
+    let mut tracks = remap_job_languages(job, &settings.language_remap)
+        .video
+        .tracks;
+    apply_undefined_language_policy(&mut tracks, &settings.undefined_language_policy);
+    let mut check = |track_type: &str, languages: &[String], what: &str| {
+        if !languages.is_empty()
+            && collect_track_ids_by_language(&tracks, track_type, languages).is_empty()
+        {
+            warnings.push(format!(
+                "No {track_type} track in {} to {what} in {}",
+                languages.join(", "),
+                job.video.name
+            ));
+        }
+    };
     if let Some(language) = &settings.make_audio_default_language {
-        let ids = collect_track_ids_by_language(&job.video.tracks, "audio", &[language.clone()]);
-        for id in ids {
-            args.push("--default-track-flag".to_string());
-            args.push(format!("{}:yes", id));
+        if !settings.no_default_audio {
+            check("audio", std::slice::from_ref(language), "make default");
         }
     }
     if let Some(language) = &settings.make_subtitle_default_language {
-        let ids = collect_track_ids_by_language(&job.video.tracks, "subtitle", &[language.clone()]);
-        for id in ids {
-            args.push("--default-track-flag".to_string());
-            args.push(format!("{}:yes", id));
+        if !settings.no_default_subtitles {
+            check("subtitle", std::slice::from_ref(language), "make default");
         }
     }
+    if settings.only_keep_audios_enabled {
+        check("audio", &settings.only_keep_audio_languages, "keep");
+    }
+    if settings.only_keep_subtitles_enabled {
+        check("subtitle", &settings.only_keep_subtitle_languages, "keep");
+    }
+    warnings
+}
 
-    let audio_keep_ids =
-        if settings.only_keep_audios_enabled && !settings.only_keep_audio_languages.is_empty() {
-            Some(collect_track_ids_by_language(
-                &job.video.tracks,
-                "audio",
-                &settings.only_keep_audio_languages,
-            ))
-        } else {
-            None
-        };
-    let subtitle_keep_ids = if settings.only_keep_subtitles_enabled
-        && !settings.only_keep_subtitle_languages.is_empty()
-    {
-        Some(collect_track_ids_by_language(
-            &job.video.tracks,
-            "subtitle",
-            &settings.only_keep_subtitle_languages,
-        ))
+/// Whether a subtitle codec name (mkvmerge/mediainfo) or file extension is bitmap-based.
+fn is_image_subtitle(codec: &str) -> bool {
+    let codec = codec.trim().to_ascii_lowercase();
+    matches!(codec.as_str(), "sup" | "sub" | "idx")
+        || ["pgs", "vobsub", "dvbsub", "dvb subtitle"]
+            .iter()
+            .any(|name| codec.contains(name))
+}
+
+fn external_subtitle_codec(subtitle: &ExternalFileInfo) -> Option<String> {
+    subtitle
+        .tracks
+        .iter()
+        .find_map(|track| track.codec.clone())
+        .or_else(|| {
+            Path::new(&subtitle.path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(str::to_string)
+        })
+}
+
+/// Warns when the subtitles marked default mix text and image formats, which players
+/// resolve inconsistently.
+fn mixed_default_subtitle_warning(job: &MuxJobRequest, settings: &MuxSettings) -> Option<String> {
+    if settings.no_default_subtitles {
+        return None;
+    }
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    let source = job.video.tracks.iter().filter(|track| {
+        track.track_type == "subtitle" && track.is_default == Some(true) && !is_track_removed(track)
+    });
+    let external = job
+        .subtitles
+        .iter()
+        .filter(|subtitle| subtitle.is_default == Some(true));
+    let codecs: Vec<String> = source
+        .filter_map(|track| track.codec.clone())
+        .chain(external.filter_map(external_subtitle_codec))
+        .collect();
+    let image = codecs
+        .iter()
+        .filter(|codec| is_image_subtitle(codec))
+        .count();
+    (image > 0 && image < codecs.len()).then(|| {
+        format!(
+            "Default subtitles in {} mix text and image formats ({}); keep a single text subtitle as default",
+            job.video.name,
+            codecs.join(", ")
+        )
+    })
+}
+
+/// How far a file's modification time may lag the clock on coarse file systems (FAT).
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// The wall-clock time `started` was taken at.
+fn wall_clock_at(started: Instant) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(started.elapsed())
+        .unwrap_or(UNIX_EPOCH)
+}
+
+/// Files mkvmerge produced when splitting `output_path` (`name-001.mkv`, `name-002.mkv`, ...)
+/// that were written `since`.
+fn split_part_paths(output_path: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let Some(stem) = output_path.file_stem().and_then(|s| s.to_str()) else {
+        return Vec::new();
+    };
+    // Parts of earlier runs or other jobs with the same name are left alone
+    let cutoff = since.checked_sub(MTIME_GRANULARITY).unwrap_or(UNIX_EPOCH);
+    let written_since = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified >= cutoff)
+    };
+    let prefix = format!("{stem}-");
+    let extension = output_path.extension();
+    let dir = output_path.parent().unwrap_or(Path::new("."));
+    let mut parts: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_stem()
+                        .and_then(|s| s.to_str())
+                        .and_then(|s| s.strip_prefix(&prefix))
+                        .map(|number| {
+                            !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+                        })
+                        .unwrap_or(false)
+                        && path.extension() == extension
+                        && written_since(path)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    parts.sort();
+    parts
+}
+
+/// Maps a split part of the temp output to the matching part name of the final output.
+fn split_part_final_path(part: &Path, output_path: &Path, final_path: &Path) -> PathBuf {
+    let output_stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let final_stem = final_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let part_name = part
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let suffix = part_name.strip_prefix(output_stem).unwrap_or(part_name);
+    final_path.with_file_name(format!("{final_stem}{suffix}"))
+}
+
+/// Moves every split part to its final name and only then removes the source (overwrite
+/// mode), so an empty or half-moved split never costs the user their only copy.
+fn promote_split_parts(
+    parts: &[PathBuf],
+    output_path: &Path,
+    final_path: &Path,
+    source_path: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    if parts.is_empty() {
+        return Err("mkvmerge wrote no split parts; the source was kept".to_string());
+    }
+    let mut promoted = Vec::with_capacity(parts.len());
+    for part in parts {
+        let target = split_part_final_path(part, output_path, final_path);
+        retry_file_in_use(|| fs::rename(part, &target)).map_err(|e| {
+            format!(
+                "Failed to move split part {} to {}: {e}; the source was kept",
+                part.to_string_lossy(),
+                target.to_string_lossy()
+            )
+        })?;
+        promoted.push(target);
+    }
+    let _ = retry_file_in_use(|| fs::remove_file(source_path));
+    Ok(promoted)
+}
+
+/// `name_path` tagged with the CRC of `read_path`, or `None` when CRC naming is off,
+/// the file can't be read or the read was cancelled.
+fn crc_tagged_path(
+    read_path: &Path,
+    name_path: &Path,
+    settings: &MuxSettings,
+    on_progress: &mut dyn FnMut(u8),
+    cancelled: &dyn Fn() -> bool,
+) -> Option<PathBuf> {
+    if !settings.add_crc || !read_path.exists() || crc_size_limit_exceeded(read_path, settings) {
+        return None;
+    }
+    let crc = compute_crc(
+        read_path,
+        settings.crc_buffer_bytes,
+        settings.crc_sequential_read,
+        on_progress,
+        cancelled,
+    )
+    .ok()?;
+    // A re-muxed file's stale tag is replaced rather than followed by a second one
+    let untagged = if crc_from_file_name(name_path).is_some() {
+        file_name_without_crc(name_path)
     } else {
-        None
+        name_path.to_path_buf()
     };
+    Some(file_name_with_crc(&untagged, &crc))
+}
 
-    apply_track_selection(&mut args, &job.video.tracks, "video", None);
-    apply_track_selection(&mut args, &job.video.tracks, "audio", audio_keep_ids);
-    apply_track_selection(&mut args, &job.video.tracks, "subtitle", subtitle_keep_ids);
+/// Whether the file is over `crc_max_size_bytes` and so goes without a CRC tag.
+fn crc_size_limit_exceeded(path: &Path, settings: &MuxSettings) -> bool {
+    let Some(max_bytes) = settings.crc_max_size_bytes else {
+        return false;
+    };
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() > max_bytes)
+}
 
-    // Apply individual track modifications (name, language, default, forced) BEFORE adding source file
-    // Format: --default-track-flag TID:value (no 0: prefix when flag comes before the file)
-    for (index, track) in job.video.tracks.iter().enumerate() {
-        if is_track_removed(track) {
-            continue;
-        }
-        let track_id = parse_track_id(track, index);
+/// Log line comparing the CRC tag a re-muxed file had with the one its output got.
+fn crc_change_log_line(job_id: &str, previous: &Path, output: &Path) -> Option<String> {
+    let old = crc_from_file_name(previous)?;
+    let new = crc_from_file_name(output)?;
+    Some(if old.eq_ignore_ascii_case(&new) {
+        format!("Job {job_id}: CRC unchanged ({old}), the content is identical")
+    } else {
+        format!("Job {job_id}: CRC changed from {old} to {new}")
+    })
+}
 
-        // Track name (skip if empty)
-        if let Some(name) = &track.name {
-            if !name.trim().is_empty() {
-                args.push("--track-name".to_string());
-                args.push(format!("{}:{}", track_id, name));
+/// Applies the add/remove CRC naming settings to a finished output file.
+fn apply_crc_naming(
+    path: &Path,
+    settings: &MuxSettings,
+    on_progress: &mut dyn FnMut(u8),
+    cancelled: &dyn Fn() -> bool,
+) -> PathBuf {
+    if settings.add_crc && path.exists() {
+        if let Some(with_crc) = crc_tagged_path(path, path, settings, on_progress, cancelled) {
+            if with_crc == path || retry_file_in_use(|| fs::rename(path, &with_crc)).is_ok() {
+                return with_crc;
             }
         }
-
-        // Language
-        if let Some(language) = &track.language {
-            args.push("--language".to_string());
-            args.push(format!("{}:{}", track_id, language));
+    } else if settings.remove_old_crc && path.exists() {
+        let without_crc = file_name_without_crc(path);
+        if retry_file_in_use(|| fs::rename(path, &without_crc)).is_ok() {
+            return without_crc;
         }
+    }
+    path.to_path_buf()
+}
 
-        // Default flag - apply individual track defaults from ModifyTracksDialog
-        // These override the bulk operations (external defaults, language filters) for specific tracks
-        if let Some(is_default) = track.is_default {
-            args.push("--default-track-flag".to_string());
-            args.push(format!(
-                "{}:{}",
-                track_id,
-                if is_default { "yes" } else { "no" }
-            ));
-        }
-
-        // Forced flag for subtitles (use forced-display-flag)
-        if track.track_type == "subtitle" {
-            if let Some(is_forced) = track.is_forced {
-                args.push("--forced-display-flag".to_string());
-                args.push(format!(
-                    "{}:{}",
-                    track_id,
-                    if is_forced { "yes" } else { "no" }
-                ));
-            }
+fn compute_sha256(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open file: {e}"))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read file: {e}"))?;
+        if read == 0 {
+            break;
         }
+        hasher.update(&buffer[..read]);
     }
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    // Enforce audio ordering when external audio exists:
-    // bulk audio (from Audio tab) -> per-file external audio -> original audio tracks.
-    if external_audio_present || external_subtitle_present {
-        let mut order: Vec<String> = Vec::new();
-        let source_video_tracks: Vec<usize> = job
-            .video
-            .tracks
-            .iter()
-            .enumerate()
-            .filter(|(_, track)| track.track_type == "video" && !is_track_removed(track))
-            .map(|(index, track)| parse_track_id(track, index))
-            .collect();
-        let source_audio_tracks: Vec<usize> = job
-            .video
-            .tracks
-            .iter()
-            .enumerate()
-            .filter(|(_, track)| track.track_type == "audio" && !is_track_removed(track))
-            .map(|(index, track)| parse_track_id(track, index))
-            .collect();
-        let source_subtitle_tracks: Vec<usize> = job
-            .video
-            .tracks
-            .iter()
-            .enumerate()
-            .filter(|(_, track)| track.track_type == "subtitle" && !is_track_removed(track))
-            .map(|(index, track)| parse_track_id(track, index))
-            .collect();
+/// Compares an input against its `<file>.sha256` sidecar. Inputs without a sidecar pass.
+fn verify_input_checksum(path: &Path) -> Result<(), String> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    let Ok(content) = fs::read_to_string(PathBuf::from(sidecar)) else {
+        return Ok(());
+    };
+    // sha256sum format: "<hash>  <file name>"; a bare hash works too
+    let Some(expected) = content.split_whitespace().next() else {
+        return Ok(());
+    };
+    let actual = compute_sha256(path)?;
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Source corrupt: {} does not match its .sha256 sidecar (expected {}, got {})",
+            path.to_string_lossy(),
+            expected,
+            actual
+        ))
+    }
+}
 
-        for id in source_video_tracks {
-            order.push(format!("0:{}", id));
-        }
+/// Opens a file for one front-to-back read, hinting the OS to read ahead aggressively
+/// when `sequential` is set.
+#[cfg(target_os = "windows")]
+fn open_for_sequential_read(path: &Path, sequential: bool) -> std::io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+    let mut options = OpenOptions::new();
+    options.read(true);
+    if sequential {
+        options.custom_flags(FILE_FLAG_SEQUENTIAL_SCAN);
+    }
+    options.open(path)
+}
 
-        let mut file_index = 1usize;
-        let mut bulk_audio_entries: Vec<String> = Vec::new();
-        let mut per_video_audio_entries: Vec<String> = Vec::new();
-        for (audio, track_id) in &resolved_external_audios {
-            let entry = format!("{}:{}", file_index, track_id);
-            let is_per_video = audio.source.as_deref() == Some("per-file");
-            if is_per_video {
-                per_video_audio_entries.push(entry);
-            } else {
-                bulk_audio_entries.push(entry);
-            }
-            file_index += 1;
+#[cfg(target_os = "linux")]
+fn open_for_sequential_read(path: &Path, sequential: bool) -> std::io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+    let file = File::open(path)?;
+    if sequential {
+        // Only a hint; a failure changes nothing about the read itself
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL);
         }
+    }
+    Ok(file)
+}
 
-        order.extend(bulk_audio_entries);
-        order.extend(per_video_audio_entries);
-        for id in source_audio_tracks {
-            order.push(format!("0:{}", id));
-        }
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn open_for_sequential_read(path: &Path, _sequential: bool) -> std::io::Result<File> {
+    File::open(path)
+}
 
-        for id in source_subtitle_tracks {
-            order.push(format!("0:{}", id));
-        }
+/// Error of a CRC read given up because `cancelled` returned true.
+const CRC_CANCELLED: &str = "CRC computation cancelled";
 
-        let mut bulk_subtitle_entries: Vec<String> = Vec::new();
-        let mut per_video_subtitle_entries: Vec<String> = Vec::new();
-        let all_subtitles: Vec<(ExternalFileInfo, u64)> = resolved_external_subtitles
-            .iter()
-            .cloned()
-            .chain(resolved_external_subtitles_from_audio.iter().cloned())
-            .collect();
-        for (subtitle, track_id) in &all_subtitles {
-            let entry = format!("{}:{}", file_index, track_id);
-            let is_per_video = subtitle.source.as_deref() == Some("per-file");
-            if is_per_video {
-                per_video_subtitle_entries.push(entry);
-            } else {
-                bulk_subtitle_entries.push(entry);
-            }
-            file_index += 1;
+/// CRC32 of the file as eight uppercase hex digits; `on_progress` gets the percentage
+/// read each time it changes. `cancelled` is checked before every read, so a stop
+/// request doesn't wait for the rest of a large file.
+fn compute_crc(
+    path: &Path,
+    buffer_bytes: usize,
+    sequential: bool,
+    on_progress: &mut dyn FnMut(u8),
+    cancelled: &dyn Fn() -> bool,
+) -> Result<String, String> {
+    let mut file = retry_file_in_use(|| open_for_sequential_read(path, sequential))
+        .map_err(|e| format!("Failed to open file for CRC: {e}"))?;
+    let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0u8; buffer_bytes.clamp(4096, 64 * 1024 * 1024)];
+    let mut done = 0u64;
+    let mut last_percent = None;
+    loop {
+        if cancelled() {
+            return Err(CRC_CANCELLED.to_string());
         }
-        order.extend(bulk_subtitle_entries);
-        order.extend(per_video_subtitle_entries);
-
-        if !order.is_empty() {
-            args.push("--track-order".to_string());
-            args.push(order.join(","));
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read file: {e}"))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        done += read as u64;
+        let percent = (done * 100).checked_div(total).unwrap_or(100).min(100) as u8;
+        if last_percent != Some(percent) {
+            last_percent = Some(percent);
+            on_progress(percent);
         }
     }
+    Ok(format!("{:08X}", hasher.finalize()))
+}
 
-    args.push(job.video.path.clone());
+/// Stem and extension of an output file, keeping the extension's casing.
+fn output_name_parts(path: &Path) -> (&str, &str) {
+    let stem = path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("mkv");
+    (stem, extension)
+}
 
-    for (audio, track_id) in &resolved_external_audios {
-        args.push("--no-video".to_string());
-        args.push("--no-subtitles".to_string());
-        args.push("--no-chapters".to_string());
-        args.push("--no-attachments".to_string());
-        args.push("--no-global-tags".to_string());
-        args.push("--audio-tracks".to_string());
-        args.push(track_id.to_string());
-        let override_entry = audio.track_overrides.get(&track_id.to_string());
-        let language = override_entry
-            .and_then(|entry| entry.language.clone())
-            .or_else(|| {
-                if audio.apply_language {
-                    audio.language.clone()
-                } else {
-                    None
-                }
-            });
-        if let Some(language) = language {
-            args.push("--language".to_string());
-            args.push(format!("{}:{}", track_id, language));
-        }
-        let track_name = override_entry
-            .and_then(|entry| entry.track_name.clone())
-            .or_else(|| audio.track_name.clone());
-        if let Some(name) = track_name {
-            if !name.trim().is_empty() {
-                args.push("--track-name".to_string());
-                args.push(format!("{}:{}", track_id, name));
-            }
-        }
-        let delay = override_entry
-            .and_then(|entry| entry.delay)
-            .or_else(|| audio.delay);
-        if let Some(delay) = delay {
-            args.push("--sync".to_string());
-            args.push(format!("{}:{}", track_id, (delay * 1000.0) as i64));
-        }
-        if let Some(is_default) = audio.is_default {
-            args.push("--default-track-flag".to_string());
-            args.push(format!(
-                "{}:{}",
-                track_id,
-                if is_default { "yes" } else { "no" }
-            ));
-        }
-        if let Some(_is_forced) = audio.is_forced {
-            // mkvmerge versions in the wild often do not support forced flag for audio tracks.
-        }
-        args.push(audio.path.clone());
-    }
+fn file_name_with_crc(path: &Path, crc: &str) -> PathBuf {
+    let (file_stem, extension) = output_name_parts(path);
+    path.with_file_name(format!("{} [{}].{}", file_stem, crc, extension))
+}
 
-    let all_subtitles: Vec<(ExternalFileInfo, u64)> = resolved_external_subtitles
-        .iter()
-        .cloned()
-        .chain(resolved_external_subtitles_from_audio.iter().cloned())
-        .collect();
-    for (subtitle, track_id) in &all_subtitles {
-        args.push("--no-video".to_string());
-        args.push("--no-audio".to_string());
-        args.push("--no-chapters".to_string());
-        args.push("--no-attachments".to_string());
-        args.push("--no-global-tags".to_string());
-        args.push("--subtitle-tracks".to_string());
-        args.push(track_id.to_string());
-        let override_entry = subtitle.track_overrides.get(&track_id.to_string());
-        let language = override_entry
-            .and_then(|entry| entry.language.clone())
-            .or_else(|| {
-                if subtitle.apply_language {
-                    subtitle.language.clone()
-                } else {
-                    None
+fn file_name_without_crc(path: &Path) -> PathBuf {
+    let (cleaned, extension) = output_name_parts(path);
+    let sanitized = if let Some(index) = cleaned.rfind('[') {
+        cleaned[..index].trim()
+    } else {
+        cleaned
+    };
+    path.with_file_name(format!("{}.{}", sanitized, extension))
+}
+
+/// The `[XXXXXXXX]` CRC tag at the end of a file stem, if there is one.
+fn crc_from_file_name(path: &Path) -> Option<String> {
+    let (stem, _) = output_name_parts(path);
+    let tag = stem.trim_end().strip_suffix(']')?;
+    let crc = &tag[tag.rfind('[')? + 1..];
+    (crc.len() == 8 && crc.chars().all(|c| c.is_ascii_hexdigit())).then(|| crc.to_string())
+}
+
+/// Splits a command line on whitespace, keeping double-quoted runs together.
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut has_arg = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
                 }
-            });
-        if let Some(language) = language {
-            args.push("--language".to_string());
-            args.push(format!("{}:{}", track_id, language));
-        }
-        let track_name = override_entry
-            .and_then(|entry| entry.track_name.clone())
-            .or_else(|| subtitle.track_name.clone());
-        if let Some(name) = track_name {
-            if !name.trim().is_empty() {
-                args.push("--track-name".to_string());
-                args.push(format!("{}:{}", track_id, name));
             }
-        }
-        let delay = override_entry
-            .and_then(|entry| entry.delay)
-            .or_else(|| subtitle.delay);
-        if let Some(delay) = delay {
-            args.push("--sync".to_string());
-            args.push(format!("{}:{}", track_id, (delay * 1000.0) as i64));
-        }
-        if let Some(is_default) = subtitle.is_default {
-            args.push("--default-track-flag".to_string());
-            args.push(format!(
-                "{}:{}",
-                track_id,
-                if is_default { "yes" } else { "no" }
-            ));
-        }
-        if let Some(is_forced) = subtitle.is_forced {
-            args.push("--forced-display-flag".to_string());
-            args.push(format!(
-                "{}:{}",
-                track_id,
-                if is_forced { "yes" } else { "no" }
-            ));
-        }
-        args.push(subtitle.path.clone());
-    }
-
-    for chapter in &job.chapters {
-        args.push("--chapters".to_string());
-        args.push(chapter.path.clone());
-        // Apply chapter delay if set (mkvmerge uses --sync after --chapters)
-        // Note: Chapter delay shifts all chapter timestamps by the specified amount
-        if let Some(delay) = chapter.delay {
-            if delay != 0.0 {
-                args.push("--sync".to_string());
-                // For chapter files, use 0:milliseconds format (0 refers to the last added file)
-                args.push(format!("0:{}", (delay * 1000.0) as i64));
+            c => {
+                current.push(c);
+                has_arg = true;
             }
         }
     }
-
-    for attachment in &job.attachments {
-        args.push("--attach-file".to_string());
-        args.push(attachment.path.clone());
+    if has_arg {
+        args.push(current);
     }
-
     args
 }
 
-fn spawn_log_reader<R: Read + Send + 'static>(
+/// The program and arguments for the post-mux command. Tokens are expanded after
+/// splitting, so a path with spaces or shell characters stays a single argument.
+fn post_mux_command_args(template: &str, output: &Path, input: &str) -> Vec<String> {
+    let output_text = output.to_string_lossy();
+    let crc = crc_from_file_name(output).unwrap_or_default();
+    split_command_line(template)
+        .into_iter()
+        .map(|arg| {
+            arg.replace("{output}", &output_text)
+                .replace("{input}", input)
+                .replace("{crc}", &crc)
+        })
+        .collect()
+}
+
+/// How long the post-mux command may run before it's killed.
+const POST_MUX_COMMAND_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Logs the post-mux command's output line by line as it arrives.
+fn spawn_post_mux_logger<R: Read + Send + 'static>(
     reader: R,
-    app: AppHandle,
     state: AppState,
-    job_id: String,
-) {
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut reader = BufReader::new(reader);
-        let mut line = String::new();
-        while let Ok(bytes) = reader.read_line(&mut line) {
-            if bytes == 0 {
-                break;
-            }
-            let trimmed = line.trim_end().to_string();
-            let _ = write_log_line(&state.paths, &trimmed);
-            if let Some(progress) = parse_progress(&trimmed) {
-                emit_progress(
-                    &app,
-                    MuxProgressEvent {
-                        job_id: job_id.clone(),
-                        status: "processing".to_string(),
-                        progress,
-                        message: None,
-                        size_after: None,
-                        error_message: None,
-                    },
-                );
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if !line.trim().is_empty() {
+                let _ = write_log_line(&state.paths, &format!("[post-mux] {line}"));
             }
-            let _ = app.emit_all(
-                "mux-log",
-                serde_json::json!({ "job_id": job_id, "line": trimmed }),
-            );
-            line.clear();
         }
-    });
+    })
 }
 
-fn run_command_with_logs(
-    app: &AppHandle,
+/// Runs the post-mux command like the mux tools: registered as the job's child so
+/// stop and reset kill it, and killed once `timeout` passes.
+fn run_post_mux_command(
     state: &AppState,
-    job: &MuxJobRequest,
-    command: &mut Command,
-) -> Result<Arc<Mutex<Child>>, String> {
-    let mut child = command
+    job_id: &str,
+    template: &str,
+    output: &Path,
+    input: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let args = post_mux_command_args(template, output, input);
+    let Some((program, rest)) = args.split_first() else {
+        return Ok(());
+    };
+    let command_line = args
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = write_log_line(
+        &state.paths,
+        &format!("Job {job_id} post-mux command: {command_line}"),
+    );
+    let mut child = hidden_command(program)
+        .args(rest)
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Failed to start process: {e}"))?;
-
-    let stdout = child.stdout.take();
-    let stderr = child.stderr.take();
+        .map_err(|e| format!("Post-mux command failed to start: {e}"))?;
+    bind_child_to_app(&child);
+    let readers: Vec<_> = [
+        child
+            .stdout
+            .take()
+            .map(|out| spawn_post_mux_logger(out, state.clone())),
+        child
+            .stderr
+            .take()
+            .map(|err| spawn_post_mux_logger(err, state.clone())),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
 
     let handle = Arc::new(Mutex::new(child));
-    {
-        let mut mux_state = state.mux_state.lock().unwrap();
-        mux_state.children.insert(job.id.clone(), handle.clone());
+    state
+        .mux_state
+        .lock()
+        .unwrap()
+        .children
+        .insert(job_id.to_string(), handle.clone());
+    let deadline = Instant::now() + timeout;
+    let status = wait_for_child_until(handle, state, Some(deadline));
+    state.mux_state.lock().unwrap().children.remove(job_id);
+    // A killed command's own children may still hold the pipes open; its readers
+    // then finish on their own
+    if status.is_some() {
+        for reader in readers {
+            let _ = reader.join();
+        }
+    }
+    match status {
+        Some(0) => Ok(()),
+        Some(code) => Err(format!("Post-mux command exited with code {code}")),
+        None if Instant::now() >= deadline => Err(format!(
+            "Post-mux command timed out after {} s",
+            timeout.as_secs()
+        )),
+        None => Err("Post-mux command was stopped".to_string()),
     }
+}
 
-    if let Some(out) = stdout {
-        spawn_log_reader(out, app.clone(), state.clone(), job.id.clone());
+/// Runs the post-mux command for every output of a finished job. Returns the error
+/// that should fail the job, if the settings ask for that.
+fn post_mux_failure(
+    state: &AppState,
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    outputs: &[PathBuf],
+) -> Option<String> {
+    let template = settings.post_mux_command.as_deref()?.trim();
+    if template.is_empty() {
+        return None;
     }
-    if let Some(err) = stderr {
-        spawn_log_reader(err, app.clone(), state.clone(), job.id.clone());
+    for output in outputs {
+        let result = run_post_mux_command(
+            state,
+            &job.id,
+            template,
+            output,
+            &job.video.path,
+            POST_MUX_COMMAND_TIMEOUT,
+        );
+        if let Err(err) = result {
+            let _ = write_log_line(&state.paths, &format!("WARNING: Job {}: {err}", job.id));
+            if settings.post_mux_command_must_succeed {
+                return Some(err);
+            }
+        }
     }
+    None
+}
 
-    Ok(handle)
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
 }
 
-fn emit_progress(app: &AppHandle, event: MuxProgressEvent) {
-    let _ = app.emit_all("mux-progress", event);
+fn free_space_error(available_bytes: u64, required_bytes: u64) -> Option<String> {
+    (available_bytes < required_bytes).then(|| {
+        format!(
+            "Not enough free space. Available: {}, required: {}",
+            format_bytes(available_bytes),
+            format_bytes(required_bytes)
+        )
+    })
 }
 
-fn wait_for_child_or_stop(handle: Arc<Mutex<Child>>, state: &AppState) -> Option<i32> {
-    loop {
-        {
-            let mux_state = state.mux_state.lock().unwrap();
-            if mux_state.stop {
-                drop(mux_state);
-                if let Ok(mut child) = handle.lock() {
-                    let _ = child.kill();
-                }
-            }
-        }
+/// Free space on the volume `path` will be created on, looking at the nearest folder
+/// that already exists.
+fn available_space_for(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|dir| dir.is_dir())?;
+    available_space(existing).ok()
+}
 
-        let status = {
-            let mut child = handle.lock().unwrap();
-            match child.try_wait() {
-                Ok(Some(status)) => return status.code(),
-                Ok(None) => None,
-                Err(_) => return None,
+fn check_free_space(path: &Path, required_bytes: u64) -> Result<(), String> {
+    let available = available_space(path).map_err(|e| format!("Failed to read free space: {e}"))?;
+    match free_space_error(available, required_bytes) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Whether a destination folder takes new files, and how much room it has.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct DestinationCheck {
+    writable: bool,
+    free_bytes: Option<u64>,
+    error: Option<String>,
+}
+
+/// Creates, writes and deletes a small file in `dir`, the way a mux would use it.
+fn destination_check(dir: &Path) -> DestinationCheck {
+    let free_bytes = available_space(dir).ok();
+    let probe = tempfile::Builder::new()
+        .prefix(".mkvbatchmux-write-test")
+        .tempfile_in(dir)
+        .and_then(|mut file| {
+            file.write_all(b"0")?;
+            file.close()
+        });
+    let error = probe
+        .err()
+        .map(|e| format!("Cannot write to {}: {e}", dir.to_string_lossy()));
+    DestinationCheck {
+        writable: error.is_none(),
+        free_bytes,
+        error,
+    }
+}
+
+/// Lets the UI flag a read-only or missing destination as soon as it is chosen.
+#[tauri::command]
+fn check_destination_writable(dir: String) -> DestinationCheck {
+    destination_check(Path::new(&dir))
+}
+
+/// The language to make default for a type: the fixed one when set, else the first
+/// language in `priority` that one of the `kept` tracks has.
+fn default_language_for(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    kept: &[usize],
+    fixed: Option<&String>,
+    priority: &[String],
+) -> Option<String> {
+    if let Some(language) = fixed {
+        return Some(language.clone());
+    }
+    priority
+        .iter()
+        .find(|language| {
+            collect_track_ids_by_language(tracks, track_type, std::slice::from_ref(*language))
+                .iter()
+                .any(|id| kept.contains(id))
+        })
+        .cloned()
+}
+
+fn collect_track_ids_by_language(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    languages: &[String],
+) -> Vec<usize> {
+    let mut ids = Vec::new();
+    for (index, track) in tracks.iter().enumerate() {
+        if track.track_type != track_type {
+            continue;
+        }
+        if let Some(language) = &track.language {
+            if languages
+                .iter()
+                .any(|lang| lang.eq_ignore_ascii_case(language))
+            {
+                if let Ok(parsed) = track.id.parse::<usize>() {
+                    ids.push(parsed);
+                } else {
+                    ids.push(index);
+                }
             }
-        };
+        }
+    }
+    ids
+}
 
-        if status.is_some() {
-            return status;
+fn collect_track_ids_by_codec(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    codecs: &[String],
+) -> Vec<usize> {
+    tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.track_type == track_type)
+        .filter(|(_, track)| {
+            [&track.codec, &track.codec_id]
+                .into_iter()
+                .flatten()
+                .any(|codec| codecs.iter().any(|keep| keep.eq_ignore_ascii_case(codec)))
+        })
+        .map(|(index, track)| parse_track_id(track, index))
+        .collect()
+}
+
+/// Source track ids the language and codec filters leave for a type; `None` when no
+/// filter applies. Both filters must pass when both are set.
+fn only_keep_track_ids(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    settings: &MuxSettings,
+) -> Option<Vec<usize>> {
+    let (enabled, languages, codecs) = match track_type {
+        "audio" => (
+            settings.only_keep_audios_enabled,
+            &settings.only_keep_audio_languages,
+            &settings.keep_audio_codecs,
+        ),
+        "subtitle" => (
+            settings.only_keep_subtitles_enabled,
+            &settings.only_keep_subtitle_languages,
+            &settings.keep_subtitle_codecs,
+        ),
+        _ => return None,
+    };
+    let by_language = (enabled && !languages.is_empty())
+        .then(|| collect_track_ids_by_language(tracks, track_type, languages));
+    let by_codec =
+        (!codecs.is_empty()).then(|| collect_track_ids_by_codec(tracks, track_type, codecs));
+    match (by_language, by_codec) {
+        (Some(language_ids), Some(codec_ids)) => Some(intersect_ids(language_ids, codec_ids)),
+        (language_ids, codec_ids) => language_ids.or(codec_ids),
+    }
+}
+
+fn parse_track_id(track: &TrackInfo, index: usize) -> usize {
+    track.id.parse::<usize>().unwrap_or(index)
+}
+
+fn is_track_removed(track: &TrackInfo) -> bool {
+    matches!(track.action.as_deref(), Some("remove"))
+}
+
+fn collect_track_ids_by_action(tracks: &[TrackInfo], track_type: &str) -> (Vec<usize>, bool) {
+    let mut ids = Vec::new();
+    let mut has_removed = false;
+    for (index, track) in tracks.iter().enumerate() {
+        if track.track_type != track_type {
+            continue;
         }
+        if is_track_removed(track) {
+            has_removed = true;
+            continue;
+        }
+        ids.push(parse_track_id(track, index));
+    }
+    (ids, has_removed)
+}
 
-        thread::sleep(Duration::from_millis(200));
+/// Kept/removed/filtered counts per source track type, matching what
+/// `apply_track_selection` passes to mkvmerge.
+fn track_action_summary(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<TrackActionSummary> {
+    let adjusted = adjusted_job(job, settings);
+    let tracks = &adjusted.as_ref().unwrap_or(job).video.tracks;
+    ["video", "audio", "subtitle"]
+        .into_iter()
+        .filter_map(|track_type| {
+            let total = tracks
+                .iter()
+                .filter(|track| track.track_type == track_type)
+                .count();
+            if total == 0 {
+                return None;
+            }
+            let (mut kept, _) = collect_track_ids_by_action(tracks, track_type);
+            let removed = total - kept.len();
+            if let Some(keep) = only_keep_track_ids(tracks, track_type, settings) {
+                kept = intersect_ids(kept, keep);
+            }
+            kept.retain(|id| !settings.block_source_track_ids.contains(id));
+            Some(TrackActionSummary {
+                track_type: track_type.to_string(),
+                kept: kept.len(),
+                removed,
+                disabled: total - removed - kept.len(),
+            })
+        })
+        .collect()
+}
+
+fn intersect_ids(left: Vec<usize>, right: Vec<usize>) -> Vec<usize> {
+    left.into_iter().filter(|id| right.contains(id)).collect()
+}
+
+/// Ids of the source tracks of a type that make it into the output: not removed, kept
+/// by `only_keep_ids` when set, and not blocked.
+fn selected_track_ids(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    only_keep_ids: Option<&[usize]>,
+    blocked_ids: &[usize],
+) -> Vec<usize> {
+    let (mut selected, _) = collect_track_ids_by_action(tracks, track_type);
+    if let Some(keep) = only_keep_ids {
+        selected = intersect_ids(selected, keep.to_vec());
     }
+    selected.retain(|id| !blocked_ids.contains(id));
+    selected
 }
 
-fn parse_progress(line: &str) -> Option<u8> {
-    let percent_pos = line.find('%')?;
-    let start = line[..percent_pos]
-        .rfind(|c: char| !c.is_ascii_digit())
-        .map(|index| index + 1)
-        .unwrap_or(0);
-    line[start..percent_pos].trim().parse::<u8>().ok()
+/// Ids of the source video, audio and subtitle tracks that reach the output, across
+/// every type: not removed, filtered or blocked.
+fn kept_source_track_ids(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<usize> {
+    ["video", "audio", "subtitle"]
+        .into_iter()
+        .flat_map(|track_type| {
+            let keep_ids = only_keep_track_ids(&job.video.tracks, track_type, settings);
+            selected_track_ids(
+                &job.video.tracks,
+                track_type,
+                keep_ids.as_deref(),
+                &settings.block_source_track_ids,
+            )
+        })
+        .collect()
 }
 
-fn process_job(app: &AppHandle, state: &AppState, settings: &MuxSettings, job: MuxJobRequest) {
-    if state.mux_state.lock().unwrap().stop {
+fn apply_track_selection(
+    args: &mut Vec<String>,
+    tracks: &[TrackInfo],
+    track_type: &str,
+    only_keep_ids: Option<Vec<usize>>,
+    blocked_ids: &[usize],
+) {
+    let (_, has_removed) = collect_track_ids_by_action(tracks, track_type);
+    let type_count = tracks
+        .iter()
+        .filter(|track| track.track_type == track_type)
+        .count();
+
+    if type_count == 0 {
         return;
     }
 
-    emit_progress(
-        app,
-        MuxProgressEvent {
-            job_id: job.id.clone(),
-            status: "processing".to_string(),
-            progress: 0,
-            message: Some("Starting muxing".to_string()),
-            size_after: None,
-            error_message: None,
-        },
-    );
-    let _ = write_log_line(
-        &state.paths,
-        &format!("Starting job {} for {}", job.id, job.video.path),
+    let selected = selected_track_ids(tracks, track_type, only_keep_ids.as_deref(), blocked_ids);
+
+    if selected.len() == type_count && !has_removed && only_keep_ids.is_none() {
+        return;
+    }
+
+    if selected.is_empty() {
+        match track_type {
+            "audio" => args.push("--no-audio".to_string()),
+            "subtitle" => args.push("--no-subtitles".to_string()),
+            "video" => args.push("--no-video".to_string()),
+            _ => {}
+        }
+        return;
+    }
+
+    let flag = match track_type {
+        "audio" => "--audio-tracks",
+        "subtitle" => "--subtitle-tracks",
+        "video" => "--video-tracks",
+        _ => return,
+    };
+    args.push(flag.to_string());
+    args.push(
+        selected
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
     );
+}
 
-    let output_dir = if settings.destination_dir.trim().is_empty() {
-        PathBuf::from(&job.video.path)
-            .parent()
-            .unwrap_or(Path::new("."))
-            .to_path_buf()
+/// True when the settings force the default flag off for every track of this type.
+fn default_flag_cleared(settings: &MuxSettings, track_type: &str) -> bool {
+    match track_type {
+        "audio" => settings.no_default_audio,
+        "subtitle" => settings.no_default_subtitles,
+        _ => false,
+    }
+}
+
+/// Default flags for the source's video tracks. Only flags the user changed are written,
+/// and turning one on turns off every other video track that was default, so at most
+/// one video track ends up default.
+fn video_default_flags(tracks: &[TrackInfo]) -> HashMap<usize, bool> {
+    let video: Vec<(usize, &TrackInfo)> = tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.track_type == "video" && !is_track_removed(track))
+        .map(|(index, track)| (parse_track_id(track, index), track))
+        .collect();
+    let changed = |track: &TrackInfo| {
+        track.original_default.is_some()
+            && track.is_default.is_some()
+            && track.original_default != track.is_default
+    };
+    let chosen = video
+        .iter()
+        .find(|(_, track)| changed(track) && track.is_default == Some(true))
+        .map(|(id, _)| *id);
+    video
+        .iter()
+        .filter_map(|(id, track)| {
+            if Some(*id) == chosen {
+                Some((*id, true))
+            } else if chosen.is_some()
+                && (track.is_default == Some(true) || track.original_default == Some(true))
+            {
+                Some((*id, false))
+            } else {
+                (changed(track) && track.is_default == Some(false)).then_some((*id, false))
+            }
+        })
+        .collect()
+}
+
+/// Default flags that make the `position`-th kept track of a type the only default one.
+/// Positions past the last kept track change nothing.
+fn position_default_flags(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    only_keep_ids: Option<&[usize]>,
+    position: usize,
+) -> Vec<(usize, bool)> {
+    let kept: Vec<usize> = tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.track_type == track_type && !is_track_removed(track))
+        .map(|(index, track)| parse_track_id(track, index))
+        .filter(|id| only_keep_ids.is_none_or(|keep| keep.contains(id)))
+        .collect();
+    if position == 0 || position > kept.len() {
+        return Vec::new();
+    }
+    kept.iter()
+        .enumerate()
+        .map(|(index, id)| (*id, index + 1 == position))
+        .collect()
+}
+
+/// Default flags for a type's source tracks from the batch settings. An external track
+/// marked default wins, then the default position, then the default language; whichever
+/// applies leaves no other track of the type default.
+fn source_default_flags(
+    tracks: &[TrackInfo],
+    track_type: &str,
+    kept_ids: &[usize],
+    external_default: bool,
+    settings: &MuxSettings,
+) -> Vec<(usize, bool)> {
+    if default_flag_cleared(settings, track_type) {
+        return Vec::new();
+    }
+    if external_default {
+        return tracks
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| track.track_type == track_type)
+            .map(|(index, track)| (parse_track_id(track, index), false))
+            .collect();
+    }
+    let (position, fixed, priority) = if track_type == "audio" {
+        (
+            settings.make_audio_default_position,
+            settings.make_audio_default_language.as_ref(),
+            &settings.audio_default_language_priority,
+        )
     } else {
-        PathBuf::from(&settings.destination_dir)
+        (
+            settings.make_subtitle_default_position,
+            settings.make_subtitle_default_language.as_ref(),
+            &settings.subtitle_default_language_priority,
+        )
     };
-    if let Err(err) = check_free_space(&output_dir, job.video.size) {
-        emit_progress(
-            app,
-            MuxProgressEvent {
-                job_id: job.id.clone(),
-                status: "error".to_string(),
-                progress: 0,
-                message: Some("Low disk space".to_string()),
-                size_after: None,
-                error_message: Some(err),
-            },
-        );
-        if settings.abort_on_errors {
-            let mut mux_state = state.mux_state.lock().unwrap();
-            mux_state.pause = true;
+    if let Some(position) = position {
+        let flags = position_default_flags(tracks, track_type, Some(kept_ids), position);
+        if !flags.is_empty() {
+            return flags;
         }
-        return;
     }
+    let Some(language) = default_language_for(tracks, track_type, kept_ids, fixed, priority) else {
+        return Vec::new();
+    };
+    let ids = collect_track_ids_by_language(tracks, track_type, &[language]);
+    if !ids.iter().any(|id| kept_ids.contains(id)) {
+        return Vec::new();
+    }
+    kept_ids.iter().map(|id| (*id, ids.contains(id))).collect()
+}
 
-    if settings.destination_dir.trim().is_empty() && !settings.overwrite_source {
-        emit_progress(
-            app,
-            MuxProgressEvent {
-                job_id: job.id.clone(),
-                status: "error".to_string(),
-                progress: 0,
-                message: Some("Destination folder required".to_string()),
-                size_after: None,
-                error_message: Some(
-                    "Set a destination folder or enable overwrite source.".to_string(),
-                ),
-            },
+fn build_mkvpropedit_args(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<String> {
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    let mut args = Vec::new();
+
+    // Apply track modifications: name, language, default, forced flags
+    // For Fast Mux, we apply edits for all tracks that have any properties set
+    for (index, track) in job.video.tracks.iter().enumerate() {
+        if is_track_removed(track) {
+            continue;
+        }
+
+        // mkvpropedit uses 1-based track IDs, so add 1 to the parsed track ID
+        let track_id = parse_track_id(track, index) + 1;
+
+        // Track name - apply if set (even if empty, to clear it)
+        if settings.strip_track_names.applies_to(&track.track_type) {
+            args.push("--edit".to_string());
+            args.push(format!("track:{}", track_id));
+            args.push("--delete".to_string());
+            args.push("name".to_string());
+        } else if let Some(name) = &track.name {
+            args.push("--edit".to_string());
+            args.push(format!("track:{}", track_id));
+            args.push("--set".to_string());
+            args.push(format!("name={}", name.trim()));
+        }
+
+        // Language - apply if set
+        if let Some(language) = &track.language {
+            args.push("--edit".to_string());
+            args.push(format!("track:{}", track_id));
+            args.push("--set".to_string());
+            args.push(format!("language={}", language));
+        }
+
+        // Default flag - apply if explicitly set (Some(true) or Some(false))
+        let is_default = if default_flag_cleared(settings, &track.track_type) {
+            Some(false)
+        } else {
+            track.is_default
+        };
+        if let Some(is_default) = is_default {
+            args.push("--edit".to_string());
+            args.push(format!("track:{}", track_id));
+            args.push("--set".to_string());
+            args.push(format!(
+                "flag-default={}",
+                if is_default { "1" } else { "0" }
+            ));
+        }
+
+        // Forced flag (for subtitles, use flag-forced-display)
+        if track.track_type == "subtitle" {
+            if let Some(is_forced) = track.is_forced {
+                args.push("--edit".to_string());
+                args.push(format!("track:{}", track_id));
+                args.push("--set".to_string());
+                args.push(format!(
+                    "flag-forced-display={}",
+                    if is_forced { "1" } else { "0" }
+                ));
+            }
+        } else if track.track_type == "audio" || track.track_type == "video" {
+            if let Some(is_forced) = track.is_forced {
+                args.push("--edit".to_string());
+                args.push(format!("track:{}", track_id));
+                args.push("--set".to_string());
+                args.push(format!("flag-forced={}", if is_forced { "1" } else { "0" }));
+            }
+        }
+    }
+
+    args.extend(muxing_app_args(settings));
+    args
+}
+
+/// The mkvpropedit edit that sets a custom muxing application; empty when the setting
+/// is unset. mkvmerge has no option for it, so it's applied to the finished output.
+fn muxing_app_args(settings: &MuxSettings) -> Vec<String> {
+    match settings.muxing_app.as_deref().map(str::trim) {
+        Some(app) if !app.is_empty() && settings.output_container == OutputContainer::Mkv => {
+            vec![
+                "--edit".to_string(),
+                "info".to_string(),
+                "--set".to_string(),
+                format!("muxing-application={app}"),
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Sets the custom muxing application on each output. A failure is logged and leaves
+/// mkvmerge's string in place; it doesn't fail the job.
+fn apply_muxing_app(state: &AppState, job_id: &str, settings: &MuxSettings, outputs: &[PathBuf]) {
+    let args = muxing_app_args(settings);
+    if args.is_empty() {
+        return;
+    }
+    for output in outputs {
+        let result = hidden_command("mkvpropedit")
+            .arg(output)
+            .args(&args)
+            .stdin(Stdio::null())
+            .output();
+        let error = match result {
+            Ok(result) if result.status.success() => continue,
+            Ok(result) => format!(
+                "mkvpropedit exited with code {}",
+                result.status.code().unwrap_or(-1)
+            ),
+            Err(err) => format!("mkvpropedit failed to start: {err}"),
+        };
+        let _ = write_log_line(
+            &state.paths,
+            &format!(
+                "WARNING: Job {job_id}: couldn't set the muxing application on {}: {error}",
+                output.to_string_lossy()
+            ),
+        );
+    }
+}
+
+fn quote_arg(arg: &str) -> String {
+    if arg.contains(' ') || arg.contains('"') || arg.contains('\'') {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+fn join_tool_command(tool: &str, args: &[String]) -> String {
+    let mut parts = Vec::with_capacity(args.len() + 1);
+    parts.push(tool.to_string());
+    for arg in args {
+        parts.push(quote_arg(arg));
+    }
+    parts.join(" ")
+}
+
+/// Splits a line built by `join_tool_command` back into its arguments, undoing
+/// `quote_arg`'s quoting and escaped quotes.
+fn split_tool_command(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut has_arg = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => {
+                quoted = !quoted;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// Arguments that differ between two command lines. An argument both have, but not in
+/// the same relative order, counts as reordered rather than added and removed.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct CommandDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    reordered: Vec<String>,
+}
+
+fn diff_command_lines(a: &str, b: &str) -> CommandDiff {
+    let (a, b) = (split_tool_command(a), split_tool_command(b));
+    // Longest common subsequence: what's outside it was added, removed or moved
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut only_a, mut only_b) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            only_a.push(a[i].clone());
+            i += 1;
+        } else {
+            only_b.push(b[j].clone());
+            j += 1;
+        }
+    }
+    only_a.extend_from_slice(&a[i..]);
+    only_b.extend_from_slice(&b[j..]);
+
+    let mut reordered = Vec::new();
+    only_a.retain(|arg| match only_b.iter().position(|other| other == arg) {
+        Some(index) => {
+            reordered.push(only_b.remove(index));
+            false
+        }
+        None => true,
+    });
+    CommandDiff {
+        added: only_b,
+        removed: only_a,
+        reordered,
+    }
+}
+
+/// Compares two tool command lines, e.g. a preview against the one a job last ran with.
+#[tauri::command]
+fn diff_commands(a: String, b: String) -> CommandDiff {
+    diff_command_lines(&a, &b)
+}
+
+/// The command line a job last ran with in this session, for comparing with a preview.
+#[tauri::command]
+fn get_last_job_command(state: State<AppState>, job_id: String) -> Option<String> {
+    state
+        .mux_state
+        .lock()
+        .unwrap()
+        .last_commands
+        .get(&job_id)
+        .cloned()
+}
+
+fn log_job_plan(state: &AppState, job: &MuxJobRequest, output_path: &Path) {
+    let audio_list = if job.audios.is_empty() {
+        "[]".to_string()
+    } else {
+        let items: Vec<String> = job
+            .audios
+            .iter()
+            .map(|audio| {
+                format!(
+                    "{{path={}, lang={:?}, default={:?}, name={:?}}}",
+                    quote_arg(&audio.path),
+                    audio.language,
+                    audio.is_default,
+                    audio.track_name
+                )
+            })
+            .collect();
+        format!("[{}]", items.join(", "))
+    };
+    let subtitle_list = if job.subtitles.is_empty() {
+        "[]".to_string()
+    } else {
+        let items: Vec<String> = job
+            .subtitles
+            .iter()
+            .map(|subtitle| {
+                format!(
+                    "{{path={}, lang={:?}, default={:?}, forced={:?}, name={:?}}}",
+                    quote_arg(&subtitle.path),
+                    subtitle.language,
+                    subtitle.is_default,
+                    subtitle.is_forced,
+                    subtitle.track_name
+                )
+            })
+            .collect();
+        format!("[{}]", items.join(", "))
+    };
+    let chapter_list = if job.chapters.is_empty() {
+        "[]".to_string()
+    } else {
+        let items: Vec<String> = job
+            .chapters
+            .iter()
+            .map(|chapter| quote_arg(&chapter.path))
+            .collect();
+        format!("[{}]", items.join(", "))
+    };
+
+    let _ = write_log_line(
+        &state.paths,
+        &format!(
+            "JOB PLAN: video={} output={} preset={} audios={} subtitles={} chapters={}",
+            quote_arg(&job.video.path),
+            quote_arg(&output_path.to_string_lossy()),
+            quote_arg(job.preset_name.as_deref().unwrap_or("")),
+            audio_list,
+            subtitle_list,
+            chapter_list
+        ),
+    );
+    for warning in external_default_warnings(job) {
+        let _ = write_log_line(&state.paths, &format!("WARNING: {warning}"));
+    }
+}
+
+fn remap_language(language: &mut Option<String>, remap: &HashMap<String, String>) {
+    let Some(current) = language.as_deref() else {
+        return;
+    };
+    if let Some((_, replacement)) = remap
+        .iter()
+        .find(|(from, _)| from.trim().eq_ignore_ascii_case(current.trim()))
+    {
+        *language = Some(replacement.trim().to_string());
+    }
+}
+
+fn is_undefined_language(language: Option<&str>) -> bool {
+    language.is_none_or(|language| {
+        let language = language.trim();
+        language.is_empty()
+            || language.eq_ignore_ascii_case("und")
+            || language.eq_ignore_ascii_case("undefined")
+    })
+}
+
+/// Applies `policy` to the source audio and subtitle tracks with an undetermined
+/// language. Video tracks are rarely tagged, so they're left alone.
+fn apply_undefined_language_policy(tracks: &mut [TrackInfo], policy: &UndefinedPolicy) {
+    for track in tracks.iter_mut() {
+        if !matches!(track.track_type.as_str(), "audio" | "subtitle")
+            || is_track_removed(track)
+            || !is_undefined_language(track.language.as_deref())
+        {
+            continue;
+        }
+        match policy {
+            UndefinedPolicy::Leave => {}
+            UndefinedPolicy::SetTo(language) => track.language = Some(language.trim().to_string()),
+            UndefinedPolicy::Drop => track.action = Some("remove".to_string()),
+        }
+    }
+}
+
+/// Why the undetermined-language policy can't be applied: setting an empty language
+/// would pass `--language N:` to mkvmerge.
+fn undefined_language_policy_error(settings: &MuxSettings) -> Option<String> {
+    match &settings.undefined_language_policy {
+        UndefinedPolicy::SetTo(language) if language.trim().is_empty() => {
+            Some("No language set for tracks with an undetermined language".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Returns a copy of the job with every source and external track language remapped.
+fn remap_job_languages(job: &MuxJobRequest, remap: &HashMap<String, String>) -> MuxJobRequest {
+    let mut job = job.clone();
+    for track in &mut job.video.tracks {
+        remap_language(&mut track.language, remap);
+    }
+    for external in job.audios.iter_mut().chain(job.subtitles.iter_mut()) {
+        remap_language(&mut external.language, remap);
+        for track in &mut external.tracks {
+            remap_language(&mut track.language, remap);
+        }
+    }
+    job
+}
+
+/// Applies batch-wide overrides to source tracks the user hasn't edited individually.
+fn apply_batch_track_overrides(tracks: &mut [TrackInfo], overrides: &[BatchTrackOverride]) {
+    for track in tracks.iter_mut() {
+        if track.action.as_deref() == Some("modify") || is_track_removed(track) {
+            continue;
+        }
+        let Some(language) = track.language.clone() else {
+            continue;
+        };
+        let language = language.trim();
+        for entry in overrides {
+            let matches = entry.track_type == track.track_type
+                && entry.match_language.trim().eq_ignore_ascii_case(language);
+            if !matches {
+                continue;
+            }
+            if let Some(name) = &entry.set_name {
+                track.name = Some(name.clone());
+            }
+            if entry.set_default.is_some() {
+                track.is_default = entry.set_default;
+            }
+            if entry.set_forced.is_some() {
+                track.is_forced = entry.set_forced;
+            }
+        }
+    }
+}
+
+/// Describes externals of one kind that are marked default after the first one.
+fn default_conflict_warning(externals: &[ExternalFileInfo], kind: &str) -> Option<String> {
+    let mut defaults = externals
+        .iter()
+        .filter(|external| external.is_default == Some(true));
+    let first = defaults.next()?;
+    let extra = defaults.count();
+    (extra > 0).then(|| {
+        format!(
+            "{} external {kind} tracks are marked default; only {} keeps the flag",
+            extra + 1,
+            first.name
+        )
+    })
+}
+
+fn external_default_warnings(job: &MuxJobRequest) -> Vec<String> {
+    [(&job.audios, "audio"), (&job.subtitles, "subtitle")]
+        .into_iter()
+        .filter_map(|(externals, kind)| default_conflict_warning(externals, kind))
+        .collect()
+}
+
+/// Leaves the default flag only on the first external that asks for it.
+fn keep_first_external_default(externals: &mut [ExternalFileInfo]) {
+    let mut seen = false;
+    for external in externals
+        .iter_mut()
+        .filter(|external| external.is_default == Some(true))
+    {
+        if seen {
+            external.is_default = Some(false);
+        }
+        seen = true;
+    }
+}
+
+/// Case-insensitive match of a track name against a pattern where `*` matches any run
+/// of characters; without a `*` the pattern may appear anywhere in the name.
+fn track_name_matches(name: &str, pattern: &str) -> bool {
+    let name = name.to_lowercase();
+    let pattern = pattern.trim().to_lowercase();
+    if pattern.is_empty() {
+        return false;
+    }
+    if !pattern.contains('*') {
+        return name.contains(&pattern);
+    }
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, rest) = parts.split_first().unwrap();
+    let Some(mut remaining) = name.strip_prefix(first) else {
+        return false;
+    };
+    let (last, middle) = rest.split_last().unwrap();
+    for part in middle {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(last)
+}
+
+/// The name patterns a job keeps tracks by: its own list, else the batch setting.
+fn keep_track_name_patterns<'a>(job: &'a MuxJobRequest, settings: &'a MuxSettings) -> &'a [String] {
+    job.keep_track_name_patterns
+        .as_deref()
+        .unwrap_or(&settings.keep_track_name_patterns)
+}
+
+/// Marks source tracks whose name matches none of `patterns` as removed, per type and only
+/// in types where some track does match, so a filter aimed at audio leaves subtitles be.
+fn apply_track_name_keep_filter(tracks: &mut [TrackInfo], patterns: &[String]) {
+    if patterns.is_empty() {
+        return;
+    }
+    let matches = |track: &TrackInfo| {
+        track.name.as_deref().is_some_and(|name| {
+            patterns
+                .iter()
+                .any(|pattern| track_name_matches(name, pattern))
+        })
+    };
+    for track_type in ["video", "audio", "subtitle"] {
+        let of_type =
+            |track: &&mut TrackInfo| track.track_type == track_type && !is_track_removed(track);
+        if !tracks
+            .iter_mut()
+            .filter(of_type)
+            .any(|track| matches(track))
+        {
+            continue;
+        }
+        for track in tracks.iter_mut().filter(of_type) {
+            if !matches(track) {
+                track.action = Some("remove".to_string());
+            }
+        }
+    }
+}
+
+/// The job with batch-level settings (language remap, track overrides) folded in and
+/// conflicting external default flags resolved, or `None` when nothing changes.
+fn adjusted_job(job: &MuxJobRequest, settings: &MuxSettings) -> Option<MuxJobRequest> {
+    let name_patterns = keep_track_name_patterns(job, settings);
+    if settings.language_remap.is_empty()
+        && settings.batch_track_overrides.is_empty()
+        && !settings.clear_imported_track_names
+        && settings.undefined_language_policy == UndefinedPolicy::Leave
+        && name_patterns.is_empty()
+        && external_default_warnings(job).is_empty()
+    {
+        return None;
+    }
+    let name_patterns = name_patterns.to_vec();
+    let mut job = remap_job_languages(job, &settings.language_remap);
+    apply_undefined_language_policy(&mut job.video.tracks, &settings.undefined_language_policy);
+    // Before names are cleared or overridden, so the source's own names are matched
+    apply_track_name_keep_filter(&mut job.video.tracks, &name_patterns);
+    if settings.clear_imported_track_names {
+        // Before the overrides, so names they set are kept. Empty rather than `None`:
+        // without a name of its own mkvmerge copies the source's
+        for track in &mut job.video.tracks {
+            if track.has_imported_name() {
+                track.name = Some(String::new());
+            }
+        }
+    }
+    apply_batch_track_overrides(&mut job.video.tracks, &settings.batch_track_overrides);
+    keep_first_external_default(&mut job.audios);
+    keep_first_external_default(&mut job.subtitles);
+    Some(job)
+}
+
+/// External inputs expanded to one entry per track, in the order they're added to mkvmerge.
+struct ResolvedExternals {
+    audios: Vec<(ExternalFileInfo, u64)>,
+    subtitles: Vec<(ExternalFileInfo, u64)>,
+    /// Subtitle tracks taken from external audio files (e.g. a companion MKA).
+    subtitles_from_audio: Vec<(ExternalFileInfo, u64)>,
+}
+
+impl ResolvedExternals {
+    fn all_subtitles(&self) -> impl Iterator<Item = &(ExternalFileInfo, u64)> {
+        self.subtitles.iter().chain(&self.subtitles_from_audio)
+    }
+}
+
+/// Expands every external file into the tracks it contributes, probing files whose
+/// tracks weren't picked explicitly.
+fn resolve_external_tracks(job: &MuxJobRequest) -> ResolvedExternals {
+    let mut resolved_external_audios: Vec<(ExternalFileInfo, u64)> = Vec::new();
+    for audio in &job.audios {
+        let mut resolved_ids: Vec<u64> = Vec::new();
+        if let Some(ids) = &audio.included_track_ids {
+            if ids.is_empty() {
+                continue;
+            }
+            resolved_ids = ids.clone();
+        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&audio.path)) {
+            let ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Audio");
+            if ids.len() > 1 {
+                resolved_ids = ids;
+            } else if let Some(id) = audio.track_id {
+                resolved_ids.push(id);
+            } else {
+                resolved_ids = ids;
+            }
+        } else if let Some(id) = audio.track_id {
+            resolved_ids.push(id);
+        }
+        if audio.first_track_only {
+            if let Some(first) = resolved_ids.iter().min().copied() {
+                resolved_ids = vec![first];
+            }
+        }
+
+        if resolved_ids.is_empty() {
+            if audio.source.as_deref() == Some(VIDEO_AUDIO_SOURCE) {
+                // Track 0 of a video file is usually its video track; nothing to take
+                continue;
+            }
+            resolved_ids.push(0);
+        }
+
+        let set_default_on_first = audio.is_default.unwrap_or(false);
+        // With a track-scoped delay only the chosen track (else the lowest) keeps it
+        let delayed_track = audio
+            .track_id
+            .filter(|id| resolved_ids.contains(id))
+            .or_else(|| resolved_ids.iter().min().copied());
+        for (index, track_id) in resolved_ids.iter().enumerate() {
+            let mut cloned = audio.clone();
+            cloned.track_id = Some(*track_id);
+            if set_default_on_first {
+                cloned.is_default = Some(index == 0);
+            }
+            if audio.delay_scope == DelayScope::Track && delayed_track != Some(*track_id) {
+                cloned.delay = None;
+            }
+            cloned.apply_language = index == 0;
+            resolved_external_audios.push((cloned, *track_id));
+        }
+    }
+
+    let mut resolved_external_subtitles: Vec<(ExternalFileInfo, u64)> = Vec::new();
+    let mut resolved_external_subtitles_from_audio: Vec<(ExternalFileInfo, u64)> = Vec::new();
+    for subtitle in &job.subtitles {
+        let mut resolved_ids: Vec<u64> = Vec::new();
+        if let Some(ids) = &subtitle.included_track_ids {
+            if ids.is_empty() {
+                continue;
+            }
+            resolved_ids = ids.clone();
+        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&subtitle.path)) {
+            let ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Text");
+            if ids.len() > 1 {
+                resolved_ids = ids;
+            } else if let Some(id) = subtitle.track_id {
+                resolved_ids.push(id);
+            } else {
+                resolved_ids = ids;
+            }
+        } else if let Some(id) = subtitle.track_id {
+            resolved_ids.push(id);
+        }
+        if subtitle.first_track_only {
+            if let Some(first) = resolved_ids.iter().min().copied() {
+                resolved_ids = vec![first];
+            }
+        }
+
+        if resolved_ids.is_empty() {
+            resolved_ids.push(0);
+        }
+
+        let set_default_on_first = subtitle.is_default.unwrap_or(false);
+        for (index, track_id) in resolved_ids.iter().enumerate() {
+            let mut cloned = subtitle.clone();
+            cloned.track_id = Some(*track_id);
+            if set_default_on_first {
+                cloned.is_default = Some(index == 0);
+            }
+            cloned.apply_language = index == 0;
+            resolved_external_subtitles.push((cloned, *track_id));
+        }
+    }
+
+    for audio in &job.audios {
+        if audio.include_subtitles != Some(true) {
+            continue;
+        }
+        let mut resolved_ids: Vec<u64> = Vec::new();
+        if let Some(ids) = &audio.included_subtitle_track_ids {
+            if ids.is_empty() {
+                continue;
+            }
+            resolved_ids = ids.clone();
+        } else if let Some(mkvmerge) = get_mkvmerge_info(Path::new(&audio.path)) {
+            resolved_ids = parse_external_track_ids_mkvmerge(&mkvmerge, "Text");
+        }
+        if resolved_ids.is_empty() {
+            continue;
+        }
+        let flags = audio.subtitle_flags.clone().unwrap_or_default();
+        for track_id in resolved_ids.iter() {
+            let mut cloned = audio.clone();
+            cloned.track_id = Some(*track_id);
+            // The audio's own language and flags are meant for its audio track
+            cloned.language = flags.language.clone();
+            cloned.apply_language = flags.language.is_some();
+            cloned.is_default = flags.is_default;
+            cloned.is_forced = flags.is_forced;
+            resolved_external_subtitles_from_audio.push((cloned, *track_id));
+        }
+    }
+
+    ResolvedExternals {
+        audios: resolved_external_audios,
+        subtitles: resolved_external_subtitles,
+        subtitles_from_audio: resolved_external_subtitles_from_audio,
+    }
+}
+
+/// Language an external track is written with: its override, else the file's language
+/// when `apply_language` is set.
+fn external_track_language(file: &ExternalFileInfo, track_id: u64) -> Option<String> {
+    file.track_overrides
+        .get(&track_id.to_string())
+        .and_then(|entry| entry.language.clone())
+        .or_else(|| file.language.clone().filter(|_| file.apply_language))
+}
+
+/// The `--sync` track id and delay for one resolved track of an external audio file.
+/// A track's own override applies to it alone; the file's delay goes to the whole
+/// input (`-1`), or with `DelayScope::Track` to the one track that kept it.
+fn external_audio_sync(file: &ExternalFileInfo, track_id: u64) -> Option<(String, f64)> {
+    let override_delay = file
+        .track_overrides
+        .get(&track_id.to_string())
+        .and_then(|entry| entry.delay);
+    if let Some(delay) = override_delay {
+        return Some((track_id.to_string(), delay));
+    }
+    let delay = file.delay?;
+    match file.delay_scope {
+        DelayScope::File => Some(("-1".to_string(), delay)),
+        DelayScope::Track => Some((track_id.to_string(), delay)),
+    }
+}
+
+fn external_track_name(file: &ExternalFileInfo, track_id: u64) -> Option<String> {
+    file.track_overrides
+        .get(&track_id.to_string())
+        .and_then(|entry| entry.track_name.clone())
+        .or_else(|| file.track_name.clone())
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct TrackOrderEntry {
+    /// Input position on the mkvmerge command line; 0 is the source video.
+    source_file_index: usize,
+    track_id: u64,
+    #[serde(rename = "type")]
+    track_type: String,
+    language: Option<String>,
+    name: Option<String>,
+}
+
+/// The order tracks end up in the output. With external audio or subtitles this is the
+/// `--track-order` mkvmerge gets: source video, bulk then per-file external audio, source
+/// audio, source subtitles, then bulk and per-file external subtitles. Without them the
+/// source order is kept as is.
+fn ordered_tracks(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    externals: &ResolvedExternals,
+) -> Vec<TrackOrderEntry> {
+    let source_entry = |index: usize, track: &TrackInfo| TrackOrderEntry {
+        source_file_index: 0,
+        track_id: parse_track_id(track, index) as u64,
+        track_type: track.track_type.clone(),
+        language: track.language.clone(),
+        name: track
+            .name
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+            .filter(|_| !settings.strip_track_names.applies_to(&track.track_type)),
+    };
+    // Only what `apply_track_selection` lets through
+    let kept = kept_source_track_ids(job, settings);
+    let survives = |index: usize, track: &TrackInfo| match track.track_type.as_str() {
+        "video" | "audio" | "subtitle" => kept.contains(&parse_track_id(track, index)),
+        _ => !is_track_removed(track),
+    };
+    let source_tracks = |track_type: &str| -> Vec<TrackOrderEntry> {
+        job.video
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(index, track)| survives(*index, track))
+            .filter(|(_, track)| track_type.is_empty() || track.track_type == track_type)
+            .map(|(index, track)| source_entry(index, track))
+            .collect()
+    };
+    if externals.audios.is_empty() && externals.all_subtitles().next().is_none() {
+        return source_tracks("");
+    }
+
+    // Appended parts take the file ids right after the source video
+    let mut file_index = job.append_sources.len();
+    let mut external_entries = |files: Vec<&(ExternalFileInfo, u64)>, track_type: &str| {
+        let mut bulk = Vec::new();
+        let mut per_file = Vec::new();
+        for (file, track_id) in files {
+            file_index += 1;
+            let entry = TrackOrderEntry {
+                source_file_index: file_index,
+                track_id: *track_id,
+                track_type: track_type.to_string(),
+                language: external_track_language(file, *track_id),
+                name: external_track_name(file, *track_id).filter(|name| !name.trim().is_empty()),
+            };
+            if file.source.as_deref() == Some("per-file") {
+                per_file.push(entry);
+            } else {
+                bulk.push(entry);
+            }
+        }
+        bulk.extend(per_file);
+        bulk
+    };
+    let external_audios = external_entries(externals.audios.iter().collect(), "audio");
+    let external_subtitles = external_entries(externals.all_subtitles().collect(), "subtitle");
+
+    let mut order = source_tracks("video");
+    order.extend(external_audios);
+    order.extend(source_tracks("audio"));
+    order.extend(source_tracks("subtitle"));
+    order.extend(external_subtitles);
+    order
+}
+
+/// The exact output track order for a job, so the UI can show it instead of guessing.
+#[tauri::command]
+fn resolve_track_order(job: MuxJobRequest, settings: MuxSettings) -> Vec<TrackOrderEntry> {
+    job_track_order(&job, &settings)
+}
+
+fn job_track_order(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<TrackOrderEntry> {
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    ordered_tracks(job, settings, &resolve_external_tracks(job))
+}
+
+fn mux_plan_event(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    output_path: &Path,
+    args: &[String],
+) -> MuxPlanEvent {
+    MuxPlanEvent {
+        job_id: job.id.clone(),
+        args: args.to_vec(),
+        output_path: output_path.to_string_lossy().to_string(),
+        track_order: job_track_order(job, settings),
+    }
+}
+
+fn build_mkvmerge_command(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    output_path: &Path,
+    state: &AppState,
+) -> Vec<String> {
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    let mut args = Vec::new();
+    if settings.gui_mode {
+        args.push("--gui-mode".to_string());
+    }
+    args.push("--output".to_string());
+    args.push(output_path.to_string_lossy().to_string());
+
+    let externals = resolve_external_tracks(job);
+    let resolved_external_audios = &externals.audios;
+
+    // A chapter entry with source "video" means "keep the main video's chapters"
+    let keep_video_chapters = job
+        .chapters
+        .iter()
+        .any(|chapter| chapter.source.as_deref() == Some("video"));
+    if settings.discard_old_chapters && !keep_video_chapters {
+        args.push("--no-chapters".to_string());
+    }
+    if settings.discard_old_attachments && settings.discard_replaced_attachments_only {
+        let has_poster = job
+            .poster_image
+            .as_deref()
+            .is_some_and(|poster| poster_attachment(Path::new(poster)).is_ok());
+        args.extend(old_attachment_args(
+            job.probed.embedded_attachments.as_deref(),
+            &attachment_paths(job, settings),
+            has_poster,
+        ));
+    } else if settings.discard_old_attachments {
+        args.push("--no-attachments".to_string());
+    }
+    if settings.remove_global_tags {
+        args.push("--no-global-tags".to_string());
+    }
+    if settings.remove_track_tags {
+        args.push("--no-track-tags".to_string());
+    }
+    if let Some(split) = settings.split.as_ref().and_then(SplitMode::to_mkvmerge_arg) {
+        args.push("--split".to_string());
+        args.push(split);
+    }
+
+    let external_audio_present = !resolved_external_audios.is_empty();
+    let external_subtitle_present = externals.all_subtitles().next().is_some();
+
+    let external_audio_default = resolved_external_audios
+        .iter()
+        .any(|(audio, _)| audio.is_default.unwrap_or(false));
+    let external_subtitle_default = externals
+        .all_subtitles()
+        .any(|(subtitle, _)| subtitle.is_default.unwrap_or(false));
+
+    let audio_keep_ids = only_keep_track_ids(&job.video.tracks, "audio", settings);
+    let subtitle_keep_ids = only_keep_track_ids(&job.video.tracks, "subtitle", settings);
+    let blocked_ids = &settings.block_source_track_ids;
+    // Only tracks that reach the output can decide the default language
+    let kept_audio_ids = selected_track_ids(
+        &job.video.tracks,
+        "audio",
+        audio_keep_ids.as_deref(),
+        blocked_ids,
+    );
+    let kept_subtitle_ids = selected_track_ids(
+        &job.video.tracks,
+        "subtitle",
+        subtitle_keep_ids.as_deref(),
+        blocked_ids,
+    );
+
+    let batch_defaults = [
+        ("audio", &kept_audio_ids, external_audio_default),
+        ("subtitle", &kept_subtitle_ids, external_subtitle_default),
+    ];
+    for (track_type, kept_ids, external_default) in batch_defaults {
+        let flags = source_default_flags(
+            &job.video.tracks,
+            track_type,
+            kept_ids,
+            external_default,
+            settings,
+        );
+        for (id, is_default) in flags {
+            args.push(track_flag_arg(state, TrackFlag::Default).to_string());
+            args.push(format!("{}:{}", id, if is_default { "yes" } else { "no" }));
+        }
+    }
+
+    // Appended parts get the same selection so their tracks line up with the first part's
+    let mut selection = Vec::new();
+    apply_track_selection(
+        &mut selection,
+        &job.video.tracks,
+        "video",
+        None,
+        blocked_ids,
+    );
+    apply_track_selection(
+        &mut selection,
+        &job.video.tracks,
+        "audio",
+        audio_keep_ids,
+        blocked_ids,
+    );
+    apply_track_selection(
+        &mut selection,
+        &job.video.tracks,
+        "subtitle",
+        subtitle_keep_ids,
+        blocked_ids,
+    );
+    args.extend(selection.iter().cloned());
+
+    // Apply individual track modifications (name, language, default, forced) BEFORE adding source file
+    // Format: --default-track-flag TID:value (no 0: prefix when flag comes before the file)
+    let video_defaults = video_default_flags(&job.video.tracks);
+    for (index, track) in job.video.tracks.iter().enumerate() {
+        if is_track_removed(track) {
+            continue;
+        }
+        let track_id = parse_track_id(track, index);
+
+        // Track name (skip if empty)
+        if settings.strip_track_names.applies_to(&track.track_type) {
+            args.push("--track-name".to_string());
+            args.push(format!("{}:", track_id));
+        } else if let Some(name) = &track.name {
+            if !name.trim().is_empty() {
+                args.push("--track-name".to_string());
+                args.push(format!("{}:{}", track_id, name));
+            } else if settings.clear_imported_track_names {
+                args.push("--track-name".to_string());
+                args.push(format!("{}:", track_id));
+            }
+        }
+
+        // Language
+        if let Some(language) = &track.language {
+            args.push("--language".to_string());
+            args.push(format!("{}:{}", track_id, language));
+        }
+
+        if let Some(delay) = track.delay.filter(|delay| *delay != 0.0) {
+            args.push("--sync".to_string());
+            args.push(format!("{}:{}", track_id, (delay * 1000.0) as i64));
+        }
+
+        // Default flag - apply individual track defaults from ModifyTracksDialog
+        // These override the bulk operations (external defaults, language filters) for specific tracks
+        let is_default = if track.track_type == "video" {
+            video_defaults.get(&track_id).copied()
+        } else if default_flag_cleared(settings, &track.track_type) {
+            Some(false)
+        } else {
+            track.is_default
+        };
+        if let Some(is_default) = is_default {
+            args.push(track_flag_arg(state, TrackFlag::Default).to_string());
+            args.push(format!(
+                "{}:{}",
+                track_id,
+                if is_default { "yes" } else { "no" }
+            ));
+        }
+
+        // Forced flag for subtitles (use forced-display-flag)
+        if track.track_type == "subtitle" {
+            if let Some(is_forced) = track.is_forced {
+                args.push(track_flag_arg(state, TrackFlag::Forced).to_string());
+                args.push(format!(
+                    "{}:{}",
+                    track_id,
+                    if is_forced { "yes" } else { "no" }
+                ));
+            }
+        }
+    }
+
+    if external_audio_present || external_subtitle_present {
+        let order: Vec<String> = ordered_tracks(job, settings, &externals)
+            .iter()
+            .map(|entry| format!("{}:{}", entry.source_file_index, entry.track_id))
+            .collect();
+        if !order.is_empty() {
+            args.push("--track-order".to_string());
+            args.push(order.join(","));
+        }
+    }
+
+    args.push(job.video.path.clone());
+    for part in &job.append_sources {
+        args.extend(selection.iter().cloned());
+        args.push(format!("+{part}"));
+    }
+
+    for (audio, track_id) in resolved_external_audios {
+        args.push("--no-video".to_string());
+        args.push("--no-subtitles".to_string());
+        args.push("--no-chapters".to_string());
+        args.push("--no-attachments".to_string());
+        args.push("--no-global-tags".to_string());
+        args.push("--audio-tracks".to_string());
+        args.push(track_id.to_string());
+        if let Some(language) = external_track_language(audio, *track_id) {
+            args.push("--language".to_string());
+            args.push(format!("{}:{}", track_id, language));
+        }
+        if let Some(name) = external_track_name(audio, *track_id) {
+            if !name.trim().is_empty() {
+                args.push("--track-name".to_string());
+                args.push(format!("{}:{}", track_id, name));
+            }
+        }
+        if let Some((sync_id, delay)) = external_audio_sync(audio, *track_id) {
+            args.push("--sync".to_string());
+            args.push(format!("{}:{}", sync_id, (delay * 1000.0) as i64));
+        }
+        let is_default = if settings.no_default_audio {
+            Some(false)
+        } else {
+            audio.is_default
+        };
+        if let Some(is_default) = is_default {
+            args.push(track_flag_arg(state, TrackFlag::Default).to_string());
+            args.push(format!(
+                "{}:{}",
+                track_id,
+                if is_default { "yes" } else { "no" }
+            ));
+        }
+        if let Some(is_forced) = audio.is_forced {
+            // Old mkvmerge builds only take the forced flag on subtitle tracks
+            if mkvmerge_major_version(state).is_none_or(|v| v >= TRACK_FLAG_NAMES_VERSION) {
+                args.push("--forced-display-flag".to_string());
+                args.push(format!(
+                    "{}:{}",
+                    track_id,
+                    if is_forced { "yes" } else { "no" }
+                ));
+            }
+        }
+        args.push(audio.path.clone());
+    }
+
+    for (subtitle, track_id) in externals.all_subtitles() {
+        args.push("--no-video".to_string());
+        args.push("--no-audio".to_string());
+        args.push("--no-chapters".to_string());
+        args.push("--no-attachments".to_string());
+        args.push("--no-global-tags".to_string());
+        args.push("--subtitle-tracks".to_string());
+        args.push(track_id.to_string());
+        let override_entry = subtitle.track_overrides.get(&track_id.to_string());
+        if let Some(language) = external_track_language(subtitle, *track_id) {
+            args.push("--language".to_string());
+            args.push(format!("{}:{}", track_id, language));
+        }
+        if let Some(name) = external_track_name(subtitle, *track_id) {
+            if !name.trim().is_empty() {
+                args.push("--track-name".to_string());
+                args.push(format!("{}:{}", track_id, name));
+            }
+        }
+        #[allow(clippy::unnecessary_lazy_evaluations)]
+        let delay = override_entry
+            .and_then(|entry| entry.delay)
+            .or_else(|| subtitle.delay);
+        if let Some(delay) = delay {
+            args.push("--sync".to_string());
+            args.push(format!("{}:{}", track_id, (delay * 1000.0) as i64));
+        }
+        let is_default = if settings.no_default_subtitles {
+            Some(false)
+        } else {
+            subtitle.is_default
+        };
+        if let Some(is_default) = is_default {
+            args.push(track_flag_arg(state, TrackFlag::Default).to_string());
+            args.push(format!(
+                "{}:{}",
+                track_id,
+                if is_default { "yes" } else { "no" }
+            ));
+        }
+        if let Some(is_forced) = subtitle.is_forced {
+            args.push(track_flag_arg(state, TrackFlag::Forced).to_string());
+            args.push(format!(
+                "{}:{}",
+                track_id,
+                if is_forced { "yes" } else { "no" }
+            ));
+        }
+        args.push(subtitle.path.clone());
+    }
+
+    // `--chapter-language` carries over to every later chapter file, so once one file
+    // sets it the ones without a language reset it
+    let mut chapter_language_set = false;
+    for chapter in &job.chapters {
+        if chapter.source.as_deref() == Some("video") {
+            continue;
+        }
+        // Tags this file's chapters that don't carry a language themselves
+        let language = chapter
+            .language
+            .as_deref()
+            .map(str::trim)
+            .filter(|language| !language.is_empty());
+        if language.is_some() || chapter_language_set {
+            args.push("--chapter-language".to_string());
+            args.push(language.unwrap_or("und").to_string());
+            chapter_language_set = true;
+        }
+        if is_matroska_path(Path::new(&chapter.path)) {
+            // --chapters only reads chapter files; a Matroska source is added as an
+            // input with everything but its chapters disabled.
+            args.push("--no-video".to_string());
+            args.push("--no-audio".to_string());
+            args.push("--no-subtitles".to_string());
+            args.push("--no-attachments".to_string());
+            args.push("--no-global-tags".to_string());
+            args.push("--no-track-tags".to_string());
+            args.push(chapter.path.clone());
+            continue;
+        }
+        args.push("--chapters".to_string());
+        args.push(chapter.path.clone());
+        // Apply chapter delay if set (mkvmerge uses --sync after --chapters)
+        // Note: Chapter delay shifts all chapter timestamps by the specified amount
+        if let Some(sync) = chapter_sync_ms(chapter.delay, job.probed.chapter_offset) {
+            args.push("--sync".to_string());
+            // For chapter files, use 0:milliseconds format (0 refers to the last added file)
+            args.push(format!("0:{sync}"));
+        }
+    }
+
+    // An unusable poster fails the job before it gets here
+    if let Some(poster) = job.poster_image.as_deref() {
+        if let Ok((name, mime_type)) = poster_attachment(Path::new(poster)) {
+            args.push("--attachment-name".to_string());
+            args.push(name.to_string());
+            args.push("--attachment-mime-type".to_string());
+            args.push(mime_type.to_string());
+            args.push("--attach-file".to_string());
+            args.push(poster.to_string());
+        }
+    }
+    for (path, mime_override) in attachment_paths(job, settings) {
+        let mime_type = mime_override
+            .filter(|mime| is_valid_mime_type(mime))
+            .unwrap_or_else(|| attachment_mime_type(Path::new(&path)).to_string());
+        args.push("--attachment-mime-type".to_string());
+        args.push(mime_type);
+        args.push("--attach-file".to_string());
+        args.push(path);
+    }
+
+    args
+}
+
+/// The tool and arguments that write a job's output in the configured container.
+fn build_job_command(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    output_path: &Path,
+    state: &AppState,
+) -> (&'static str, Vec<String>) {
+    match settings.output_container {
+        OutputContainer::Mkv => (
+            "mkvmerge",
+            build_mkvmerge_command(job, settings, output_path, state),
+        ),
+        OutputContainer::Mp4 => ("ffmpeg", build_ffmpeg_command(job, settings, output_path)),
+    }
+}
+
+/// One output stream of an ffmpeg remux.
+struct FfmpegStream {
+    map: String,
+    language: Option<String>,
+    name: Option<String>,
+    is_default: Option<bool>,
+    is_forced: Option<bool>,
+}
+
+/// ffmpeg arguments that stream-copy the job's kept tracks into an MP4, with the same
+/// track order, languages, names and default/forced flags mkvmerge would get. Chapter
+/// files, attachments, splitting and appended parts are mkvmerge-only; see
+/// `mp4_validation_errors`.
+fn build_ffmpeg_command(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    output_path: &Path,
+) -> Vec<String> {
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    let externals = resolve_external_tracks(job);
+
+    let external_default = |track_type: &str| {
+        if track_type == "audio" {
+            externals
+                .audios
+                .iter()
+                .any(|(audio, _)| audio.is_default == Some(true))
+        } else {
+            externals
+                .all_subtitles()
+                .any(|(subtitle, _)| subtitle.is_default == Some(true))
+        }
+    };
+
+    let source_streams = |track_type: &str| -> Vec<FfmpegStream> {
+        let keep_ids = only_keep_track_ids(&job.video.tracks, track_type, settings);
+        let kept_ids = selected_track_ids(
+            &job.video.tracks,
+            track_type,
+            keep_ids.as_deref(),
+            &settings.block_source_track_ids,
+        );
+        // The same defaults mkvmerge would set; a track's own flag still wins
+        let batch_defaults: HashMap<usize, bool> = source_default_flags(
+            &job.video.tracks,
+            track_type,
+            &kept_ids,
+            external_default(track_type),
+            settings,
+        )
+        .into_iter()
+        .collect();
+        job.video
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| track.track_type == track_type && !is_track_removed(track))
+            .map(|(index, track)| (parse_track_id(track, index), track))
+            .filter(|(id, _)| keep_ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            .filter(|(id, _)| !settings.block_source_track_ids.contains(id))
+            .map(|(id, track)| FfmpegStream {
+                map: format!("0:{id}"),
+                language: track.language.clone(),
+                name: track
+                    .name
+                    .clone()
+                    .filter(|_| !settings.strip_track_names.applies_to(track_type)),
+                is_default: if default_flag_cleared(settings, track_type) {
+                    Some(false)
+                } else {
+                    track.is_default.or(batch_defaults.get(&id).copied())
+                },
+                is_forced: track.is_forced.filter(|_| track_type == "subtitle"),
+            })
+            .collect()
+    };
+    // Inputs after the source, one per external file, with the file's delay
+    let mut inputs: Vec<(String, Option<f64>)> = Vec::new();
+    let mut external_streams = |files: Vec<&(ExternalFileInfo, u64)>, track_type: &str| {
+        let mut streams = Vec::new();
+        for (file, track_id) in files {
+            let input = match inputs.iter().position(|(path, _)| *path == file.path) {
+                Some(position) => position + 1,
+                None => {
+                    inputs.push((file.path.clone(), file.delay));
+                    inputs.len()
+                }
+            };
+            streams.push(FfmpegStream {
+                map: format!("{input}:{track_id}"),
+                language: external_track_language(file, *track_id),
+                name: external_track_name(file, *track_id),
+                is_default: if default_flag_cleared(settings, track_type) {
+                    Some(false)
+                } else {
+                    file.is_default
+                },
+                is_forced: file.is_forced.filter(|_| track_type == "subtitle"),
+            });
+        }
+        streams
+    };
+    let external_audios = external_streams(externals.audios.iter().collect(), "audio");
+    let external_subtitles = external_streams(externals.all_subtitles().collect(), "subtitle");
+
+    let mut streams = source_streams("video");
+    streams.extend(external_audios);
+    streams.extend(source_streams("audio"));
+    let has_subtitles =
+        externals.all_subtitles().next().is_some() || !source_streams("subtitle").is_empty();
+    streams.extend(source_streams("subtitle"));
+    streams.extend(external_subtitles);
+
+    // Overwrites an existing output, as mkvmerge does
+    let mut args: Vec<String> = ["-hide_banner", "-nostdin", "-y", "-i", &job.video.path]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    for (path, delay) in &inputs {
+        if let Some(delay) = delay.filter(|delay| *delay != 0.0) {
+            args.push("-itsoffset".to_string());
+            args.push(delay.to_string());
+        }
+        args.push("-i".to_string());
+        args.push(path.clone());
+    }
+    for stream in &streams {
+        args.push("-map".to_string());
+        args.push(stream.map.clone());
+    }
+    args.push("-c".to_string());
+    args.push("copy".to_string());
+    if has_subtitles {
+        // MP4 only carries text subtitles as mov_text
+        args.push("-c:s".to_string());
+        args.push("mov_text".to_string());
+    }
+    if settings.discard_old_chapters {
+        args.push("-map_chapters".to_string());
+        args.push("-1".to_string());
+    }
+    if settings.remove_global_tags {
+        args.push("-map_metadata".to_string());
+        args.push("-1".to_string());
+    }
+    for (index, stream) in streams.iter().enumerate() {
+        if let Some(language) = &stream.language {
+            args.push(format!("-metadata:s:{index}"));
+            args.push(format!("language={language}"));
+        }
+        if let Some(name) = stream.name.as_ref().filter(|name| !name.trim().is_empty()) {
+            args.push(format!("-metadata:s:{index}"));
+            args.push(format!("title={name}"));
+        }
+        if stream.is_default.is_some() || stream.is_forced.is_some() {
+            let flags: Vec<&str> = [
+                stream.is_default.unwrap_or(false).then_some("default"),
+                stream.is_forced.unwrap_or(false).then_some("forced"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            args.push(format!("-disposition:{index}"));
+            args.push(if flags.is_empty() {
+                "0".to_string()
+            } else {
+                flags.join("+")
+            });
+        }
+    }
+    args.push(output_path.to_string_lossy().to_string());
+    args
+}
+
+/// Subtitle codecs MP4 can't carry; ffmpeg only writes text subtitles as mov_text.
+const MP4_UNSUPPORTED_SUBTITLES: [&str; 5] = ["ass", "ssa", "pgs", "vobsub", "sup"];
+
+/// Why a job can't be written as MP4, or nothing when it can.
+fn mp4_validation_errors(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<String> {
+    let mut errors = Vec::new();
+    if settings.output_container != OutputContainer::Mp4 {
+        return errors;
+    }
+    if job_destination_dir(job, settings).is_empty() || settings.overwrite_source {
+        // The source would be deleted with an `.mp4` left next to where it was
+        errors.push("MP4 output can't replace the source; choose a destination folder".to_string());
+    }
+    let unsupported = |codec: &str| {
+        let codec = codec.to_ascii_lowercase();
+        MP4_UNSUPPORTED_SUBTITLES
+            .iter()
+            .any(|name| codec.contains(name))
+            || codec.contains("hdmv")
+    };
+    let kept = kept_source_track_ids(job, settings);
+    for (index, track) in job.video.tracks.iter().enumerate() {
+        let id = parse_track_id(track, index);
+        if track.track_type != "subtitle" || !kept.contains(&id) {
+            continue;
+        }
+        let codec = track
+            .codec_id
+            .as_deref()
+            .or(track.codec.as_deref())
+            .unwrap_or("");
+        if unsupported(codec) {
+            errors.push(format!("MP4 can't hold {codec} subtitles (track {id})"));
+        }
+    }
+    for subtitle in &job.subtitles {
+        let ext = Path::new(&subtitle.path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        if unsupported(ext) || ext.eq_ignore_ascii_case("sub") || ext.eq_ignore_ascii_case("idx") {
+            errors.push(format!("MP4 can't hold the subtitles in {}", subtitle.path));
+        }
+    }
+    if job
+        .chapters
+        .iter()
+        .any(|chapter| chapter.source.as_deref() != Some("video"))
+    {
+        errors.push("Chapter files can only be muxed into MKV".to_string());
+    }
+    if !job.attachments.is_empty() || job.poster_image.is_some() {
+        errors.push("MP4 outputs can't carry attachments".to_string());
+    }
+    if settings.split.is_some() {
+        errors.push("Splitting needs MKV output".to_string());
+    }
+    if !job.append_sources.is_empty() {
+        errors.push("Appending parts needs MKV output".to_string());
+    }
+    errors
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobValidation {
+    job_id: String,
+    /// Problems that keep the job from running as asked.
+    errors: Vec<String>,
+    /// Settings that likely won't do what was meant; the job still runs.
+    warnings: Vec<String>,
+}
+
+/// For every job whose output path another job of the batch also writes, a message
+/// naming the other jobs. Paths are compared the way the file system would.
+fn duplicate_output_messages(
+    jobs: &[MuxJobRequest],
+    settings: &MuxSettings,
+) -> HashMap<String, String> {
+    let mut by_output: HashMap<PathBuf, Vec<(&str, PathBuf)>> = HashMap::new();
+    for job in jobs {
+        let (_, final_path, _) = get_output_paths(job, settings);
+        by_output
+            .entry(comparable_path(&final_path))
+            .or_default()
+            .push((job.id.as_str(), final_path));
+    }
+    let mut messages = HashMap::new();
+    for targets in by_output.values().filter(|targets| targets.len() > 1) {
+        for (job_id, path) in targets {
+            let others: Vec<&str> = targets
+                .iter()
+                .map(|(other, _)| *other)
+                .filter(|other| other != job_id)
+                .collect();
+            messages.insert(
+                job_id.to_string(),
+                format!(
+                    "Output {} is also written by job {}",
+                    path.to_string_lossy(),
+                    others.join(", ")
+                ),
+            );
+        }
+    }
+    messages
+}
+
+/// The pre-flight check of a batch: jobs the configured output container can't be
+/// written for, whose output would overwrite an input or another job's output, or whose
+/// settings look wrong for their files, with the reasons.
+fn validate_job_list(jobs: &[MuxJobRequest], settings: &MuxSettings) -> Vec<JobValidation> {
+    let mut duplicates = duplicate_output_messages(jobs, settings);
+    jobs.iter()
+        .map(|job| {
+            let (output_path, _, _) = get_output_paths(job, settings);
+            let mut errors = mp4_validation_errors(job, settings);
+            errors.extend(check_output_collision(job, &output_path).err());
+            errors.extend(duplicates.remove(&job.id));
+            errors.extend(poster_error(job));
+            errors.extend(undefined_language_policy_error(settings));
+            let mut warnings = language_setting_warnings(job, settings);
+            warnings.extend(external_default_warnings(job));
+            warnings.extend(fast_mux_warning(job, settings));
+            warnings.extend(mixed_default_subtitle_warning(job, settings));
+            warnings.extend(dolby_vision_warning(job));
+            JobValidation {
+                job_id: job.id.clone(),
+                errors,
+                warnings,
+            }
+        })
+        .filter(|validation| !validation.errors.is_empty() || !validation.warnings.is_empty())
+        .collect()
+}
+
+#[tauri::command]
+fn validate_jobs(request: MuxStartRequest) -> Vec<JobValidation> {
+    validate_job_list(&request.jobs, &request.settings)
+}
+
+/// Why the job's poster image can't be attached, if it has one.
+fn poster_error(job: &MuxJobRequest) -> Option<String> {
+    let poster = job.poster_image.as_deref()?;
+    poster_attachment(Path::new(poster)).err()
+}
+
+const FONT_EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+/// Attachment name and MIME type for a poster image, told apart by its leading bytes.
+fn poster_attachment(path: &Path) -> Result<(&'static str, &'static str), String> {
+    let mut header = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|e| format!("Poster image {} can't be read: {e}", path.to_string_lossy()))?;
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Ok(("cover.jpg", "image/jpeg"))
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Ok(("cover.png", "image/png"))
+    } else {
+        Err(format!(
+            "Poster image {} is not a JPEG or PNG",
+            path.to_string_lossy()
+        ))
+    }
+}
+
+/// MIME type for an attachment from its extension; players need it to recognise
+/// fonts and cover art.
+fn attachment_mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "ttc" => "font/collection",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "txt" | "nfo" => "text/plain",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A `type/subtype` pair without spaces; anything else is ignored as an override.
+fn is_valid_mime_type(value: &str) -> bool {
+    value.split_once('/').is_some_and(|(kind, subtype)| {
+        !kind.is_empty()
+            && !subtype.is_empty()
+            && !subtype.contains('/')
+            && !value.chars().any(char::is_whitespace)
+    })
+}
+
+/// Attachment files for a job; a directory attachment expands to the fonts directly inside it.
+/// Unless duplicates are allowed, later files with an already attached name are dropped.
+/// Each path comes with the attachment's MIME override; expanded fonts have none.
+fn attachment_paths(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<(String, Option<String>)> {
+    let mut paths = Vec::new();
+    for attachment in &job.attachments {
+        let path = Path::new(&attachment.path);
+        if path.is_dir() {
+            let mut fonts: Vec<(String, Option<String>)> = fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .map(|entry| entry.path())
+                        .filter(|font| {
+                            font.is_file()
+                                && font
+                                    .extension()
+                                    .and_then(|ext| ext.to_str())
+                                    .map(|ext| {
+                                        FONT_EXTENSIONS
+                                            .iter()
+                                            .any(|allowed| ext.eq_ignore_ascii_case(allowed))
+                                    })
+                                    .unwrap_or(false)
+                        })
+                        .map(|font| (font.to_string_lossy().to_string(), None))
+                        .collect()
+                })
+                .unwrap_or_default();
+            fonts.sort();
+            paths.extend(fonts);
+        } else {
+            paths.push((attachment.path.clone(), attachment.mime_type.clone()));
+        }
+    }
+
+    let attachment_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+    if !settings.allow_duplicate_attachments {
+        let mut seen = HashSet::new();
+        paths.retain(|(path, _)| seen.insert(attachment_name(path)));
+    }
+    if settings.sort_attachments {
+        paths.sort_by_cached_key(|(path, _)| (attachment_name(path), path.clone()));
+    }
+    paths
+}
+
+/// An attachment already inside a Matroska file, as `mkvmerge -J` lists it.
+#[derive(Debug, Clone, PartialEq)]
+struct EmbeddedAttachment {
+    id: u64,
+    name: String,
+}
+
+fn embedded_attachments(info: &serde_json::Value) -> Vec<EmbeddedAttachment> {
+    info.get("attachments")
+        .and_then(|items| items.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    Some(EmbeddedAttachment {
+                        id: item.get("id")?.as_u64()?,
+                        name: item.get("file_name")?.as_str()?.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Source attachment options when only replaced attachments are dropped: the embedded
+/// ones whose name (case-insensitive) no new attachment takes are copied. A poster
+/// replaces every embedded `cover.*`, whatever its extension. Without a listing every
+/// old attachment goes, as with plain `discard_old_attachments`.
+fn old_attachment_args(
+    embedded: Option<&[EmbeddedAttachment]>,
+    new_paths: &[(String, Option<String>)],
+    has_poster: bool,
+) -> Vec<String> {
+    let Some(embedded) = embedded else {
+        return vec!["--no-attachments".to_string()];
+    };
+    let new_names: HashSet<String> = new_paths
+        .iter()
+        .filter_map(|(path, _)| Path::new(path).file_name())
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .collect();
+    let is_cover = |name: &str| {
+        Path::new(name)
+            .file_stem()
+            .is_some_and(|stem| stem.eq_ignore_ascii_case("cover"))
+    };
+    let kept: Vec<String> = embedded
+        .iter()
+        .filter(|attachment| !new_names.contains(&attachment.name.to_lowercase()))
+        .filter(|attachment| !(has_poster && is_cover(&attachment.name)))
+        .map(|attachment| attachment.id.to_string())
+        .collect();
+    if kept.is_empty() {
+        vec!["--no-attachments".to_string()]
+    } else if kept.len() == embedded.len() {
+        Vec::new()
+    } else {
+        vec!["--attachments".to_string(), kept.join(",")]
+    }
+}
+
+/// mkvmerge prints its errors and warnings on stdout, so those count as diagnostics too.
+fn is_diagnostic_line(line: &str) -> bool {
+    ["Error", "Warning", "#GUI#error", "#GUI#warning"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+fn record_stderr_line(state: &AppState, job_id: &str, line: &str) {
+    if line.trim().is_empty() {
+        return;
+    }
+    let mut mux_state = state.mux_state.lock().unwrap();
+    let tail = mux_state
+        .stderr_tails
+        .entry(job_id.to_string())
+        .or_default();
+    if tail.len() == STDERR_TAIL_LINES {
+        tail.pop_front();
+    }
+    tail.push_back(line.to_string());
+}
+
+/// Removes and returns the captured diagnostic lines for a job.
+fn take_stderr_tail(state: &AppState, job_id: &str) -> Option<String> {
+    let tail = state
+        .mux_state
+        .lock()
+        .unwrap()
+        .stderr_tails
+        .remove(job_id)?;
+    Some(Vec::from(tail).join("\n"))
+}
+
+/// Why a job's input could not be opened, as found after mkvmerge reported it.
+#[derive(Debug, PartialEq)]
+enum InputProblem {
+    Missing,
+    Unreadable(String),
+}
+
+/// True when mkvmerge's diagnostics say it failed to open one of its inputs.
+fn is_unopenable_input_error(stderr_tail: &str) -> bool {
+    stderr_tail
+        .to_ascii_lowercase()
+        .contains("could not be opened")
+}
+
+/// The first of the job's inputs that no longer exists or can't be read, labelled by
+/// its role in the job.
+fn find_unopenable_input(job: &MuxJobRequest) -> Option<(&'static str, &str, InputProblem)> {
+    let externals = [
+        ("Audio file", &job.audios),
+        ("Subtitle file", &job.subtitles),
+        ("Chapter file", &job.chapters),
+        ("Attachment", &job.attachments),
+    ];
+    let inputs = std::iter::once(("Video file", job.video.path.as_str()))
+        .chain(
+            job.append_sources
+                .iter()
+                .map(|path| ("Appended part", path.as_str())),
+        )
+        .chain(externals.into_iter().flat_map(|(role, files)| {
+            files
+                .iter()
+                .map(move |external| (role, external.path.as_str()))
+        }))
+        .chain(
+            job.poster_image
+                .as_deref()
+                .map(|path| ("Poster image", path)),
+        );
+    inputs.into_iter().find_map(|(role, path)| {
+        let problem = match File::open(path) {
+            Ok(_) => return None,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => InputProblem::Missing,
+            Err(err) => InputProblem::Unreadable(err.to_string()),
+        };
+        Some((role, path, problem))
+    })
+}
+
+/// A readable replacement for mkvmerge's exit code when it couldn't open an input,
+/// naming the input that went missing or unreadable since the scan.
+fn unopenable_input_message(job: &MuxJobRequest, stderr_tail: Option<&str>) -> Option<String> {
+    if !stderr_tail.is_some_and(is_unopenable_input_error) {
+        return None;
+    }
+    let message = match find_unopenable_input(job) {
+        Some((role, path, InputProblem::Missing)) => {
+            format!("{role} {path} no longer exists")
+        }
+        Some((role, path, InputProblem::Unreadable(reason))) => {
+            format!("{role} {path} can't be read: {reason}")
+        }
+        None => "mkvmerge could not open one of the inputs, but all of them exist and are readable"
+            .to_string(),
+    };
+    Some(message)
+}
+
+/// Parses a progress line and returns it only when it is ahead of the last emitted value,
+/// smoothed ones included, so the bar never moves back.
+fn next_progress(state: &AppState, job_id: &str, line: &str) -> Option<u8> {
+    let progress = parse_progress(line)?;
+    let mut mux_state = state.mux_state.lock().unwrap();
+    let last = mux_state.last_progress.get(job_id).copied();
+    if last.is_some_and(|last| progress <= last) {
+        return None;
+    }
+    mux_state.last_progress.insert(job_id.to_string(), progress);
+    Some(progress)
+}
+
+/// Clears the job's progress tracking; true when 100% was never emitted for it.
+fn final_progress_pending(state: &AppState, job_id: &str) -> bool {
+    state.mux_state.lock().unwrap().last_progress.remove(job_id) != Some(100)
+}
+
+/// Logs every line a tool prints, keeps diagnostics for the error event and hands each
+/// line (with any new progress value) to `on_line`.
+fn drain_tool_output<R: Read>(
+    reader: R,
+    state: &AppState,
+    job_id: &str,
+    is_stderr: bool,
+    mut on_line: impl FnMut(&str, Option<u8>),
+) {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    while let Ok(bytes) = reader.read_line(&mut line) {
+        if bytes == 0 {
+            break;
+        }
+        let trimmed = line.trim_end();
+        let _ = write_log_line(&state.paths, trimmed);
+        if is_stderr || is_diagnostic_line(trimmed) {
+            record_stderr_line(state, job_id, trimmed);
+        }
+        on_line(trimmed, next_progress(state, job_id, trimmed));
+        line.clear();
+    }
+}
+
+fn spawn_log_reader<R: Read + Send + 'static>(
+    reader: R,
+    app: AppHandle,
+    state: AppState,
+    job_id: String,
+    is_stderr: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        drain_tool_output(reader, &state, &job_id, is_stderr, |line, progress| {
+            if let Some(progress) = progress {
+                emit_progress(
+                    &app,
+                    MuxProgressEvent {
+                        job_id: job_id.clone(),
+                        status: JobPhase::Processing,
+                        progress,
+                        message: None,
+                        size_after: None,
+                        error_message: None,
+                        error_detail: None,
+                        elapsed_ms: None,
+                    },
+                );
+            }
+            let _ = app.emit_all(
+                "mux-log",
+                serde_json::json!({ "job_id": job_id, "line": line }),
+            );
+        });
+    })
+}
+
+/// How often the progress smoother checks the output's size.
+const SMOOTH_PROGRESS_POLL: Duration = Duration::from_millis(500);
+
+/// Bytes written so far to the output, or to the parts written `since` when splitting.
+fn written_output_bytes(output_path: &Path, since: SystemTime) -> u64 {
+    match fs::metadata(output_path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => split_part_paths(output_path, since)
+            .iter()
+            .filter_map(|part| fs::metadata(part).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+    }
+}
+
+/// A progress value estimated from how much of `expected_bytes` the output holds, when
+/// it is ahead of the last emitted one. Recorded like a real report, so a later real
+/// report behind it is dropped. Stays under 100 so only mkvmerge itself reports
+/// completion.
+fn poll_smoothed_progress(
+    state: &AppState,
+    job_id: &str,
+    output_path: &Path,
+    since: SystemTime,
+    expected_bytes: u64,
+) -> Option<u8> {
+    if expected_bytes == 0 {
+        return None;
+    }
+    let written = written_output_bytes(output_path, since) as u128;
+    let estimate = (written * 100 / expected_bytes as u128).min(99) as u8;
+    let mut mux_state = state.mux_state.lock().unwrap();
+    let last = mux_state.last_progress.get(job_id).copied().unwrap_or(0);
+    if estimate <= last {
+        return None;
+    }
+    mux_state.last_progress.insert(job_id.to_string(), estimate);
+    Some(estimate)
+}
+
+/// Emits smoothed progress for a running job until `done` is set.
+fn spawn_progress_smoother(
+    app: AppHandle,
+    state: AppState,
+    job_id: String,
+    output_path: PathBuf,
+    since: SystemTime,
+    expected_bytes: u64,
+    done: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !done.load(Ordering::Relaxed) {
+            let smoothed =
+                poll_smoothed_progress(&state, &job_id, &output_path, since, expected_bytes);
+            if let Some(progress) = smoothed {
+                emit_progress(
+                    &app,
+                    MuxProgressEvent {
+                        job_id: job_id.clone(),
+                        status: JobPhase::Processing,
+                        progress,
+                        message: None,
+                        size_after: None,
+                        error_message: None,
+                        error_detail: None,
+                        elapsed_ms: None,
+                    },
+                );
+            }
+            thread::sleep(SMOOTH_PROGRESS_POLL);
+        }
+    })
+}
+
+/// A running child process plus the threads draining its stdout/stderr.
+struct SpawnedProcess {
+    child: Arc<Mutex<Child>>,
+    readers: Vec<thread::JoinHandle<()>>,
+}
+
+fn run_command_with_logs(
+    app: &AppHandle,
+    state: &AppState,
+    job: &MuxJobRequest,
+    command: &mut Command,
+) -> Result<SpawnedProcess, String> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start process: {e}"))?;
+
+    bind_child_to_app(&child);
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let handle = Arc::new(Mutex::new(child));
+    {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        mux_state.children.insert(job.id.clone(), handle.clone());
+    }
+
+    {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        mux_state.stderr_tails.remove(&job.id);
+        mux_state.last_progress.remove(&job.id);
+    }
+    let mut readers = Vec::new();
+    if let Some(out) = stdout {
+        readers.push(spawn_log_reader(
+            out,
+            app.clone(),
+            state.clone(),
+            job.id.clone(),
+            false,
+        ));
+    }
+    if let Some(err) = stderr {
+        readers.push(spawn_log_reader(
+            err,
+            app.clone(),
+            state.clone(),
+            job.id.clone(),
+            true,
+        ));
+    }
+
+    Ok(SpawnedProcess {
+        child: handle,
+        readers,
+    })
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Why a job should not run at all, or `None` when it should.
+fn skip_reason(settings: &MuxSettings, overwrite_mode: bool, final_path: &Path) -> Option<String> {
+    (settings.skip_existing && !overwrite_mode && final_path.exists())
+        .then(|| format!("Output already exists: {}", final_path.to_string_lossy()))
+}
+
+/// Terminal event for a job that never ran, kept apart from `completed` so it isn't counted.
+fn skipped_event(job_id: &str, reason: String, started: Instant) -> MuxProgressEvent {
+    MuxProgressEvent {
+        job_id: job_id.to_string(),
+        status: JobPhase::Skipped,
+        progress: 100,
+        message: Some(reason),
+        size_after: None,
+        error_message: None,
+        error_detail: None,
+        elapsed_ms: Some(elapsed_ms(started)),
+    }
+}
+
+/// mkvpropedit rarely prints progress, so the fast path reports the halfway mark once it runs.
+/// Recorded like a real report, so mkvpropedit's own lines at or below it aren't emitted.
+fn fast_mux_started_event(state: &AppState, job_id: &str) -> MuxProgressEvent {
+    let progress = 50;
+    state
+        .mux_state
+        .lock()
+        .unwrap()
+        .last_progress
+        .insert(job_id.to_string(), progress);
+    MuxProgressEvent {
+        job_id: job_id.to_string(),
+        status: JobPhase::Processing,
+        progress,
+        message: Some("Editing track properties".to_string()),
+        size_after: None,
+        error_message: None,
+        error_detail: None,
+        elapsed_ms: None,
+    }
+}
+
+fn emit_progress(app: &AppHandle, event: MuxProgressEvent) {
+    let _ = app.emit_all("mux-progress", event);
+}
+
+/// Waits for the child to exit. Returns `None` when it was killed because of a stop request,
+/// so a killed process is never mistaken for one that exited with a warning code.
+fn wait_for_child_or_stop(handle: Arc<Mutex<Child>>, state: &AppState) -> Option<i32> {
+    wait_for_child_until(handle, state, None)
+}
+
+/// `wait_for_child_or_stop`, also killing the child once `deadline` passes.
+fn wait_for_child_until(
+    handle: Arc<Mutex<Child>>,
+    state: &AppState,
+    deadline: Option<Instant>,
+) -> Option<i32> {
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            if let Ok(mut child) = handle.lock() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            return None;
+        }
+        {
+            let mux_state = state.mux_state.lock().unwrap();
+            if mux_state.stop {
+                drop(mux_state);
+                if let Ok(mut child) = handle.lock() {
+                    if let Ok(Some(status)) = child.try_wait() {
+                        return status.code();
+                    }
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return None;
+            }
+        }
+
+        let status = {
+            let mut child = handle.lock().unwrap();
+            match child.try_wait() {
+                Ok(Some(status)) => return status.code(),
+                Ok(None) => None,
+                Err(_) => return None,
+            }
+        };
+
+        if status.is_some() {
+            return status;
+        }
+
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Parses `#GUI#progress 45%` (gui mode) as well as plain `Progress: 45%` lines, clamped to 0-100.
+fn parse_progress(line: &str) -> Option<u8> {
+    let line = line.strip_prefix("#GUI#progress").unwrap_or(line);
+    let percent_pos = line.find('%')?;
+    let start = line[..percent_pos]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    let value = line[start..percent_pos].trim().parse::<u32>().ok()?;
+    Some(value.min(100) as u8)
+}
+
+/// Moves the finished output into place, applies CRC naming and reports completion.
+/// Emits `finalizing` events (with CRC read progress) before the final `completed`.
+#[allow(clippy::too_many_arguments)]
+fn finalize_job(
+    state: &AppState,
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    output_path: &Path,
+    final_path: &Path,
+    overwrite_mode: bool,
+    started: Instant,
+    mux_ms: u64,
+    emit: &mut dyn FnMut(MuxProgressEvent),
+) {
+    let finalizing = |progress: u8, message: &str| MuxProgressEvent {
+        job_id: job.id.clone(),
+        status: JobPhase::Finalizing,
+        progress,
+        message: Some(message.to_string()),
+        size_after: None,
+        error_message: None,
+        error_detail: None,
+        elapsed_ms: None,
+    };
+    emit(finalizing(0, "Finalizing output"));
+    let mut crc_progress = |percent: u8| emit(finalizing(percent, "Computing CRC"));
+    let stop_requested = || state.mux_state.lock().unwrap().stop;
+
+    let split_parts = if settings.split.is_some() {
+        let parts = split_part_paths(output_path, wall_clock_at(started));
+        let promoted = if overwrite_mode {
+            promote_split_parts(&parts, output_path, final_path, Path::new(&job.video.path))
+        } else if parts.is_empty() {
+            Err("mkvmerge wrote no split parts".to_string())
+        } else {
+            Ok(parts)
+        };
+        match promoted {
+            Ok(parts) => parts,
+            Err(err) => {
+                let _ = write_log_line(&state.paths, &format!("Job {} failed: {err}", job.id));
+                emit(MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 100,
+                    message: Some("Split output incomplete".to_string()),
+                    size_after: None,
+                    error_message: Some(err),
+                    error_detail: None,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                });
+                pause_on_error(state, settings, job, JobErrorKind::MuxFailed);
+                return;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    // Before CRC naming, so the checksum covers the edited file
+    if split_parts.is_empty() {
+        apply_muxing_app(state, &job.id, settings, &[output_path.to_path_buf()]);
+    } else {
+        apply_muxing_app(state, &job.id, settings, &split_parts);
+    }
+    let mut outputs = Vec::new();
+    let size_after = if split_parts.is_empty() {
+        // The temp output is byte-identical to what lands at `final_path`
+        let early_target = (overwrite_mode && settings.crc_on_temp_output)
+            .then(|| {
+                crc_tagged_path(
+                    output_path,
+                    final_path,
+                    settings,
+                    &mut crc_progress,
+                    &stop_requested,
+                )
+            })
+            .flatten();
+        let final_output = match early_target {
+            Some(target) => {
+                promote_temp_output(output_path, &target, Path::new(&job.video.path));
+                target
+            }
+            None => {
+                // Appended parts are kept: only the video the job names is replaced
+                if overwrite_mode && output_path.exists() {
+                    promote_temp_output(output_path, final_path, Path::new(&job.video.path));
+                }
+                apply_crc_naming(final_path, settings, &mut crc_progress, &stop_requested)
+            }
+        };
+        if overwrite_mode && settings.add_crc {
+            if let Some(line) = crc_change_log_line(&job.id, final_path, &final_output) {
+                let _ = write_log_line(&state.paths, &line);
+            }
+        }
+        let size = fs::metadata(&final_output).map(|m| m.len()).ok();
+        outputs.push(final_output);
+        size
+    } else {
+        // Split outputs: every part is finalized on its own and the sizes are summed
+        let mut total = 0u64;
+        for part in &split_parts {
+            let part_output = apply_crc_naming(part, settings, &mut crc_progress, &stop_requested);
+            let _ = write_log_line(
+                &state.paths,
+                &format!("Split part: {}", part_output.to_string_lossy()),
+            );
+            total += fs::metadata(&part_output).map(|m| m.len()).unwrap_or(0);
+            outputs.push(part_output);
+        }
+        Some(total)
+    };
+
+    if settings.add_crc {
+        for output in outputs
+            .iter()
+            .filter(|output| crc_size_limit_exceeded(output, settings))
+        {
+            let _ = write_log_line(
+                &state.paths,
+                &format!(
+                    "Job {}: CRC skipped for {}, larger than {} bytes",
+                    job.id,
+                    output.to_string_lossy(),
+                    settings.crc_max_size_bytes.unwrap_or_default()
+                ),
+            );
+        }
+    }
+
+    // The output is complete but a stop cut its finalizing short (e.g. mid-CRC)
+    if stop_requested() {
+        emit(MuxProgressEvent {
+            job_id: job.id.clone(),
+            status: JobPhase::Stopped,
+            progress: 100,
+            message: Some("Stopped while finalizing".to_string()),
+            size_after,
+            error_message: None,
+            error_detail: None,
+            elapsed_ms: Some(elapsed_ms(started)),
+        });
+        let _ = write_log_line(
+            &state.paths,
+            &format!("Job {} stopped while finalizing", job.id),
+        );
+        return;
+    }
+
+    if let Some(err) = post_mux_failure(state, job, settings, &outputs) {
+        emit(MuxProgressEvent {
+            job_id: job.id.clone(),
+            status: JobPhase::Error,
+            progress: 100,
+            message: Some("Post-mux command failed".to_string()),
+            size_after,
+            error_message: Some(err),
+            error_detail: None,
+            elapsed_ms: Some(elapsed_ms(started)),
+        });
+        pause_on_error(state, settings, job, JobErrorKind::MuxFailed);
+        return;
+    }
+    if settings.write_nfo_sidecar {
+        write_output_sidecars(state, job, &outputs);
+    }
+
+    record_finished_outputs(state, &outputs);
+    record_completed_job(
+        state,
+        job.video.size,
+        size_after,
+        Some((job_input_bytes(job), mux_ms)),
+    );
+    emit(MuxProgressEvent {
+        job_id: job.id.clone(),
+        status: JobPhase::Completed,
+        progress: 100,
+        message: Some("Muxing completed".to_string()),
+        size_after,
+        error_message: None,
+        error_detail: None,
+        elapsed_ms: Some(elapsed_ms(started)),
+    });
+    let _ = write_log_line(
+        &state.paths,
+        &format!(
+            "Job {} completed successfully in {} ms",
+            job.id,
+            elapsed_ms(started)
+        ),
+    );
+}
+
+/// Why fast muxing can't handle the job, or `None` when it can. mkvpropedit only edits
+/// metadata in place, so anything that adds, drops or rewrites data needs mkvmerge.
+fn fast_mux_blocker(job: &MuxJobRequest, settings: &MuxSettings) -> Option<&'static str> {
+    if settings.output_container != OutputContainer::Mkv {
+        Some("MP4 outputs are written by ffmpeg")
+    } else if !job_destination_dir(job, settings).is_empty() || !settings.overwrite_source {
+        Some("it edits files in place, but this job writes a new output file")
+    } else if settings.split.is_some() {
+        Some("splitting needs mkvmerge")
+    } else if !job.append_sources.is_empty() {
+        Some("appending parts needs mkvmerge")
+    } else if settings.remove_track_tags {
+        Some("removing track tags needs mkvmerge")
+    } else if job
+        .video
+        .tracks
+        .iter()
+        .any(|track| track.delay.is_some_and(|delay| delay != 0.0))
+    {
+        Some("delaying a track needs mkvmerge")
+    } else if !job.audios.is_empty()
+        || !job.subtitles.is_empty()
+        || !job.chapters.is_empty()
+        || !job.attachments.is_empty()
+        || job.poster_image.is_some()
+    {
+        Some("the job adds external files")
+    } else if job.video.tracks.iter().enumerate().any(|(index, track)| {
+        settings
+            .block_source_track_ids
+            .contains(&parse_track_id(track, index))
+    }) {
+        Some("blocking source tracks removes tracks")
+    } else if settings.undefined_language_policy == UndefinedPolicy::Drop
+        && job.video.tracks.iter().any(|track| {
+            matches!(track.track_type.as_str(), "audio" | "subtitle")
+                && !is_track_removed(track)
+                && is_undefined_language(track.language.as_deref())
+        })
+    {
+        Some("dropping undetermined-language tracks removes tracks")
+    } else if !keep_track_name_patterns(job, settings).is_empty() {
+        Some("keeping only tracks with matching names removes tracks")
+    } else if (settings.only_keep_audios_enabled && !settings.only_keep_audio_languages.is_empty())
+        || (settings.only_keep_subtitles_enabled
+            && !settings.only_keep_subtitle_languages.is_empty())
+    {
+        Some("keeping only some languages removes tracks")
+    } else if !settings.keep_audio_codecs.is_empty() || !settings.keep_subtitle_codecs.is_empty() {
+        Some("keeping only some codecs removes tracks")
+    } else {
+        None
+    }
+}
+
+/// Remux speed assumed until a batch has been timed, roughly a hard drive's.
+const DEFAULT_REMUX_BYTES_PER_SEC: f64 = 100.0 * 1024.0 * 1024.0;
+/// Time allowed for a fast mux, which only rewrites headers in place.
+const FAST_MUX_ESTIMATE_SECS: f64 = 1.0;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchTimeEstimate {
+    seconds: f64,
+    bytes_per_second: f64,
+    /// Whether `bytes_per_second` comes from `stats.json` rather than the default guess.
+    from_history: bool,
+    fast_mux_jobs: usize,
+}
+
+fn job_input_bytes(job: &MuxJobRequest) -> u64 {
+    let externals = job
+        .audios
+        .iter()
+        .chain(&job.subtitles)
+        .chain(&job.chapters)
+        .chain(&job.attachments)
+        .filter_map(|external| external.size);
+    let parts = job
+        .append_sources
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok().map(|m| m.len()));
+    job.video.size + externals.sum::<u64>() + parts.sum::<u64>()
+}
+
+/// Rough run time of a batch: each job's input size at the historical remux speed,
+/// handed out in queue order to whichever of the `max_parallel_jobs` workers frees up first.
+fn estimate_batch(
+    jobs: &[MuxJobRequest],
+    settings: &MuxSettings,
+    stats: &UsageStats,
+) -> BatchTimeEstimate {
+    let history = stats.remux_bytes_per_second();
+    let bytes_per_second = history.unwrap_or(DEFAULT_REMUX_BYTES_PER_SEC);
+    let mut workers = vec![0.0f64; settings.max_parallel_jobs.unwrap_or(1).max(1)];
+    let mut fast_mux_jobs = 0;
+    for job in jobs {
+        let seconds = if settings.use_mkvpropedit && fast_mux_blocker(job, settings).is_none() {
+            fast_mux_jobs += 1;
+            FAST_MUX_ESTIMATE_SECS
+        } else {
+            job_input_bytes(job) as f64 / bytes_per_second
+        };
+        if let Some(worker) = workers.iter_mut().min_by(|a, b| a.total_cmp(b)) {
+            *worker += seconds;
+        }
+    }
+    BatchTimeEstimate {
+        seconds: workers.into_iter().fold(0.0, f64::max),
+        bytes_per_second,
+        from_history: history.is_some(),
+        fast_mux_jobs,
+    }
+}
+
+#[tauri::command]
+fn estimate_batch_time(
+    state: State<AppState>,
+    jobs: Vec<MuxJobRequest>,
+    settings: MuxSettings,
+) -> Result<BatchTimeEstimate, String> {
+    let stats = read_stats(&state.paths.stats_path).unwrap_or_default();
+    Ok(estimate_batch(&jobs, &settings, &stats))
+}
+
+/// Explains why a job that asked for fast muxing falls back to mkvmerge.
+fn fast_mux_warning(job: &MuxJobRequest, settings: &MuxSettings) -> Option<String> {
+    if !settings.use_mkvpropedit {
+        return None;
+    }
+    fast_mux_blocker(job, settings)
+        .map(|reason| format!("Fast mux disabled for {}: {reason}", job.video.name))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ExpectedTrack {
+    track_type: String,
+    language: Option<String>,
+    label: String,
+}
+
+/// How many tracks an external file contributes, without probing it. Files whose
+/// tracks aren't listed count as one, so multi-track files are never over-counted.
+fn expected_external_track_count(file: &ExternalFileInfo) -> usize {
+    match &file.included_track_ids {
+        Some(ids) if file.first_track_only => ids.len().min(1),
+        Some(ids) => ids.len(),
+        None if file.source.as_deref() == Some(VIDEO_AUDIO_SOURCE) && file.track_id.is_none() => 0,
+        None => 1,
+    }
+}
+
+/// The tracks a job should produce: kept source tracks, then every external track.
+fn expected_output_tracks(job: &MuxJobRequest, settings: &MuxSettings) -> Vec<ExpectedTrack> {
+    let adjusted = adjusted_job(job, settings);
+    let job = adjusted.as_ref().unwrap_or(job);
+    let mut expected = Vec::new();
+    let kept = kept_source_track_ids(job, settings);
+    for (index, track) in job.video.tracks.iter().enumerate() {
+        if !matches!(track.track_type.as_str(), "video" | "audio" | "subtitle")
+            || !kept.contains(&parse_track_id(track, index))
+        {
+            continue;
+        }
+        expected.push(ExpectedTrack {
+            track_type: track.track_type.clone(),
+            language: track.language.clone(),
+            label: format!(
+                "{} track {} of {}",
+                track.track_type,
+                parse_track_id(track, index),
+                job.video.name
+            ),
+        });
+    }
+    let externals = job
+        .audios
+        .iter()
+        .map(|file| ("audio", file))
+        .chain(job.subtitles.iter().map(|file| ("subtitle", file)));
+    for (track_type, file) in externals {
+        for index in 0..expected_external_track_count(file) {
+            expected.push(ExpectedTrack {
+                track_type: track_type.to_string(),
+                // Only the first track of an external file gets its language
+                language: file.language.clone().filter(|_| index == 0),
+                label: format!("{track_type} from {}", file.name),
+            });
+        }
+    }
+    expected
+}
+
+/// Lists expected tracks the output doesn't have. Tracks are paired by type, preferring
+/// a matching language, so the leftovers name what mkvmerge skipped.
+fn dropped_track_warning(expected: &[ExpectedTrack], output: &[TrackInfo]) -> Option<String> {
+    let mut remaining: Vec<&TrackInfo> = output.iter().collect();
+    let mut unmatched: Vec<&ExpectedTrack> = Vec::new();
+    for track in expected.iter().filter(|track| track.language.is_some()) {
+        let found = remaining.iter().position(|candidate| {
+            candidate.track_type == track.track_type
+                && candidate
+                    .language
+                    .as_deref()
+                    .zip(track.language.as_deref())
+                    .is_some_and(|(left, right)| left.eq_ignore_ascii_case(right))
+        });
+        match found {
+            Some(position) => {
+                remaining.remove(position);
+            }
+            None => unmatched.push(track),
+        }
+    }
+    unmatched.extend(expected.iter().filter(|track| track.language.is_none()));
+    let mut dropped: Vec<&str> = Vec::new();
+    for track in unmatched {
+        match remaining
+            .iter()
+            .position(|candidate| candidate.track_type == track.track_type)
+        {
+            Some(position) => {
+                remaining.remove(position);
+            }
+            None => dropped.push(&track.label),
+        }
+    }
+    if dropped.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "Output has {} of {} expected tracks; missing: {}",
+        expected.len() - dropped.len(),
+        expected.len(),
+        dropped.join(", ")
+    ))
+}
+
+/// Pauses the queue after a failed job when the settings say this kind of failure
+/// should stop the batch. A job's own `abort_on_errors` wins over the batch setting.
+fn pause_on_error(
+    state: &AppState,
+    settings: &MuxSettings,
+    job: &MuxJobRequest,
+    kind: JobErrorKind,
+) {
+    let abort = job.abort_on_errors.unwrap_or(settings.abort_on_errors);
+    if abort && settings.abort_on.contains(&kind) {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        mux_state.pause = true;
+    }
+}
+
+/// Stands in for `process_job` when `dry_run` is set: the command is built and logged,
+/// then the job is reported completed. Nothing is spawned, created or renamed.
+fn dry_run_job(
+    state: &AppState,
+    settings: &MuxSettings,
+    job: &MuxJobRequest,
+    emit: &mut dyn FnMut(MuxProgressEvent),
+) {
+    let started = Instant::now();
+    let (output_path, final_path, _) = get_output_paths(job, settings);
+    let mut job = job.clone();
+    probe_job_inputs(&mut job, settings);
+    let (tool, args) = build_job_command(&job, settings, &output_path, state);
+    let command_line = join_tool_command(tool, &args);
+    let _ = write_log_line(
+        &state.paths,
+        &format!(
+            "Dry run: job {} would write {}",
+            job.id,
+            final_path.to_string_lossy()
+        ),
+    );
+    let _ = write_log_line(&state.paths, &format!("Dry run: {command_line}"));
+    emit(MuxProgressEvent {
+        job_id: job.id.clone(),
+        status: JobPhase::Completed,
+        progress: 100,
+        message: Some("Dry run: nothing was written".to_string()),
+        size_after: None,
+        error_message: None,
+        error_detail: None,
+        elapsed_ms: Some(elapsed_ms(started)),
+    });
+}
+
+fn process_job(app: &AppHandle, state: &AppState, settings: &MuxSettings, mut job: MuxJobRequest) {
+    if state.mux_state.lock().unwrap().stop {
+        return;
+    }
+    if settings.dry_run {
+        dry_run_job(state, settings, &job, &mut |event| {
+            emit_progress(app, event)
+        });
+        return;
+    }
+    let started = Instant::now();
+
+    emit_progress(
+        app,
+        MuxProgressEvent {
+            job_id: job.id.clone(),
+            status: JobPhase::Processing,
+            progress: 0,
+            message: Some(
+                fast_mux_warning(&job, settings).unwrap_or_else(|| "Starting muxing".to_string()),
+            ),
+            size_after: None,
+            error_message: None,
+            error_detail: None,
+            elapsed_ms: None,
+        },
+    );
+    let _ = write_log_line(
+        &state.paths,
+        &format!("Starting job {} for {}", job.id, job.video.path),
+    );
+
+    let output_dir = match prepare_output_dir(&job, settings) {
+        Ok(dir) => dir,
+        Err(err) => {
+            emit_progress(
+                app,
+                MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 0,
+                    message: Some("Cannot create destination folder".to_string()),
+                    size_after: None,
+                    error_message: Some(err),
+                    error_detail: None,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                },
+            );
+            pause_on_error(state, settings, &job, JobErrorKind::Destination);
+            return;
+        }
+    };
+    if let Err(err) = check_free_space(&output_dir, job_input_bytes(&job)) {
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some("Low disk space".to_string()),
+                size_after: None,
+                error_message: Some(err),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::DiskSpace);
+        return;
+    }
+
+    if job_destination_dir(&job, settings).is_empty() && !settings.overwrite_source {
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some("Destination folder required".to_string()),
+                size_after: None,
+                error_message: Some(
+                    "Set a destination folder or enable overwrite source.".to_string(),
+                ),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::Destination);
+        return;
+    }
+
+    let (output_path, final_path, overwrite_mode) = get_output_paths(&job, settings);
+    if let Some(note) = filename_truncation_note(&job, settings) {
+        let _ = write_log_line(&state.paths, &note);
+    }
+    if let Err(err) = check_output_collision(&job, &output_path) {
+        let _ = write_log_line(&state.paths, &format!("Job {} refused: {}", job.id, err));
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some("Output would overwrite an input".to_string()),
+                size_after: None,
+                error_message: Some(err),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::OutputCollision);
+        return;
+    }
+    if let Some(err) = poster_error(&job) {
+        let _ = write_log_line(&state.paths, &format!("Job {} refused: {}", job.id, err));
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some("Poster image unusable".to_string()),
+                size_after: None,
+                error_message: Some(err),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::SourceCorrupt);
+        return;
+    }
+    if let Some(reason) = skip_reason(settings, overwrite_mode, &final_path) {
+        let _ = write_log_line(&state.paths, &format!("Job {} skipped: {}", job.id, reason));
+        emit_progress(app, skipped_event(&job.id, reason, started));
+        return;
+    }
+    if settings.verify_input_checksums {
+        if let Err(err) =
+            job_input_paths(&job).try_for_each(|input| verify_input_checksum(Path::new(input)))
+        {
+            let _ = write_log_line(&state.paths, &format!("Job {} refused: {}", job.id, err));
+            emit_progress(
+                app,
+                MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 0,
+                    message: Some("Source corrupt".to_string()),
+                    size_after: None,
+                    error_message: Some(err),
+                    error_detail: None,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                },
+            );
+            pause_on_error(state, settings, &job, JobErrorKind::SourceCorrupt);
+            return;
+        }
+    }
+    let _ = write_log_line(
+        &state.paths,
+        &format!("Output path: {}", output_path.to_string_lossy()),
+    );
+    let fast_mux_disabled = fast_mux_warning(&job, settings);
+    let can_use_mkvpropedit = settings.use_mkvpropedit && fast_mux_disabled.is_none();
+    if let Some(warning) = &fast_mux_disabled {
+        let _ = write_log_line(&state.paths, warning);
+    }
+    for warning in skipped_audio_forced_warnings(&job, state) {
+        let _ = write_log_line(&state.paths, &warning);
+    }
+
+    if can_use_mkvpropedit {
+        if !tool_available("mkvpropedit", "-V") {
+            emit_progress(
+                app,
+                MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 0,
+                    message: Some("mkvpropedit not found".to_string()),
+                    size_after: None,
+                    error_message: Some("Install mkvpropedit or disable fast muxing.".to_string()),
+                    error_detail: None,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                },
+            );
+            return;
+        }
+
+        let edit_args = build_mkvpropedit_args(&job, settings);
+        if !edit_args.is_empty() {
+            let full_command = format!("mkvpropedit {} {}", job.video.path, edit_args.join(" "));
+            let _ = write_log_line(&state.paths, &full_command);
+            let _ = app.emit_all(
+                "mux-log",
+                serde_json::json!({ "job_id": job.id, "line": full_command }),
+            );
+
+            let mut cmd = hidden_command("mkvpropedit");
+            cmd.arg(&job.video.path);
+            for arg in edit_args {
+                cmd.arg(arg);
+            }
+
+            let SpawnedProcess {
+                child: handle,
+                readers,
+            } = match run_command_with_logs(app, state, &job, &mut cmd) {
+                Ok(spawned) => spawned,
+                Err(err) => {
+                    emit_progress(
+                        app,
+                        MuxProgressEvent {
+                            job_id: job.id.clone(),
+                            status: JobPhase::Error,
+                            progress: 0,
+                            message: Some("Failed to start mkvpropedit".to_string()),
+                            size_after: None,
+                            error_message: Some(err),
+                            error_detail: None,
+                            elapsed_ms: Some(elapsed_ms(started)),
+                        },
+                    );
+                    return;
+                }
+            };
+            emit_progress(app, fast_mux_started_event(state, &job.id));
+
+            let status = wait_for_child_or_stop(handle.clone(), state);
+            for reader in readers {
+                let _ = reader.join();
+            }
+            let stderr_tail = take_stderr_tail(state, &job.id);
+            {
+                let mut mux_state = state.mux_state.lock().unwrap();
+                mux_state.children.remove(&job.id);
+                mux_state.last_progress.remove(&job.id);
+            }
+
+            #[allow(clippy::redundant_guards)]
+            match status {
+                Some(code) if code == 0 => {
+                    let final_size = fs::metadata(&job.video.path).ok().map(|m| m.len());
+                    let edited = [PathBuf::from(&job.video.path)];
+                    if let Some(err) = post_mux_failure(state, &job, settings, &edited) {
+                        emit_progress(
+                            app,
+                            MuxProgressEvent {
+                                job_id: job.id.clone(),
+                                status: JobPhase::Error,
+                                progress: 100,
+                                message: Some("Post-mux command failed".to_string()),
+                                size_after: final_size,
+                                error_message: Some(err),
+                                error_detail: None,
+                                elapsed_ms: Some(elapsed_ms(started)),
+                            },
+                        );
+                        pause_on_error(state, settings, &job, JobErrorKind::MuxFailed);
+                        return;
+                    }
+                    record_completed_job(state, job.video.size, final_size, None);
+                    emit_progress(
+                        app,
+                        MuxProgressEvent {
+                            job_id: job.id.clone(),
+                            status: JobPhase::Completed,
+                            progress: 100,
+                            message: Some("Fast mux completed".to_string()),
+                            size_after: final_size,
+                            error_message: None,
+                            error_detail: None,
+                            elapsed_ms: Some(elapsed_ms(started)),
+                        },
+                    );
+                }
+                Some(code) => {
+                    let error_output = format!("mkvpropedit exited with code: {code}");
+                    let _ = write_log_line(&state.paths, &error_output);
+                    emit_progress(
+                        app,
+                        MuxProgressEvent {
+                            job_id: job.id.clone(),
+                            status: JobPhase::Error,
+                            progress: 0,
+                            message: Some("mkvpropedit failed".to_string()),
+                            size_after: None,
+                            error_message: Some(error_output),
+                            error_detail: stderr_tail,
+                            elapsed_ms: Some(elapsed_ms(started)),
+                        },
+                    );
+                }
+                None => {
+                    emit_progress(
+                        app,
+                        MuxProgressEvent {
+                            job_id: job.id.clone(),
+                            status: JobPhase::Error,
+                            progress: 0,
+                            message: Some("mkvpropedit error".to_string()),
+                            size_after: None,
+                            error_message: Some("Failed to wait for mkvpropedit".to_string()),
+                            error_detail: None,
+                            elapsed_ms: Some(elapsed_ms(started)),
+                        },
+                    );
+                }
+            }
+            return;
+        } else {
+            let _ = write_log_line(
+                &state.paths,
+                "Fast mux requested but no track modifications detected. Falling back to mkvmerge.",
+            );
+        }
+    }
+
+    let mp4_errors = mp4_validation_errors(&job, settings);
+    if !mp4_errors.is_empty() {
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some("Job can't be written as MP4".to_string()),
+                size_after: None,
+                error_message: Some(mp4_errors.join("; ")),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::MuxFailed);
+        return;
+    }
+
+    let (tool, version_arg, install_hint) = match settings.output_container {
+        OutputContainer::Mkv => (
+            "mkvmerge",
+            "-V",
+            "Install mkvmerge (MKVToolNix) and try again.",
+        ),
+        OutputContainer::Mp4 => ("ffmpeg", "-version", "Install ffmpeg to write MP4 outputs."),
+    };
+    if !tool_available(tool, version_arg) {
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Error,
+                progress: 0,
+                message: Some(format!("{tool} not found")),
+                size_after: None,
+                error_message: Some(install_hint.to_string()),
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        pause_on_error(state, settings, &job, JobErrorKind::ToolMissing);
+        return;
+    }
+
+    probe_job_inputs(&mut job, settings);
+    let mut command = hidden_command(tool);
+    // Mux from flagged copies of the chapter files; they are deleted once the job returns
+    let (flagged_job, _edition_files) = match edition_flagged_job(&job) {
+        Ok(flagged) => flagged,
+        Err(err) => {
+            let _ = write_log_line(
+                &state.paths,
+                &format!("WARNING: Job {}: edition flags not applied: {err}", job.id),
+            );
+            (job.clone(), Vec::new())
+        }
+    };
+    let (_, mut command_args) = build_job_command(&flagged_job, settings, &output_path, state);
+    // --global-tags is a global option, so it can follow the inputs
+    let comment_file = if tool == "mkvmerge" {
+        comment_tag_file(&job, settings).unwrap_or_else(|err| {
+            let _ = write_log_line(
+                &state.paths,
+                &format!("WARNING: Job {}: comment tag not added: {err}", job.id),
+            );
+            None
+        })
+    } else {
+        None
+    };
+    if let Some(file) = &comment_file {
+        command_args.push("--global-tags".to_string());
+        command_args.push(file.path().to_string_lossy().to_string());
+    }
+    log_job_plan(state, &job, &output_path);
+    let command_line = join_tool_command(tool, &command_args);
+    let _ = write_log_line(&state.paths, &command_line);
+    state
+        .mux_state
+        .lock()
+        .unwrap()
+        .last_commands
+        .insert(job.id.clone(), command_line);
+    let _ = app.emit_all(
+        "mux-plan",
+        mux_plan_event(&job, settings, &output_path, &command_args),
+    );
+    for arg in command_args {
+        command.arg(arg);
+    }
+
+    let mux_started = Instant::now();
+    let SpawnedProcess {
+        child: handle,
+        readers,
+    } = match run_command_with_logs(app, state, &job, &mut command) {
+        Ok(spawned) => spawned,
+        Err(err) => {
+            emit_progress(
+                app,
+                MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 0,
+                    message: Some("Failed to start process".to_string()),
+                    size_after: None,
+                    error_message: Some(err),
+                    error_detail: None,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                },
+            );
+            pause_on_error(state, settings, &job, JobErrorKind::ToolMissing);
+            return;
+        }
+    };
+
+    let smoother_done = Arc::new(AtomicBool::new(false));
+    let smoother = settings.smooth_progress.then(|| {
+        spawn_progress_smoother(
+            app.clone(),
+            state.clone(),
+            job.id.clone(),
+            output_path.clone(),
+            wall_clock_at(started),
+            job_input_bytes(&job),
+            smoother_done.clone(),
+        )
+    });
+
+    let exit_status = wait_for_child_or_stop(handle.clone(), state);
+    let mux_ms = elapsed_ms(mux_started);
+    let exit_code = exit_status.unwrap_or(-1);
+    {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        mux_state.children.remove(&job.id);
+    }
+    // Let the readers drain the pipes so the captured tail is complete
+    for reader in readers {
+        let _ = reader.join();
+    }
+    smoother_done.store(true, Ordering::Relaxed);
+    if let Some(smoother) = smoother {
+        let _ = smoother.join();
+    }
+    let stderr_tail = take_stderr_tail(state, &job.id);
+    let progress_pending = final_progress_pending(state, &job.id);
+
+    let (stopped, keep_outputs) = {
+        let mux_state = state.mux_state.lock().unwrap();
+        (mux_state.stop, mux_state.keep_outputs)
+    };
+    if stopped && keep_outputs && exit_code != 0 {
+        let kept = settle_stopped_output(
+            &output_path,
+            &final_path,
+            Path::new(&job.video.path),
+            overwrite_mode,
+            finished_writing(exit_status, settings),
+        );
+        let _ = write_log_line(
+            &state.paths,
+            &format!("Job {} stopped; kept {}", job.id, kept.to_string_lossy()),
+        );
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Stopped,
+                progress: 0,
+                message: Some(format!("Stopped, kept {}", kept.to_string_lossy())),
+                size_after: None,
+                error_message: None,
+                error_detail: None,
+                elapsed_ms: Some(elapsed_ms(started)),
+            },
+        );
+        return;
+    }
+
+    if exit_code != 0 {
+        // mkvmerge exits with 1 for warnings; for ffmpeg any non-zero code is a failure
+        let treat_as_success = exit_code == 1
+            && settings.output_container == OutputContainer::Mkv
+            && (output_path.exists()
+                || final_path.exists()
+                || !split_part_paths(&output_path, wall_clock_at(started)).is_empty());
+        if treat_as_success {
+            let _ = write_log_line(
+                &state.paths,
+                &format!("Job {} completed with warnings (exit code 1)", job.id),
+            );
+        } else {
+            let _ = write_log_line(
+                &state.paths,
+                &format!("Job {} failed with exit code {}", job.id, exit_code),
+            );
+            let error_message = unopenable_input_message(&job, stderr_tail.as_deref())
+                .unwrap_or_else(|| format!("Process exited with code {exit_code}"));
+            emit_progress(
+                app,
+                MuxProgressEvent {
+                    job_id: job.id.clone(),
+                    status: JobPhase::Error,
+                    progress: 0,
+                    message: Some("Muxing failed".to_string()),
+                    size_after: None,
+                    error_message: Some(error_message),
+                    error_detail: stderr_tail,
+                    elapsed_ms: Some(elapsed_ms(started)),
+                },
+            );
+            pause_on_error(state, settings, &job, JobErrorKind::MuxFailed);
+            return;
+        }
+    }
+
+    // mkvmerge doesn't always print a final 100% before exiting
+    if progress_pending {
+        emit_progress(
+            app,
+            MuxProgressEvent {
+                job_id: job.id.clone(),
+                status: JobPhase::Processing,
+                progress: 100,
+                message: None,
+                size_after: None,
+                error_message: None,
+                error_detail: None,
+                elapsed_ms: None,
+            },
+        );
+    }
+
+    // Split outputs are spread over several files, so only whole outputs are checked
+    if settings.verify_output && settings.split.is_none() {
+        let output_tracks = probe_mkvmerge(&output_path)
+            .ok()
+            .flatten()
+            .map(|info| parse_mkvmerge_tracks(&info));
+        let expected = expected_output_tracks(&job, settings);
+        if let Some(warning) =
+            output_tracks.and_then(|tracks| dropped_track_warning(&expected, &tracks))
+        {
+            let line = format!("WARNING: {warning}");
+            let _ = write_log_line(&state.paths, &line);
+            let _ = app.emit_all(
+                "mux-log",
+                serde_json::json!({ "job_id": job.id, "line": line }),
+            );
+        }
+    }
+
+    finalize_job(
+        state,
+        &job,
+        settings,
+        &output_path,
+        &final_path,
+        overwrite_mode,
+        started,
+        mux_ms,
+        &mut |event| emit_progress(app, event),
+    );
+
+    if settings.keep_log_file && !job_destination_dir(&job, settings).is_empty() {
+        let _ = fs::copy(
+            &state.paths.log_path,
+            output_dir.join("muxing_log_file.txt"),
+        );
+    }
+}
+
+/// How often the low-space watchdog reads the destinations' free space.
+const LOW_SPACE_POLL: Duration = Duration::from_secs(5);
+
+/// One watchdog reading: pauses the batch when `available_bytes` is under the threshold,
+/// and resumes it once space is back if the settings allow. `paused_here` remembers
+/// whether the pause is the watchdog's own, so a pause the user chose is left alone.
+fn low_space_step(
+    state: &AppState,
+    settings: &MuxSettings,
+    available_bytes: u64,
+    paused_here: &mut bool,
+) -> Option<MuxLowSpaceEvent> {
+    let threshold_bytes = settings.low_space_threshold_bytes?;
+    let mut mux_state = state.mux_state.lock().unwrap();
+    if available_bytes < threshold_bytes {
+        if mux_state.pause || mux_state.stop {
+            return None;
+        }
+        mux_state.pause = true;
+        *paused_here = true;
+    } else if *paused_here && settings.resume_on_space_recovered {
+        *paused_here = false;
+        if !mux_state.pause {
+            return None;
+        }
+        mux_state.pause = false;
+    } else {
+        return None;
+    }
+    Some(MuxLowSpaceEvent {
+        available_bytes,
+        threshold_bytes,
+        paused: mux_state.pause,
+    })
+}
+
+/// Polls the free space of the batch's output folders until `done` is set.
+fn spawn_low_space_watchdog(
+    app: AppHandle,
+    state: AppState,
+    settings: MuxSettings,
+    dirs: Vec<PathBuf>,
+    done: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut paused_here = false;
+        while !done.load(Ordering::Relaxed) {
+            let available = dirs.iter().filter_map(|dir| available_space_for(dir)).min();
+            if let Some(available) = available {
+                if let Some(event) = low_space_step(&state, &settings, available, &mut paused_here)
+                {
+                    let _ = write_log_line(
+                        &state.paths,
+                        &format!(
+                            "{} batch: {} free, threshold {}",
+                            if event.paused { "Paused" } else { "Resumed" },
+                            format_bytes(event.available_bytes),
+                            format_bytes(event.threshold_bytes)
+                        ),
+                    );
+                    let _ = app.emit_all("mux-low-space", event);
+                }
+            }
+            let polled = Instant::now();
+            while polled.elapsed() < LOW_SPACE_POLL && !done.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(200));
+            }
+        }
+    })
+}
+
+fn run_mux_queue(app: AppHandle, state: AppState) {
+    let settings = {
+        let mux_state = state.mux_state.lock().unwrap();
+        mux_state.settings.clone()
+    };
+    let Some(settings) = settings else {
+        return;
+    };
+
+    let queued = {
+        let mux_state = state.mux_state.lock().unwrap();
+        mux_state.queue.clone()
+    };
+    let queued_count = queued.len();
+    let jobs = jobs_to_run(queued, &settings);
+    let complete = MuxCompleteEvent {
+        processed: jobs.len(),
+        not_run: queued_count - jobs.len(),
+    };
+    if complete.not_run > 0 {
+        let _ = write_log_line(
+            &state.paths,
+            &format!(
+                "Stop after first job: {} queued job(s) won't run",
+                complete.not_run
+            ),
+        );
+    }
+
+    let watchdog_done = Arc::new(AtomicBool::new(false));
+    let watchdog = settings.low_space_threshold_bytes.map(|_| {
+        let mut dirs: Vec<PathBuf> = jobs
+            .iter()
+            .map(|job| resolve_output_dir(job, &settings))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        spawn_low_space_watchdog(
+            app.clone(),
+            state.clone(),
+            settings.clone(),
+            dirs,
+            watchdog_done.clone(),
+        )
+    });
+
+    let max_parallel = settings.max_parallel_jobs.unwrap_or(1).max(1);
+    let (tx, rx) = mpsc::channel::<MuxJobRequest>();
+    for job in jobs {
+        let _ = tx.send(job);
+    }
+    drop(tx);
+
+    let receiver = Arc::new(Mutex::new(rx));
+    let mut workers = Vec::new();
+
+    for _ in 0..max_parallel {
+        let app_handle = app.clone();
+        let state_clone = state.clone();
+        let settings_clone = settings.clone();
+        let rx_clone = receiver.clone();
+        workers.push(thread::spawn(move || loop {
+            {
+                let mux_state = state_clone.mux_state.lock().unwrap();
+                if mux_state.stop {
+                    break;
+                }
+                if mux_state.pause {
+                    drop(mux_state);
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+            }
+
+            let job = {
+                let rx_lock = rx_clone.lock().unwrap();
+                rx_lock.recv_timeout(Duration::from_millis(200))
+            };
+
+            match job {
+                Ok(job) => {
+                    process_job(&app_handle, &state_clone, &settings_clone, job);
+                    if let Some(reason) = enforce_output_cap(&state_clone, &settings_clone) {
+                        let _ = write_log_line(&state_clone.paths, &reason);
+                        let _ = app_handle.emit_all("mux-aborted", MuxAbortedEvent { reason });
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }));
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    watchdog_done.store(true, Ordering::Relaxed);
+    if let Some(watchdog) = watchdog {
+        let _ = watchdog.join();
+    }
+
+    if let Some(destination) = settings.keep_log_destination.as_deref() {
+        if let Err(error) = copy_log_to(&state.paths, destination) {
+            let _ = write_log_line(&state.paths, &error);
+        }
+    }
+
+    let batch_stats = std::mem::take(&mut state.mux_state.lock().unwrap().batch_stats);
+    if let Err(error) = record_batch_stats(&state.paths, &batch_stats) {
+        let _ = write_log_line(&state.paths, &error);
+    }
+
+    {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        mux_state.running = false;
+        mux_state.children.clear();
+        mux_state.batch_output_bytes = 0;
+    }
+    let _ = app.emit_all("mux-complete", complete);
+}
+
+/// The queued jobs a batch actually runs: only the first with `stop_after_first`.
+fn jobs_to_run(mut queued: Vec<MuxJobRequest>, settings: &MuxSettings) -> Vec<MuxJobRequest> {
+    if settings.stop_after_first {
+        queued.truncate(1);
+    }
+    queued
+}
+
+#[tauri::command]
+fn start_muxing(
+    app: AppHandle,
+    state: State<AppState>,
+    request: MuxStartRequest,
+) -> Result<(), String> {
+    state.paths.use_settings_for_log(&request.settings);
+    clear_log(&state.paths)?;
+    write_log_line(&state.paths, "Starting muxing session")?;
+
+    let mut mux_state = state.mux_state.lock().unwrap();
+    store_batch(&mut mux_state, request);
+
+    if mux_state.running {
+        return Ok(());
+    }
+    mux_state.running = true;
+
+    let app_handle = app.clone();
+    let state_clone = state.inner().clone();
+    thread::spawn(move || run_mux_queue(app_handle, state_clone));
+
+    Ok(())
+}
+
+/// The output an earlier run of `job` finished, if there is one. With CRC naming the
+/// tag isn't known up front, so any tagged file whose untagged name matches counts.
+/// Only files recorded as finished, at the size they were finished with, count: a
+/// crash mid-mux leaves a partial file at the final path. In overwrite mode the source
+/// itself sits there, so nothing counts.
+fn finished_output(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+    finished: &FinishedOutputs,
+) -> Option<PathBuf> {
+    let (_, final_path, overwrite_mode) = get_output_paths(job, settings);
+    if overwrite_mode {
+        return None;
+    }
+    let mut candidates = vec![final_path.clone()];
+    if settings.add_crc {
+        if let Some(dir) = final_path.parent().and_then(|dir| fs::read_dir(dir).ok()) {
+            candidates.extend(dir.flatten().map(|entry| entry.path()).filter(|path| {
+                crc_from_file_name(path).is_some() && file_name_without_crc(path) == final_path
+            }));
+        }
+    }
+    candidates.into_iter().find(|path| {
+        let recorded = finished.get(comparable_path(path).to_string_lossy().as_ref());
+        fs::metadata(path)
+            .is_ok_and(|metadata| metadata.is_file() && recorded == Some(&metadata.len()))
+    })
+}
+
+/// Splits a batch into the jobs whose outputs are already finished and the rest, which
+/// are returned as the request to run.
+fn split_resumable(
+    request: MuxStartRequest,
+    recorded: &FinishedOutputs,
+) -> (MuxStartRequest, Vec<(String, PathBuf)>) {
+    let mut finished = Vec::new();
+    let mut remaining = Vec::new();
+    for job in request.jobs {
+        match finished_output(&job, &request.settings, recorded) {
+            Some(output) => finished.push((job.id, output)),
+            None => remaining.push(job),
+        }
+    }
+    let request = MuxStartRequest {
+        settings: request.settings,
+        jobs: remaining,
+    };
+    (request, finished)
+}
+
+/// Re-runs a batch after a crash or stop, skipping jobs whose output is already there.
+#[tauri::command]
+fn resume_batch(
+    app: AppHandle,
+    state: State<AppState>,
+    request: MuxStartRequest,
+) -> Result<ResumeSummary, String> {
+    let started = Instant::now();
+    let recorded = read_finished_outputs(&state.paths.finished_outputs_path);
+    let (request, finished) = split_resumable(request, &recorded);
+    let summary = ResumeSummary {
+        skipped: finished.iter().map(|(job_id, _)| job_id.clone()).collect(),
+        run: request.jobs.iter().map(|job| job.id.clone()).collect(),
+    };
+    let log_lines: Vec<String> = finished
+        .iter()
+        .map(|(job_id, output)| {
+            format!(
+                "Job {job_id}: already finished as {}",
+                output.to_string_lossy()
+            )
+        })
+        .collect();
+
+    start_muxing(app.clone(), state.clone(), request)?;
+    for (job_id, output) in finished {
+        let reason = format!("Output already exists: {}", output.to_string_lossy());
+        emit_progress(&app, skipped_event(&job_id, reason, started));
+    }
+    for line in &log_lines {
+        let _ = write_log_line(&state.paths, line);
+    }
+    let _ = write_log_line(
+        &state.paths,
+        &format!(
+            "Resuming batch: {} job(s) already finished, {} to run",
+            summary.skipped.len(),
+            summary.run.len()
+        ),
+    );
+    let _ = app.emit_all("mux-resume", summary.clone());
+    Ok(summary)
+}
+
+/// Queues a new batch and keeps its settings, already resolved with defaults, for
+/// `get_last_batch_settings`.
+fn store_batch(mux_state: &mut MuxState, request: MuxStartRequest) {
+    mux_state.batch_job_count = request.jobs.len();
+    mux_state.queue = request.jobs;
+    mux_state.settings = Some(request.settings);
+    mux_state.stop = false;
+    mux_state.pause = false;
+    mux_state.keep_outputs = false;
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LastBatchSettings {
+    settings: MuxSettings,
+    job_count: usize,
+}
+
+fn last_batch_settings(mux_state: &MuxState) -> Option<LastBatchSettings> {
+    mux_state
+        .settings
+        .clone()
+        .map(|settings| LastBatchSettings {
+            settings,
+            job_count: mux_state.batch_job_count,
+        })
+}
+
+/// The settings and job count of the most recent `start_muxing`, defaults filled in,
+/// or `None` before the first batch (and after a reset).
+#[tauri::command]
+fn get_last_batch_settings(state: State<AppState>) -> Option<LastBatchSettings> {
+    last_batch_settings(&state.mux_state.lock().unwrap())
+}
+
+/// The command line, warnings and plan for one job, as `preview_mux` reports each job.
+fn preview_job_result(
+    state: &AppState,
+    mut job: MuxJobRequest,
+    settings: &MuxSettings,
+) -> MuxPreviewResult {
+    probe_job_inputs(&mut job, settings);
+    let (output_path, final_path, overwrite_mode) = get_output_paths(&job, settings);
+    let (tool, command_args) = build_job_command(&job, settings, &output_path, state);
+    let command_line = join_tool_command(tool, &command_args);
+    let mut warnings = mp4_validation_errors(&job, settings);
+    if overwrite_mode {
+        warnings.extend(
+            orphan_temp_outputs(&final_path, settings)
+                .into_iter()
+                .map(|orphan| {
+                    format!(
+                        "Leftover temp output from an earlier run: {}",
+                        orphan.to_string_lossy()
+                    )
+                }),
+        );
+    }
+
+    if let Err(err) = check_output_collision(&job, &output_path) {
+        warnings.push(err);
+    }
+    if !Path::new(&job.video.path).exists() {
+        warnings.push(format!("Video file missing: {}", job.video.path));
+    }
+    warnings.extend(language_setting_warnings(&job, settings));
+    warnings.extend(external_default_warnings(&job));
+    warnings.extend(fast_mux_warning(&job, settings));
+    warnings.extend(mixed_default_subtitle_warning(&job, settings));
+    warnings.extend(dolby_vision_warning(&job));
+    warnings.extend(legacy_mkvmerge_warning(state, &command_args));
+    warnings.extend(skipped_audio_forced_warnings(&job, state));
+    for audio in &job.audios {
+        if !Path::new(&audio.path).exists() {
+            warnings.push(format!("Audio file missing: {}", audio.path));
+        } else if let Some(warning) = video_audio_source_warning(audio) {
+            warnings.push(warning);
+        }
+    }
+    for subtitle in &job.subtitles {
+        if !Path::new(&subtitle.path).exists() {
+            warnings.push(format!("Subtitle file missing: {}", subtitle.path));
+        }
+    }
+    for chapter in &job.chapters {
+        if !Path::new(&chapter.path).exists() {
+            warnings.push(format!("Chapter file missing: {}", chapter.path));
+        }
+    }
+    for attachment in &job.attachments {
+        if !Path::new(&attachment.path).exists() {
+            warnings.push(format!("Attachment file missing: {}", attachment.path));
+        }
+    }
+    warnings.extend(poster_error(&job));
+    let required_bytes = job_input_bytes(&job);
+    let available_bytes = output_path.parent().and_then(available_space_for);
+    let space_ok = available_bytes.is_none_or(|available| available >= required_bytes);
+    if let Some(available) = available_bytes {
+        warnings.extend(free_space_error(available, required_bytes));
+    }
+
+    let plan = MuxPreviewPlan {
+        video: job.video.path.clone(),
+        output: output_path.to_string_lossy().to_string(),
+        audios: job.audios.clone(),
+        subtitles: job.subtitles.clone(),
+        chapters: job.chapters.clone(),
+        attachments: job.attachments.clone(),
+        will_overwrite_source: overwrite_mode,
+        temp_path: overwrite_mode.then(|| output_path.to_string_lossy().to_string()),
+    };
+
+    MuxPreviewResult {
+        track_actions: track_action_summary(&job, settings),
+        job_id: job.id,
+        command: command_line,
+        warnings,
+        plan,
+        space_ok,
+        required_bytes,
+        available_bytes,
+    }
+}
+
+fn preview_jobs(state: &AppState, request: MuxStartRequest) -> Vec<MuxPreviewResult> {
+    let settings = request.settings;
+    let mut duplicates = duplicate_output_messages(&request.jobs, &settings);
+    request
+        .jobs
+        .into_iter()
+        .map(|job| {
+            let duplicate = duplicates.remove(&job.id);
+            let mut result = preview_job_result(state, job, &settings);
+            result.warnings.extend(duplicate);
+            result
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn preview_mux(
+    state: State<AppState>,
+    request: MuxStartRequest,
+) -> Result<Vec<MuxPreviewResult>, String> {
+    Ok(preview_jobs(&state, request))
+}
+
+/// Everything a review-before-run screen shows for one job.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobAnalysis {
+    /// Command, warnings, plan, space check and `required_bytes` as the size estimate.
+    #[serde(flatten)]
+    preview: MuxPreviewResult,
+    track_order: Vec<TrackOrderEntry>,
+    /// What `validate_jobs` would refuse the job for; these aren't repeated in `warnings`.
+    errors: Vec<String>,
+    /// Whether the job will run through mkvpropedit.
+    fast_mux: bool,
+    /// Why the job can't be fast-muxed, whether or not fast muxing is enabled.
+    fast_mux_blocker: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchAnalysis {
+    jobs: Vec<JobAnalysis>,
+    estimate: BatchTimeEstimate,
+}
+
+/// Preview, validation, track order and time estimate of a batch in one pass.
+fn analyze_jobs(state: &AppState, request: MuxStartRequest, stats: &UsageStats) -> BatchAnalysis {
+    let settings = request.settings;
+    let estimate = estimate_batch(&request.jobs, &settings, stats);
+    let mut errors: HashMap<String, Vec<String>> = validate_job_list(&request.jobs, &settings)
+        .into_iter()
+        .map(|validation| (validation.job_id, validation.errors))
+        .collect();
+    let jobs = request
+        .jobs
+        .into_iter()
+        .map(|job| {
+            let errors = errors.remove(&job.id).unwrap_or_default();
+            let track_order = job_track_order(&job, &settings);
+            let fast_mux_blocker = fast_mux_blocker(&job, &settings).map(str::to_string);
+            let fast_mux = settings.use_mkvpropedit && fast_mux_blocker.is_none();
+            let mut preview = preview_job_result(state, job, &settings);
+            preview.warnings.retain(|warning| !errors.contains(warning));
+            JobAnalysis {
+                preview,
+                track_order,
+                errors,
+                fast_mux,
+                fast_mux_blocker,
+            }
+        })
+        .collect();
+    BatchAnalysis { jobs, estimate }
+}
+
+/// Probes and checks a batch without muxing anything.
+#[tauri::command]
+fn analyze_batch(state: State<AppState>, request: MuxStartRequest) -> BatchAnalysis {
+    let stats = read_stats(&state.paths.stats_path).unwrap_or_default();
+    analyze_jobs(&state, request, &stats)
+}
+
+/// Previews a single job, cheap enough to refresh on every option change.
+#[tauri::command]
+fn preview_job(
+    state: State<AppState>,
+    job: MuxJobRequest,
+    settings: MuxSettings,
+) -> Result<MuxPreviewResult, String> {
+    Ok(preview_job_result(&state, job, &settings))
+}
+
+fn describe_mkvmerge_info(info: &serde_json::Value) -> OutputDescription {
+    let attachments = info
+        .get("attachments")
+        .and_then(|items| items.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| OutputAttachment {
+                    name: item
+                        .get("file_name")
+                        .and_then(|name| name.as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    size: item.get("size").and_then(|size| size.as_u64()).unwrap_or(0),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // mkvmerge lists one entry per edition, each with its own chapter count
+    let chapter_count = info
+        .get("chapters")
+        .and_then(|items| items.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.get("num_entries").and_then(|n| n.as_u64()))
+                .sum()
+        })
+        .unwrap_or(0);
+    OutputDescription {
+        tracks: parse_mkvmerge_tracks(info),
+        attachments,
+        chapter_count,
+    }
+}
+
+#[tauri::command]
+fn describe_output(path: String) -> Result<OutputDescription, String> {
+    let info = probe_mkvmerge(Path::new(&path))?
+        .ok_or_else(|| "mkvmerge is needed to describe an output".to_string())?;
+    Ok(describe_mkvmerge_info(&info))
+}
+
+/// Splits `video.size` across its tracks as bitrate × duration.
+fn track_size_breakdown(video: &VideoFileInfo) -> TrackSizeBreakdown {
+    let duration = video.duration_seconds.filter(|seconds| *seconds > 0.0);
+    let tracks: Vec<TrackSizeEstimate> = video
+        .tracks
+        .iter()
+        .map(|track| {
+            let estimated_bytes = track
+                .bitrate
+                .zip(duration)
+                .map(|(bitrate, seconds)| (bitrate as f64 * seconds / 8.0).round() as u64);
+            TrackSizeEstimate {
+                track_id: track.id.clone(),
+                track_type: track.track_type.clone(),
+                language: track.language.clone(),
+                bitrate: track.bitrate,
+                estimated_bytes,
+                fraction: estimated_bytes
+                    .filter(|_| video.size > 0)
+                    .map(|bytes| bytes as f64 / video.size as f64),
+            }
+        })
+        .collect();
+    let estimated: u64 = tracks
+        .iter()
+        .filter_map(|track| track.estimated_bytes)
+        .sum();
+    TrackSizeBreakdown {
+        file_size: video.size,
+        duration_seconds: duration,
+        tracks,
+        remainder_bytes: video.size.saturating_sub(estimated),
+    }
+}
+
+#[tauri::command]
+fn estimate_track_sizes(path: String) -> Result<TrackSizeBreakdown, String> {
+    let info = build_file_info(
+        Path::new(&path),
+        "video",
+        true,
+        DurationSource::default(),
+        MetadataLevel::default(),
+        &ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES),
+    )?;
+    let video: VideoFileInfo = serde_json::from_value(info)
+        .map_err(|e| format!("Failed to read probe result for {path}: {e}"))?;
+    if let Some(error) = &video.probe_error {
+        return Err(error.clone());
+    }
+    Ok(track_size_breakdown(&video))
+}
+
+/// `{output}.json`, e.g. `Show [ABCD1234].mkv.json`.
+fn sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_os_string();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Writes the sidecar for `output` from its mkvmerge identification.
+fn write_output_sidecar(
+    output: &Path,
+    source: &str,
+    info: &serde_json::Value,
+) -> Result<PathBuf, String> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    let sidecar = OutputSidecar {
+        source: file_name(Path::new(source)),
+        output: file_name(output),
+        crc: crc_from_file_name(output),
+        description: describe_mkvmerge_info(info),
+    };
+    let path = sidecar_path(output);
+    let content = serde_json::to_string_pretty(&sidecar)
+        .map_err(|e| format!("Failed to serialize sidecar: {e}"))?;
+    fs::write(&path, content)
+        .map_err(|e| format!("Failed to write {}: {e}", path.to_string_lossy()))?;
+    Ok(path)
+}
+
+/// Probes each finished output and writes its sidecar. Failures are logged; the
+/// output itself is already complete, so they don't fail the job.
+fn write_output_sidecars(state: &AppState, job: &MuxJobRequest, outputs: &[PathBuf]) {
+    for output in outputs {
+        let result = probe_mkvmerge(output)
+            .and_then(|info| info.ok_or_else(|| "mkvmerge is not available".to_string()))
+            .and_then(|info| write_output_sidecar(output, &job.video.path, &info));
+        if let Err(err) = result {
+            let _ = write_log_line(
+                &state.paths,
+                &format!("WARNING: Job {}: no sidecar written: {err}", job.id),
+            );
+        }
+    }
+}
+
+#[tauri::command]
+fn pause_muxing(state: State<AppState>) -> Result<(), String> {
+    let mut mux_state = state.mux_state.lock().unwrap();
+    mux_state.pause = true;
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_muxing(state: State<AppState>) -> Result<(), String> {
+    let mut mux_state = state.mux_state.lock().unwrap();
+    mux_state.pause = false;
+    Ok(())
+}
+
+fn kill_children(mux_state: &mut MuxState) {
+    for (_, handle) in mux_state.children.drain() {
+        if let Ok(mut child) = handle.lock() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Stops the queue and kills running tools. Also run when the app exits, so no mkvmerge
+/// keeps writing into the destination after the window is gone.
+fn shutdown_muxing(state: &AppState) {
+    let mut mux_state = state.mux_state.lock().unwrap();
+    mux_state.stop = true;
+    kill_children(&mut mux_state);
+}
+
+#[tauri::command]
+fn stop_muxing(state: State<AppState>) -> Result<(), String> {
+    shutdown_muxing(&state);
+    Ok(())
+}
+
+/// Stops the queue like `stop_muxing`, but settles the outputs of interrupted jobs:
+/// finished temp files are promoted, unfinished ones are removed so the source survives.
+#[tauri::command]
+fn stop_keep_outputs(state: State<AppState>) -> Result<(), String> {
+    let mut mux_state = state.mux_state.lock().unwrap();
+    mux_state.stop = true;
+    mux_state.keep_outputs = true;
+    kill_children(&mut mux_state);
+    Ok(())
+}
+
+/// How long a reset waits for a running batch to wind down before resetting anyway.
+const RESET_WAIT: Duration = Duration::from_secs(5);
+
+/// Stops the batch, kills its tools and puts `MuxState` back to its defaults, keeping
+/// the command history and the stats not yet written. Workers still running after
+/// `wait` would race the reset, so it is refused; the stopped batch winds down on its own.
+fn reset_mux(state: &AppState, wait: Duration) -> Result<(), String> {
+    shutdown_muxing(state);
+    state.mux_state.lock().unwrap().queue.clear();
+    let deadline = Instant::now() + wait;
+    while state.mux_state.lock().unwrap().running && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+    let mut mux_state = state.mux_state.lock().unwrap();
+    if mux_state.running {
+        return Err("Muxing state not reset: the stopped batch is still finishing".to_string());
+    }
+    *mux_state = MuxState {
+        last_commands: std::mem::take(&mut mux_state.last_commands),
+        batch_stats: std::mem::take(&mut mux_state.batch_stats),
+        ..MuxState::default()
+    };
+    Ok(())
+}
+
+/// Clean-slate reset after a batch went wrong. Waits for the workers off the main
+/// thread and emits `mux-reset` once the state is back to its defaults.
+#[tauri::command]
+fn reset_mux_state(app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let state = state.inner().clone();
+    thread::spawn(move || match reset_mux(&state, RESET_WAIT) {
+        Ok(()) => {
+            let _ = write_log_line(&state.paths, "Muxing state reset");
+            let _ = app.emit_all("mux-reset", ());
+        }
+        Err(reason) => {
+            let _ = write_log_line(&state.paths, &reason);
+            let _ = app.emit_all("mux-reset-failed", reason);
+        }
+    });
+    Ok(())
+}
+
+/// Settings for a throwaway test run: the output lands in `destination` and nothing
+/// replaces or edits the source in place.
+fn single_job_settings(settings: &MuxSettings, destination: &Path) -> MuxSettings {
+    let mut settings = settings.clone();
+    settings.destination_dir = destination.to_string_lossy().to_string();
+    settings.overwrite_source = false;
+    settings.use_mkvpropedit = false;
+    settings.skip_existing = false;
+    settings.abort_on_errors = false;
+    settings
+}
+
+/// Muxes one job into a fresh temp folder and returns the output file (or the folder,
+/// when splitting or CRC naming renamed it). The queue counts as running meanwhile, so
+/// no batch starts alongside. `mux` runs the job; `process_job` outside of tests.
+fn run_single_job_in(
+    state: &AppState,
+    mut job: MuxJobRequest,
+    settings: &MuxSettings,
+    mux: impl FnOnce(&MuxSettings, MuxJobRequest),
+) -> Result<String, String> {
+    {
+        let mut mux_state = state.mux_state.lock().unwrap();
+        if mux_state.running {
+            return Err("A batch is already running".to_string());
+        }
+        mux_state.running = true;
+        mux_state.stop = false;
+        mux_state.pause = false;
+        mux_state.keep_outputs = false;
+    }
+    let destination = std::env::temp_dir().join(generate_id("mkvbatchmux-test"));
+    let settings = single_job_settings(settings, &destination);
+    state.paths.use_settings_for_log(&settings);
+    job.destination_dir = None;
+    let (_, final_path, _) = get_output_paths(&job, &settings);
+    let _ = write_log_line(
+        &state.paths,
+        &format!("Test run of job {} into {}", job.id, destination.display()),
+    );
+
+    mux(&settings, job);
+    state.mux_state.lock().unwrap().running = false;
+
+    if final_path.exists() {
+        return Ok(final_path.to_string_lossy().to_string());
+    }
+    let extension = output_extension(&settings);
+    let produced = fs::read_dir(&destination)
+        .map(|mut entries| {
+            entries.any(|entry| {
+                entry.is_ok_and(|entry| {
+                    entry
+                        .path()
+                        .extension()
+                        .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case(extension))
+                })
+            })
+        })
+        .unwrap_or(false);
+    if produced {
+        Ok(destination.to_string_lossy().to_string())
+    } else {
+        Err("Test run produced no output; see the log for details".to_string())
+    }
+}
+
+/// Runs one job like `run_single_job_in`, off the invoking thread, with the usual
+/// progress events.
+#[tauri::command]
+async fn run_single_job(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    job: MuxJobRequest,
+    settings: MuxSettings,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        run_single_job_in(&state, job, &settings, |settings, job| {
+            process_job(&app, &state, settings, job)
+        })
+    })
+    .await
+    .map_err(|e| format!("Test run failed: {e}"))?
+}
+
+/// Files of one preset folder, or none when the preset leaves the folder unset.
+fn scan_preset_folder(
+    folder: &str,
+    extensions: &[String],
+    file_type: &str,
+    groups: &HashMap<String, Vec<String>>,
+) -> Result<Vec<PathBuf>, String> {
+    if folder.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let request = ScanRequest {
+        folder: folder.to_string(),
+        extensions: extensions.to_vec(),
+        recursive: false,
+        file_type: file_type.to_string(),
+        include_tracks: true,
+        modified_since: None,
+        duration_source: DurationSource::default(),
+        skip_trackless: false,
+        metadata_level: MetadataLevel::default(),
+        verify_magic: false,
+        max_concurrent_probes: DEFAULT_MAX_CONCURRENT_PROBES,
+        require_languages: Vec::new(),
+        require_language_types: Vec::new(),
+    };
+    scan_files(&request, groups)
+}
+
+/// Whether an external file belongs to a video: same stem, optionally followed by
+/// more dotted parts (`show.ass`, `show.eng.ass`).
+fn external_matches_video(video: &Path, external: &Path) -> bool {
+    let (Some(stem), Some(name)) = (
+        video.file_stem().and_then(|s| s.to_str()),
+        external.file_name().and_then(|s| s.to_str()),
+    ) else {
+        return false;
+    };
+    let (stem, name) = (stem.to_lowercase(), name.to_lowercase());
+    name.strip_prefix(&stem)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn probe_external(path: &Path, file_type: &str) -> Result<ExternalFileInfo, String> {
+    let info = build_file_info(
+        path,
+        file_type,
+        true,
+        DurationSource::default(),
+        MetadataLevel::default(),
+        &ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES),
+    )?;
+    serde_json::from_value(info).map_err(|e| format!("Invalid file info: {e}"))
+}
+
+fn preset_externals(
+    paths: &[PathBuf],
+    video: &Path,
+    file_type: &str,
+    language: &str,
+) -> Result<Vec<ExternalFileInfo>, String> {
+    let mut externals = Vec::new();
+    for path in paths {
+        if !external_matches_video(video, path) {
+            continue;
+        }
+        let mut external = probe_external(path, file_type)?;
+        external.source = Some("bulk".to_string());
+        if !language.trim().is_empty() {
+            external.language = Some(language.trim().to_string());
+        }
+        externals.push(external);
+    }
+    Ok(externals)
+}
+
+/// Language names as the options dialog lists them, mapped to their ISO 639-2 codes.
+const LANGUAGE_NAMES_JSON: &str = include_str!("../../src/shared/assets/language.json");
+
+/// The ISO 639-2 code for a language given by name (`English`) or already as a code.
+fn language_code(language: &str) -> String {
+    static NAMES: OnceLock<HashMap<String, String>> = OnceLock::new();
+    let names = NAMES.get_or_init(|| {
+        serde_json::from_str::<HashMap<String, String>>(LANGUAGE_NAMES_JSON)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, code)| (name.to_lowercase(), code))
+            .collect()
+    });
+    let language = language.trim();
+    names
+        .get(&language.to_lowercase())
+        .cloned()
+        .unwrap_or_else(|| language.to_ascii_lowercase())
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct LanguageEntry {
+    /// ISO 639-2 code, as mkvmerge takes it.
+    code: String,
+    name: String,
+}
+
+/// The bundled language table, sorted by name.
+fn builtin_languages() -> Vec<LanguageEntry> {
+    let mut languages: Vec<LanguageEntry> =
+        serde_json::from_str::<HashMap<String, String>>(LANGUAGE_NAMES_JSON)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, code)| LanguageEntry { code, name })
+            .collect();
+    languages.sort_by(|a, b| a.name.cmp(&b.name));
+    languages
+}
+
+/// Parses the table `mkvmerge --list-languages` prints: name, ISO 639-3, ISO 639-2 and
+/// ISO 639-1 columns separated by `|`. Languages without a 639-2 code are left out.
+fn parse_mkvmerge_languages(text: &str) -> Vec<LanguageEntry> {
+    text.lines()
+        .filter(|line| !line.contains("ISO 639"))
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+            let [name, _, code, ..] = columns[..] else {
+                return None;
+            };
+            let is_code = code.len() == 3 && code.chars().all(|c| c.is_ascii_lowercase());
+            (is_code && !name.is_empty()).then(|| LanguageEntry {
+                code: code.to_string(),
+                name: name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// What `mkvmerge --list-languages` prints, or `None` when mkvmerge can't be run.
+fn list_mkvmerge_languages() -> Option<String> {
+    let output = hidden_command("mkvmerge")
+        .arg("--list-languages")
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The installed mkvmerge's languages. Only a successful listing is kept, so a later
+/// call tries again.
+fn mkvmerge_languages(state: &AppState) -> Option<&Vec<LanguageEntry>> {
+    if let Some(languages) = state.mkvmerge_languages.get() {
+        return Some(languages);
+    }
+    let languages = parse_mkvmerge_languages(&list_mkvmerge_languages()?);
+    (!languages.is_empty()).then(|| state.mkvmerge_languages.get_or_init(|| languages))
+}
+
+/// The languages the UI offers: what the installed mkvmerge accepts, or the bundled
+/// table when mkvmerge can't be asked.
+fn supported_languages(state: &AppState) -> Vec<LanguageEntry> {
+    mkvmerge_languages(state)
+        .cloned()
+        .unwrap_or_else(builtin_languages)
+}
+
+#[tauri::command]
+fn get_supported_languages(state: State<AppState>) -> Vec<LanguageEntry> {
+    supported_languages(&state)
+}
+
+/// A preset's favorite languages as default-track priority lists, in the user's order.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct FavoriteDefaults {
+    audio_languages: Vec<String>,
+    subtitle_languages: Vec<String>,
+}
+
+fn favorite_defaults(preset: &Preset) -> FavoriteDefaults {
+    let codes = |favorites: &[String]| {
+        let mut codes: Vec<String> = Vec::new();
+        for favorite in favorites.iter().filter(|f| !f.trim().is_empty()) {
+            let code = language_code(favorite);
+            if !codes.contains(&code) {
+                codes.push(code);
+            }
+        }
+        codes
+    };
+    FavoriteDefaults {
+        audio_languages: codes(&preset.default_favorite_audio_languages),
+        subtitle_languages: codes(&preset.default_favorite_subtitle_languages),
+    }
+}
+
+/// The default-track priority lists a preset's favorite languages give, for
+/// `audio_default_language_priority` and `subtitle_default_language_priority`.
+#[tauri::command]
+fn build_defaults_from_favorites(
+    state: State<AppState>,
+    preset_id: usize,
+) -> Result<FavoriteDefaults, String> {
+    let options = read_options(&state.paths.options_path)?;
+    let preset = options
+        .presets
+        .get(preset_id)
+        .ok_or_else(|| format!("No preset with id {preset_id}"))?;
+    Ok(favorite_defaults(preset))
+}
+
+/// Builds a ready batch from a preset's folders and defaults, matching externals to
+/// videos by file name.
+fn jobs_from_preset(
+    preset: &Preset,
+    groups: &HashMap<String, Vec<String>>,
+) -> Result<MuxStartRequest, String> {
+    let videos = scan_preset_folder(
+        &preset.default_video_directory,
+        &preset.default_video_extensions,
+        "video",
+        groups,
+    )?;
+    let subtitles = scan_preset_folder(
+        &preset.default_subtitle_directory,
+        &preset.default_subtitle_extensions,
+        "subtitle",
+        groups,
+    )?;
+    let audios = scan_preset_folder(
+        &preset.default_audio_directory,
+        &preset.default_audio_extensions,
+        "audio",
+        groups,
+    )?;
+
+    let mut settings = preset.default_mux_settings.clone().unwrap_or_default();
+    if settings.destination_dir.trim().is_empty() {
+        settings.destination_dir = preset.default_destination_directory.clone();
+    }
+    let favorites = favorite_defaults(preset);
+    if settings.audio_default_language_priority.is_empty() {
+        settings.audio_default_language_priority = favorites.audio_languages;
+    }
+    if settings.subtitle_default_language_priority.is_empty() {
+        settings.subtitle_default_language_priority = favorites.subtitle_languages;
+    }
+
+    let mut jobs = Vec::new();
+    for video_path in &videos {
+        let info = build_file_info(
+            video_path,
+            "video",
+            true,
+            DurationSource::default(),
+            MetadataLevel::default(),
+            &ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES),
+        )?;
+        let video: VideoFileInfo =
+            serde_json::from_value(info).map_err(|e| format!("Invalid file info: {e}"))?;
+        jobs.push(MuxJobRequest {
+            id: generate_id("job"),
+            video,
+            audios: preset_externals(&audios, video_path, "audio", &preset.default_audio_language)?,
+            subtitles: preset_externals(
+                &subtitles,
+                video_path,
+                "subtitle",
+                &preset.default_subtitle_language,
+            )?,
+            chapters: Vec::new(),
+            attachments: Vec::new(),
+            output_name: None,
+            preset_name: Some(preset.preset_name.clone()),
+            destination_dir: None,
+            append_sources: Vec::new(),
+            abort_on_errors: None,
+            poster_image: None,
+            keep_track_name_patterns: None,
+            probed: ProbedInputs::default(),
+        });
+    }
+    Ok(MuxStartRequest { settings, jobs })
+}
+
+const FOLDER_VIDEO_EXTENSIONS: [&str; 6] = ["mkv", "mp4", "m4v", "avi", "m2ts", "ts"];
+const FOLDER_AUDIO_EXTENSIONS: [&str; 12] = [
+    "aac", "ac3", "eac3", "dts", "thd", "flac", "mka", "mp3", "m4a", "opus", "ogg", "wav",
+];
+// `.sub` is left out: it's either MicroDVD or the data half of an `.idx` pair
+const FOLDER_SUBTITLE_EXTENSIONS: [&str; 6] = ["ass", "ssa", "srt", "sup", "idx", "vtt"];
+
+/// File info for a file that couldn't be probed: name, path and size, with the reason
+/// in `probeError` so the file is still listed.
+fn unprobed_file_info(path: &Path, file_type: &str, error: String) -> serde_json::Value {
+    serde_json::json!({
+        "id": generate_id(file_type),
+        "name": path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default(),
+        "path": path.to_string_lossy(),
+        "type": file_type,
+        "size": fs::metadata(path).map_or(0, |metadata| metadata.len()),
+        "status": "error",
+        "tracks": [],
+        "probeError": error,
+    })
+}
+
+/// One job for an episode folder: its largest video plus every audio, subtitle, XML
+/// chapter and font file next to it. `None` when the folder holds no video. A file that
+/// fails to probe is kept with its `probeError` instead of failing the folder.
+fn folder_job(folder: &Path) -> Result<Option<MuxJobRequest>, String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(folder)
+        .map_err(|e| format!("Failed to read {}: {e}", folder.to_string_lossy()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    let Some(video_path) = files
+        .iter()
+        .filter(|path| FOLDER_VIDEO_EXTENSIONS.contains(&extension(path).as_str()))
+        .max_by_key(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+    else {
+        return Ok(None);
+    };
+    let info = build_file_info(
+        video_path,
+        "video",
+        true,
+        DurationSource::default(),
+        MetadataLevel::default(),
+        &ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES),
+    )
+    .unwrap_or_else(|error| unprobed_file_info(video_path, "video", error));
+    let video: VideoFileInfo =
+        serde_json::from_value(info).map_err(|e| format!("Invalid file info: {e}"))?;
+
+    let mut job = MuxJobRequest {
+        id: generate_id("job"),
+        video,
+        audios: Vec::new(),
+        subtitles: Vec::new(),
+        chapters: Vec::new(),
+        attachments: Vec::new(),
+        output_name: None,
+        preset_name: None,
+        destination_dir: None,
+        append_sources: Vec::new(),
+        abort_on_errors: None,
+        poster_image: None,
+        keep_track_name_patterns: None,
+        probed: ProbedInputs::default(),
+    };
+    for path in files.iter().filter(|path| *path != video_path) {
+        let extension = extension(path);
+        let (file_type, list) = if FOLDER_AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+            ("audio", &mut job.audios)
+        } else if FOLDER_SUBTITLE_EXTENSIONS.contains(&extension.as_str()) {
+            ("subtitle", &mut job.subtitles)
+        } else if extension == "xml" {
+            ("chapter", &mut job.chapters)
+        } else if FONT_EXTENSIONS.contains(&extension.as_str()) {
+            ("attachment", &mut job.attachments)
+        } else {
+            continue;
+        };
+        let mut external = match probe_external(path, file_type) {
+            Ok(external) => external,
+            Err(error) => serde_json::from_value(unprobed_file_info(path, file_type, error))
+                .map_err(|e| format!("Invalid file info: {e}"))?,
+        };
+        external.source = Some("per-file".to_string());
+        external.matched_video_id = Some(job.video.id.clone());
+        list.push(external);
+    }
+    Ok(Some(job))
+}
+
+/// Builds one job per subfolder of `root`, for libraries that keep each episode in its
+/// own folder. Subfolders without a video are skipped.
+#[tauri::command]
+fn match_by_folder(root: String) -> Result<Vec<MuxJobRequest>, String> {
+    let mut folders: Vec<PathBuf> = fs::read_dir(&root)
+        .map_err(|e| format!("Failed to read {root}: {e}"))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    folders.sort();
+    let mut jobs = Vec::new();
+    for folder in &folders {
+        jobs.extend(folder_job(folder)?);
+    }
+    Ok(jobs)
+}
+
+/// Assembles a batch from a saved preset without the UI, e.g. for automation.
+#[tauri::command]
+fn build_jobs_from_preset(
+    state: State<AppState>,
+    preset_id: usize,
+) -> Result<MuxStartRequest, String> {
+    let options = read_options(&state.paths.options_path)?;
+    let preset = options
+        .presets
+        .get(preset_id)
+        .ok_or_else(|| format!("No preset with id {preset_id}"))?;
+    jobs_from_preset(preset, &options.extension_groups)
+}
+
+const SPLIT_PART_MARKERS: [&str; 5] = ["part", "pt", "cd", "disc", "disk"];
+
+/// Finds a part marker like `part1`, `Part 2`, `pt.3`, `CD1` or `disc-2` in a file stem.
+/// Returns the stem with the marker cut out and the part number.
+fn split_part_number(stem: &str) -> Option<(String, u32)> {
+    let lower = stem.to_ascii_lowercase();
+    let is_separator = |c: char| matches!(c, ' ' | '.' | '-' | '_' | '(' | ')' | '[' | ']');
+    for (start, _) in lower.char_indices() {
+        if start > 0 && !lower[..start].ends_with(is_separator) {
+            continue;
+        }
+        let rest = &lower[start..];
+        let Some(marker) = SPLIT_PART_MARKERS
+            .iter()
+            .find(|marker| rest.starts_with(*marker))
+        else {
+            continue;
+        };
+        let after_marker = &rest[marker.len()..];
+        let digits_at = after_marker.len() - after_marker.trim_start_matches(is_separator).len();
+        if digits_at > 1 {
+            continue;
+        }
+        let digits: String = after_marker[digits_at..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        let end = start + marker.len() + digits_at + digits.len();
+        if digits.is_empty() || !lower[end..].chars().next().is_none_or(is_separator) {
+            continue;
+        }
+        let is_filler = |c: char| matches!(c, ' ' | '.' | '-' | '_');
+        let base = format!(
+            "{}{}",
+            stem[..start].trim_end_matches(is_filler),
+            &stem[end..]
+        );
+        let base = base.trim_matches(is_filler).to_string();
+        return Some((base, digits.parse().ok()?));
+    }
+    None
+}
+
+/// Externals that won't be muxed anywhere: no `matched_video_id`, or one that isn't
+/// among `videos`.
+#[tauri::command]
+fn find_orphan_externals(
+    videos: Vec<VideoFileInfo>,
+    externals: Vec<ExternalFileInfo>,
+) -> Vec<ExternalFileInfo> {
+    let video_ids: HashSet<&str> = videos.iter().map(|video| video.id.as_str()).collect();
+    externals
+        .into_iter()
+        .filter(|external| {
+            external
+                .matched_video_id
+                .as_deref()
+                .is_none_or(|id| !video_ids.contains(id))
+        })
+        .collect()
+}
+
+/// The finished output of a queue entry saved in the session, if it's on disk and
+/// looks whole: not empty and, for Matroska, starting with an EBML header. With CRC
+/// naming the output carries a tag, so a tagged sibling of the planned name counts.
+fn saved_job_output(job: &serde_json::Value, settings: &MuxSettings) -> Option<PathBuf> {
+    let video: VideoFileInfo = serde_json::from_value(job.get("videoFile")?.clone()).ok()?;
+    let text = |key: &str| {
+        job.get(key)
+            .and_then(|value| value.as_str())
+            .map(String::from)
+    };
+    let request = MuxJobRequest {
+        id: job.get("id")?.as_str()?.to_string(),
+        video,
+        audios: Vec::new(),
+        subtitles: Vec::new(),
+        chapters: Vec::new(),
+        attachments: Vec::new(),
+        output_name: text("outputName"),
+        preset_name: text("presetName"),
+        destination_dir: text("destinationDir"),
+        append_sources: Vec::new(),
+        abort_on_errors: None,
+        poster_image: None,
+        keep_track_name_patterns: None,
+        probed: ProbedInputs::default(),
+    };
+    let (_, final_path, _) = get_output_paths(&request, settings);
+    let output = if final_path.is_file() || !settings.add_crc {
+        final_path
+    } else {
+        fs::read_dir(final_path.parent()?)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|path| path != &final_path && file_name_without_crc(path) == final_path)?
+    };
+    let whole = fs::metadata(&output).is_ok_and(|m| m.is_file() && m.len() > 0)
+        && (!is_matroska_path(&output) || has_ebml_header(&output));
+    whole.then_some(output)
+}
+
+/// Drops completed entries whose output already exists from a saved session's `jobs`.
+/// Returns how many were dropped; entries in any other state are always kept.
+fn compact_queue(session: &mut serde_json::Value) -> usize {
+    let mut errors = Vec::new();
+    let settings = match session
+        .get("muxSettings")
+        .and_then(|value| value.as_object())
+    {
+        Some(value) => merge_lenient(&MuxSettings::default(), value, "", &mut errors),
+        None => return 0,
+    };
+    let Some(jobs) = session.get_mut("jobs").and_then(|jobs| jobs.as_array_mut()) else {
+        return 0;
+    };
+    let before = jobs.len();
+    jobs.retain(|job| {
+        let completed = job.get("status").and_then(|status| status.as_str()) == Some("completed");
+        !(completed && saved_job_output(job, &settings).is_some())
+    });
+    before - jobs.len()
+}
+
+/// Rewrites the saved session without the queue entries that already finished, so a
+/// resume after a crash doesn't show or rerun them. Returns how many were removed.
+#[tauri::command]
+fn compact_saved_queue(app: AppHandle) -> Result<usize, String> {
+    let Some(mut saved) = session::load_session_data(&app)? else {
+        return Ok(0);
+    };
+    let removed = compact_queue(&mut saved);
+    if removed > 0 {
+        session::save_session_data(&app, saved)?;
+    }
+    Ok(removed)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SplitPartGroups {
+    jobs: Vec<MuxJobRequest>,
+    ungrouped: Vec<VideoFileInfo>,
+}
+
+/// Groups videos that are parts of one title (same folder, extension and name apart from
+/// the part marker) into one job per title: the lowest part is the video and the rest
+/// are appended in part order. Files without a partner are returned as they were.
+#[tauri::command]
+fn group_split_parts(files: Vec<VideoFileInfo>) -> SplitPartGroups {
+    // Keyed by folder, lowercased base name and extension
+    type PartGroup = ((PathBuf, String, String), Vec<(u32, VideoFileInfo)>);
+    let mut groups: Vec<PartGroup> = Vec::new();
+    let mut ungrouped = Vec::new();
+    for file in files {
+        let path = Path::new(&file.path);
+        let part = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(split_part_number);
+        let Some((base, number)) = part else {
+            ungrouped.push(file);
+            continue;
+        };
+        let key = (
+            path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            base.to_lowercase(),
+            path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        );
+        match groups.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, parts)) => parts.push((number, file)),
+            None => groups.push((key, vec![(number, file)])),
+        }
+    }
+
+    let mut jobs = Vec::new();
+    for (_, mut parts) in groups {
+        if parts.len() < 2 {
+            ungrouped.extend(parts.into_iter().map(|(_, file)| file));
+            continue;
+        }
+        parts.sort_by_key(|(number, _)| *number);
+        let mut parts = parts.into_iter().map(|(_, file)| file);
+        let Some(video) = parts.next() else {
+            continue;
+        };
+        let output_name = Path::new(&video.path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(split_part_number)
+            .map(|(base, _)| base);
+        jobs.push(MuxJobRequest {
+            id: generate_id("job"),
+            video,
+            audios: Vec::new(),
+            subtitles: Vec::new(),
+            chapters: Vec::new(),
+            attachments: Vec::new(),
+            output_name,
+            preset_name: None,
+            destination_dir: None,
+            append_sources: parts.map(|part| part.path).collect(),
+            abort_on_errors: None,
+            poster_image: None,
+            keep_track_name_patterns: None,
+            probed: ProbedInputs::default(),
+        });
+    }
+    SplitPartGroups { jobs, ungrouped }
+}
+
+/// Fills a rename template for the `index`-th (1-based) of `total` files. `{stem}` and
+/// `{ext}` come from the file name and `{index}` is zero-padded to at least two digits.
+/// A template without `{ext}` keeps the original extension.
+fn apply_rename_template(path: &Path, template: &str, index: usize, total: usize) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let width = total.to_string().len().max(2);
+    let mut name = template
+        .replace("{stem}", &stem)
+        .replace("{index}", &format!("{index:0width$}"))
+        .replace("{ext}", &ext);
+    if !template.contains("{ext}") && !ext.is_empty() {
+        name = format!("{name}.{ext}");
+    }
+    sanitize_filename(&name, SanitizePolicy::Replace("_"))
+}
+
+#[tauri::command]
+fn preview_rename(paths: Vec<String>, template: String) -> Vec<RenameItem> {
+    let total = paths.len();
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, path)| RenameItem {
+            new_name: apply_rename_template(Path::new(&path), &template, index + 1, total),
+            path,
+        })
+        .collect()
+}
+
+fn rename_file(item: &RenameItem) -> Result<PathBuf, String> {
+    let source = Path::new(&item.path);
+    let new_name = item.new_name.trim();
+    if new_name.is_empty() || sanitize_filename(new_name, SanitizePolicy::Replace("_")) != new_name
+    {
+        return Err(format!("Invalid file name: {}", item.new_name));
+    }
+    let target = source
+        .parent()
+        .map(|parent| parent.join(new_name))
+        .unwrap_or_else(|| PathBuf::from(new_name));
+    if target == source {
+        return Ok(target);
+    }
+    if !source.is_file() {
+        return Err(format!("File not found: {}", item.path));
+    }
+    if target.exists() {
+        return Err(format!("{} already exists", target.to_string_lossy()));
+    }
+    fs::rename(source, &target).map_err(|e| format!("Failed to rename {}: {e}", item.path))?;
+    Ok(target)
+}
+
+/// Renames each file within its folder. A name that's already taken is an error for
+/// that file; nothing is ever overwritten.
+#[tauri::command]
+fn rename_files(items: Vec<RenameItem>) -> Vec<RenameResult> {
+    items
+        .into_iter()
+        .map(|item| match rename_file(&item) {
+            Ok(target) => RenameResult {
+                path: item.path,
+                new_path: Some(target.to_string_lossy().to_string()),
+                error: None,
+            },
+            Err(error) => RenameResult {
+                path: item.path,
+                new_path: None,
+                error: Some(error),
+            },
+        })
+        .collect()
+}
+
+/// Chapter file layouts mkvmerge reads.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum ChapterFormat {
+    /// `CHAPTER01=00:00:00.000` / `CHAPTER01NAME=Intro` pairs.
+    Ogm,
+    /// Matroska chapter XML.
+    Xml,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Chapter {
+    start_ns: u64,
+    name: String,
+}
+
+/// Parses `HH:MM:SS` with an optional fraction of any precision. `None` when malformed
+/// or too large for nanoseconds in a `u64`.
+fn parse_chapter_time(value: &str) -> Option<u64> {
+    let (clock, fraction) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+    let parts: Vec<u64> = clock
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hours, minutes, seconds] = parts[..] else {
+        return None;
+    };
+    if fraction.len() > 9 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{fraction:0<9}").parse::<u64>().ok()?;
+    hours
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1_000_000_000)?
+        .checked_add(nanos)
+}
+
+fn format_chapter_time(start_ns: u64, fraction_digits: u32) -> String {
+    let seconds = start_ns / 1_000_000_000;
+    let fraction = (start_ns % 1_000_000_000) / 10u64.pow(9 - fraction_digits);
+    format!(
+        "{:02}:{:02}:{:02}.{:0width$}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        fraction,
+        width = fraction_digits as usize
+    )
+}
+
+fn parse_ogm_chapters(text: &str) -> Result<Vec<Chapter>, String> {
+    let mut starts: Vec<(String, u64)> = Vec::new();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || format!("Line {}: not an OGM chapter line", number + 1);
+        let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+        let key = key.trim().to_ascii_uppercase();
+        if !key.starts_with("CHAPTER") {
+            return Err(invalid());
+        }
+        if let Some(id) = key.strip_suffix("NAME") {
+            names.insert(id.to_string(), value.trim().to_string());
+        } else {
+            let start = parse_chapter_time(value)
+                .ok_or_else(|| format!("Line {}: invalid time {}", number + 1, value.trim()))?;
+            starts.push((key, start));
+        }
+    }
+    Ok(starts
+        .into_iter()
+        .map(|(id, start_ns)| Chapter {
+            start_ns,
+            name: names.remove(&id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+fn xml_element_text<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let start = block.find(&open)? + open.len();
+    let end = block[start..].find(&format!("</{tag}>"))? + start;
+    Some(block[start..end].trim())
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `YYYY-MM-DD` of a Unix timestamp, in UTC.
+fn utc_date(unix_seconds: u64) -> String {
+    // Civil-from-days over 400-year eras (Howard Hinnant's algorithm)
+    let days = (unix_seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// ISO 8601 `YYYY-MM-DDTHH:MM:SS.mmmZ` of a time since the Unix epoch.
+fn utc_timestamp(since_epoch: Duration) -> String {
+    let seconds = since_epoch.as_secs();
+    let time_of_day = seconds % 86_400;
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        utc_date(seconds),
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn expand_comment_tag(template: &str, job: &MuxJobRequest, unix_seconds: u64) -> String {
+    template
+        .replace("{version}", env!("CARGO_PKG_VERSION"))
+        .replace("{date}", &utc_date(unix_seconds))
+        .replace("{source}", &job.video.name)
+        .replace("{preset}", job.preset_name.as_deref().unwrap_or_default())
+}
+
+/// A Matroska tags file with one global COMMENT tag.
+fn comment_tag_xml(comment: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE Tags SYSTEM \"matroskatags.dtd\">\n\
+         <Tags>\n  <Tag>\n    <Targets />\n    <Simple>\n      <Name>COMMENT</Name>\n      \
+         <String>{}</String>\n    </Simple>\n  </Tag>\n</Tags>\n",
+        escape_xml(comment)
+    )
+}
+
+/// Writes the job's comment tag to a temp file for `--global-tags`. Like the edition
+/// files, it's deleted when dropped, so it has to outlive the mkvmerge run.
+fn comment_tag_file(
+    job: &MuxJobRequest,
+    settings: &MuxSettings,
+) -> Result<Option<tempfile::NamedTempFile>, String> {
+    let Some(template) = settings
+        .comment_tag
+        .as_deref()
+        .filter(|template| !template.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut file = tempfile::Builder::new()
+        .prefix("tags-")
+        .suffix(".xml")
+        .tempfile()
+        .map_err(|e| format!("Failed to create tags file: {e}"))?;
+    file.write_all(comment_tag_xml(&expand_comment_tag(template, job, now)).as_bytes())
+        .map_err(|e| format!("Failed to write tags file: {e}"))?;
+    Ok(Some(file))
+}
+
+/// Reads the start time and first display string of every `ChapterAtom`, nested atoms
+/// included, in document order.
+fn parse_xml_chapters(text: &str) -> Result<Vec<Chapter>, String> {
+    if !text.contains("<Chapters>") {
+        return Err("Not a Matroska chapter XML file".to_string());
+    }
+    text.split("<ChapterAtom>")
+        .skip(1)
+        .enumerate()
+        .map(|(index, atom)| {
+            let atom = atom.split("<ChapterAtom>").next().unwrap_or(atom);
+            let start = xml_element_text(atom, "ChapterTimeStart")
+                .and_then(parse_chapter_time)
+                .ok_or_else(|| format!("Chapter {}: missing or invalid start time", index + 1))?;
+            Ok(Chapter {
+                start_ns: start,
+                name: xml_element_text(atom, "ChapterString")
+                    .map(unescape_xml)
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+fn chapters_to_ogm(chapters: &[Chapter]) -> String {
+    let width = chapters.len().to_string().len().max(2);
+    let mut ogm = String::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        let id = format!("CHAPTER{:0width$}", index + 1);
+        ogm.push_str(&format!(
+            "{id}={}\n{id}NAME={}\n",
+            format_chapter_time(chapter.start_ns, 3),
+            chapter.name
+        ));
+    }
+    ogm
+}
+
+fn chapters_to_xml(chapters: &[Chapter]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE Chapters SYSTEM \"matroskachapters.dtd\">\n<Chapters>\n  <EditionEntry>\n",
+    );
+    for chapter in chapters {
+        xml.push_str(&format!(
+            "    <ChapterAtom>\n      <ChapterTimeStart>{}</ChapterTimeStart>\n      <ChapterDisplay>\n        <ChapterString>{}</ChapterString>\n      </ChapterDisplay>\n    </ChapterAtom>\n",
+            format_chapter_time(chapter.start_ns, 9),
+            escape_xml(&chapter.name)
+        ));
+    }
+    xml.push_str("  </EditionEntry>\n</Chapters>\n");
+    xml
+}
+
+/// Sets `EditionFlagDefault` on the first edition and `EditionFlagOrdered` on every
+/// edition of chapter XML, replacing the flags already there. `None` leaves a flag alone.
+fn set_edition_flags(xml: &str, default_edition: Option<bool>, ordered: Option<bool>) -> String {
+    let mut xml = xml.to_string();
+    for (tag, value) in [
+        ("EditionFlagDefault", default_edition),
+        ("EditionFlagOrdered", ordered),
+    ] {
+        if value.is_none() {
+            continue;
+        }
+        let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+        while let Some(start) = xml.find(&open) {
+            let Some(end) = xml[start..].find(&close) else {
+                break;
+            };
+            xml.replace_range(start..start + end + close.len(), "");
+        }
+    }
+    let flag = |tag: &str, value: bool| format!("\n    <{tag}>{}</{tag}>", u8::from(value));
+    let mut flagged = String::new();
+    for (index, part) in xml.split("<EditionEntry>").enumerate() {
+        if index > 0 {
+            flagged.push_str("<EditionEntry>");
+            if let Some(value) = default_edition.filter(|_| index == 1) {
+                flagged.push_str(&flag("EditionFlagDefault", value));
+            }
+            if let Some(value) = ordered {
+                flagged.push_str(&flag("EditionFlagOrdered", value));
+            }
+        }
+        flagged.push_str(part);
+    }
+    flagged
+}
+
+/// Chapter files with edition flags, rewritten as flagged XML temp files that the
+/// returned job points at instead. The temp files are removed when dropped, so they
+/// have to outlive the mkvmerge run.
+fn edition_flagged_job(
+    job: &MuxJobRequest,
+) -> Result<(MuxJobRequest, Vec<tempfile::NamedTempFile>), String> {
+    let mut job = job.clone();
+    let mut files = Vec::new();
+    for chapter in &mut job.chapters {
+        let flagged = chapter.default_edition.is_some() || chapter.ordered.is_some();
+        if !flagged || chapter.source.as_deref() == Some("video") {
+            continue;
+        }
+        let text = fs::read_to_string(&chapter.path)
+            .map_err(|e| format!("Failed to read {}: {e}", chapter.path))?;
+        let xml = if text
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with('<')
+        {
+            text
+        } else {
+            convert_chapter_text(&text, ChapterFormat::Xml)?
+        };
+        let mut file = tempfile::Builder::new()
+            .prefix("chapters-")
+            .suffix(".xml")
+            .tempfile()
+            .map_err(|e| format!("Failed to create chapter file: {e}"))?;
+        file.write_all(
+            set_edition_flags(&xml, chapter.default_edition, chapter.ordered).as_bytes(),
+        )
+        .map_err(|e| format!("Failed to write chapter file: {e}"))?;
+        chapter.path = file.path().to_string_lossy().to_string();
+        files.push(file);
+    }
+    Ok((job, files))
+}
+
+/// Parses OGM or XML chapter text (detected from its content) and writes it in `to`.
+fn convert_chapter_text(text: &str, to: ChapterFormat) -> Result<String, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let chapters = if text.trim_start().starts_with('<') {
+        parse_xml_chapters(text)?
+    } else {
+        parse_ogm_chapters(text)?
+    };
+    if chapters.is_empty() {
+        return Err("No chapters found".to_string());
+    }
+    Ok(match to {
+        ChapterFormat::Ogm => chapters_to_ogm(&chapters),
+        ChapterFormat::Xml => chapters_to_xml(&chapters),
+    })
+}
+
+/// Converts a chapter file between the OGM and XML layouts and returns the new text;
+/// nothing is written to disk.
+#[tauri::command]
+fn convert_chapters(path: String, to: ChapterFormat) -> Result<String, String> {
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    convert_chapter_text(&text, to)
+}
+
+/// XML files mkvmerge takes alongside the media.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum XmlFileKind {
+    Chapters,
+    Tags,
+}
+
+impl XmlFileKind {
+    /// The root element and the child it must hold at least one of.
+    fn expected_elements(self) -> (&'static str, &'static str) {
+        match self {
+            XmlFileKind::Chapters => ("Chapters", "EditionEntry"),
+            XmlFileKind::Tags => ("Tags", "Tag"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct XmlIssue {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct XmlValidation {
+    errors: Vec<XmlIssue>,
+    /// The file re-indented, when it parsed.
+    formatted: Option<String>,
+}
+
+#[derive(Debug)]
+enum XmlNode {
+    Element {
+        name: String,
+        /// Attributes exactly as written, with their leading whitespace.
+        attributes: String,
+        children: Vec<XmlNode>,
+        offset: usize,
+    },
+    Text(String),
+    /// Declarations, doctypes and comments, kept verbatim.
+    Markup(String),
+}
+
+/// 1-based line and column of a byte offset.
+fn text_position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn xml_issue(text: &str, offset: usize, message: String) -> XmlIssue {
+    let (line, column) = text_position(text, offset);
+    XmlIssue {
+        line,
+        column,
+        message,
+    }
+}
+
+fn is_xml_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
+
+/// Reads the document into its top-level nodes. Only well-formedness is checked: tags
+/// must nest and close, and text must sit inside the root element.
+fn parse_xml_nodes(text: &str) -> Result<Vec<XmlNode>, XmlIssue> {
+    let mut top_level = Vec::new();
+    // Open elements: name, attributes, children so far, offset of the `<`
+    let mut open: Vec<(String, String, Vec<XmlNode>, usize)> = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let terminated = |terminator: &str, what: &str| {
+            rest.find(terminator)
+                .map(|end| end + terminator.len())
+                .ok_or_else(|| xml_issue(text, pos, format!("Unterminated {what}")))
+        };
+        let (node, len) = if rest.starts_with("<!--") {
+            let len = terminated("-->", "comment")?;
+            (Some(XmlNode::Markup(rest[..len].to_string())), len)
+        } else if rest.starts_with("<?") {
+            let len = terminated("?>", "declaration")?;
+            (Some(XmlNode::Markup(rest[..len].to_string())), len)
+        } else if rest.starts_with("<!") {
+            let len = terminated(">", "declaration")?;
+            (Some(XmlNode::Markup(rest[..len].to_string())), len)
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let len = terminated(">", "closing tag")?;
+            let name = tag[..len - 3].trim();
+            let Some((open_name, attributes, children, offset)) = open.pop() else {
+                return Err(xml_issue(
+                    text,
+                    pos,
+                    format!("Closing tag </{name}> has no matching opening tag"),
+                ));
+            };
+            if open_name != name {
+                let (line, _) = text_position(text, offset);
+                return Err(xml_issue(
+                    text,
+                    pos,
+                    format!("Expected </{open_name}> (opened on line {line}) but found </{name}>"),
+                ));
+            }
+            let element = XmlNode::Element {
+                name: open_name,
+                attributes,
+                children,
+                offset,
+            };
+            (Some(element), len)
+        } else if rest.starts_with('<') {
+            let len = terminated(">", "tag")?;
+            let inner = &rest[1..len - 1];
+            let (inner, self_closing) = match inner.strip_suffix('/') {
+                Some(inner) => (inner, true),
+                None => (inner, false),
+            };
+            let name_len = inner.find(char::is_whitespace).unwrap_or(inner.len());
+            let (name, attributes) = inner.split_at(name_len);
+            if !is_xml_name(name) || attributes.contains('<') {
+                return Err(xml_issue(text, pos, format!("Malformed tag <{inner}>")));
+            }
+            let attributes = attributes.trim_end().to_string();
+            if self_closing {
+                let element = XmlNode::Element {
+                    name: name.to_string(),
+                    attributes,
+                    children: Vec::new(),
+                    offset: pos,
+                };
+                (Some(element), len)
+            } else {
+                open.push((name.to_string(), attributes, Vec::new(), pos));
+                (None, len)
+            }
+        } else {
+            let len = rest.find('<').unwrap_or(rest.len());
+            let content = rest[..len].trim();
+            if content.is_empty() {
+                (None, len)
+            } else if open.is_empty() {
+                return Err(xml_issue(
+                    text,
+                    pos + rest[..len].find(content).unwrap_or(0),
+                    "Text outside the root element".to_string(),
+                ));
+            } else {
+                (Some(XmlNode::Text(content.to_string())), len)
+            }
+        };
+        if let Some(node) = node {
+            match open.last_mut() {
+                Some((_, _, children, _)) => children.push(node),
+                None => top_level.push(node),
+            }
+        }
+        pos += len;
+    }
+    if let Some((name, _, _, offset)) = open.pop() {
+        return Err(xml_issue(text, offset, format!("<{name}> is never closed")));
+    }
+    Ok(top_level)
+}
+
+fn write_xml_node(node: &XmlNode, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match node {
+        XmlNode::Element {
+            name,
+            attributes,
+            children,
+            ..
+        } => match children.as_slice() {
+            [] => out.push_str(&format!("{indent}<{name}{attributes}/>\n")),
+            [XmlNode::Text(content)] => {
+                out.push_str(&format!("{indent}<{name}{attributes}>{content}</{name}>\n"))
+            }
+            _ => {
+                out.push_str(&format!("{indent}<{name}{attributes}>\n"));
+                for child in children {
+                    write_xml_node(child, depth + 1, out);
+                }
+                out.push_str(&format!("{indent}</{name}>\n"));
+            }
+        },
+        XmlNode::Text(content) | XmlNode::Markup(content) => {
+            out.push_str(&format!("{indent}{content}\n"))
+        }
+    }
+}
+
+/// Checks chapter or tags XML for well-formedness and the root element mkvmerge expects,
+/// returning the problems with their line numbers and, when it parsed, a re-indented copy.
+fn validate_xml_text(text: &str, kind: XmlFileKind) -> XmlValidation {
+    let text = text.trim_start_matches('\u{feff}');
+    let nodes = match parse_xml_nodes(text) {
+        Ok(nodes) => nodes,
+        Err(issue) => {
+            return XmlValidation {
+                errors: vec![issue],
+                formatted: None,
+            }
+        }
+    };
+
+    let (root_name, child_name) = kind.expected_elements();
+    let roots: Vec<(&str, &[XmlNode], usize)> = nodes
+        .iter()
+        .filter_map(|node| match node {
+            XmlNode::Element {
+                name,
+                children,
+                offset,
+                ..
+            } => Some((name.as_str(), children.as_slice(), *offset)),
+            _ => None,
+        })
+        .collect();
+    let mut errors = Vec::new();
+    match roots.as_slice() {
+        [] => errors.push(xml_issue(text, 0, "No root element".to_string())),
+        [(name, children, offset)] => {
+            if *name != root_name {
+                errors.push(xml_issue(
+                    text,
+                    *offset,
+                    format!("Root element is <{name}>, expected <{root_name}>"),
+                ));
+            } else if !children
+                .iter()
+                .any(|child| matches!(child, XmlNode::Element { name, .. } if name == child_name))
+            {
+                errors.push(xml_issue(
+                    text,
+                    *offset,
+                    format!("<{root_name}> has no <{child_name}>"),
+                ));
+            }
+        }
+        [_, (_, _, offset), ..] => errors.push(xml_issue(
+            text,
+            *offset,
+            "More than one root element".to_string(),
+        )),
+    }
+
+    let mut formatted = String::new();
+    for node in &nodes {
+        write_xml_node(node, 0, &mut formatted);
+    }
+    XmlValidation {
+        errors,
+        formatted: Some(formatted),
+    }
+}
+
+/// Checks a chapter or tags XML file before it's used in a batch; nothing is written.
+#[tauri::command]
+fn validate_xml_file(path: String, kind: XmlFileKind) -> Result<XmlValidation, String> {
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    Ok(validate_xml_text(&text, kind))
+}
+
+#[tauri::command]
+fn set_id_seed(seed: Option<u64>) -> Result<(), String> {
+    set_seeded_ids(seed);
+    Ok(())
+}
+
+#[tauri::command]
+fn check_dependencies() -> Vec<DependencyStatus> {
+    TOOL_DEPENDENCIES
+        .iter()
+        .map(|(tool, version_arg)| dependency_status(tool, version_arg))
+        .collect()
+}
+
+#[tauri::command]
+fn get_stats(state: State<AppState>) -> Result<UsageStats, String> {
+    read_stats(&state.paths.stats_path)
+}
+
+#[tauri::command]
+fn reset_stats(state: State<AppState>) -> Result<(), String> {
+    write_stats(&state.paths.stats_path, &UsageStats::default())
+}
+
+#[tauri::command]
+fn tail_log(state: State<AppState>, lines: usize) -> Result<Vec<String>, String> {
+    read_log_tail(&state.paths.log_path, lines)
+}
+
+#[tauri::command]
+fn open_log_file(app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    if !state.paths.log_path.exists() {
+        File::create(&state.paths.log_path)
+            .map_err(|e| format!("Failed to create log file: {e}"))?;
+    }
+    let path_string = state.paths.log_path.to_string_lossy().to_string();
+    if tauri::api::shell::open(&app.shell_scope(), path_string.clone(), None).is_ok() {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let status = Command::new("open")
+            .arg(&path_string)
+            .status()
+            .map_err(|e| format!("Failed to open log file: {e}"))?;
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let status = Command::new("cmd")
+            .args(["/C", "start", "", &path_string])
+            .status()
+            .map_err(|e| format!("Failed to open log file: {e}"))?;
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let status = Command::new("xdg-open")
+            .arg(&path_string)
+            .status()
+            .map_err(|e| format!("Failed to open log file: {e}"))?;
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    Err("Failed to open log file".to_string())
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SelfTestStep {
+    name: String,
+    passed: bool,
+    elapsed_ms: u64,
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SelfTestReport {
+    passed: bool,
+    elapsed_ms: u64,
+    steps: Vec<SelfTestStep>,
+}
+
+const SELF_TEST_SUBTITLE: &str = "1\n00:00:00,000 --> 00:00:01,000\nMkvBatchMux self-test\n\n2\n00:00:01,000 --> 00:00:02,000\nSecond line\n";
+
+fn self_test_settings(destination: &Path) -> MuxSettings {
+    MuxSettings {
+        destination_dir: destination.to_string_lossy().to_string(),
+        add_crc: true,
+        abort_on_errors: true,
+        max_parallel_jobs: Some(1),
+        discard_old_attachments: false,
+        remove_global_tags: false,
+        ..MuxSettings::default()
+    }
+}
+
+/// Runs one self-test step, recording its timing and outcome. Returns false when it failed.
+fn run_self_test_step<F>(steps: &mut Vec<SelfTestStep>, name: &str, step: F) -> bool
+where
+    F: FnOnce() -> Result<Option<String>, String>,
+{
+    let started = Instant::now();
+    let (passed, detail) = match step() {
+        Ok(detail) => (true, detail),
+        Err(error) => (false, Some(error)),
+    };
+    steps.push(SelfTestStep {
+        name: name.to_string(),
+        passed,
+        elapsed_ms: elapsed_ms(started),
+        detail,
+    });
+    passed
+}
+
+/// Builds a subtitle-only MKV, muxes it again through the regular command builder and checks
+/// that the result probes cleanly.
+fn self_test(state: &AppState) -> SelfTestReport {
+    let started = Instant::now();
+    let mut steps = Vec::new();
+    let workdir = match tempfile::tempdir() {
+        Ok(dir) => dir,
+        Err(error) => {
+            steps.push(SelfTestStep {
+                name: "Create work folder".to_string(),
+                passed: false,
+                elapsed_ms: 0,
+                detail: Some(error.to_string()),
+            });
+            return SelfTestReport {
+                passed: false,
+                elapsed_ms: elapsed_ms(started),
+                steps,
+            };
+        }
+    };
+    let subtitle_path = workdir.path().join("self-test.srt");
+    let sample_path = workdir.path().join("self-test.mkv");
+    let destination = workdir.path().join("output");
+    let mut video: Option<VideoFileInfo> = None;
+    let mut muxed: Option<PathBuf> = None;
+
+    let passed = run_self_test_step(&mut steps, "Find mkvmerge", || {
+        if mkvmerge_available() {
+            Ok(None)
+        } else {
+            Err("mkvmerge was not found or failed to run".to_string())
+        }
+    }) && run_self_test_step(&mut steps, "Create sample", || {
+        fs::write(&subtitle_path, SELF_TEST_SUBTITLE)
+            .map_err(|e| format!("Failed to write sample subtitle: {e}"))?;
+        let output = hidden_command("mkvmerge")
+            .arg("--output")
+            .arg(&sample_path)
+            .arg(&subtitle_path)
+            .output()
+            .map_err(|e| format!("Failed to start mkvmerge: {e}"))?;
+        if output.status.code().unwrap_or(2) > 1 || !sample_path.exists() {
+            return Err(String::from_utf8_lossy(&output.stdout).trim().to_string());
+        }
+        Ok(None)
+    }) && run_self_test_step(&mut steps, "Probe sample", || {
+        let info =
+            probe_mkvmerge(&sample_path)?.ok_or_else(|| "mkvmerge is not available".to_string())?;
+        let tracks = parse_mkvmerge_tracks(&info);
+        if tracks.is_empty() {
+            return Err("The sample has no tracks".to_string());
+        }
+        let detail = format!("{} track(s)", tracks.len());
+        video = Some(VideoFileInfo {
+            id: generate_id("video"),
+            name: "self-test.mkv".to_string(),
+            path: sample_path.to_string_lossy().to_string(),
+            size: fs::metadata(&sample_path).map(|m| m.len()).unwrap_or(0),
+            duration: parse_mkvmerge_duration(&info),
+            duration_seconds: parse_mkvmerge_duration_seconds(&info),
+            fps: None,
+            status: "pending".to_string(),
+            tracks,
+            probe_error: None,
+            warning: None,
+            sources: HashMap::new(),
+            dolby_vision_profile: None,
+            probe_warning: None,
+        });
+        Ok(Some(detail))
+    }) && run_self_test_step(&mut steps, "Mux", || {
+        let video = video.take().ok_or_else(|| "No sample to mux".to_string())?;
+        let settings = self_test_settings(&destination);
+        let job = MuxJobRequest {
+            id: generate_id("self-test"),
+            video,
+            audios: Vec::new(),
+            subtitles: Vec::new(),
+            chapters: Vec::new(),
+            attachments: Vec::new(),
+            output_name: None,
+            preset_name: None,
+            destination_dir: None,
+            append_sources: Vec::new(),
+            abort_on_errors: None,
+            poster_image: None,
+            keep_track_name_patterns: None,
+            probed: ProbedInputs::default(),
+        };
+        prepare_output_dir(&job, &settings)?;
+        let (output_path, _final_path, _) = get_output_paths(&job, &settings);
+        let args = build_mkvmerge_command(&job, &settings, &output_path, state);
+        let output = hidden_command("mkvmerge")
+            .args(&args)
+            .output()
+            .map_err(|e| format!("Failed to start mkvmerge: {e}"))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let last_progress = stdout.lines().filter_map(parse_progress).last();
+        if output.status.code().unwrap_or(2) > 1 || !output_path.exists() {
+            let tail: Vec<&str> = stdout.lines().filter(|l| is_diagnostic_line(l)).collect();
+            return Err(format!(
+                "mkvmerge exited with {:?}: {}",
+                output.status.code(),
+                tail.join("\n")
+            ));
+        }
+        muxed = Some(apply_crc_naming(
+            &output_path,
+            &settings,
+            &mut |_| {},
+            &|| false,
+        ));
+        Ok(last_progress.map(|progress| format!("Last progress: {progress}%")))
+    }) && run_self_test_step(&mut steps, "Verify output", || {
+        let output = muxed
+            .as_ref()
+            .ok_or_else(|| "No output was produced".to_string())?;
+        let info =
+            probe_mkvmerge(output)?.ok_or_else(|| "mkvmerge is not available".to_string())?;
+        let tracks = parse_mkvmerge_tracks(&info);
+        if tracks.is_empty() {
+            return Err("The muxed file has no tracks".to_string());
+        }
+        Ok(Some(
+            output
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ))
+    });
+
+    SelfTestReport {
+        passed,
+        elapsed_ms: elapsed_ms(started),
+        steps,
+    }
+}
+
+#[tauri::command]
+fn run_self_test(state: State<AppState>) -> Result<SelfTestReport, String> {
+    let report = self_test(&state);
+    for step in &report.steps {
+        let _ = write_log_line(
+            &state.paths,
+            &format!(
+                "Self-test {}: {} ({} ms){}",
+                step.name,
+                if step.passed { "ok" } else { "FAILED" },
+                step.elapsed_ms,
+                step.detail
+                    .as_ref()
+                    .map(|detail| format!(" - {detail}"))
+                    .unwrap_or_default()
+            ),
+        );
+    }
+    Ok(report)
+}
+
+fn main() {
+    tauri::Builder::default()
+        .setup(|app| {
+            let paths = resolve_app_paths(tauri::api::path::app_data_dir(&app.config()))
+                .ok_or("Failed to resolve app data directory")?;
+            ensure_dir(&paths.app_data_dir)?;
+            let state = AppState {
+                paths,
+                mux_state: Arc::new(Mutex::new(MuxState::default())),
+                finished_outputs_lock: Arc::new(Mutex::new(())),
+                mkvmerge_version: Arc::new(OnceLock::new()),
+                mkvmerge_languages: Arc::new(OnceLock::new()),
+            };
+            app.manage(state);
+            if let Some(seed) = std::env::var(ID_SEED_ENV)
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+            {
+                set_seeded_ids(Some(seed));
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_app_paths,
+            load_options,
+            read_options_only,
+            relocate_preset_paths,
+            save_options,
+            scan_media,
+            scan_media_with_summary,
+            inspect_paths,
+            inspect_paths_stream,
+            start_muxing,
+            resume_batch,
+            check_destination_writable,
+            preview_mux,
+            preview_job,
+            analyze_batch,
+            describe_output,
+            resolve_track_order,
+            pause_muxing,
+            resume_muxing,
+            stop_muxing,
+            stop_keep_outputs,
+            reset_mux_state,
+            validate_jobs,
+            run_single_job,
+            build_jobs_from_preset,
+            group_split_parts,
+            build_defaults_from_favorites,
+            find_orphan_externals,
+            match_by_folder,
+            compact_saved_queue,
+            get_last_batch_settings,
+            get_supported_languages,
+            diff_commands,
+            get_last_job_command,
+            estimate_track_sizes,
+            open_log_file,
+            tail_log,
+            check_dependencies,
+            invalidate_probe_cache,
+            clear_probe_cache,
+            get_stats,
+            reset_stats,
+            estimate_batch_time,
+            set_id_seed,
+            preview_rename,
+            rename_files,
+            preview_clean_title,
+            convert_chapters,
+            validate_xml_file,
+            run_self_test,
+            session::save_session,
+            session::load_session,
+            session::clear_session,
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if matches!(event, RunEvent::ExitRequested { .. } | RunEvent::Exit) {
+                shutdown_muxing(&app.state::<AppState>());
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn external_tracks_carry_per_track_bitrates() {
+        let mkvmerge = json!({
+            "tracks": [
+                { "id": 0, "type": "audio", "codec": "AAC", "properties": { "language": "eng" } },
+                { "id": 1, "type": "audio", "codec": "AC-3", "properties": { "language": "jpn" } }
+            ]
+        });
+        let mediainfo = json!({
+            "media": { "track": [
+                { "@type": "General" },
+                { "@type": "Audio", "BitRate": "192000" },
+                { "@type": "Audio", "BitRate": "640000" }
+            ] }
+        });
+        let mut tracks = parse_mkvmerge_tracks(&mkvmerge);
+        apply_mediainfo_audio_bitrates(&mut tracks, &mediainfo);
+        let bitrates: Vec<Option<u64>> = tracks.iter().map(|t| t.bitrate).collect();
+        assert_eq!(bitrates, vec![Some(192_000), Some(640_000)]);
+    }
+
+    fn test_state(dir: &Path) -> AppState {
+        AppState {
+            paths: AppPaths::in_dir(dir.to_path_buf()),
+            mux_state: Arc::new(Mutex::new(MuxState::default())),
+            finished_outputs_lock: Arc::new(Mutex::new(())),
+            mkvmerge_version: Arc::new(OnceLock::new()),
+            mkvmerge_languages: Arc::new(OnceLock::new()),
+        }
+    }
+
+    fn test_settings() -> MuxSettings {
+        serde_json::from_value(json!({
+            "destinationDir": "",
+            "overwriteSource": true,
+            "addCrc": false,
+            "removeOldCrc": false,
+            "keepLogFile": false,
+            "abortOnErrors": false,
+            "maxParallelJobs": 1,
+            "onlyKeepAudiosEnabled": false,
+            "onlyKeepSubtitlesEnabled": false,
+            "onlyKeepAudioLanguages": [],
+            "onlyKeepSubtitleLanguages": [],
+            "discardOldChapters": false,
+            "discardOldAttachments": false,
+            "allowDuplicateAttachments": false,
+            "attachmentsExpertMode": false,
+            "removeGlobalTags": false,
+            "useMkvpropedit": false
+        }))
+        .unwrap()
+    }
+
+    fn test_track(id: &str, track_type: &str, language: &str) -> TrackInfo {
+        TrackInfo {
+            id: id.to_string(),
+            track_type: track_type.to_string(),
+            codec: None,
+            codec_id: None,
+            language: Some(language.to_string()),
+            name: None,
+            is_default: None,
+            is_forced: None,
+            bitrate: None,
+            action: Some("keep".to_string()),
+            original_name: None,
+            original_default: None,
+            delay: None,
+        }
+    }
+
+    fn test_job(video_path: &str, tracks: Vec<TrackInfo>) -> MuxJobRequest {
+        serde_json::from_value(json!({
+            "id": "job-1",
+            "video": {
+                "id": "video-1",
+                "name": "video.mkv",
+                "path": video_path,
+                "size": 0,
+                "duration": null,
+                "fps": null,
+                "status": "pending",
+                "tracks": tracks
+            },
+            "audios": [],
+            "subtitles": [],
+            "chapters": [],
+            "attachments": []
+        }))
+        .unwrap()
+    }
+
+    fn test_external(path: &str, file_type: &str) -> ExternalFileInfo {
+        serde_json::from_value(json!({
+            "id": format!("{file_type}-1"),
+            "name": Path::new(path).file_name().and_then(|n| n.to_str()),
+            "path": path,
+            "type": file_type
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn gui_mode_flag_can_be_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job("/media/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        let output = Path::new("/out/show.mkv");
+
+        let args = build_mkvmerge_command(&job, &settings, output, &state);
+        assert_eq!(args[0], "--gui-mode");
+
+        settings.gui_mode = false;
+        let args = build_mkvmerge_command(&job, &settings, output, &state);
+        assert!(!args.iter().any(|arg| arg == "--gui-mode"));
+        assert_eq!(parse_progress("Progress: 45%"), Some(45));
+        assert_eq!(parse_progress("#GUI#progress 45%"), Some(45));
+    }
+
+    #[test]
+    fn missing_destination_tree_is_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("a").join("b").join("c");
+        let mut settings = test_settings();
+        settings.destination_dir = destination.to_string_lossy().to_string();
+        settings.overwrite_source = false;
+        let job = test_job("/media/show.mkv", Vec::new());
+
+        let output_dir = prepare_output_dir(&job, &settings).unwrap();
+        assert_eq!(output_dir, destination);
+        assert!(destination.is_dir());
+    }
+
+    #[test]
+    fn scan_skips_files_older_than_cutoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_file = dir.path().join("old.mkv");
+        let new_file = dir.path().join("new.mkv");
+        File::create(&old_file)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1_000))
+            .unwrap();
+        File::create(&new_file)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(5_000))
+            .unwrap();
+        let request = ScanRequest {
+            folder: dir.path().to_string_lossy().to_string(),
+            extensions: vec!["mkv".to_string()],
+            recursive: false,
+            file_type: "video".to_string(),
+            include_tracks: false,
+            modified_since: Some(3_000),
+            duration_source: DurationSource::Auto,
+            skip_trackless: false,
+            metadata_level: MetadataLevel::Full,
+            verify_magic: false,
+            max_concurrent_probes: DEFAULT_MAX_CONCURRENT_PROBES,
+            require_languages: Vec::new(),
+            require_language_types: Vec::new(),
+        };
+
+        let (files, skipped) = scan_files_counting(&request, &HashMap::new()).unwrap();
+        assert_eq!(files, vec![new_file]);
+        assert_eq!(skipped, 1);
+    }
+
+    #[test]
+    fn stderr_tail_keeps_last_lines_for_error_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        for index in 0..12 {
+            record_stderr_line(&state, "job-1", &format!("line {index}"));
+        }
+        record_stderr_line(&state, "job-2", "other job");
+
+        let detail = take_stderr_tail(&state, "job-1").unwrap();
+        let lines: Vec<&str> = detail.lines().collect();
+        assert_eq!(lines.len(), STDERR_TAIL_LINES);
+        assert_eq!(lines.first(), Some(&"line 2"));
+        assert_eq!(lines.last(), Some(&"line 11"));
+        assert!(take_stderr_tail(&state, "job-1").is_none());
+        assert!(is_diagnostic_line(
+            "Error: The file 'x.ass' could not be opened"
+        ));
+        assert!(!is_diagnostic_line("Progress: 12%"));
+    }
+
+    #[test]
+    fn stopped_job_promotes_complete_temp_and_restores_source_otherwise() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("show.mkv");
+        let temp = dir.path().join("show#123.mkv");
+
+        fs::write(&source, b"original").unwrap();
+        fs::write(&temp, b"muxed").unwrap();
+        let kept = settle_stopped_output(&temp, &source, &source, true, true);
+        assert_eq!(kept, source);
+        assert_eq!(fs::read(&source).unwrap(), b"muxed");
+        assert!(!temp.exists());
+
+        fs::write(&source, b"original").unwrap();
+        fs::write(&temp, b"partial").unwrap();
+        let kept = settle_stopped_output(&temp, &source, &source, true, false);
+        assert_eq!(kept, source);
+        assert_eq!(fs::read(&source).unwrap(), b"original");
+        assert!(!temp.exists());
+
+        // Only a tool that exited on its own finished writing; a killed one did not
+        let mut settings = test_settings();
+        assert!(finished_writing(Some(1), &settings));
+        assert!(!finished_writing(None, &settings));
+        assert!(!finished_writing(Some(2), &settings));
+        settings.output_container = OutputContainer::Mp4;
+        assert!(!finished_writing(Some(1), &settings));
+    }
+
+    #[test]
+    fn output_name_override_is_sanitized_into_final_path() {
+        let mut settings = test_settings();
+        settings.destination_dir = "/out".to_string();
+        settings.overwrite_source = false;
+        let mut job = test_job("/media/video.mkv", Vec::new());
+        job.output_name = Some("Show: Episode 1?.mkv".to_string());
+
+        let (output_path, final_path, overwrite_mode) = get_output_paths(&job, &settings);
+        assert!(!overwrite_mode);
+        assert_eq!(final_path, Path::new("/out").join("Show_ Episode 1_.mkv"));
+        assert_eq!(output_path, final_path);
+    }
+
+    #[test]
+    fn output_equal_to_an_input_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("show.mp4");
+        let audio = dir.path().join("show.mkv");
+        fs::write(&video, b"").unwrap();
+        fs::write(&audio, b"").unwrap();
+        let mut job = test_job(&video.to_string_lossy(), Vec::new());
+        job.audios
+            .push(test_external(&audio.to_string_lossy(), "audio"));
+        let mut settings = test_settings();
+        settings.destination_dir = dir.path().to_string_lossy().to_string();
+        settings.overwrite_source = false;
+
+        let (output_path, _, _) = get_output_paths(&job, &settings);
+        let error = check_output_collision(&job, &output_path).unwrap_err();
+        assert!(error.contains("is also an input file"));
+
+        job.audios.clear();
+        assert!(check_output_collision(&job, &output_path).is_ok());
+    }
+
+    #[test]
+    fn matroska_chapter_source_is_added_as_chapters_only_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.chapters
+            .push(test_external("/media/chapters-source.mkv", "chapter"));
+        let settings = test_settings();
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(!args.iter().any(|arg| arg == "--chapters"));
+        let source_index = args
+            .iter()
+            .position(|arg| arg == "/media/chapters-source.mkv")
+            .unwrap();
+        assert_eq!(
+            &args[source_index - 6..source_index],
+            [
+                "--no-video",
+                "--no-audio",
+                "--no-subtitles",
+                "--no-attachments",
+                "--no-global-tags",
+                "--no-track-tags"
+            ]
+        );
+
+        let mut keep = test_external("/media/show.mkv", "chapter");
+        keep.source = Some("video".to_string());
+        job.chapters = vec![keep];
+        let mut settings = test_settings();
+        settings.discard_old_chapters = true;
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(!args.iter().any(|arg| arg == "--no-chapters"));
+        assert_eq!(args.last().map(String::as_str), Some("/media/show.mkv"));
+    }
+
+    #[test]
+    fn elapsed_time_is_reported_in_milliseconds() {
+        let started = Instant::now();
+        thread::sleep(Duration::from_millis(5));
+        assert!(elapsed_ms(started) >= 5);
+    }
+
+    #[test]
+    fn audio_can_be_taken_from_another_video() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/a.mkv", Vec::new());
+        let mut audio = test_external("/media/b.mkv", "audio");
+        audio.source = Some(VIDEO_AUDIO_SOURCE.to_string());
+        audio.included_track_ids = Some(vec![2]);
+        job.audios.push(audio.clone());
+
+        let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/out/a.mkv"), &state);
+        let source_index = args.iter().position(|arg| arg == "/media/b.mkv").unwrap();
+        let source_args = &args[..source_index];
+        let start = source_args
+            .iter()
+            .rposition(|arg| arg == "/media/a.mkv")
+            .unwrap();
+        let source_args = &source_args[start + 1..];
+        assert!(source_args.iter().any(|arg| arg == "--no-video"));
+        assert!(source_args.iter().any(|arg| arg == "--no-subtitles"));
+        assert!(source_args.ends_with(&["--audio-tracks".to_string(), "2".to_string()]));
+
+        audio.tracks = vec![test_track("0", "video", "und")];
+        assert!(video_audio_source_warning(&audio).is_some());
+        audio.tracks.push(test_track("2", "audio", "jpn"));
+        assert!(video_audio_source_warning(&audio).is_none());
+    }
+
+    #[test]
+    fn first_track_only_keeps_the_lowest_external_track() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut audio = test_external("/media/dub.mka", "audio");
+        audio.included_track_ids = Some(vec![3, 1, 2]);
+        audio.first_track_only = true;
+        job.audios.push(audio);
+
+        let output = Path::new("/out/show.mkv");
+        let args = build_mkvmerge_command(&job, &test_settings(), output, &state);
+        let tracks: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--audio-tracks")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(tracks, vec!["1"]);
+    }
+
+    #[test]
+    fn only_the_first_default_external_audio_stays_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        for path in ["/media/show.eng.aac", "/media/show.jpn.aac"] {
+            let mut audio = test_external(path, "audio");
+            audio.is_default = Some(true);
+            audio.track_id = Some(0);
+            job.audios.push(audio);
+        }
+
+        assert_eq!(external_default_warnings(&job).len(), 1);
+        let output = Path::new("/out/show.mkv");
+        let args = build_mkvmerge_command(&job, &test_settings(), output, &state);
+        let defaults = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag" && pair[1].ends_with(":yes"))
+            .count();
+        assert_eq!(defaults, 1);
+        let first = args
+            .iter()
+            .position(|arg| arg == "/media/show.eng.aac")
+            .unwrap();
+        let flag = args[..first]
+            .iter()
+            .rposition(|arg| arg == "--default-track-flag")
+            .unwrap();
+        assert_eq!(args[flag + 1], "0:yes");
+    }
+
+    #[test]
+    fn split_by_chapters_emits_argument_and_collects_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job("/media/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        settings.split = Some(SplitMode::Chapters("5,10".to_string()));
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let index = args.iter().position(|arg| arg == "--split").unwrap();
+        assert_eq!(args[index + 1], "chapters:5,10");
+
+        // mkvmerge writes numbered parts next to the requested output
+        let output = dir.path().join("show#123.mkv");
+        let stale = dir.path().join("show#123-003.mkv");
+        fs::write(&stale, b"").unwrap();
+        File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let started = SystemTime::now();
+        for name in ["show#123-002.mkv", "show#123-001.mkv", "show#123-extra.mkv"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let parts = split_part_paths(&output, started);
+        assert_eq!(
+            parts,
+            vec![
+                dir.path().join("show#123-001.mkv"),
+                dir.path().join("show#123-002.mkv")
+            ]
+        );
+        assert_eq!(
+            split_part_final_path(&parts[0], &output, &dir.path().join("show.mkv")),
+            dir.path().join("show-001.mkv")
+        );
+    }
+
+    #[test]
+    fn split_source_survives_an_empty_or_failed_promote() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let source = dir.path().join("show.mkv");
+        let output = dir.path().join("show#123.mkv");
+        fs::write(&source, b"source").unwrap();
+
+        // No parts written: the job fails and the source stays
+        let job = test_job(&source.to_string_lossy(), Vec::new());
+        let mut settings = test_settings();
+        settings.split = Some(SplitMode::Chapters("all".to_string()));
+        let mut events = Vec::new();
+        finalize_job(
+            &state,
+            &job,
+            &settings,
+            &output,
+            &source,
+            true,
+            Instant::now(),
+            0,
+            &mut |event| events.push(event),
+        );
+        assert_eq!(events.last().map(|e| e.status), Some(JobPhase::Error));
+        assert!(source.exists());
+
+        // A part that can't be moved stops the promote before the source goes
+        let written = dir.path().join("show#123-001.mkv");
+        let missing = dir.path().join("show#123-002.mkv");
+        fs::write(&written, b"part").unwrap();
+        let error = promote_split_parts(&[written.clone(), missing], &output, &source, &source)
+            .unwrap_err();
+        assert!(error.contains("show#123-002.mkv"), "{error}");
+        assert!(source.exists());
+
+        let moved = dir.path().join("show-001.mkv");
+        fs::rename(&moved, &written).unwrap();
+        assert_eq!(
+            promote_split_parts(&[written], &output, &source, &source).unwrap(),
+            vec![moved.clone()]
+        );
+        assert!(!source.exists());
+        assert!(moved.exists());
+    }
+
+    #[test]
+    fn duration_source_prefers_the_requested_probe() {
+        let mkvmerge = json!({ "container": { "properties": {} } });
+        let mediainfo = json!({
+            "media": { "track": [{ "@type": "General", "Duration": "83.5" }] }
+        });
+
+        let forced = select_duration(DurationSource::Mediainfo, Some(&mkvmerge), Some(&mediainfo));
+        assert!(forced.is_some());
+        let forced = forced.map(|(duration, _)| duration);
+        assert_eq!(forced, parse_duration(&mediainfo));
+
+        // With both probes reporting, the chosen one wins over the other
+        let mkvmerge = json!({ "container": { "properties": { "duration": 10_000_000_000u64 } } });
+        assert_eq!(
+            select_duration(DurationSource::Mediainfo, Some(&mkvmerge), Some(&mediainfo)),
+            parse_duration(&mediainfo).map(|duration| (duration, "mediainfo"))
+        );
+        assert_eq!(
+            select_duration(DurationSource::Auto, Some(&mkvmerge), Some(&mediainfo)),
+            parse_mkvmerge_duration(&mkvmerge).map(|duration| (duration, "mkvmerge"))
+        );
+    }
+
+    #[test]
+    fn field_sources_record_the_supplying_tool() {
+        let mkvmerge = json!({ "container": { "properties": { "duration": 10_000_000_000u64 } } });
+        let (_, duration_tool) =
+            select_duration(DurationSource::Auto, Some(&mkvmerge), None).unwrap();
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.bitrate = Some(192_000);
+        let tracks = vec![test_track("0", "video", "und"), audio];
+
+        let sources = field_sources(
+            Some(duration_tool),
+            Some("mediainfo"),
+            None,
+            &tracks,
+            "mkvmerge",
+            &[1],
+        );
+        assert_eq!(sources["duration"], "mkvmerge");
+        assert_eq!(sources["fps"], "mediainfo");
+        assert_eq!(sources["track:1:language"], "mkvmerge");
+        assert_eq!(sources["track:1:bitrate"], "mediainfo");
+        assert!(!sources.contains_key("bitrate"));
+    }
+
+    #[test]
+    fn self_test_passes_when_mkvmerge_is_installed() {
+        if !mkvmerge_available() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let report = self_test(&test_state(dir.path()));
+        assert!(report.passed, "{:?}", report.steps);
+        assert_eq!(report.steps.len(), 5);
+    }
+
+    #[test]
+    fn progress_is_clamped_and_reaches_100_once() {
+        assert_eq!(parse_progress("Progress: 250%"), Some(100));
+        assert_eq!(parse_progress("#GUI#progress 1000%"), Some(100));
+        assert_eq!(parse_progress("Progress: abc%"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let emitted: Vec<u8> = ["Progress: 99%", "100%", "Progress: 100%", "Progress: 100%"]
+            .iter()
+            .filter_map(|line| next_progress(&state, "job-1", line))
+            .collect();
+        assert_eq!(emitted, vec![99, 100]);
+        // 100 was already emitted, so the completion path doesn't synthesize another
+        assert!(!final_progress_pending(&state, "job-1"));
+
+        assert_eq!(next_progress(&state, "job-2", "Progress: 97%"), Some(97));
+        assert!(final_progress_pending(&state, "job-2"));
+    }
+
+    #[test]
+    fn no_default_subtitles_clears_every_subtitle_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut subtitle = test_track("2", "subtitle", "eng");
+        subtitle.is_default = Some(true);
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.is_default = Some(true);
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![test_track("0", "video", "und"), audio, subtitle],
+        );
+        let mut external = test_external("/media/show.ass", "subtitle");
+        external.is_default = Some(true);
+        external.track_id = Some(0);
+        job.subtitles.push(external);
+        let mut settings = test_settings();
+        settings.make_subtitle_default_language = Some("eng".to_string());
+        settings.no_default_subtitles = true;
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let flags: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(flags, vec!["1:yes", "2:no", "0:no"]);
+    }
+
+    #[test]
+    fn log_is_copied_to_keep_log_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        write_log_line(&state.paths, "Job job-1 completed successfully").unwrap();
+        let destination = dir.path().join("logs");
+
+        let copied = copy_log_to(&state.paths, &destination.to_string_lossy()).unwrap();
+        assert_eq!(copied, Some(destination.join("muxing_log_file.txt")));
+        let content = fs::read_to_string(destination.join("muxing_log_file.txt")).unwrap();
+        assert!(content.contains("Job job-1 completed successfully"));
+
+        assert_eq!(copy_log_to(&state.paths, "  ").unwrap(), None);
+    }
+
+    #[test]
+    fn language_remap_applies_before_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "und"),
+                test_track("2", "audio", "jpn"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["eng".to_string()];
+        settings.make_audio_default_language = Some("eng".to_string());
+        settings
+            .language_remap
+            .insert("und".to_string(), "eng".to_string());
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let value_after = |flag: &str| -> Vec<&str> {
+            args.windows(2)
+                .filter(|pair| pair[0] == flag)
+                .map(|pair| pair[1].as_str())
+                .collect()
+        };
+        assert_eq!(value_after("--audio-tracks"), vec!["1"]);
+        assert!(value_after("--language").contains(&"1:eng"));
+        assert!(value_after("--default-track-flag").contains(&"1:yes"));
+    }
+
+    #[test]
+    fn undefined_language_policy_leaves_sets_or_drops_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "und"),
+                test_track("2", "audio", "jpn"),
+                test_track("3", "subtitle", "undefined"),
+            ],
+        );
+        let mut settings = test_settings();
+        let args_for = |settings: &MuxSettings| {
+            build_mkvmerge_command(&job, settings, Path::new("/out/show.mkv"), &state)
+        };
+        let values_after = |args: &[String], flag: &str| -> Vec<String> {
+            args.windows(2)
+                .filter(|pair| pair[0] == flag)
+                .map(|pair| pair[1].clone())
+                .collect()
+        };
+
+        let args = args_for(&settings);
+        assert!(values_after(&args, "--language").contains(&"1:und".to_string()));
+        assert!(values_after(&args, "--audio-tracks").is_empty());
+
+        settings.undefined_language_policy = UndefinedPolicy::SetTo("eng".to_string());
+        let args = args_for(&settings);
+        let languages = values_after(&args, "--language");
+        assert!(languages.contains(&"0:und".to_string()));
+        assert!(languages.contains(&"1:eng".to_string()));
+        assert!(languages.contains(&"3:eng".to_string()));
+
+        // Tagged `eng` by the policy, the track passes the keep filter
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["eng".to_string()];
+        let args = args_for(&settings);
+        assert_eq!(values_after(&args, "--audio-tracks"), vec!["1"]);
+        assert!(language_setting_warnings(&job, &settings).is_empty());
+
+        settings.only_keep_audios_enabled = false;
+        settings.undefined_language_policy = UndefinedPolicy::Drop;
+        let args = args_for(&settings);
+        assert_eq!(values_after(&args, "--audio-tracks"), vec!["2"]);
+        assert_eq!(
+            values_after(&args, "--subtitle-tracks"),
+            Vec::<String>::new()
+        );
+        assert!(args.contains(&"--no-subtitles".to_string()));
+
+        // Only mkvmerge can drop the tracks
+        settings.use_mkvpropedit = true;
+        assert!(fast_mux_blocker(&job, &settings).is_some_and(|reason| reason.contains("drop")));
+        settings.undefined_language_policy = UndefinedPolicy::Leave;
+        assert_eq!(fast_mux_blocker(&job, &settings), None);
+
+        assert_eq!(undefined_language_policy_error(&settings), None);
+        settings.undefined_language_policy = UndefinedPolicy::SetTo(" ".to_string());
+        assert!(undefined_language_policy_error(&settings).is_some());
+        assert!(validate_job_list(std::slice::from_ref(&job), &settings)
+            .iter()
+            .any(|validation| validation
+                .errors
+                .iter()
+                .any(|error| error.contains("undetermined"))));
+    }
+
+    #[test]
+    fn input_checksums_are_verified_against_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.mkv");
+        let bad = dir.path().join("bad.mkv");
+        let unchecked = dir.path().join("unchecked.mkv");
+        for path in [&good, &bad, &unchecked] {
+            fs::write(path, b"abc").unwrap();
+        }
+        // sha256("abc")
+        let hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let sidecar = format!("{hash}  good.mkv\n");
+        fs::write(dir.path().join("good.mkv.sha256"), sidecar).unwrap();
+        fs::write(dir.path().join("bad.mkv.sha256"), "0".repeat(64)).unwrap();
+
+        assert!(verify_input_checksum(&good).is_ok());
+        assert!(verify_input_checksum(&unchecked).is_ok());
+        let error = verify_input_checksum(&bad).unwrap_err();
+        assert!(error.starts_with("Source corrupt"), "{error}");
+    }
+
+    #[test]
+    fn strip_track_names_can_target_subtitles_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.name = Some("Stereo".to_string());
+        let mut subtitle = test_track("2", "subtitle", "eng");
+        subtitle.name = Some("Subs by someone".to_string());
+        let job = test_job("/media/show.mkv", vec![audio, subtitle]);
+        let mut settings = test_settings();
+        settings.strip_track_names = serde_json::from_value(json!(["subtitle"])).unwrap();
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let names: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--track-name")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(names, vec!["1:Stereo", "2:"]);
+
+        let edits = build_mkvpropedit_args(&job, &settings);
+        let has_pair = |first: &str, second: &str| {
+            edits
+                .windows(2)
+                .any(|pair| pair[0] == first && pair[1] == second)
+        };
+        assert!(has_pair("--set", "name=Stereo"));
+        assert!(has_pair("--delete", "name"));
+
+        // Clearing imported names empties them but keeps names set in the app
+        settings.strip_track_names = StripTrackNames::default();
+        settings.clear_imported_track_names = true;
+        let mut edited = job.clone();
+        edited.video.tracks[1].original_name = Some("Subs by someone".to_string());
+        edited.video.tracks[1].name = Some("English".to_string());
+        for (job, expected) in [(&job, vec!["1:", "2:"]), (&edited, vec!["1:", "2:English"])] {
+            let args = build_mkvmerge_command(job, &settings, Path::new("/out/show.mkv"), &state);
+            let names: Vec<&str> = args
+                .windows(2)
+                .filter(|pair| pair[0] == "--track-name")
+                .map(|pair| pair[1].as_str())
+                .collect();
+            assert_eq!(names, expected);
+        }
+
+        // A bare `true` keeps meaning every track type
+        let all: StripTrackNames = serde_json::from_value(json!(true)).unwrap();
+        assert!(all.applies_to("audio") && all.applies_to("subtitle"));
+    }
+
+    #[test]
+    fn log_tail_returns_the_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        state.paths.log_timestamps.store(false, Ordering::Relaxed);
+        for index in 0..5000 {
+            write_log_line(&state.paths, &format!("line {index}")).unwrap();
+        }
+
+        let tail = read_log_tail(&state.paths.log_path, 3).unwrap();
+        assert_eq!(tail, vec!["line 4997", "line 4998", "line 4999"]);
+        let everything = read_log_tail(&state.paths.log_path, 10_000).unwrap();
+        assert_eq!(everything.len(), 5000);
+        assert!(read_log_tail(&dir.path().join("missing.txt"), 3)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn remove_track_tags_controls_the_source_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job("/media/show.mkv", Vec::new());
+        let output = Path::new("/out/show.mkv");
+        let mut settings = test_settings();
+
+        let args = build_mkvmerge_command(&job, &settings, output, &state);
+        assert!(!args.iter().any(|arg| arg == "--no-track-tags"));
+
+        settings.remove_track_tags = true;
+        let args = build_mkvmerge_command(&job, &settings, output, &state);
+        let position = |value: &str| args.iter().position(|arg| arg == value);
+        assert!(position("--no-track-tags").unwrap() < position("/media/show.mkv").unwrap());
+    }
+
+    #[test]
+    fn warns_when_a_configured_language_matches_no_track() {
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "eng"),
+                test_track("2", "subtitle", "eng"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.make_audio_default_language = Some("jpn".to_string());
+        settings.make_subtitle_default_language = Some("eng".to_string());
+
+        let warnings = language_setting_warnings(&job, &settings);
+        assert_eq!(
+            warnings,
+            vec!["No audio track in jpn to make default in video.mkv".to_string()]
+        );
+    }
+
+    #[test]
+    fn skip_trackless_drops_videos_without_a_video_track() {
+        let mut request: ScanRequest = serde_json::from_value(json!({
+            "folder": "/media",
+            "extensions": ["mkv"],
+            "recursive": false,
+            "type": "video",
+            "include_tracks": true,
+            "skip_trackless": true
+        }))
+        .unwrap();
+        let tracks = vec![
+            test_track("0", "video", "und"),
+            test_track("1", "audio", "eng"),
+        ];
+        let valid = serde_json::to_value(test_job("/media/show.mkv", tracks).video).unwrap();
+        let trackless =
+            serde_json::to_value(test_job("/media/notes.mkv", Vec::new()).video).unwrap();
+
+        let (kept, dropped) = drop_trackless(&request, vec![valid.clone(), trackless.clone()]);
+        assert_eq!(kept, vec![valid.clone()]);
+        assert_eq!(dropped, 1);
+
+        // A basic probe lists no tracks, so nothing can be told apart
+        request.metadata_level = MetadataLevel::Basic;
+        let (kept, dropped) = drop_trackless(&request, vec![valid.clone(), trackless.clone()]);
+        assert_eq!((kept.len(), dropped), (2, 0));
+
+        request.metadata_level = MetadataLevel::Full;
+        request.skip_trackless = false;
+        let (kept, dropped) = drop_trackless(&request, vec![valid, trackless]);
+        assert_eq!((kept.len(), dropped), (2, 0));
+    }
+
+    #[test]
+    fn require_languages_keeps_files_with_a_matching_track() {
+        let request: ScanRequest = serde_json::from_value(json!({
+            "folder": "/media",
+            "extensions": ["mkv"],
+            "recursive": false,
+            "type": "video",
+            "include_tracks": false,
+            "require_languages": ["jpn"],
+            "require_language_types": ["audio"]
+        }))
+        .unwrap();
+        assert_eq!(request.probes_tracks(), (true, MetadataLevel::Full));
+        let video = |path: &str, tracks: Vec<TrackInfo>| {
+            serde_json::to_value(test_job(path, tracks).video).unwrap()
+        };
+        let japanese = video(
+            "/media/a.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "JPN"),
+            ],
+        );
+        let english = video("/media/b.mkv", vec![test_track("1", "audio", "eng")]);
+        let subtitles_only = video("/media/c.mkv", vec![test_track("2", "subtitle", "jpn")]);
+
+        let (kept, dropped) =
+            drop_missing_languages(&request, vec![japanese.clone(), english, subtitles_only]);
+        assert_eq!(kept, vec![japanese]);
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn verify_magic_flags_matroska_names_without_ebml_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real.mkv");
+        let fake = dir.path().join("fake.mkv");
+        fs::write(&real, [0x1A, 0x45, 0xDF, 0xA3, 0x01, 0x00, 0x00, 0x00]).unwrap();
+        fs::write(&fake, b"\x00\x00\x00\x20ftypisom").unwrap();
+        let mut request: ScanRequest = serde_json::from_value(json!({
+            "folder": dir.path(),
+            "extensions": ["mkv"],
+            "recursive": false,
+            "type": "video",
+            "include_tracks": false,
+            "verify_magic": true
+        }))
+        .unwrap();
+        let items = || {
+            vec![
+                json!({ "path": real.to_string_lossy(), "warning": null }),
+                json!({ "path": fake.to_string_lossy(), "warning": null }),
+            ]
+        };
+
+        let mut flagged = items();
+        flag_magic_mismatches(&request, &mut flagged);
+        assert!(flagged[0]["warning"].is_null());
+        assert!(flagged[1]["warning"]
+            .as_str()
+            .is_some_and(|w| w.contains("EBML")));
+
+        request.verify_magic = false;
+        let mut unchecked = items();
+        flag_magic_mismatches(&request, &mut unchecked);
+        assert!(unchecked[1]["warning"].is_null());
+    }
+
+    #[test]
+    fn preset_name_reaches_job_plan_and_output_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.preset_name = Some("Anime".to_string());
+        job.output_name = Some("show [{preset}]".to_string());
+
+        assert_eq!(output_file_stem(&job, &test_settings()), "show [Anime]");
+        for name in ["show.mkv", "show.MP4", "show.webm", "show"] {
+            job.output_name = Some(name.to_string());
+            assert_eq!(output_file_stem(&job, &test_settings()), "show");
+        }
+        job.output_name = Some("Mr. Robot".to_string());
+        assert_eq!(output_file_stem(&job, &test_settings()), "Mr. Robot");
+        job.output_name = Some("show [{preset}]".to_string());
+        log_job_plan(&state, &job, Path::new("/out/show.mkv"));
+        let log = fs::read_to_string(&state.paths.log_path).unwrap();
+        assert!(log.contains("preset=Anime"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shutdown_kills_running_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let child = Command::new("sleep").arg("30").spawn().unwrap();
+        let handle = Arc::new(Mutex::new(child));
+        state
+            .mux_state
+            .lock()
+            .unwrap()
+            .children
+            .insert("job-1".to_string(), handle.clone());
+
+        shutdown_muxing(&state);
+
+        let status = handle.lock().unwrap().wait().unwrap();
+        assert!(!status.success());
+        let mux_state = state.mux_state.lock().unwrap();
+        assert!(mux_state.stop);
+        assert!(mux_state.children.is_empty());
+    }
+
+    #[test]
+    fn free_space_error_reports_available_and_required() {
+        let err = free_space_error(512 * 1024 * 1024, 3 * 1024 * 1024 * 1024).unwrap();
+        assert!(err.contains("Available: 512.00 MB"), "{err}");
+        assert!(err.contains("required: 3.00 GB"), "{err}");
+        assert!(free_space_error(2048, 1024).is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let err = check_free_space(dir.path(), u64::MAX).unwrap_err();
+        assert!(err.contains("Available: "), "{err}");
+        assert!(err.contains("required: 16777216.00 TB"), "{err}");
+    }
+
+    #[test]
+    fn single_job_run_targets_temp_and_spares_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("show.mkv");
+        fs::write(&source, b"source").unwrap();
+        let job = test_job(&source.to_string_lossy(), Vec::new());
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        settings.skip_existing = true;
+        let temp = dir.path().join("test-run");
+
+        let single = single_job_settings(&settings, &temp);
+        let (output_path, final_path, overwrite_mode) = get_output_paths(&job, &single);
+        assert!(!overwrite_mode);
+        assert_eq!(output_path, final_path);
+        assert_eq!(output_path, temp.join("show.mkv"));
+        assert!(!single.use_mkvpropedit && !single.skip_existing);
+
+        // Stands in for mkvmerge: writes the output wherever the run points it
+        let state = test_state(dir.path());
+        let mut output = None;
+        let result = run_single_job_in(&state, job.clone(), &settings, |settings, job| {
+            assert!(state.mux_state.lock().unwrap().running);
+            let (output_path, _, _) = get_output_paths(&job, settings);
+            fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+            fs::write(&output_path, b"muxed").unwrap();
+            output = Some(output_path);
+        });
+        let output = output.unwrap();
+        assert_eq!(result, Ok(output.to_string_lossy().to_string()));
+        assert!(output.starts_with(std::env::temp_dir()));
+        assert_ne!(output, source);
+        assert_eq!(fs::read(&source).unwrap(), b"source");
+        assert!(!state.mux_state.lock().unwrap().running);
+        fs::remove_dir_all(output.parent().unwrap()).unwrap();
+
+        // A batch already running refuses the test run
+        state.mux_state.lock().unwrap().running = true;
+        let refused = run_single_job_in(&state, job, &settings, |_, _| unreachable!());
+        assert!(refused.is_err());
+    }
+
+    #[test]
+    fn job_destination_overrides_the_batch_folder() {
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out/a".to_string();
+        let first = test_job("/media/one.mkv", Vec::new());
+        let mut second = test_job("/media/two.mkv", Vec::new());
+        second.destination_dir = Some("/out/b".to_string());
+
+        let (first_output, _, first_overwrite) = get_output_paths(&first, &settings);
+        let (second_output, _, second_overwrite) = get_output_paths(&second, &settings);
+        assert_eq!(first_output, Path::new("/out/a/one.mkv"));
+        assert_eq!(second_output, Path::new("/out/b/two.mkv"));
+        assert!(!first_overwrite && !second_overwrite);
+
+        second.destination_dir = Some("  ".to_string());
+        assert_eq!(resolve_output_dir(&second, &settings), Path::new("/out/a"));
+    }
+
+    #[test]
+    fn preset_batch_matches_subtitles_by_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let videos = dir.path().join("videos");
+        let subtitles = dir.path().join("subs");
+        fs::create_dir_all(&videos).unwrap();
+        fs::create_dir_all(&subtitles).unwrap();
+        fs::write(videos.join("Show - 01.mkv"), b"video").unwrap();
+        fs::write(subtitles.join("Show - 01.eng.ass"), b"[Script Info]").unwrap();
+        fs::write(subtitles.join("Show - 02.ass"), b"[Script Info]").unwrap();
+        let preset = Preset {
+            preset_name: "Anime".to_string(),
+            default_video_directory: videos.to_string_lossy().to_string(),
+            default_subtitle_directory: subtitles.to_string_lossy().to_string(),
+            default_subtitle_language: "eng".to_string(),
+            default_destination_directory: "/out".to_string(),
+            ..Preset::default()
+        };
+
+        let request = jobs_from_preset(&preset, &HashMap::new()).unwrap();
+        assert_eq!(request.jobs.len(), 1);
+        let job = &request.jobs[0];
+        assert_eq!(job.video.name, "Show - 01.mkv");
+        assert_eq!(job.subtitles.len(), 1);
+        assert_eq!(job.subtitles[0].name, "Show - 01.eng.ass");
+        assert_eq!(job.subtitles[0].language.as_deref(), Some("eng"));
+        assert_eq!(job.preset_name.as_deref(), Some("Anime"));
+        assert_eq!(request.settings.destination_dir, "/out");
+    }
+
+    #[test]
+    fn output_extension_keeps_configured_casing() {
+        let job = test_job("/media/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        settings.output_extension = Some(".MKV".to_string());
+
+        let (output_path, final_path, overwrite_mode) = get_output_paths(&job, &settings);
+        assert!(overwrite_mode);
+        assert_eq!(final_path, Path::new("/media/show.MKV"));
+        assert_eq!(output_path.extension(), Some("MKV".as_ref()));
+        assert_eq!(
+            file_name_with_crc(&final_path, "0A1B2C3D"),
+            Path::new("/media/show [0A1B2C3D].MKV")
+        );
+
+        settings.output_extension = None;
+        let (_, final_path, _) = get_output_paths(&job, &settings);
+        assert_eq!(final_path, Path::new("/media/show.mkv"));
+    }
+
+    #[test]
+    fn presets_round_trip_default_mux_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        let mut settings = test_settings();
+        settings.add_crc = true;
+        settings.use_mkvpropedit = true;
+        let mut options = OptionsData::default();
+        options.presets[0].default_mux_settings = Some(settings.clone());
+
+        write_options(&path, &options).unwrap();
+        let loaded = read_options(&path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded.presets[0].default_mux_settings).unwrap(),
+            serde_json::to_value(Some(settings)).unwrap()
+        );
+
+        // Options files written before the field existed still load
+        let mut old = serde_json::to_value(OptionsData::default()).unwrap();
+        old["Presets"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("Default_Mux_Settings");
+        let old: OptionsData = serde_json::from_value(old).unwrap();
+        assert!(old.presets[0].default_mux_settings.is_none());
+    }
+
+    #[test]
+    fn existing_outputs_are_reported_as_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("show.mkv");
+        fs::write(&existing, b"done").unwrap();
+        let mut settings = test_settings();
+
+        assert_eq!(skip_reason(&settings, false, &existing), None);
+        settings.skip_existing = true;
+        assert_eq!(skip_reason(&settings, true, &existing), None);
+        assert!(skip_reason(&settings, false, &dir.path().join("new.mkv")).is_none());
+
+        let reason = skip_reason(&settings, false, &existing).unwrap();
+        let event = skipped_event("job-1", reason, Instant::now());
+        assert_eq!(event.status, JobPhase::Skipped);
+        assert!(event.message.unwrap().starts_with("Output already exists"));
+    }
+
+    #[test]
+    fn attachment_folder_expands_to_its_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let fonts = dir.path().join("fonts");
+        fs::create_dir(&fonts).unwrap();
+        for name in ["a.ttf", "b.OTF", "c.ttc", "readme.txt"] {
+            fs::write(fonts.join(name), b"").unwrap();
+        }
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.attachments
+            .push(test_external(&fonts.to_string_lossy(), "attachment"));
+        let duplicate = fonts.join("a.ttf");
+        job.attachments
+            .push(test_external(&duplicate.to_string_lossy(), "attachment"));
+        let mut settings = test_settings();
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let attached: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--attach-file")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        let expected: Vec<String> = ["a.ttf", "b.OTF", "c.ttc"]
+            .iter()
+            .map(|name| fonts.join(name).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(attached, expected);
+
+        settings.allow_duplicate_attachments = true;
+        assert_eq!(attachment_paths(&job, &settings).len(), 4);
+    }
+
+    #[test]
+    fn batch_track_overrides_apply_across_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.batch_track_overrides = serde_json::from_value(json!([{
+            "matchLanguage": "jpn",
+            "trackType": "audio",
+            "setName": "Japanese 5.1",
+            "setDefault": true
+        }]))
+        .unwrap();
+        let mut edited = test_track("2", "audio", "jpn");
+        edited.name = Some("Commentary".to_string());
+        edited.action = Some("modify".to_string());
+        let first = test_job(
+            "/media/ep01.mkv",
+            vec![test_track("1", "audio", "jpn"), edited],
+        );
+        let second = test_job(
+            "/media/ep02.mkv",
+            vec![
+                test_track("1", "audio", "eng"),
+                test_track("2", "audio", "jpn"),
+            ],
+        );
+
+        let names = |job: &MuxJobRequest| -> Vec<String> {
+            build_mkvmerge_command(job, &settings, Path::new("/out/x.mkv"), &state)
+                .windows(2)
+                .filter(|pair| pair[0] == "--track-name")
+                .map(|pair| pair[1].clone())
+                .collect()
+        };
+        // The explicitly edited track keeps its own name
+        assert_eq!(names(&first), vec!["1:Japanese 5.1", "2:Commentary"]);
+        assert_eq!(names(&second), vec!["2:Japanese 5.1"]);
+    }
+
+    #[test]
+    fn missing_options_file_loads_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        let options = load_options_or_default(&path).unwrap();
+        assert!(options.load_error.is_none());
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn reading_options_only_leaves_the_file_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        let content = "{\"Dark_Mode\":true,   \"Presets\": []}";
+        fs::write(&path, content).unwrap();
+
+        let options = read_options_or_default(&path);
+        assert!(options.dark_mode);
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        let missing = dir.path().join("missing.json");
+        assert!(read_options_or_default(&missing).load_error.is_none());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn options_path_that_is_a_directory_is_reported_and_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        fs::create_dir(&path).unwrap();
+
+        assert!(read_options(&path).unwrap_err().contains("is a directory"));
+        let options = load_options_or_default(&path).unwrap();
+        assert!(options.load_error.is_some());
+        assert_eq!(options.presets.len(), 1);
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn one_bad_options_field_keeps_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        let mut preset = serde_json::to_value(Preset::default()).unwrap();
+        preset["Preset_Name"] = json!("Anime");
+        preset["Default_Video_Directory"] = json!("/media/anime");
+        preset["Default_Audio_Extensions"] = json!(42);
+        let content = json!({
+            "Presets": [preset],
+            "FavoritePresetId": "not a number",
+            "Dark_Mode": "true",
+            "Attachment_Expert_Mode_Info_Message_Show": false,
+            "Choose_Preset_On_Startup": true
+        })
+        .to_string();
+        fs::write(&path, &content).unwrap();
+
+        let options = load_options_or_default(&path).unwrap();
+        assert_eq!(options.presets[0].preset_name, "Anime");
+        assert_eq!(options.presets[0].default_video_directory, "/media/anime");
+        assert_eq!(options.presets[0].default_audio_extensions, vec!["AAC"]);
+        assert_eq!(options.favorite_preset_id, 0);
+        assert!(options.dark_mode);
+        assert!(!options.attachment_expert_mode_info_message_show);
+        assert!(options.choose_preset_on_startup);
+        let error = options.load_error.unwrap();
+        assert!(error.contains("FavoritePresetId"), "{error}");
+        assert!(
+            error.contains("Presets[0].Default_Audio_Extensions"),
+            "{error}"
+        );
+        assert!(!error.contains("Dark_Mode"), "{error}");
+        // The user's file stays as it was until they save
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn writable_destination_passes_the_check() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let check = destination_check(dir.path());
+        assert!(check.writable, "{:?}", check.error);
+        assert!(check.free_bytes.is_some());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let missing = destination_check(&dir.path().join("missing"));
+        assert!(!missing.writable && missing.error.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_destination_fails_the_check() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+        // Permission bits don't apply to root
+        if fs::write(locked.join("probe"), b"").is_ok() {
+            return;
+        }
+
+        let check = destination_check(&locked);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(!check.writable);
+        assert!(check.error.unwrap().starts_with("Cannot write to"));
+        assert!(check.free_bytes.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_options_file_is_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permission bits don't apply to root
+        if fs::read(&path).is_ok() {
+            return;
+        }
+
+        let options = load_options_or_default(&path).unwrap();
+        let error = options.load_error.unwrap();
+        assert!(error.contains("Failed to read options"), "{error}");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn name_size_only_never_probes() {
+        for file_type in ["video", "audio", "subtitle", "chapter", "attachment"] {
+            for include_tracks in [false, true] {
+                assert_eq!(
+                    probe_plan(file_type, include_tracks, MetadataLevel::NameSizeOnly),
+                    (false, false)
+                );
+            }
+        }
+        assert_eq!(probe_plan("audio", true, MetadataLevel::Full), (true, true));
+        let basic = probe_plan("video", true, MetadataLevel::Basic);
+        assert_eq!(basic, (true, false));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.aac");
+        fs::write(&path, b"not really audio").unwrap();
+        let info = build_file_info(
+            &path,
+            "audio",
+            true,
+            DurationSource::Auto,
+            MetadataLevel::NameSizeOnly,
+            &ProbeLimiter::new(DEFAULT_MAX_CONCURRENT_PROBES),
+        )
+        .unwrap();
+        assert_eq!(info["size"], json!(16));
+        assert!(info["probeError"].is_null());
+        assert_eq!(info["tracks"], json!([]));
+    }
+
+    #[test]
+    fn seeded_ids_are_deterministic() {
+        let mut first = SeededIds::new(42);
+        let mut second = SeededIds::new(42);
+        let ids: Vec<String> = ["job", "video", "job"]
+            .iter()
+            .map(|prefix| first.next_id(prefix))
+            .collect();
+        assert_eq!(ids, vec!["job-42-0", "video-42-1", "job-42-2"]);
+        assert_eq!(second.next_id("job"), "job-42-0");
+
+        let mut seeded = SeededIds::new(7);
+        assert_eq!(id_from(Some(&mut seeded), "seeded"), "seeded-7-0");
+        assert!(!id_from(None, "seeded").starts_with("seeded-7-"));
+    }
+
+    #[test]
+    fn no_default_audio_clears_every_audio_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.is_default = Some(true);
+        let mut subtitle = test_track("2", "subtitle", "eng");
+        subtitle.is_default = Some(true);
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![test_track("0", "video", "und"), audio, subtitle],
+        );
+        let mut external = test_external("/media/show.aac", "audio");
+        external.is_default = Some(true);
+        external.track_id = Some(0);
+        job.audios.push(external);
+        let mut settings = test_settings();
+        settings.make_audio_default_language = Some("jpn".to_string());
+        settings.no_default_audio = true;
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let flags: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(flags, vec!["1:no", "2:yes", "0:no"]);
+    }
+
+    #[test]
+    fn finalize_reports_crc_phase_before_completion() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let output = dir.path().join("show.mkv");
+        fs::write(&output, vec![7u8; 4096]).unwrap();
+        let mut job = test_job(&output.to_string_lossy(), Vec::new());
+        job.video.size = 4096;
+        let mut audio = test_external("/media/show.mka", "audio");
+        audio.size = Some(1024);
+        job.audios.push(audio);
+        let mut settings = test_settings();
+        settings.add_crc = true;
+
+        let mut events = Vec::new();
+        finalize_job(
+            &state,
+            &job,
+            &settings,
+            &output,
+            &output,
+            false,
+            Instant::now(),
+            2_000,
+            &mut |event| events.push(event),
+        );
+        // The history counts what `estimate_batch` counts, over mkvmerge's run only
+        let stats = state.mux_state.lock().unwrap().batch_stats.clone();
+        assert_eq!((stats.remuxed_bytes, stats.remux_ms), (5120, 2_000));
+
+        let statuses: Vec<JobPhase> = events.iter().map(|e| e.status).collect();
+        assert_eq!(statuses.first(), Some(&JobPhase::Finalizing));
+        assert_eq!(statuses.last(), Some(&JobPhase::Completed));
+        assert!(events
+            .iter()
+            .any(|e| e.status == JobPhase::Finalizing && e.progress == 100));
+        assert!(!dir.path().join("show.mkv").exists());
+    }
+
+    #[test]
+    fn post_mux_command_expands_tokens_per_argument() {
+        let args = post_mux_command_args(
+            "notify \"done: {crc}\" {output} --from={input}",
+            Path::new("/out/My Show; rm -rf [0A1B2C3D].mkv"),
+            "/in/show.mkv",
+        );
+        assert_eq!(
+            args,
+            vec![
+                "notify",
+                "done: 0A1B2C3D",
+                "/out/My Show; rm -rf [0A1B2C3D].mkv",
+                "--from=/in/show.mkv",
+            ]
+        );
+        assert_eq!(crc_from_file_name(Path::new("/out/show.mkv")), None);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn post_mux_command_runs_with_the_output_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let output = dir.path().join("my show.mkv");
+        fs::write(&output, b"muxed").unwrap();
+        let copy = dir.path().join("uploaded.mkv");
+        let job = test_job("/in/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        settings.post_mux_command = Some(format!("cp {{output}} \"{}\"", copy.display()));
+
+        assert_eq!(
+            post_mux_failure(&state, &job, &settings, &[output.clone()]),
+            None
+        );
+        assert_eq!(fs::read(&copy).unwrap(), b"muxed");
+
+        settings.post_mux_command = Some("false".to_string());
+        assert_eq!(
+            post_mux_failure(&state, &job, &settings, &[output.clone()]),
+            None
+        );
+        settings.post_mux_command_must_succeed = true;
+        assert!(post_mux_failure(&state, &job, &settings, &[output.clone()]).is_some());
+
+        let err = run_post_mux_command(
+            &state,
+            &job.id,
+            "sleep 5",
+            &output,
+            &job.video.path,
+            Duration::from_millis(300),
+        )
+        .unwrap_err();
+        assert!(err.contains("timed out"));
+        assert!(state.mux_state.lock().unwrap().children.is_empty());
+
+        // Registered as the job's child, so a stop kills it
+        let stopper = {
+            let state = state.clone();
+            let job_id = job.id.clone();
+            thread::spawn(move || {
+                while !state
+                    .mux_state
+                    .lock()
+                    .unwrap()
+                    .children
+                    .contains_key(&job_id)
+                {
+                    thread::sleep(Duration::from_millis(20));
+                }
+                shutdown_muxing(&state);
+            })
+        };
+        let started = Instant::now();
+        let err = run_post_mux_command(
+            &state,
+            &job.id,
+            "sleep 30",
+            &output,
+            &job.video.path,
+            POST_MUX_COMMAND_TIMEOUT,
+        )
+        .unwrap_err();
+        stopper.join().unwrap();
+        assert!(err.contains("stopped"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn fast_mux_reports_intermediate_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let event = fast_mux_started_event(&state, "job-1");
+        assert_eq!(event.status, JobPhase::Processing);
+        assert!(event.progress > 0 && event.progress < 100);
+
+        // mkvpropedit's own reports up to the halfway mark are repeats
+        assert_eq!(next_progress(&state, "job-1", "Progress: 40%"), None);
+        assert_eq!(next_progress(&state, "job-1", "Progress: 50%"), None);
+        assert_eq!(next_progress(&state, "job-1", "Progress: 80%"), Some(80));
+        assert!(final_progress_pending(&state, "job-1"));
+    }
+
+    #[test]
+    fn mkvpropedit_output_lands_in_log_and_error_detail() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let stdout: &[u8] = b"The file is being analyzed.\nDone.\n";
+        let stderr: &[u8] = b"Error: The file '/media/show.mkv' is not a Matroska file.\n";
+
+        let mut lines = Vec::new();
+        drain_tool_output(stdout, &state, "job-1", false, |line, _| {
+            lines.push(line.to_string())
+        });
+        drain_tool_output(stderr, &state, "job-1", true, |line, _| {
+            lines.push(line.to_string())
+        });
+
+        assert_eq!(lines.len(), 3);
+        let log = fs::read_to_string(&state.paths.log_path).unwrap();
+        assert!(log.contains("The file is being analyzed."));
+        assert!(log.contains("is not a Matroska file"));
+        let detail = take_stderr_tail(&state, "job-1").unwrap();
+        assert!(detail.contains("is not a Matroska file"));
+    }
+
+    #[test]
+    fn fast_mux_with_external_audio_reports_why_it_is_disabled() {
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        assert_eq!(fast_mux_warning(&job, &settings), None);
+
+        job.audios.push(test_external("/media/show.aac", "audio"));
+        let warning = fast_mux_warning(&job, &settings).unwrap();
+        assert!(warning.contains("external files"), "{warning}");
+
+        settings.use_mkvpropedit = false;
+        assert_eq!(fast_mux_warning(&job, &settings), None);
+    }
+
+    #[test]
+    fn mixed_text_and_image_default_subtitles_warn() {
+        let mut pgs = test_track("2", "subtitle", "eng");
+        pgs.codec = Some("HDMV PGS".to_string());
+        pgs.is_default = Some(true);
+        let tracks = vec![test_track("0", "video", "und"), pgs];
+        let mut job = test_job("/media/show.mkv", tracks);
+        let mut settings = test_settings();
+        assert_eq!(mixed_default_subtitle_warning(&job, &settings), None);
+
+        let mut srt = test_external("/media/show.eng.srt", "subtitle");
+        srt.is_default = Some(true);
+        job.subtitles.push(srt);
+        let warning = mixed_default_subtitle_warning(&job, &settings).unwrap();
+        assert!(warning.contains("HDMV PGS, srt"), "{warning}");
+
+        settings.no_default_subtitles = true;
+        assert_eq!(mixed_default_subtitle_warning(&job, &settings), None);
+        assert!(!is_image_subtitle("SubRip/SRT"));
+    }
+
+    #[test]
+    fn describe_output_reads_tracks_attachments_and_chapters() {
+        let info = json!({
+            "tracks": [
+                { "id": 0, "type": "video", "codec": "AVC/H.264/MPEG-4p10", "properties": {} },
+                { "id": 1, "type": "audio", "codec": "AAC", "properties": { "language": "jpn" } },
+                { "id": 2, "type": "subtitles", "codec": "SubStationAlpha", "properties": { "language": "eng" } }
+            ],
+            "attachments": [
+                { "id": 1, "file_name": "font.ttf", "size": 20480, "content_type": "font/ttf" }
+            ],
+            "chapters": [{ "num_entries": 12 }]
+        });
+
+        let description = describe_mkvmerge_info(&info);
+        let types: Vec<&str> = description
+            .tracks
+            .iter()
+            .map(|track| track.track_type.as_str())
+            .collect();
+        assert_eq!(types, vec!["video", "audio", "subtitle"]);
+        assert_eq!(
+            description.attachments,
+            vec![OutputAttachment {
+                name: "font.ttf".to_string(),
+                size: 20480
+            }]
+        );
+        assert_eq!(description.chapter_count, 12);
+    }
+
+    #[test]
+    fn output_sidecar_lists_track_languages_and_crc() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("Show - 01 [0A1B2C3D].mkv");
+        let info = json!({
+            "tracks": [
+                { "id": 0, "type": "video", "codec": "HEVC/H.265/MPEG-H", "properties": {} },
+                { "id": 1, "type": "audio", "codec": "FLAC", "properties": { "language": "jpn" } },
+                { "id": 2, "type": "subtitles", "codec": "SubStationAlpha", "properties": { "language": "eng" } }
+            ]
+        });
+
+        let path = write_output_sidecar(&output, "/src/Show - 01.mkv", &info).unwrap();
+        assert_eq!(path, dir.path().join("Show - 01 [0A1B2C3D].mkv.json"));
+        let sidecar: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(sidecar["crc"], "0A1B2C3D");
+        assert_eq!(sidecar["source"], "Show - 01.mkv");
+        let languages: Vec<&str> = sidecar["tracks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|track| track["language"].as_str())
+            .collect();
+        assert_eq!(languages, vec!["jpn", "eng"]);
+    }
+
+    #[test]
+    fn data_dir_env_overrides_default_app_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::env::set_var(DATA_DIR_ENV, dir.path());
+        let paths = resolve_app_paths(Some(PathBuf::from("/default/profile")));
+        std::env::remove_var(DATA_DIR_ENV);
+
+        let paths = paths.unwrap();
+        assert_eq!(paths.app_data_dir, dir.path());
+        assert_eq!(paths.options_path, dir.path().join("setting.json"));
+        assert_eq!(paths.log_path, dir.path().join("muxing_log_file.txt"));
+        assert_eq!(paths.stats_path, dir.path().join("stats.json"));
+    }
+
+    #[test]
+    fn missing_output_track_is_reported_as_dropped() {
+        let mut job = test_job(
+            "/videos/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+            ],
+        );
+        job.subtitles
+            .push(test_external("/subs/show.ass", "subtitle"));
+        let expected = expected_output_tracks(&job, &test_settings());
+        assert_eq!(expected.len(), 4);
+
+        let output = vec![
+            test_track("0", "video", "und"),
+            test_track("1", "audio", "jpn"),
+            test_track("2", "subtitle", "und"),
+        ];
+        let warning = dropped_track_warning(&expected, &output).unwrap();
+        assert!(warning.contains("3 of 4"), "{warning}");
+        assert!(warning.contains("audio track 2 of video.mkv"), "{warning}");
+        assert!(!warning.contains("show.ass"), "{warning}");
+
+        let mut complete = output.clone();
+        complete.push(test_track("3", "audio", "eng"));
+        assert_eq!(dropped_track_warning(&expected, &complete), None);
+    }
+
+    #[test]
+    fn track_order_matches_mkvmerge_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job(
+            "/videos/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "subtitle", "eng"),
+                test_track("2", "audio", "jpn"),
+            ],
+        );
+        let mut per_file_audio = test_external("/audio/show.commentary.mka", "audio");
+        per_file_audio.source = Some("per-file".to_string());
+        per_file_audio.included_track_ids = Some(vec![0, 1]);
+        let mut bulk_audio = test_external("/audio/show.eng.ac3", "audio");
+        bulk_audio.language = Some("eng".to_string());
+        bulk_audio.included_track_ids = Some(vec![0]);
+        let mut subtitle = test_external("/subs/show.ass", "subtitle");
+        subtitle.included_track_ids = Some(vec![0]);
+        job.audios = vec![per_file_audio, bulk_audio];
+        job.subtitles = vec![subtitle];
+        let settings = test_settings();
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let position = args.iter().position(|arg| arg == "--track-order").unwrap();
+        let order = resolve_track_order(job.clone(), settings);
+        let resolved: Vec<String> = order
+            .iter()
+            .map(|entry| format!("{}:{}", entry.source_file_index, entry.track_id))
+            .collect();
+        assert_eq!(args[position + 1], resolved.join(","));
+        assert_eq!(
+            resolved,
+            vec!["0:0", "3:0", "1:0", "2:1", "0:2", "0:1", "4:0"]
+        );
+        assert_eq!(order[1].language.as_deref(), Some("eng"));
+        let types: Vec<&str> = order
+            .iter()
+            .map(|entry| entry.track_type.as_str())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["video", "audio", "audio", "audio", "audio", "subtitle", "subtitle"]
+        );
+
+        // A source track the selection drops is in neither
+        let mut settings = test_settings();
+        settings.block_source_track_ids = vec![1];
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let position = args.iter().position(|arg| arg == "--track-order").unwrap();
+        let resolved: Vec<String> = resolve_track_order(job, settings)
+            .iter()
+            .map(|entry| format!("{}:{}", entry.source_file_index, entry.track_id))
+            .collect();
+        assert_eq!(args[position + 1], resolved.join(","));
+        assert_eq!(resolved, vec!["0:0", "3:0", "1:0", "2:1", "0:2", "4:0"]);
+    }
+
+    #[test]
+    fn default_audio_can_be_chosen_by_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job(
+            "/videos/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "und"),
+                test_track("2", "subtitle", "und"),
+                test_track("3", "audio", "und"),
+                test_track("4", "audio", "und"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.make_audio_default_position = Some(2);
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let flags: Vec<&str> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(flags, vec!["1:no", "3:yes", "4:no"]);
+
+        settings.make_audio_default_position = Some(4);
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(!args.iter().any(|arg| arg == "--default-track-flag"));
+    }
+
+    #[test]
+    fn stats_accumulate_across_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+
+        let mut first = UsageStats::default();
+        first.add_job(1_000, Some(800));
+        first.add_job(500, Some(600));
+        record_batch_stats(&state.paths, &first).unwrap();
+        let mut second = UsageStats::default();
+        second.add_job(2_000, Some(1_500));
+        record_batch_stats(&state.paths, &second).unwrap();
+
+        assert_eq!(
+            read_stats(&state.paths.stats_path).unwrap(),
+            UsageStats {
+                jobs_completed: 3,
+                bytes_processed: 3_500,
+                bytes_saved: 700,
+                ..UsageStats::default()
+            }
+        );
+        assert!(!dir.path().join("stats.tmp.json").exists());
+
+        write_stats(&state.paths.stats_path, &UsageStats::default()).unwrap();
+        assert_eq!(
+            read_stats(&state.paths.stats_path).unwrap(),
+            UsageStats::default()
+        );
+    }
+
+    #[test]
+    fn codec_allowlist_keeps_only_matching_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut flac = test_track("2", "audio", "jpn");
+        flac.codec = Some("FLAC".to_string());
+        let mut ac3 = test_track("1", "audio", "jpn");
+        ac3.codec = Some("AC-3".to_string());
+        let mut aac = test_track("3", "audio", "eng");
+        aac.codec = Some("AAC".to_string());
+        aac.codec_id = Some("A_AAC".to_string());
+        let job = test_job(
+            "/videos/show.mkv",
+            vec![test_track("0", "video", "und"), ac3, flac, aac],
+        );
+        let mut settings = test_settings();
+        settings.keep_audio_codecs = vec!["flac".to_string()];
+
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let position = args.iter().position(|arg| arg == "--audio-tracks").unwrap();
+        assert_eq!(args[position + 1], "2");
+
+        // Codec ids match too, and the language filter still applies on top
+        settings.keep_audio_codecs = vec!["FLAC".to_string(), "A_AAC".to_string()];
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["eng".to_string()];
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let position = args.iter().position(|arg| arg == "--audio-tracks").unwrap();
+        assert_eq!(args[position + 1], "3");
+    }
+
+    #[test]
+    fn rename_template_renames_and_refuses_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for name in ["[Grp] Show - 01.ass", "[Grp] Show - 02.ass"] {
+            let path = dir.path().join(name);
+            fs::write(&path, name).unwrap();
+            paths.push(path.to_string_lossy().to_string());
+        }
+
+        let items = preview_rename(paths.clone(), "Show E{index}".to_string());
+        let names: Vec<&str> = items.iter().map(|item| item.new_name.as_str()).collect();
+        assert_eq!(names, vec!["Show E01.ass", "Show E02.ass"]);
+        assert_eq!(
+            serde_json::to_value(&items[0]).unwrap()["newName"],
+            "Show E01.ass"
+        );
+
+        let results = rename_files(items);
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("Show E02.ass")).unwrap(),
+            "[Grp] Show - 02.ass"
+        );
+        assert!(!Path::new(&paths[0]).exists());
+
+        let first = dir.path().join("Show E01.ass");
+        let clash = rename_files(vec![RenameItem {
+            path: first.to_string_lossy().to_string(),
+            new_name: "Show E02.ass".to_string(),
+        }]);
+        assert!(clash[0].new_path.is_none());
+        let error = clash[0].error.as_deref().unwrap();
+        assert!(error.contains("already exists"), "{error}");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("Show E01.ass")).unwrap(),
+            "[Grp] Show - 01.ass"
+        );
+    }
+
+    #[test]
+    fn single_job_preview_matches_batch_preview() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        let first = test_job("/videos/one.mkv", vec![test_track("0", "video", "und")]);
+        let mut second = test_job(
+            "/videos/two.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+            ],
+        );
+        second.id = "job-2".to_string();
+        second
+            .subtitles
+            .push(test_external("/subs/two.ass", "subtitle"));
+
+        let request = MuxStartRequest {
+            settings: settings.clone(),
+            jobs: vec![first, second.clone()],
+        };
+        let batch = preview_jobs(&state, request);
+        let single = preview_job_result(&state, second, &settings);
+        assert_eq!(
+            serde_json::to_value(&single).unwrap(),
+            serde_json::to_value(&batch[1]).unwrap()
+        );
+        assert!(single.command.contains("two.ass"));
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn rename_retries_while_file_is_in_use() {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("show.mkv");
+        let target = dir.path().join("show [ABCD1234].mkv");
+        fs::write(&path, b"output").unwrap();
+        // No sharing allowed, like a scanner holding the fresh output
+        let lock = OpenOptions::new()
+            .read(true)
+            .share_mode(0)
+            .open(&path)
+            .unwrap();
+        let error = fs::rename(&path, &target).unwrap_err();
+        assert!(is_file_in_use(&error), "{error}");
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            drop(lock);
+        });
+        retry_file_in_use(|| fs::rename(&path, &target)).unwrap();
+        release.join().unwrap();
+        assert!(target.is_file());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn subtitles_taken_from_audio_can_carry_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/videos/show.mkv", vec![test_track("0", "video", "und")]);
+        let mut audio = test_external("/audio/show.mka", "audio");
+        audio.language = Some("jpn".to_string());
+        audio.is_default = Some(true);
+        audio.included_track_ids = Some(vec![0]);
+        audio.include_subtitles = Some(true);
+        audio.included_subtitle_track_ids = Some(vec![2]);
+        job.audios.push(audio);
+
+        let subtitle_args = |job: &MuxJobRequest| -> Vec<String> {
+            let args = build_mkvmerge_command(job, &test_settings(), Path::new("/o.mkv"), &state);
+            let start = args
+                .iter()
+                .position(|arg| arg == "--subtitle-tracks")
+                .unwrap();
+            args[start..].to_vec()
+        };
+        let plain = subtitle_args(&job);
+        assert!(!plain.iter().any(|arg| arg == "--forced-display-flag"));
+        assert!(!plain.iter().any(|arg| arg == "2:jpn"));
+
+        job.audios[0].subtitle_flags = Some(ExtractedSubtitleFlags {
+            is_default: None,
+            is_forced: Some(true),
+            language: Some("eng".to_string()),
+        });
+        let flagged = subtitle_args(&job);
+        let has_pair = |first: &str, second: &str| {
+            flagged
+                .windows(2)
+                .any(|pair| pair[0] == first && pair[1] == second)
+        };
+        assert!(has_pair("--forced-display-flag", "2:yes"));
+        assert!(has_pair("--language", "2:eng"));
+        assert!(!flagged.iter().any(|arg| arg == "--default-track-flag"));
+    }
+
+    #[test]
+    fn source_track_delay_is_synced_before_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.delay = Some(-0.25);
+        let job = test_job(
+            "/videos/show.mkv",
+            vec![test_track("0", "video", "und"), audio],
+        );
+
+        let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/o.mkv"), &state);
+        let sync = args.iter().position(|arg| arg == "--sync").unwrap();
+        let source = args.iter().position(|arg| arg == &job.video.path).unwrap();
+        // Before the source file the track id needs no file prefix
+        assert_eq!(args[sync + 1], "1:-250");
+        assert!(sync < source);
+        assert_eq!(args.iter().filter(|arg| *arg == "--sync").count(), 1);
+
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        assert!(fast_mux_warning(&job, &settings).is_some());
+    }
+
+    #[test]
+    fn dolby_vision_profile_seven_warns() {
+        let mediainfo = json!({
+            "media": { "track": [
+                { "@type": "General" },
+                {
+                    "@type": "Video",
+                    "HDR_Format": "Dolby Vision / SMPTE ST 2086",
+                    "HDR_Format_Profile": "dvhe.07.06 / dvhe.07.06"
+                }
+            ] }
+        });
+        let mut job = test_job("/videos/movie.mkv", Vec::new());
+        job.video.dolby_vision_profile = parse_dolby_vision_profile(&mediainfo);
+        assert_eq!(job.video.dolby_vision_profile, Some(7));
+        let warning = dolby_vision_warning(&job).unwrap();
+        assert!(warning.contains("profile 7"), "{warning}");
+
+        let profile_eight = json!({
+            "media": { "track": [{ "@type": "Video", "HDR_Format_Profile": "dvhe.08.06" }] }
+        });
+        job.video.dolby_vision_profile = parse_dolby_vision_profile(&profile_eight);
+        assert_eq!(job.video.dolby_vision_profile, Some(8));
+        assert_eq!(dolby_vision_warning(&job), None);
+    }
+
+    #[test]
+    fn dependency_status_reports_present_and_missing_tools() {
+        let cargo = std::env::var("CARGO").expect("cargo sets CARGO for tests");
+        let present = dependency_status(&cargo, "--version");
+        assert!(present.found);
+        assert!(present.version.unwrap().starts_with("cargo"));
+        assert_eq!(present.path.as_deref(), Some(cargo.as_str()));
+        assert!(present.install_hint.is_none());
+
+        let missing = dependency_status("mkvbatchmux-missing-tool", "-V");
+        assert!(!missing.found);
+        assert!(missing.version.is_none());
+        assert!(missing.path.is_none());
+        assert!(missing.install_hint.unwrap().contains("mkvtoolnix"));
+    }
+
+    #[test]
+    fn probe_limiter_caps_concurrent_probes() {
+        use std::sync::atomic::AtomicUsize;
+
+        let limiter = ProbeLimiter::new(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        (0..16).into_par_iter().for_each(|_| {
+            limiter.run(|| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert!(peak.load(Ordering::SeqCst) >= 1);
+
+        let request: ScanRequest = serde_json::from_value(json!({
+            "folder": "/videos",
+            "extensions": ["mkv"],
+            "recursive": false,
+            "type": "video",
+            "include_tracks": true
+        }))
+        .unwrap();
+        assert_eq!(request.max_concurrent_probes, DEFAULT_MAX_CONCURRENT_PROBES);
+    }
+
+    #[test]
+    fn abort_on_errors_only_pauses_for_listed_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.abort_on_errors = true;
+        let job = test_job("/media/show.mkv", Vec::new());
+
+        pause_on_error(&state, &settings, &job, JobErrorKind::Destination);
+        assert!(!state.mux_state.lock().unwrap().pause);
+
+        pause_on_error(&state, &settings, &job, JobErrorKind::DiskSpace);
+        assert!(state.mux_state.lock().unwrap().pause);
+
+        state.mux_state.lock().unwrap().pause = false;
+        pause_on_error(&state, &settings, &job, JobErrorKind::MuxFailed);
+        assert!(state.mux_state.lock().unwrap().pause);
+
+        state.mux_state.lock().unwrap().pause = false;
+        settings.abort_on = vec![JobErrorKind::Destination];
+        pause_on_error(&state, &settings, &job, JobErrorKind::Destination);
+        assert!(state.mux_state.lock().unwrap().pause);
+
+        state.mux_state.lock().unwrap().pause = false;
+        settings.abort_on_errors = false;
+        pause_on_error(&state, &settings, &job, JobErrorKind::Destination);
+        assert!(!state.mux_state.lock().unwrap().pause);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn failed_job_pauses_the_batch_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let output = dir.path().join("show.mkv");
+        let job = test_job(&output.to_string_lossy(), Vec::new());
+        let mut settings = test_settings();
+        settings.abort_on_errors = true;
+        settings.post_mux_command = Some("false".to_string());
+        settings.post_mux_command_must_succeed = true;
+        let finalize = |settings: &MuxSettings| {
+            fs::write(&output, b"muxed").unwrap();
+            let mut events = Vec::new();
+            finalize_job(
+                &state,
+                &job,
+                settings,
+                &output,
+                &output,
+                false,
+                Instant::now(),
+                0,
+                &mut |event| events.push(event),
+            );
+            events.last().map(|event| event.status)
+        };
+
+        assert_eq!(finalize(&settings), Some(JobPhase::Error));
+        assert!(state.mux_state.lock().unwrap().pause);
+
+        state.mux_state.lock().unwrap().pause = false;
+        settings.abort_on = vec![JobErrorKind::DiskSpace];
+        assert_eq!(finalize(&settings), Some(JobPhase::Error));
+        assert!(!state.mux_state.lock().unwrap().pause);
+    }
+
+    #[test]
+    fn job_can_opt_out_of_abort_on_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.abort_on_errors = true;
+        let mut experimental = test_job("/media/experiment.mkv", Vec::new());
+        experimental.abort_on_errors = Some(false);
+
+        pause_on_error(&state, &settings, &experimental, JobErrorKind::DiskSpace);
+        assert!(!state.mux_state.lock().unwrap().pause);
+
+        settings.abort_on_errors = false;
+        let mut critical = test_job("/media/critical.mkv", Vec::new());
+        critical.abort_on_errors = Some(true);
+        pause_on_error(&state, &settings, &critical, JobErrorKind::DiskSpace);
+        assert!(state.mux_state.lock().unwrap().pause);
+    }
+
+    #[test]
+    fn mux_plan_event_carries_the_spawned_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let settings = test_settings();
+        let job = test_job(
+            "/videos/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "eng"),
+            ],
+        );
+        let output_path = Path::new("/out/show.mkv");
+        let args = build_mkvmerge_command(&job, &settings, output_path, &state);
+
+        let event = mux_plan_event(&job, &settings, output_path, &args);
+        assert_eq!(event.job_id, job.id);
+        assert_eq!(event.args, args);
+        assert_eq!(event.output_path, output_path.to_string_lossy());
+        let order: Vec<(String, u64)> = event
+            .track_order
+            .iter()
+            .map(|entry| (entry.track_type.clone(), entry.track_id))
+            .collect();
+        assert_eq!(
+            order,
+            vec![("video".to_string(), 0), ("audio".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn ogm_chapters_round_trip_through_xml() {
+        let ogm = "CHAPTER01=00:00:00.000\nCHAPTER01NAME=Intro\nCHAPTER02=00:01:30.500\nCHAPTER02NAME=Tom & Jerry <live>\nCHAPTER03=01:02:03.004\nCHAPTER03NAME=Credits\n";
+
+        let xml = convert_chapter_text(ogm, ChapterFormat::Xml).unwrap();
+        assert!(xml.contains("<ChapterTimeStart>00:01:30.500000000</ChapterTimeStart>"));
+        assert!(xml.contains("<ChapterString>Tom &amp; Jerry &lt;live&gt;</ChapterString>"));
+        assert_eq!(parse_xml_chapters(&xml).unwrap().len(), 3);
+
+        assert_eq!(convert_chapter_text(&xml, ChapterFormat::Ogm).unwrap(), ogm);
+        assert!(convert_chapter_text("CHAPTER01=soon", ChapterFormat::Xml).is_err());
+        assert_eq!(parse_chapter_time("99999999999:00:00"), None);
+        assert_eq!(
+            parse_chapter_time("5124095:34:33.709551615"),
+            Some(u64::MAX)
+        );
+        assert!(convert_chapter_text("", ChapterFormat::Xml).is_err());
+    }
+
+    #[test]
+    fn attachments_get_a_mime_type_before_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.attachments
+            .push(test_external("/media/cover.PNG", "attachment"));
+        let mut poster = test_external("/media/poster.bin", "attachment");
+        poster.mime_type = Some("image/jpeg".to_string());
+        job.attachments.push(poster);
+        let mut notes = test_external("/media/notes.dat", "attachment");
+        notes.mime_type = Some("not a mime".to_string());
+        job.attachments.push(notes);
+
+        let args =
+            build_mkvmerge_command(&job, &test_settings(), Path::new("/out/show.mkv"), &state);
+        let attached: Vec<(&str, &str)> = args
+            .windows(4)
+            .filter(|window| window[0] == "--attachment-mime-type" && window[2] == "--attach-file")
+            .map(|window| (window[1].as_str(), window[3].as_str()))
+            .collect();
+        assert_eq!(
+            attached,
+            vec![
+                ("image/png", "/media/cover.PNG"),
+                ("image/jpeg", "/media/poster.bin"),
+                ("application/octet-stream", "/media/notes.dat"),
+            ]
+        );
+    }
+
+    #[test]
+    fn preview_reports_when_the_source_will_be_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job("/media/show.mkv", Vec::new());
+        let mut settings = test_settings();
+        settings.overwrite_source = true;
+
+        let preview = preview_job_result(&state, job.clone(), &settings);
+        assert!(preview.plan.will_overwrite_source);
+        let temp_path = preview.plan.temp_path.unwrap();
+        assert!(temp_path.contains('#') && temp_path.ends_with(".mkv"));
+
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        let preview = preview_job_result(&state, job, &settings);
+        assert!(!preview.plan.will_overwrite_source);
+        assert_eq!(preview.plan.temp_path, None);
+    }
+
+    #[test]
+    fn output_cap_stops_the_batch_before_the_next_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        assert_eq!(enforce_output_cap(&state, &settings), None);
+        settings.max_total_output_bytes = Some(1_000);
+
+        record_completed_job(&state, 700, Some(600), None);
+        assert_eq!(enforce_output_cap(&state, &settings), None);
+        assert!(!state.mux_state.lock().unwrap().stop);
+
+        record_completed_job(&state, 700, Some(600), None);
+        let reason = enforce_output_cap(&state, &settings).unwrap();
+        assert!(reason.contains("limit"));
+        // The workers check `stop` before taking the third job
+        assert!(state.mux_state.lock().unwrap().stop);
+        assert_eq!(enforce_output_cap(&state, &settings), None);
+    }
+
+    #[test]
+    fn attachments_can_be_sorted_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        for path in [
+            "/b/cover.jpg",
+            "/a/Zeta.ttf",
+            "/c/alpha.otf",
+            "/a/cover.jpg",
+        ] {
+            job.attachments.push(test_external(path, "attachment"));
+        }
+        let mut settings = test_settings();
+        settings.allow_duplicate_attachments = true;
+        let attached = |settings: &MuxSettings| -> Vec<String> {
+            build_mkvmerge_command(&job, settings, Path::new("/out/show.mkv"), &state)
+                .windows(2)
+                .filter(|pair| pair[0] == "--attach-file")
+                .map(|pair| pair[1].clone())
+                .collect()
+        };
+
+        assert_eq!(attached(&settings)[0], "/b/cover.jpg");
+        settings.sort_attachments = true;
+        assert_eq!(
+            attached(&settings),
+            vec![
+                "/c/alpha.otf",
+                "/a/cover.jpg",
+                "/b/cover.jpg",
+                "/a/Zeta.ttf"
+            ]
+        );
+    }
+
+    #[test]
+    fn preview_summarises_track_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut removed = test_track("3", "subtitle", "fre");
+        removed.action = Some("remove".to_string());
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+                test_track("4", "audio", "ger"),
+                removed,
+                test_track("5", "subtitle", "eng"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["jpn".to_string(), "eng".to_string()];
+
+        let preview = preview_job_result(&state, job, &settings);
+        let summary = |track_type: &str, kept, removed, disabled| TrackActionSummary {
+            track_type: track_type.to_string(),
+            kept,
+            removed,
+            disabled,
+        };
+        assert_eq!(
+            preview.track_actions,
+            vec![
+                summary("video", 1, 0, 0),
+                summary("audio", 2, 0, 1),
+                summary("subtitle", 1, 1, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn dry_run_completes_without_touching_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let media = dir.path().join("media");
+        fs::create_dir(&media).unwrap();
+        let video = media.join("show.mkv");
+        fs::write(&video, b"source").unwrap();
+        let destination = media.join("out");
+        let job = test_job(
+            &video.to_string_lossy(),
+            vec![test_track("0", "video", "und")],
+        );
+        let mut settings = test_settings();
+        settings.dry_run = true;
+        settings.overwrite_source = false;
+        settings.destination_dir = destination.to_string_lossy().to_string();
+
+        let mut events = Vec::new();
+        dry_run_job(&state, &settings, &job, &mut |event| events.push(event));
+
+        let statuses: Vec<JobPhase> = events.iter().map(|e| e.status).collect();
+        assert_eq!(statuses, vec![JobPhase::Completed]);
+        assert!(state.mux_state.lock().unwrap().children.is_empty());
+        assert!(!destination.exists());
+        assert_eq!(fs::read(&video).unwrap(), b"source");
+        let log = fs::read_to_string(&state.paths.log_path).unwrap();
+        assert!(log.contains("Dry run: mkvmerge"));
+    }
+
+    #[test]
+    fn split_part_markers_are_recognised() {
+        let part = |stem: &str| split_part_number(stem);
+        assert_eq!(part("Movie.part1"), Some(("Movie".to_string(), 1)));
+        assert_eq!(part("Movie Part 2"), Some(("Movie".to_string(), 2)));
+        assert_eq!(
+            part("Movie (2004) CD2"),
+            Some(("Movie (2004)".to_string(), 2))
+        );
+        assert_eq!(
+            part("Movie-pt.3-1080p"),
+            Some(("Movie-1080p".to_string(), 3))
+        );
+        assert_eq!(part("Movie_Disc_10"), Some(("Movie".to_string(), 10)));
+        assert_eq!(part("Particle Fever"), None);
+        assert_eq!(part("Movie.part"), None);
+        assert_eq!(part("Movie.cd2x"), None);
+        assert_eq!(part("Apt2B"), None);
+    }
+
+    #[test]
+    fn split_parts_become_one_job_with_appended_sources() {
+        let video = |path: &str| test_job(path, Vec::new()).video;
+        let groups = group_split_parts(vec![
+            video("/movies/Movie.part2.mkv"),
+            video("/movies/Other.mkv"),
+            video("/movies/Movie.part1.mkv"),
+            video("/movies/Lonely.cd1.mkv"),
+        ]);
+
+        assert_eq!(groups.jobs.len(), 1);
+        let job = &groups.jobs[0];
+        assert_eq!(job.video.path, "/movies/Movie.part1.mkv");
+        assert_eq!(job.append_sources, vec!["/movies/Movie.part2.mkv"]);
+        assert_eq!(job.output_name.as_deref(), Some("Movie"));
+        let ungrouped: Vec<&str> = groups.ungrouped.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(
+            ungrouped,
+            vec!["/movies/Other.mkv", "/movies/Lonely.cd1.mkv"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let args =
+            build_mkvmerge_command(job, &test_settings(), Path::new("/out/Movie.mkv"), &state);
+        let source = args.iter().position(|arg| arg == "/movies/Movie.part1.mkv");
+        assert_eq!(
+            args.get(source.unwrap() + 1).map(String::as_str),
+            Some("+/movies/Movie.part2.mkv")
+        );
+    }
+
+    #[test]
+    fn invalidating_the_probe_cache_only_drops_the_given_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let stale = format!("{root}/stale.mkv");
+        let kept = format!("{root}/kept.mkv");
+        put_cached_file_info(format!("{stale}|10|1|video|true|Auto|Full"), &json!(1));
+        put_cached_file_info(format!("{stale}|10|1|audio|false|Auto|Basic"), &json!(2));
+        put_cached_file_info(format!("{stale}.old|10|1|video|true|Auto|Full"), &json!(3));
+        put_cached_file_info(format!("{kept}|10|1|video|true|Auto|Full"), &json!(4));
+
+        assert_eq!(invalidate_probe_cache(vec![stale.clone()]), 2);
+        assert!(get_cached_file_info(&format!("{stale}|10|1|video|true|Auto|Full")).is_none());
+        assert!(get_cached_file_info(&format!("{stale}.old|10|1|video|true|Auto|Full")).is_some());
+        assert!(get_cached_file_info(&format!("{kept}|10|1|video|true|Auto|Full")).is_some());
+    }
+
+    #[test]
+    fn stop_during_crc_aborts_the_read_early() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.mkv");
+        fs::write(&path, vec![7u8; 1024 * 1024]).unwrap();
+        let stop = AtomicBool::new(false);
+        let mut last = 0;
+
+        let result = compute_crc(
+            &path,
+            4096,
+            false,
+            &mut |percent| {
+                last = percent;
+                if percent >= 10 {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            },
+            &|| stop.load(Ordering::Relaxed),
+        );
+        assert_eq!(result, Err(CRC_CANCELLED.to_string()));
+        assert!((10..20).contains(&last), "stopped at {last}%");
+
+        let mut settings = test_settings();
+        settings.add_crc = true;
+        let renamed = apply_crc_naming(&path, &settings, &mut |_| {}, &|| true);
+        assert_eq!(renamed, path);
+    }
+
+    #[test]
+    fn crc_is_the_same_for_every_buffer_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sample.mkv");
+        let data: Vec<u8> = (0..3_000_017u32).map(|i| (i * 31 % 251) as u8).collect();
+        fs::write(&path, &data).unwrap();
+        let expected = format!("{:08X}", crc32fast::hash(&data));
+
+        for buffer_bytes in [0, 4096, 10_007, DEFAULT_CRC_BUFFER_BYTES, 8 * 1024 * 1024] {
+            for sequential in [false, true] {
+                let mut last = 0;
+                let crc = compute_crc(&path, buffer_bytes, sequential, &mut |p| last = p, &|| {
+                    false
+                })
+                .unwrap();
+                assert_eq!(
+                    crc, expected,
+                    "buffer {buffer_bytes}, sequential {sequential}"
+                );
+                assert_eq!(last, 100);
+            }
+        }
+    }
+
+    #[test]
+    fn preset_folders_move_to_a_new_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("setting.json");
+        let mut options = OptionsData::default();
+        let preset = &mut options.presets[0];
+        preset.default_video_directory = "/media/tv/Show".to_string();
+        preset.default_subtitle_directory = "/media/tv".to_string();
+        preset.default_audio_directory = "/media/tv2/Show".to_string();
+        preset.default_destination_directory = "/backup/tv".to_string();
+        write_options(&path, &options).unwrap();
+
+        let changed = relocate_preset_in_options(&path, 0, "/media/tv/", "/mnt/library").unwrap();
+        assert_eq!(
+            changed,
+            vec!["Default_Video_Directory", "Default_Subtitle_Directory"]
+        );
+        let preset = &read_options(&path).unwrap().presets[0];
+        assert_eq!(preset.default_video_directory, "/mnt/library/Show");
+        assert_eq!(preset.default_subtitle_directory, "/mnt/library");
+        assert_eq!(preset.default_audio_directory, "/media/tv2/Show");
+        assert_eq!(preset.default_destination_directory, "/backup/tv");
+        assert!(relocate_preset_in_options(&path, 5, "/a", "/b").is_err());
+    }
+
+    #[test]
+    fn chapter_language_is_set_per_chapter_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut german = test_external("/chapters/show.de.xml", "chapter");
+        german.language = Some("ger".to_string());
+        job.chapters.push(german);
+        job.chapters
+            .push(test_external("/chapters/show.txt", "chapter"));
+
+        let args =
+            build_mkvmerge_command(&job, &test_settings(), Path::new("/out/show.mkv"), &state);
+        let german_at = args
+            .iter()
+            .position(|arg| arg == "/chapters/show.de.xml")
+            .unwrap();
+        assert_eq!(
+            args[german_at - 3..german_at],
+            ["--chapter-language", "ger", "--chapters"]
+        );
+        // The later file doesn't inherit the German tag
+        let text_at = args
+            .iter()
+            .position(|arg| arg == "/chapters/show.txt")
+            .unwrap();
+        assert_eq!(
+            args[text_at - 3..text_at],
+            ["--chapter-language", "und", "--chapters"]
+        );
+
+        // Without any language the flag is left out
+        job.chapters[0].language = None;
+        let args =
+            build_mkvmerge_command(&job, &test_settings(), Path::new("/out/show.mkv"), &state);
+        assert!(!args.contains(&"--chapter-language".to_string()));
+    }
+
+    #[test]
+    fn duration_is_reported_formatted_and_in_seconds() {
+        let mkvmerge =
+            json!({ "container": { "properties": { "duration": 5_025_250_000_000u64 } } });
+        let mediainfo = json!({
+            "media": { "track": [{ "@type": "General", "Duration": "01:23:45.250" }] }
+        });
+
+        let (duration, tool) =
+            select_duration(DurationSource::Auto, Some(&mkvmerge), Some(&mediainfo)).unwrap();
+        let seconds = tool_duration_seconds(tool, Some(&mkvmerge), Some(&mediainfo));
+        assert_eq!(duration, "01:23:45");
+        assert_eq!(seconds, Some(5025.25));
+
+        let (duration, tool) =
+            select_duration(DurationSource::Mediainfo, Some(&mkvmerge), Some(&mediainfo)).unwrap();
+        let seconds = tool_duration_seconds(tool, Some(&mkvmerge), Some(&mediainfo));
+        assert_eq!(duration, "01:23:45");
+        assert_eq!(seconds, Some(5025.25));
+
+        let mut external = test_external("/media/show.ac3", "audio");
+        external.duration_seconds = parse_duration_seconds(&mediainfo);
+        external.duration = external.duration_seconds.map(format_duration);
+        let value = serde_json::to_value(&external).unwrap();
+        assert_eq!(value["duration"], "01:23:45");
+        assert_eq!(value["durationSeconds"], 5025.25);
+    }
+
+    #[test]
+    fn old_mkvmerge_gets_the_legacy_forced_flag() {
+        assert_eq!(
+            parse_mkvmerge_major_version("mkvmerge v81.0 ('Milliontown') 64-bit"),
+            Some(81)
+        );
+        assert_eq!(parse_mkvmerge_major_version("mkvmerge"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        state.mkvmerge_version.set(Some(40)).unwrap();
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut subtitle = test_external("/media/show.srt", "subtitle");
+        subtitle.is_forced = Some(true);
+        subtitle.is_default = Some(true);
+        job.subtitles.push(subtitle);
+
+        let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/o.mkv"), &state);
+        assert!(args.iter().any(|arg| arg == "--forced-track"));
+        assert!(args.iter().any(|arg| arg == "--default-track"));
+        assert!(!args.iter().any(|arg| arg == "--forced-display-flag"));
+        assert!(!args.iter().any(|arg| arg == "--default-track-flag"));
+        let preview = preview_job_result(&state, job.clone(), &test_settings());
+        assert!(preview
+            .warnings
+            .iter()
+            .any(|w| w.contains("--forced-track")));
+
+        let state = test_state(dir.path());
+        state.mkvmerge_version.set(Some(57)).unwrap();
+        let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/o.mkv"), &state);
+        assert!(args.iter().any(|arg| arg == "--forced-display-flag"));
+        assert!(args.iter().any(|arg| arg == "--default-track-flag"));
+    }
+
+    #[test]
+    fn only_one_video_track_is_made_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut main = test_track("0", "video", "und");
+        main.is_default = Some(true);
+        main.original_default = Some(true);
+        let mut angle = test_track("1", "video", "und");
+        angle.is_default = Some(true);
+        angle.original_default = Some(true);
+        let mut job = test_job("/media/show.mkv", vec![main, angle]);
+        let video_flags = |job: &MuxJobRequest| -> Vec<String> {
+            let args = build_mkvmerge_command(job, &test_settings(), Path::new("/o.mkv"), &state);
+            args.windows(2)
+                .filter(|pair| pair[0] == "--default-track-flag")
+                .map(|pair| pair[1].clone())
+                .collect()
+        };
+
+        // Unchanged flags are left as they are in the source
+        assert!(video_flags(&job).is_empty());
+
+        job.video.tracks[0].is_default = Some(false);
+        job.video.tracks[1].original_default = Some(false);
+        assert_eq!(video_flags(&job), vec!["0:no", "1:yes"]);
+    }
+
+    #[test]
+    fn crc_from_temp_output_matches_crc_from_final_output() {
+        let finalize = |crc_on_temp_output: bool| -> Vec<String> {
+            let dir = tempfile::tempdir().unwrap();
+            let state = test_state(dir.path());
+            let source = dir.path().join("show.mkv");
+            let temp = dir.path().join("show.tmp.mkv");
+            fs::write(&source, b"source").unwrap();
+            fs::write(&temp, vec![3u8; 10_000]).unwrap();
+            let job = test_job(&source.to_string_lossy(), Vec::new());
+            let mut settings = test_settings();
+            settings.add_crc = true;
+            settings.crc_on_temp_output = crc_on_temp_output;
+
+            finalize_job(
+                &state,
+                &job,
+                &settings,
+                &temp,
+                &source,
+                true,
+                Instant::now(),
+                0,
+                &mut |_| {},
+            );
+            let mut names: Vec<String> = fs::read_dir(dir.path())
+                .unwrap()
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.ends_with(".mkv"))
+                .collect();
+            names.sort();
+            names
+        };
+
+        let from_final = finalize(false);
+        assert_eq!(from_final.len(), 1);
+        assert!(from_final[0].starts_with("show ["));
+        assert_eq!(finalize(true), from_final);
+    }
+
+    #[test]
+    fn reset_restores_default_mux_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        {
+            let mut mux_state = state.mux_state.lock().unwrap();
+            mux_state.running = true;
+            mux_state.pause = true;
+            mux_state.queue = vec![test_job("/media/show.mkv", Vec::new())];
+            mux_state.settings = Some(test_settings());
+            mux_state
+                .stderr_tails
+                .insert("job".to_string(), VecDeque::from(["Error".to_string()]));
+            mux_state.last_progress.insert("job".to_string(), 40);
+            mux_state.batch_output_bytes = 1024;
+            mux_state
+                .last_commands
+                .insert("job".to_string(), "mkvmerge".to_string());
+            mux_state.batch_stats.add_job(100, Some(80));
+        }
+
+        // Workers still running: stopped, but not reset under them
+        assert!(reset_mux(&state, Duration::from_millis(10)).is_err());
+        {
+            let mut mux_state = state.mux_state.lock().unwrap();
+            assert!(mux_state.running && mux_state.stop);
+            assert!(mux_state.queue.is_empty());
+            mux_state.running = false;
+        }
+
+        reset_mux(&state, Duration::from_millis(10)).unwrap();
+
+        let mut mux_state = state.mux_state.lock().unwrap();
+        assert_eq!(mux_state.last_commands.len(), 1);
+        assert_eq!(mux_state.batch_stats.jobs_completed, 1);
+        mux_state.last_commands.clear();
+        mux_state.batch_stats = UsageStats::default();
+        assert_eq!(
+            format!("{mux_state:?}"),
+            format!("{:?}", MuxState::default())
+        );
+    }
+
+    #[test]
+    fn audio_forced_flag_needs_a_modern_mkvmerge() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut audio = test_external("/media/show.mka", "audio");
+        audio.included_track_ids = Some(vec![0]);
+        audio.is_forced = Some(true);
+        job.audios.push(audio);
+        let audio_args = |version: Option<u32>| -> (Vec<String>, Vec<String>) {
+            let state = test_state(dir.path());
+            state.mkvmerge_version.set(version).unwrap();
+            let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/o.mkv"), &state);
+            let start = args.iter().position(|arg| arg == "--audio-tracks").unwrap();
+            (
+                args[start..].to_vec(),
+                skipped_audio_forced_warnings(&job, &state),
+            )
+        };
+
+        // An undetected version is assumed to be modern
+        for version in [Some(81), None] {
+            let (args, warnings) = audio_args(version);
+            assert!(args
+                .windows(2)
+                .any(|pair| pair[0] == "--forced-display-flag" && pair[1] == "0:yes"));
+            assert!(warnings.is_empty());
+        }
+        let (old, warnings) = audio_args(Some(40));
+        assert!(!old
+            .iter()
+            .any(|arg| arg == "--forced-display-flag" || arg == "--forced-track"));
+        assert_eq!(
+            warnings,
+            ["Skipping the forced flag of /media/show.mka: mkvmerge v57 or newer is needed"]
+        );
+    }
+
+    #[test]
+    fn batch_estimate_uses_historical_throughput() {
+        let stats = UsageStats {
+            remuxed_bytes: 100_000_000,
+            remux_ms: 10_000,
+            ..UsageStats::default()
+        };
+        let mut settings = test_settings();
+        settings.max_parallel_jobs = Some(2);
+        let jobs: Vec<MuxJobRequest> = [30_000_000, 20_000_000, 20_000_000]
+            .into_iter()
+            .map(|size| {
+                let mut job = test_job("/media/show.mkv", Vec::new());
+                job.video.size = size;
+                job.audios.push(test_external("/media/show.mka", "audio"));
+                job
+            })
+            .collect();
+
+        // 10 MB/s over two workers: 3 s on one, 2 + 2 s on the other
+        let estimate = estimate_batch(&jobs, &settings, &stats);
+        assert!(estimate.from_history);
+        assert_eq!(estimate.bytes_per_second, 10_000_000.0);
+        assert!((estimate.seconds - 4.0).abs() < 1e-9);
+
+        // Fast-mux jobs barely count
+        settings.use_mkvpropedit = true;
+        let fast: Vec<MuxJobRequest> = jobs
+            .into_iter()
+            .map(|mut job| {
+                job.audios.clear();
+                job
+            })
+            .collect();
+        let estimate = estimate_batch(&fast, &settings, &stats);
+        assert_eq!(estimate.fast_mux_jobs, 3);
+        assert!(estimate.seconds <= 2.0 * FAST_MUX_ESTIMATE_SECS);
+    }
+
+    #[test]
+    fn poster_image_is_attached_first_as_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let poster = dir.path().join("Folder Art.jpeg");
+        fs::write(&poster, [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F']).unwrap();
+        let font = dir.path().join("font.ttf");
+        fs::write(&font, b"font").unwrap();
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.attachments
+            .push(test_external(&font.to_string_lossy(), "attachment"));
+        job.poster_image = Some(poster.to_string_lossy().to_string());
+
+        let args = build_mkvmerge_command(&job, &test_settings(), Path::new("/o.mkv"), &state);
+        let first = args.iter().position(|arg| arg == "--attach-file").unwrap();
+        assert_eq!(
+            args[first - 4..=first + 1],
+            [
+                "--attachment-name",
+                "cover.jpg",
+                "--attachment-mime-type",
+                "image/jpeg",
+                "--attach-file",
+                &poster.to_string_lossy(),
+            ]
+        );
+
+        fs::write(&poster, b"GIF89a..").unwrap();
+        assert!(poster_attachment(&poster)
+            .unwrap_err()
+            .contains("JPEG or PNG"));
+        let validation = validate_job_list(std::slice::from_ref(&job), &test_settings());
+        assert!(validation[0]
+            .errors
+            .iter()
+            .any(|error| error.contains("JPEG or PNG")));
+
+        // mkvpropedit can't attach the poster
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        job.attachments.clear();
+        assert!(fast_mux_blocker(&job, &settings).is_some());
+        job.poster_image = None;
+        assert_eq!(fast_mux_blocker(&job, &settings), None);
+    }
+
+    #[test]
+    fn messy_release_names_parse_into_clean_titles() {
+        let template = "{show} - S{season:02}E{episode:02}";
+        let clean = |name: &str| clean_title_name(Path::new(name), template, "_");
+        assert_eq!(
+            clean("/in/[SubsPlease] My Show - S01E05v2 (1080p) [ABCD1234].mkv").as_deref(),
+            Some("My Show - S01E05")
+        );
+        assert_eq!(
+            clean("/in/My.Show.2.s2e7.1080p.WEB-DL.x264-GRP.mkv").as_deref(),
+            Some("My Show 2 - S02E07")
+        );
+        assert_eq!(
+            clean("/in/[Grp] My Show - 12 [720p].mkv").as_deref(),
+            Some("My Show - S01E12")
+        );
+        assert_eq!(clean("/in/home video.mkv"), None);
+
+        let mut settings = test_settings();
+        settings.title_parser = Some(template.to_string());
+        let job = test_job("/in/My_Show_1x03_[HEVC].mkv", Vec::new());
+        assert_eq!(output_file_stem(&job, &settings), "My Show - S01E03");
+        // Forbidden characters from the template take the configured substitute
+        settings.title_parser = Some("{show}: {episode}".to_string());
+        settings.sanitize_substitute = " -".to_string();
+        assert_eq!(output_file_stem(&job, &settings), "My Show - 3");
+    }
+
+    #[test]
+    fn preview_flags_jobs_that_would_not_fit() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = dir.path().join("new").to_string_lossy().to_string();
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.video.size = 1024;
+        let mut audio = test_external("/media/show.mka", "audio");
+        audio.size = Some(512);
+        job.audios.push(audio);
+
+        let preview = preview_job_result(&state, job.clone(), &settings);
+        assert_eq!(preview.required_bytes, 1536);
+        assert!(preview.available_bytes.is_some());
+        assert!(preview.space_ok);
+
+        job.video.size = u64::MAX / 2;
+        let preview = preview_job_result(&state, job, &settings);
+        assert!(!preview.space_ok);
+        assert!(preview
+            .warnings
+            .iter()
+            .any(|w| w.contains("Not enough free space")));
+    }
+
+    #[test]
+    fn flagged_chapter_file_is_muxed_with_edition_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let chapters = dir.path().join("extended.txt");
+        fs::write(
+            &chapters,
+            "CHAPTER01=00:00:00.000\nCHAPTER01NAME=Intro\nCHAPTER02=00:05:00.000\nCHAPTER02NAME=Extended Scene\n",
+        )
+        .unwrap();
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        let mut chapter = test_external(&chapters.to_string_lossy(), "chapter");
+        chapter.default_edition = Some(true);
+        chapter.ordered = Some(false);
+        job.chapters.push(chapter);
+
+        let (flagged, files) = edition_flagged_job(&job).unwrap();
+        assert_eq!(files.len(), 1);
+        let xml = fs::read_to_string(&flagged.chapters[0].path).unwrap();
+        assert!(xml.contains("<EditionEntry>\n    <EditionFlagDefault>1</EditionFlagDefault>"));
+        assert!(xml.contains("<EditionFlagOrdered>0</EditionFlagOrdered>"));
+        assert_eq!(parse_xml_chapters(&xml).unwrap().len(), 2);
+        let args = build_mkvmerge_command(&flagged, &test_settings(), Path::new("/o.mkv"), &state);
+        assert!(args
+            .windows(2)
+            .any(|pair| pair[0] == "--chapters" && pair[1] == flagged.chapters[0].path));
+
+        // Flags already in the file are replaced rather than duplicated
+        let reflagged = set_edition_flags(&xml, Some(false), None);
+        assert_eq!(reflagged.matches("EditionFlagDefault>").count(), 2);
+        assert!(reflagged.contains("<EditionFlagDefault>0</EditionFlagDefault>"));
+        assert!(reflagged.contains("<EditionFlagOrdered>0</EditionFlagOrdered>"));
+
+        let path = flagged.chapters[0].path.clone();
+        drop(files);
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn low_space_pauses_the_batch_and_resumes_when_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        let mut paused_here = false;
+        assert_eq!(low_space_step(&state, &settings, 0, &mut paused_here), None);
+
+        settings.low_space_threshold_bytes = Some(1_000);
+        assert_eq!(
+            low_space_step(&state, &settings, 5_000, &mut paused_here),
+            None
+        );
+        let event = low_space_step(&state, &settings, 500, &mut paused_here).unwrap();
+        assert!(event.paused);
+        assert!(state.mux_state.lock().unwrap().pause);
+        // Already paused: no repeated event while space stays low
+        assert_eq!(
+            low_space_step(&state, &settings, 400, &mut paused_here),
+            None
+        );
+        // Recovery doesn't resume unless configured to
+        assert_eq!(
+            low_space_step(&state, &settings, 5_000, &mut paused_here),
+            None
+        );
+        assert!(state.mux_state.lock().unwrap().pause);
+
+        settings.resume_on_space_recovered = true;
+        let event = low_space_step(&state, &settings, 5_000, &mut paused_here).unwrap();
+        assert!(!event.paused);
+        assert!(!state.mux_state.lock().unwrap().pause);
+    }
+
+    #[test]
+    fn ffmpeg_command_maps_kept_tracks_and_flags() {
+        let mut audio = test_track("1", "audio", "jpn");
+        audio.is_default = Some(true);
+        let mut removed = test_track("2", "audio", "eng");
+        removed.action = Some("remove".to_string());
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![test_track("0", "video", "und"), audio, removed],
+        );
+        let mut subtitle = test_external("/media/show.srt", "subtitle");
+        subtitle.included_track_ids = Some(vec![0]);
+        subtitle.language = Some("eng".to_string());
+        subtitle.is_forced = Some(true);
+        subtitle.delay = Some(1.5);
+        job.subtitles.push(subtitle);
+        let mut settings = test_settings();
+        settings.output_container = OutputContainer::Mp4;
+
+        let args = build_ffmpeg_command(&job, &settings, Path::new("/out/show.mp4"));
+        let expected: Vec<String> = [
+            "-hide_banner",
+            "-nostdin",
+            "-y",
+            "-i",
+            "/media/show.mkv",
+            "-itsoffset",
+            "1.5",
+            "-i",
+            "/media/show.srt",
+            "-map",
+            "0:0",
+            "-map",
+            "0:1",
+            "-map",
+            "1:0",
+            "-c",
+            "copy",
+            "-c:s",
+            "mov_text",
+            "-metadata:s:0",
+            "language=und",
+            "-metadata:s:1",
+            "language=jpn",
+            "-disposition:1",
+            "default",
+            "-metadata:s:2",
+            "language=eng",
+            "-disposition:2",
+            "forced",
+            "/out/show.mp4",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert_eq!(args, expected);
+        assert_eq!(output_extension(&settings), "mp4");
+        assert!(fast_mux_blocker(&job, &settings).is_some());
+
+        // Batch defaults reach the dispositions like they reach mkvmerge's flags
+        job.video.tracks[1].is_default = None;
+        job.video.tracks[2].action = None;
+        settings.make_audio_default_language = Some("eng".to_string());
+        let dispositions = |settings: &MuxSettings| {
+            let args = build_ffmpeg_command(&job, settings, Path::new("/out/show.mp4"));
+            args.windows(2)
+                .filter(|pair| pair[0].starts_with("-disposition:"))
+                .map(|pair| format!("{} {}", pair[0], pair[1]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            dispositions(&settings),
+            [
+                "-disposition:1 0",
+                "-disposition:2 default",
+                "-disposition:3 forced"
+            ]
+        );
+        settings.make_audio_default_language = None;
+        settings.make_audio_default_position = Some(1);
+        assert_eq!(
+            dispositions(&settings),
+            [
+                "-disposition:1 default",
+                "-disposition:2 0",
+                "-disposition:3 forced"
+            ]
         );
-        if settings.abort_on_errors {
-            let mut mux_state = state.mux_state.lock().unwrap();
-            mux_state.pause = true;
-        }
-        return;
     }
 
-    let (output_path, final_path, overwrite_mode) = get_output_paths(&job, settings);
-    let _ = write_log_line(
-        &state.paths,
-        &format!("Output path: {}", output_path.to_string_lossy()),
-    );
-    // mkvpropedit is in-place metadata editing only.
-    // Allow it only when the user is explicitly overwriting source files.
-    let fast_mux_in_place_allowed =
-        settings.destination_dir.trim().is_empty() && settings.overwrite_source;
-    let can_use_mkvpropedit = settings.use_mkvpropedit
-        && fast_mux_in_place_allowed
-        && job.audios.is_empty()
-        && job.subtitles.is_empty()
-        && job.chapters.is_empty()
-        && job.attachments.is_empty()
-        && (!settings.only_keep_audios_enabled || settings.only_keep_audio_languages.is_empty())
-        && (!settings.only_keep_subtitles_enabled
-            || settings.only_keep_subtitle_languages.is_empty());
-    if settings.use_mkvpropedit && !can_use_mkvpropedit {
-        let _ = write_log_line(
-            &state.paths,
-            "Fast muxing requested but this job requires full mkvmerge (fast mux works only for in-place metadata edits).",
+    #[test]
+    fn mp4_validation_rejects_image_and_ass_subtitles() {
+        let mut pgs = test_track("2", "subtitle", "eng");
+        pgs.codec_id = Some("S_HDMV/PGS".to_string());
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![test_track("0", "video", "und"), pgs],
         );
+        job.subtitles
+            .push(test_external("/media/show.ass", "subtitle"));
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        assert!(validate_job_list(std::slice::from_ref(&job), &settings).is_empty());
+
+        settings.output_container = OutputContainer::Mp4;
+        let validations = validate_job_list(std::slice::from_ref(&job), &settings);
+        assert_eq!(validations.len(), 1);
+        assert_eq!(validations[0].errors.len(), 2);
+        assert!(validations[0].errors[0].contains("track 2"));
+        assert!(validations[0].errors[1].contains("show.ass"));
+
+        job.video.tracks[1].action = Some("remove".to_string());
+        job.subtitles.clear();
+        assert!(validate_job_list(&[job], &settings).is_empty());
     }
 
-    if can_use_mkvpropedit {
-        if !tool_available("mkvpropedit", "-V") {
-            emit_progress(
-                app,
-                MuxProgressEvent {
-                    job_id: job.id.clone(),
-                    status: "error".to_string(),
-                    progress: 0,
-                    message: Some("mkvpropedit not found".to_string()),
-                    size_after: None,
-                    error_message: Some("Install mkvpropedit or disable fast muxing.".to_string()),
-                },
-            );
-            return;
+    #[test]
+    fn scan_with_extension_group_matches_expanded_list() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.ass", "b.SRT", "c.sup", "d.mkv", "e.txt"] {
+            fs::write(dir.path().join(name), b"x").unwrap();
         }
+        let request = |extensions: &[&str]| ScanRequest {
+            folder: dir.path().to_string_lossy().to_string(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+            recursive: false,
+            file_type: "subtitle".to_string(),
+            include_tracks: false,
+            modified_since: None,
+            duration_source: DurationSource::Auto,
+            skip_trackless: false,
+            metadata_level: MetadataLevel::Full,
+            verify_magic: false,
+            max_concurrent_probes: DEFAULT_MAX_CONCURRENT_PROBES,
+            require_languages: Vec::new(),
+            require_language_types: Vec::new(),
+        };
+        let groups = HashMap::from([(
+            "subs".to_string(),
+            vec!["ass".to_string(), ".srt".to_string(), "SUP".to_string()],
+        )]);
+
+        let mut grouped = scan_files(&request(&["Subs"]), &groups).unwrap();
+        let mut expanded = scan_files(&request(&["ass", "srt", "sup"]), &HashMap::new()).unwrap();
+        grouped.sort();
+        expanded.sort();
+        assert_eq!(grouped.len(), 3);
+        assert_eq!(grouped, expanded);
+    }
 
-        let edit_args = build_mkvpropedit_args(&job);
-        if !edit_args.is_empty() {
-            let full_command = format!("mkvpropedit {} {}", job.video.path, edit_args.join(" "));
-            let _ = write_log_line(&state.paths, &full_command);
-            let _ = app.emit_all(
-                "mux-log",
-                serde_json::json!({ "job_id": job.id, "line": full_command }),
+    #[test]
+    fn orphan_externals_are_unmatched_or_point_at_missing_videos() {
+        let video = test_job("/media/show.mkv", Vec::new()).video;
+        let mut matched = test_external("/media/show.ass", "subtitle");
+        matched.matched_video_id = Some(video.id.clone());
+        let mut orphan = test_external("/media/other.ass", "subtitle");
+        orphan.id = "subtitle-2".to_string();
+        orphan.matched_video_id = Some("video-gone".to_string());
+        let mut unmatched = test_external("/media/extra.aac", "audio");
+        unmatched.matched_video_id = None;
+
+        let orphans = find_orphan_externals(vec![video], vec![matched, orphan, unmatched]);
+        let ids: Vec<&str> = orphans
+            .iter()
+            .map(|external| external.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["subtitle-2", "audio-1"]);
+    }
+
+    #[test]
+    fn custom_muxing_app_adds_info_edit() {
+        let mut settings = test_settings();
+        let job = test_job("/media/video.mkv", Vec::new());
+        assert!(muxing_app_args(&settings).is_empty());
+
+        settings.muxing_app = Some(" MyArchiver 2.1 ".to_string());
+        let edit = vec![
+            "--edit".to_string(),
+            "info".to_string(),
+            "--set".to_string(),
+            "muxing-application=MyArchiver 2.1".to_string(),
+        ];
+        assert_eq!(muxing_app_args(&settings), edit);
+        assert!(build_mkvpropedit_args(&job, &settings).ends_with(&edit));
+
+        settings.output_container = OutputContainer::Mp4;
+        assert!(muxing_app_args(&settings).is_empty());
+    }
+
+    #[test]
+    fn match_by_folder_builds_a_job_per_episode_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("Episode 01");
+        let second = dir.path().join("Episode 02");
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("ep1.mkv"), b"video").unwrap();
+        fs::write(first.join("sample.mkv"), b"v").unwrap();
+        fs::write(first.join("signs.ass"), b"[Script Info]").unwrap();
+        fs::write(first.join("commentary.flac"), b"fLaC").unwrap();
+        fs::write(first.join("notes.txt"), b"notes").unwrap();
+        fs::write(second.join("ep2.mkv"), b"video").unwrap();
+        fs::write(second.join("full.srt"), b"1").unwrap();
+        fs::create_dir_all(dir.path().join("extras")).unwrap();
+
+        let jobs = match_by_folder(dir.path().to_string_lossy().to_string()).unwrap();
+        assert_eq!(jobs.len(), 2);
+        let names = |externals: &[ExternalFileInfo]| -> Vec<String> {
+            externals
+                .iter()
+                .map(|external| external.name.clone())
+                .collect()
+        };
+        assert_eq!(jobs[0].video.name, "ep1.mkv");
+        assert_eq!(names(&jobs[0].subtitles), vec!["signs.ass"]);
+        assert_eq!(names(&jobs[0].audios), vec!["commentary.flac"]);
+        assert_eq!(
+            jobs[0].subtitles[0].matched_video_id.as_deref(),
+            Some(jobs[0].video.id.as_str())
+        );
+        assert_eq!(jobs[1].video.name, "ep2.mkv");
+        assert_eq!(names(&jobs[1].subtitles), vec!["full.srt"]);
+        assert!(jobs[1].audios.is_empty());
+
+        // What folder_job keeps for a file whose probe failed
+        let path = first.join("signs.ass");
+        let external: ExternalFileInfo = serde_json::from_value(unprobed_file_info(
+            &path,
+            "subtitle",
+            "mkvmerge failed".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(external.name, "signs.ass");
+        assert_eq!(external.size, Some(13));
+        assert_eq!(external.probe_error.as_deref(), Some("mkvmerge failed"));
+        let video: VideoFileInfo = serde_json::from_value(unprobed_file_info(
+            &first.join("ep1.mkv"),
+            "video",
+            "unreadable".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(video.probe_error.as_deref(), Some("unreadable"));
+    }
+
+    #[test]
+    fn job_phase_keeps_the_status_strings_on_the_wire() {
+        let phases = [
+            (JobPhase::Processing, "processing"),
+            (JobPhase::Finalizing, "finalizing"),
+            (JobPhase::Completed, "completed"),
+            (JobPhase::Error, "error"),
+            (JobPhase::Stopped, "stopped"),
+            (JobPhase::Skipped, "skipped"),
+        ];
+        for (phase, wire) in phases {
+            assert_eq!(serde_json::to_value(phase).unwrap(), json!(wire));
+            assert_eq!(
+                serde_json::from_value::<JobPhase>(json!(wire)).unwrap(),
+                phase
             );
+        }
+        assert!(serde_json::from_value::<JobPhase>(json!("complete")).is_err());
+    }
 
-            let mut cmd = hidden_command("mkvpropedit");
-            cmd.arg(&job.video.path);
-            for arg in edit_args {
-                cmd.arg(arg);
-            }
+    #[test]
+    fn chapter_offset_comes_from_the_first_video_frame() {
+        let mediainfo = json!({
+            "media": { "track": [
+                { "@type": "General", "Duration": "1420.500" },
+                { "@type": "Video", "Delay": "10.010" },
+                { "@type": "Audio", "Delay": "10.000" }
+            ] }
+        });
+        let offset = parse_video_start_seconds(&mediainfo).unwrap();
+        assert!((offset - 10.01).abs() < 1e-9);
+
+        assert_eq!(chapter_sync_ms(None, offset), Some(10010));
+        assert_eq!(chapter_sync_ms(Some(-0.5), offset), Some(9510));
+        assert_eq!(chapter_sync_ms(Some(0.0), 0.0), None);
+        assert_eq!(chapter_sync_ms(Some(1.25), 0.0), Some(1250));
+
+        let mut settings = test_settings();
+        let mut job = test_job("/missing/video.mkv", Vec::new());
+        job.chapters = vec![test_external("/missing/chapters.xml", "chapter")];
+        settings.chapter_auto_offset = true;
+        // Nothing to probe, so the chapters keep their own timing
+        assert_eq!(chapter_offset_seconds(&job, &settings), 0.0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        job.probed.chapter_offset = offset;
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/video.mkv"), &state);
+        let chapters = args.iter().position(|arg| arg == "--chapters").unwrap();
+        assert_eq!(
+            args[chapters..chapters + 4],
+            ["--chapters", "/missing/chapters.xml", "--sync", "0:10010"]
+        );
+    }
 
-            let child = match cmd.spawn() {
-                Ok(c) => c,
-                Err(e) => {
-                    emit_progress(
-                        app,
-                        MuxProgressEvent {
-                            job_id: job.id.clone(),
-                            status: "error".to_string(),
-                            progress: 0,
-                            message: Some("Failed to start mkvpropedit".to_string()),
-                            size_after: None,
-                            error_message: Some(format!("Failed to start mkvpropedit: {e}")),
-                        },
-                    );
-                    return;
+    #[test]
+    fn compacting_the_saved_queue_drops_finished_jobs_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let out = dir.path().join("out");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&out).unwrap();
+        let ebml = [0x1A, 0x45, 0xDF, 0xA3, 0x01, 0x00];
+        fs::write(out.join("done.mkv"), ebml).unwrap();
+        fs::write(out.join("tagged [0A1B2C3D].mkv"), ebml).unwrap();
+        fs::write(out.join("broken.mkv"), b"").unwrap();
+        let saved_job = |id: &str, name: &str, status: &str| {
+            json!({
+                "id": id,
+                "status": status,
+                "progress": 0,
+                "videoFile": {
+                    "id": format!("video-{id}"),
+                    "name": format!("{name}.mkv"),
+                    "path": source.join(format!("{name}.mkv")),
+                    "size": 1,
+                    "status": "pending",
+                    "tracks": []
                 }
-            };
+            })
+        };
+        let mut saved = json!({
+            "version": "1.0.0",
+            "muxSettings": {
+                "destinationDir": out,
+                "overwriteSource": false,
+                "addCrc": true
+            },
+            "jobs": [
+                saved_job("1", "done", "completed"),
+                saved_job("2", "tagged", "completed"),
+                saved_job("3", "broken", "completed"),
+                saved_job("4", "missing", "completed"),
+                saved_job("5", "pending", "queued"),
+            ]
+        });
 
-            let handle = Arc::new(Mutex::new(child));
-            {
-                let mut mux_state = state.mux_state.lock().unwrap();
-                mux_state.children.insert(job.id.clone(), handle.clone());
-            }
+        assert_eq!(compact_queue(&mut saved), 2);
+        let ids: Vec<&str> = saved["jobs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|job| job["id"].as_str())
+            .collect();
+        assert_eq!(ids, vec!["3", "4", "5"]);
+
+        // The job's own output name and destination decide where its output is
+        let elsewhere = dir.path().join("elsewhere");
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(elsewhere.join("Renamed Anime [0A1B2C3D].mkv"), ebml).unwrap();
+        let mut renamed = saved_job("6", "renamed", "completed");
+        renamed["outputName"] = json!("Renamed {preset}");
+        renamed["presetName"] = json!("Anime");
+        renamed["destinationDir"] = json!(elsewhere);
+        saved["jobs"] = json!([renamed]);
+        assert_eq!(compact_queue(&mut saved), 1);
+    }
 
-            let status = wait_for_child_or_stop(handle.clone(), state);
-            {
-                let mut mux_state = state.mux_state.lock().unwrap();
-                mux_state.children.remove(&job.id);
-            }
+    #[test]
+    fn track_count_mismatch_warns_and_matches_bitrates_by_language_and_codec() {
+        let mkvmerge = json!({
+            "tracks": [
+                { "id": 0, "type": "video", "codec": "AVC/H.264/MPEG-4p10", "properties": {} },
+                { "id": 1, "type": "audio", "codec": "FLAC", "properties": { "language": "jpn" } },
+                { "id": 2, "type": "audio", "codec": "E-AC-3", "properties": { "language": "eng" } }
+            ]
+        });
+        let mediainfo = json!({
+            "media": { "track": [
+                { "@type": "General" },
+                { "@type": "Video", "Format": "AVC" },
+                { "@type": "Audio", "Format": "AC-3", "Language": "en", "BitRate": "192000" },
+                { "@type": "Audio", "Format": "E-AC-3", "Language": "en", "BitRate": "640000" },
+                { "@type": "Audio", "Format": "FLAC", "Language": "ja", "BitRate": "900000" },
+                { "@type": "Menu" }
+            ] }
+        });
 
-            match status {
-                Some(code) if code == 0 => {
-                    let final_size = fs::metadata(&job.video.path).ok().map(|m| m.len());
-                    emit_progress(
-                        app,
-                        MuxProgressEvent {
-                            job_id: job.id.clone(),
-                            status: "completed".to_string(),
-                            progress: 100,
-                            message: Some("Fast mux completed".to_string()),
-                            size_after: final_size,
-                            error_message: None,
-                        },
-                    );
-                }
-                Some(code) => {
-                    let error_output = format!("mkvpropedit exited with code: {code}");
-                    emit_progress(
-                        app,
-                        MuxProgressEvent {
-                            job_id: job.id.clone(),
-                            status: "error".to_string(),
-                            progress: 0,
-                            message: Some("mkvpropedit failed".to_string()),
-                            size_after: None,
-                            error_message: Some(error_output),
-                        },
-                    );
-                }
-                None => {
-                    emit_progress(
-                        app,
-                        MuxProgressEvent {
-                            job_id: job.id.clone(),
-                            status: "error".to_string(),
-                            progress: 0,
-                            message: Some("mkvpropedit error".to_string()),
-                            size_after: None,
-                            error_message: Some("Failed to wait for mkvpropedit".to_string()),
-                        },
-                    );
-                }
-            }
-            return;
-        } else {
-            let _ = write_log_line(
-                &state.paths,
-                "Fast mux requested but no track modifications detected. Falling back to mkvmerge.",
-            );
-        }
+        let warning = track_count_warning(&mkvmerge, &mediainfo).unwrap();
+        assert!(warning.contains("mkvmerge found 3 tracks but mediainfo found 4"));
+
+        let listed = " English language name | ISO 639-3 code | ISO 639-2 code | ISO 639-1 code\n\
+                      German                | deu            | ger            | de\n\
+                      Japanese              | jpn            | jpn            | ja\n\
+                      Slovak                | slk            | slo            | sk\n\
+                      Marathi               | mar            | mar            | mr\n\
+                      Maori                 | mri            | mao            | mi\n\
+                      Esperanto             | epo            | epo            | eo\n\
+                      Luxembourgish         | ltz            | ltz            | lb\n\
+                      Ghotuo                | aaa            |                |\n";
+        // Stands in for the installed mkvmerge's list; a real one agrees on these
+        let _ = ISO_639_1_CODES.set(parse_iso_639_1_codes(listed));
+        let mut tracks = parse_mkvmerge_tracks(&mkvmerge);
+        let applied = apply_mediainfo_audio_bitrates(&mut tracks, &mediainfo);
+        assert_eq!(applied, vec![1, 2]);
+        assert_eq!(tracks[1].bitrate, Some(900_000));
+        assert_eq!(tracks[2].bitrate, Some(640_000));
+
+        let matching = json!({
+            "media": { "track": [
+                { "@type": "Video", "Format": "AVC" },
+                { "@type": "Audio", "Format": "FLAC", "BitRate": "1" },
+                { "@type": "Audio", "Format": "E-AC-3", "BitRate": "2" }
+            ] }
+        });
+        assert_eq!(track_count_warning(&mkvmerge, &matching), None);
+        let codes = parse_iso_639_1_codes(listed);
+        assert_eq!(codes.get("ger").map(String::as_str), Some("de"));
+        assert_eq!(codes.get("deu").map(String::as_str), Some("de"));
+        assert!(!codes.contains_key("aaa"));
+        let same = |a: &str, b: &str| same_language(Some(a), Some(b), &codes);
+        assert!(same("ger", "de-DE"));
+        assert!(same("jpn", "ja-JP"));
+        assert!(!same("jpn", "en"));
+        assert!(same("slo", "sk"));
+        assert!(same("mar", "mr"));
+        assert!(!same("mri", "mr"));
+        assert!(same("epo", "eo"));
+        assert!(same("ltz", "lb"));
+        // Not listed: the first two letters decide
+        assert!(same("eng", "en"));
+        assert!(!same_codec(Some("E-AC-3"), Some("AC-3")));
+        assert!(same_codec(Some("DTS-HD Master Audio"), Some("DTS")));
     }
 
-    if !tool_available("mkvmerge", "-V") {
-        emit_progress(
-            app,
-            MuxProgressEvent {
-                job_id: job.id.clone(),
-                status: "error".to_string(),
-                progress: 0,
-                message: Some("mkvmerge not found".to_string()),
-                size_after: None,
-                error_message: Some("Install mkvmerge (MKVToolNix) and try again.".to_string()),
-            },
+    #[test]
+    fn comment_tag_xml_carries_the_expanded_text() {
+        let mut job = test_job("/media/Show - 01.mkv", Vec::new());
+        job.video.name = "Show - 01.mkv".to_string();
+        job.preset_name = Some("Anime".to_string());
+        // 2024-01-01T12:00:00Z
+        let comment = expand_comment_tag(
+            "Muxed by MkvBatchMux v{version} on {date} from {source} <{preset}>",
+            &job,
+            1_704_110_400,
         );
-        if settings.abort_on_errors {
-            let mut mux_state = state.mux_state.lock().unwrap();
-            mux_state.pause = true;
-        }
-        return;
+        assert_eq!(
+            comment,
+            format!(
+                "Muxed by MkvBatchMux v{} on 2024-01-01 from Show - 01.mkv <Anime>",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        let xml = comment_tag_xml(&comment);
+        assert!(xml.contains("<Name>COMMENT</Name>"));
+        assert!(xml.contains("on 2024-01-01 from Show - 01.mkv &lt;Anime&gt;</String>"));
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+
+        let mut settings = test_settings();
+        assert!(comment_tag_file(&job, &settings).unwrap().is_none());
+        settings.comment_tag = Some("Archived {date}".to_string());
+        let file = comment_tag_file(&job, &settings).unwrap().unwrap();
+        assert!(fs::read_to_string(file.path())
+            .unwrap()
+            .contains("<String>Archived "));
     }
 
-    let mut command = hidden_command("mkvmerge");
-    let command_args = build_mkvmerge_command(&job, settings, &output_path, state);
-    log_job_plan(state, &job, &output_path);
-    let command_line = command_args
-        .iter()
-        .map(|arg| quote_arg(arg))
-        .collect::<Vec<_>>()
-        .join(" ");
-    let _ = write_log_line(&state.paths, &format!("mkvmerge {}", command_line));
-    for arg in command_args {
-        command.arg(arg);
+    #[test]
+    fn sanitize_filename_replaces_windows_illegal_characters() {
+        let policy = SanitizePolicy::Replace("-");
+        assert_eq!(
+            sanitize_filename("Show: Part 1? <Final> *cut* a/b\\c|d\"e\".", policy),
+            "Show- Part 1- -Final- -cut- a-b-c-d-e-"
+        );
+        assert_eq!(
+            sanitize_filename("Why?", SanitizePolicy::Replace("")),
+            "Why"
+        );
+        assert_eq!(sanitize_filename("Why?", SanitizePolicy::Keep), "Why?");
+        let safe = "Show - S01E02 - Title [1080p] (2024)";
+        assert_eq!(sanitize_filename(safe, policy), safe);
+
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        settings.sanitize_filenames = true;
+        settings.sanitize_substitute = " ?".to_string();
+        let job = test_job("/media/Show: Special?.mkv", Vec::new());
+        let (_, final_path, _) = get_output_paths(&job, &settings);
+        assert_eq!(final_path, Path::new("/out/Show  Special.mkv"));
+
+        settings.sanitize_filenames = false;
+        let (_, final_path, _) = get_output_paths(&job, &settings);
+        assert_eq!(final_path, Path::new("/out/Show: Special?.mkv"));
     }
 
-    let handle = match run_command_with_logs(app, state, &job, &mut command) {
-        Ok(child) => child,
-        Err(err) => {
-            emit_progress(
-                app,
-                MuxProgressEvent {
-                    job_id: job.id.clone(),
-                    status: "error".to_string(),
-                    progress: 0,
-                    message: Some("Failed to start process".to_string()),
-                    size_after: None,
-                    error_message: Some(err),
-                },
-            );
-            if settings.abort_on_errors {
-                let mut mux_state = state.mux_state.lock().unwrap();
-                mux_state.pause = true;
-            }
-            return;
-        }
-    };
+    #[test]
+    fn last_batch_settings_round_trip_after_start() {
+        let mut mux_state = MuxState::default();
+        assert!(last_batch_settings(&mux_state).is_none());
+
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        settings.max_parallel_jobs = Some(3);
+        let request = MuxStartRequest {
+            settings: settings.clone(),
+            jobs: vec![
+                test_job("/media/a.mkv", Vec::new()),
+                test_job("/media/b.mkv", Vec::new()),
+            ],
+        };
+        store_batch(&mut mux_state, request);
 
-    let exit_code = wait_for_child_or_stop(handle.clone(), state).unwrap_or(-1);
-    {
-        let mut mux_state = state.mux_state.lock().unwrap();
-        mux_state.children.remove(&job.id);
+        let last = last_batch_settings(&mux_state).unwrap();
+        assert_eq!(last.job_count, 2);
+        assert_eq!(
+            serde_json::to_value(&last.settings).unwrap(),
+            serde_json::to_value(&settings).unwrap()
+        );
+        let value = serde_json::to_value(&last).unwrap();
+        assert_eq!(value["jobCount"], 2);
+        assert_eq!(value["settings"]["useMkvpropedit"], true);
     }
 
-    if exit_code != 0 {
-        let treat_as_success = exit_code == 1 && (output_path.exists() || final_path.exists());
-        if treat_as_success {
-            let _ = write_log_line(
-                &state.paths,
-                &format!("Job {} completed with warnings (exit code 1)", job.id),
-            );
-        } else {
-            let _ = write_log_line(
-                &state.paths,
-                &format!("Job {} failed with exit code {}", job.id, exit_code),
-            );
-            emit_progress(
-                app,
-                MuxProgressEvent {
-                    job_id: job.id.clone(),
-                    status: "error".to_string(),
-                    progress: 0,
-                    message: Some("Muxing failed".to_string()),
-                    size_after: None,
-                    error_message: Some(format!("Process exited with code {exit_code}")),
-                },
-            );
-            if settings.abort_on_errors {
-                let mut mux_state = state.mux_state.lock().unwrap();
-                mux_state.pause = true;
-            }
-            return;
-        }
+    #[test]
+    fn blocked_source_track_ids_are_excluded() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "jpn"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+                test_track("3", "subtitle", "eng"),
+                test_track("4", "audio", "eng"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.block_source_track_ids = vec![4];
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let value_after = |flag: &str| {
+            args.windows(2)
+                .find(|pair| pair[0] == flag)
+                .map(|pair| pair[1].clone())
+        };
+        assert_eq!(value_after("--audio-tracks").as_deref(), Some("1,2"));
+        assert_eq!(value_after("--video-tracks"), None);
+        assert_eq!(value_after("--subtitle-tracks"), None);
+
+        // Combined with the language filter, a track must pass both to stay
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["eng".to_string()];
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let audio_tracks = args
+            .windows(2)
+            .find(|pair| pair[0] == "--audio-tracks")
+            .map(|pair| pair[1].clone());
+        assert_eq!(audio_tracks.as_deref(), Some("2"));
+
+        let audio = track_action_summary(&job, &settings)
+            .into_iter()
+            .find(|summary| summary.track_type == "audio")
+            .unwrap();
+        assert_eq!((audio.kept, audio.disabled), (1, 2));
     }
 
-    if overwrite_mode && output_path.exists() {
-        let _ = fs::remove_file(&job.video.path);
-        let _ = fs::rename(&output_path, &final_path);
+    #[test]
+    fn blocked_tracks_are_left_out_of_every_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut pgs = test_track("3", "subtitle", "eng");
+        pgs.codec_id = Some("S_HDMV/PGS".to_string());
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "jpn"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+                pgs,
+                test_track("4", "audio", "eng"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        settings.block_source_track_ids = vec![9];
+        assert_eq!(fast_mux_blocker(&job, &settings), None);
+        settings.block_source_track_ids = vec![3, 4];
+        assert_eq!(
+            fast_mux_blocker(&job, &settings),
+            Some("blocking source tracks removes tracks")
+        );
+
+        let expected: Vec<String> = expected_output_tracks(&job, &settings)
+            .into_iter()
+            .map(|track| track.label)
+            .collect();
+        assert_eq!(
+            expected,
+            [
+                "video track 0 of video.mkv",
+                "audio track 1 of video.mkv",
+                "audio track 2 of video.mkv"
+            ]
+        );
+
+        settings.make_audio_default_position = Some(2);
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let flags: Vec<&String> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag")
+            .map(|pair| &pair[1])
+            .collect();
+        assert_eq!(flags, vec!["1:no", "2:yes"]);
+
+        let mut mp4 = settings.clone();
+        mp4.output_container = OutputContainer::Mp4;
+        mp4.overwrite_source = false;
+        mp4.destination_dir = "/out".to_string();
+        assert!(mp4_validation_errors(&job, &mp4).is_empty());
+        mp4.block_source_track_ids.clear();
+        assert!(mp4_validation_errors(&job, &mp4)
+            .iter()
+            .any(|error| error.contains("track 3")));
     }
 
-    let mut final_output = final_path.clone();
-    if settings.add_crc && final_path.exists() {
-        if let Ok(crc) = compute_crc(&final_path) {
-            let with_crc = file_name_with_crc(&final_path, &crc);
-            let _ = fs::rename(&final_path, &with_crc);
-            final_output = with_crc;
-        }
-    } else if settings.remove_old_crc && final_path.exists() {
-        let without_crc = file_name_without_crc(&final_path);
-        let _ = fs::rename(&final_path, &without_crc);
-        final_output = without_crc;
+    #[test]
+    fn unopenable_input_error_names_the_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("show.mkv");
+        let audio = dir.path().join("show.eng.aac");
+        fs::write(&video, b"video").unwrap();
+        fs::write(&audio, b"audio").unwrap();
+        let mut job = test_job(&video.to_string_lossy(), Vec::new());
+        job.audios
+            .push(test_external(&audio.to_string_lossy(), "audio"));
+        let stderr = format!(
+            "Error: The file '{}' could not be opened for reading: open file error.",
+            audio.to_string_lossy()
+        );
+
+        assert_eq!(
+            unopenable_input_message(&job, Some("Error: not a Matroska file")),
+            None
+        );
+        assert_eq!(unopenable_input_message(&job, None), None);
+        let message = unopenable_input_message(&job, Some(&stderr)).unwrap();
+        assert!(message.contains("all of them exist"), "{message}");
+
+        fs::remove_file(&audio).unwrap();
+        let message = unopenable_input_message(&job, Some(&stderr)).unwrap();
+        assert_eq!(
+            message,
+            format!("Audio file {} no longer exists", audio.to_string_lossy())
+        );
     }
 
-    let size_after = fs::metadata(&final_output).map(|m| m.len()).ok();
+    #[test]
+    fn sparse_progress_is_smoothed_from_output_growth() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let output = dir.path().join("show.tmp.mkv");
+        let poll = || poll_smoothed_progress(&state, "job-1", &output, UNIX_EPOCH, 1000);
+
+        assert_eq!(next_progress(&state, "job-1", "Progress: 0%"), Some(0));
+        assert_eq!(poll(), None);
+        fs::write(&output, vec![0u8; 250]).unwrap();
+        assert_eq!(poll(), Some(25));
+        assert_eq!(poll(), None);
+        fs::write(&output, vec![0u8; 600]).unwrap();
+        assert_eq!(poll(), Some(60));
+
+        // A real report behind the smoothed value would move the bar back
+        assert_eq!(next_progress(&state, "job-1", "Progress: 40%"), None);
+        assert_eq!(next_progress(&state, "job-1", "Progress: 70%"), Some(70));
+        assert_eq!(poll(), None);
+
+        // Never claims completion before mkvmerge does
+        fs::write(&output, vec![0u8; 1200]).unwrap();
+        assert_eq!(poll(), Some(99));
+
+        assert_eq!(next_progress(&state, "job-1", "Progress: 100%"), Some(100));
+        fs::write(&output, vec![0u8; 1500]).unwrap();
+        assert_eq!(poll(), None);
+    }
 
-    emit_progress(
-        app,
-        MuxProgressEvent {
-            job_id: job.id.clone(),
-            status: "completed".to_string(),
-            progress: 100,
-            message: Some("Muxing completed".to_string()),
-            size_after,
-            error_message: None,
-        },
-    );
-    let _ = write_log_line(
-        &state.paths,
-        &format!("Job {} completed successfully", job.id),
-    );
+    #[test]
+    fn valid_chapters_xml_is_reindented() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chapters.xml");
+        fs::write(
+            &path,
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE Chapters SYSTEM \"matroskachapters.dtd\">\n<Chapters><EditionEntry><ChapterAtom>\n<ChapterTimeStart>00:00:00.000</ChapterTimeStart><ChapterDisplay><ChapterString>Tom &amp; Jerry</ChapterString><ChapterLanguage>eng</ChapterLanguage></ChapterDisplay></ChapterAtom></EditionEntry></Chapters>\n",
+        )
+        .unwrap();
+
+        let validation =
+            validate_xml_file(path.to_string_lossy().to_string(), XmlFileKind::Chapters).unwrap();
+        assert_eq!(validation.errors, Vec::new());
+        let formatted = validation.formatted.unwrap();
+        assert!(formatted.starts_with("<?xml version=\"1.0\"?>\n<!DOCTYPE"));
+        assert!(formatted.contains("\n      <ChapterTimeStart>00:00:00.000</ChapterTimeStart>\n"));
+        assert!(formatted.contains("<ChapterString>Tom &amp; Jerry</ChapterString>"));
+        assert!(formatted.ends_with("</Chapters>\n"));
+
+        // Well-formed, but not what a tags file looks like
+        let validation =
+            validate_xml_file(path.to_string_lossy().to_string(), XmlFileKind::Tags).unwrap();
+        assert_eq!(validation.errors.len(), 1);
+        assert_eq!(validation.errors[0].line, 3);
+        assert!(validation.errors[0].message.contains("expected <Tags>"));
+    }
+
+    #[test]
+    fn malformed_xml_reports_the_error_location() {
+        let text =
+            "<Chapters>\n  <EditionEntry>\n    <ChapterAtom>\n  </EditionEntry>\n</Chapters>\n";
+        let validation = validate_xml_text(text, XmlFileKind::Chapters);
+        assert!(validation.formatted.is_none());
+        assert_eq!(
+            validation.errors,
+            vec![XmlIssue {
+                line: 4,
+                column: 3,
+                message: "Expected </ChapterAtom> (opened on line 3) but found </EditionEntry>"
+                    .to_string(),
+            }]
+        );
+
+        let validation = validate_xml_text("<Tags>\n  <Tag>\n", XmlFileKind::Tags);
+        assert_eq!(
+            (validation.errors[0].line, validation.errors[0].column),
+            (2, 3)
+        );
+        assert!(validation.errors[0].message.contains("never closed"));
+    }
+
+    #[test]
+    fn keep_track_name_patterns_keep_only_matching_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let named = |id: &str, track_type: &str, name: &str| {
+            let mut track = test_track(id, track_type, "eng");
+            track.name = Some(name.to_string());
+            track
+        };
+        let mut job = test_job(
+            "/media/film.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                named("1", "audio", "Stereo"),
+                named("2", "audio", "Director's Cut Commentary"),
+                test_track("3", "audio", "eng"),
+                named("4", "subtitle", "Full"),
+            ],
+        );
+        let mut settings = test_settings();
+        let audio_tracks = |job: &MuxJobRequest, settings: &MuxSettings| {
+            let args = build_mkvmerge_command(job, settings, Path::new("/out/film.mkv"), &state);
+            let subtitle_tracks = args
+                .windows(2)
+                .find(|pair| pair[0] == "--subtitle-tracks")
+                .map(|pair| pair[1].clone());
+            assert_eq!(subtitle_tracks, None, "subtitles have no match and stay");
+            args.windows(2)
+                .find(|pair| pair[0] == "--audio-tracks")
+                .map(|pair| pair[1].clone())
+        };
+
+        settings.keep_track_name_patterns = vec!["director's cut".to_string()];
+        assert_eq!(audio_tracks(&job, &settings).as_deref(), Some("2"));
+
+        // A job's own patterns win over the batch setting
+        job.keep_track_name_patterns = Some(vec!["st*o".to_string()]);
+        assert_eq!(audio_tracks(&job, &settings).as_deref(), Some("1"));
+
+        // mkvpropedit can't remove the tracks the patterns leave out
+        settings.use_mkvpropedit = true;
+        assert!(fast_mux_blocker(&job, &settings).is_some_and(|reason| reason.contains("names")));
+
+        job.keep_track_name_patterns = Some(Vec::new());
+        assert_eq!(audio_tracks(&job, &settings), None);
+        assert_eq!(fast_mux_blocker(&job, &settings), None);
+
+        assert!(track_name_matches("Director's Cut", "*CUT"));
+        assert!(!track_name_matches("Cut scenes", "*cut"));
+    }
+
+    #[test]
+    fn first_available_favorite_language_becomes_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let preset = Preset {
+            default_favorite_audio_languages: vec![
+                "Arabic".to_string(),
+                "English".to_string(),
+                "jpn".to_string(),
+            ],
+            default_favorite_subtitle_languages: vec!["english".to_string(), "eng".to_string()],
+            ..Preset::default()
+        };
+        let favorites = favorite_defaults(&preset);
+        assert_eq!(favorites.audio_languages, vec!["ara", "eng", "jpn"]);
+        assert_eq!(favorites.subtitle_languages, vec!["eng"]);
+
+        let job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+                test_track("3", "subtitle", "spa"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.audio_default_language_priority = favorites.audio_languages;
+        settings.subtitle_default_language_priority = favorites.subtitle_languages;
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        let flags: Vec<&String> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--default-track-flag")
+            .map(|pair| &pair[1])
+            .collect();
+        // The other kept audio track loses its default flag
+        assert_eq!(flags, vec!["1:no", "2:yes"]);
+
+        // A fixed language still wins over the priority list
+        settings.make_audio_default_language = Some("jpn".to_string());
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(args.contains(&"1:yes".to_string()));
+        assert!(!args.contains(&"2:yes".to_string()));
+
+        // A favorite the selection drops is passed over for the next one
+        settings.make_audio_default_language = None;
+        settings.only_keep_audios_enabled = true;
+        settings.only_keep_audio_languages = vec!["jpn".to_string()];
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(args.contains(&"1:yes".to_string()));
+        assert!(!args.contains(&"2:yes".to_string()));
+    }
+
+    #[test]
+    fn external_default_beats_position_which_beats_language() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+                test_track("2", "audio", "eng"),
+                test_track("3", "audio", "fre"),
+            ],
+        );
+        let mut settings = test_settings();
+        settings.make_audio_default_language = Some("eng".to_string());
+        settings.make_audio_default_position = Some(3);
+        let source_flags = |job: &MuxJobRequest, settings: &MuxSettings| {
+            let args = build_mkvmerge_command(job, settings, Path::new("/out/show.mkv"), &state);
+            let end = args.iter().position(|arg| arg == &job.video.path).unwrap();
+            args[..end]
+                .windows(2)
+                .filter(|pair| pair[0] == "--default-track-flag")
+                .map(|pair| pair[1].clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(source_flags(&job, &settings), ["1:no", "2:no", "3:yes"]);
+
+        settings.make_audio_default_position = None;
+        assert_eq!(source_flags(&job, &settings), ["1:no", "2:yes", "3:no"]);
 
-    if settings.keep_log_file && !settings.destination_dir.trim().is_empty() {
-        let _ = fs::copy(
-            &state.paths.log_path,
-            output_dir.join("muxing_log_file.txt"),
+        settings.make_audio_default_position = Some(3);
+        let mut audio = test_external("/media/show.ger.mka", "audio");
+        audio.is_default = Some(true);
+        job.audios.push(audio);
+        assert_eq!(source_flags(&job, &settings), ["1:no", "2:no", "3:no"]);
+    }
+
+    #[test]
+    fn stop_after_first_runs_only_the_first_job() {
+        let queue: Vec<MuxJobRequest> = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                let mut job = test_job(&format!("/media/{name}.mkv"), Vec::new());
+                job.id = format!("job-{name}");
+                job
+            })
+            .collect();
+        let mut settings = test_settings();
+
+        let ids = |jobs: Vec<MuxJobRequest>| jobs.into_iter().map(|job| job.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(jobs_to_run(queue.clone(), &settings)),
+            vec!["job-a", "job-b", "job-c"]
         );
+        settings.stop_after_first = true;
+        assert_eq!(ids(jobs_to_run(queue, &settings)), vec!["job-a"]);
+        assert!(jobs_to_run(Vec::new(), &settings).is_empty());
     }
-}
 
-fn run_mux_queue(app: AppHandle, state: AppState) {
-    let settings = {
-        let mux_state = state.mux_state.lock().unwrap();
-        mux_state.settings.clone()
-    };
-    let Some(settings) = settings else {
-        return;
-    };
+    #[test]
+    fn remux_replaces_a_stale_crc_tag_and_logs_the_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = test_settings();
+        settings.add_crc = true;
+        let crc_of = |path: &Path| {
+            compute_crc(path, DEFAULT_CRC_BUFFER_BYTES, false, &mut |_| {}, &|| {
+                false
+            })
+            .unwrap()
+        };
 
-    let jobs = {
-        let mux_state = state.mux_state.lock().unwrap();
-        mux_state.queue.clone()
-    };
+        let previous = dir.path().join("show [00000000].mkv");
+        fs::write(&previous, b"re-muxed content").unwrap();
+        let crc = crc_of(&previous);
+        let renamed = apply_crc_naming(&previous, &settings, &mut |_| {}, &|| false);
+        assert_eq!(renamed, dir.path().join(format!("show [{crc}].mkv")));
+        assert_eq!(
+            crc_change_log_line("job-1", &previous, &renamed).unwrap(),
+            format!("Job job-1: CRC changed from 00000000 to {crc}")
+        );
 
-    let max_parallel = settings.max_parallel_jobs.unwrap_or(1).max(1);
-    let (tx, rx) = mpsc::channel::<MuxJobRequest>();
-    for job in jobs {
-        let _ = tx.send(job);
+        // Bit-identical content keeps the tag it already had
+        let again = apply_crc_naming(&renamed, &settings, &mut |_| {}, &|| false);
+        assert_eq!(again, renamed);
+        assert!(again.exists());
+        assert_eq!(
+            crc_change_log_line("job-1", &renamed, &again).unwrap(),
+            format!("Job job-1: CRC unchanged ({crc}), the content is identical")
+        );
+
+        assert_eq!(
+            crc_change_log_line("job-1", &dir.path().join("show.mkv"), &renamed),
+            None
+        );
     }
-    drop(tx);
 
-    let receiver = Arc::new(Mutex::new(rx));
-    let mut workers = Vec::new();
+    #[test]
+    fn command_diff_finds_the_one_changed_flag() {
+        let args = |extra: &[&str]| {
+            let mut args: Vec<String> = ["-o", "/out/my show.mkv", "--language", "1:eng"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+            args.extend(extra.iter().map(|arg| arg.to_string()));
+            args.push("/media/my \"show\".mkv".to_string());
+            args
+        };
+        let before = join_tool_command("mkvmerge", &args(&[]));
+        let after = join_tool_command("mkvmerge", &args(&["--no-chapters"]));
+        assert_eq!(
+            split_tool_command(&after).last().unwrap(),
+            "/media/my \"show\".mkv"
+        );
 
-    for _ in 0..max_parallel {
-        let app_handle = app.clone();
-        let state_clone = state.clone();
-        let settings_clone = settings.clone();
-        let rx_clone = receiver.clone();
-        workers.push(thread::spawn(move || loop {
-            {
-                let mux_state = state_clone.mux_state.lock().unwrap();
-                if mux_state.stop {
-                    break;
-                }
-                if mux_state.pause {
-                    drop(mux_state);
-                    thread::sleep(Duration::from_millis(200));
-                    continue;
-                }
+        assert_eq!(
+            diff_command_lines(&before, &after),
+            CommandDiff {
+                added: vec!["--no-chapters".to_string()],
+                removed: Vec::new(),
+                reordered: Vec::new(),
             }
+        );
+        assert_eq!(
+            diff_command_lines(&after, &before).removed,
+            vec!["--no-chapters"]
+        );
 
-            let job = {
-                let rx_lock = rx_clone.lock().unwrap();
-                rx_lock.recv_timeout(Duration::from_millis(200))
-            };
-
-            match job {
-                Ok(job) => process_job(&app_handle, &state_clone, &settings_clone, job),
-                Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
-            }
-        }));
+        let moved = diff_command_lines("mkvmerge --a --b --c", "mkvmerge --c --a --b");
+        assert_eq!(moved.reordered, vec!["--c"]);
+        assert!(moved.added.is_empty() && moved.removed.is_empty());
     }
 
-    for worker in workers {
-        let _ = worker.join();
+    #[test]
+    fn overlong_output_names_are_truncated_on_a_character_boundary() {
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        settings.add_crc = true;
+        settings.max_filename_bytes = Some(63);
+        // Every `é` is two bytes; the 48 bytes left for the stem end inside one
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.output_name = Some(format!("Show {} Episode", "é".repeat(60)));
+
+        let (_, final_path, _) = get_output_paths(&job, &settings);
+        let name = final_path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("Show é"), "{name}");
+        assert!(name.ends_with(".mkv"));
+        let with_crc = file_name_with_crc(&final_path, "0A1B2C3D");
+        assert_eq!(with_crc.file_name().unwrap().len(), 62);
+
+        let note = filename_truncation_note(&job, &settings).unwrap();
+        assert!(note.contains("shortened to fit 63 bytes"), "{note}");
+
+        assert_eq!(truncate_to_bytes("aé", 2), "a");
+        assert_eq!(truncate_to_bytes("aé", 3), "aé");
+
+        settings.max_filename_bytes = None;
+        assert_eq!(filename_truncation_note(&job, &settings), None);
+        let (_, final_path, _) = get_output_paths(&job, &settings);
+        assert!(final_path.to_string_lossy().ends_with("Episode.mkv"));
     }
 
-    let mut mux_state = state.mux_state.lock().unwrap();
-    mux_state.running = false;
-    mux_state.children.clear();
-}
+    #[test]
+    fn validation_folds_in_every_pre_flight_check() {
+        let mut job = test_job(
+            "/media/show.mkv",
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+            ],
+        );
+        job.video.dolby_vision_profile = Some(7);
+        let mut settings = test_settings();
+        settings.make_audio_default_language = Some("fre".to_string());
+
+        let validations = validate_job_list(std::slice::from_ref(&job), &settings);
+        assert_eq!(validations.len(), 1);
+        assert!(validations[0].errors.is_empty());
+        assert!(validations[0]
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("Dolby Vision profile 7")));
+        assert!(validations[0].warnings.len() >= 2);
+
+        // The output may not overwrite one of the job's inputs
+        job.video.dolby_vision_profile = None;
+        settings.make_audio_default_language = None;
+        settings.overwrite_source = false;
+        settings.destination_dir = "/media".to_string();
+        job.subtitles
+            .push(test_external("/media/show.mkv", "subtitle"));
+        let validations = validate_job_list(std::slice::from_ref(&job), &settings);
+        assert!(validations[0].errors[0].contains("is also an input file"));
+
+        // MP4 in overwrite mode would delete the MKV and leave an .mp4 beside it
+        job.subtitles.clear();
+        settings.overwrite_source = true;
+        settings.output_container = OutputContainer::Mp4;
+        let validations = validate_job_list(&[job], &settings);
+        assert!(validations[0].errors[0].contains("can't replace the source"));
+    }
 
-#[tauri::command]
-fn start_muxing(
-    app: AppHandle,
-    state: State<AppState>,
-    request: MuxStartRequest,
-) -> Result<(), String> {
-    clear_log(&state.paths)?;
-    write_log_line(&state.paths, "Starting muxing session")?;
+    #[test]
+    fn jobs_sharing_an_output_path_are_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = "/out".to_string();
+        let job = |id: &str, path: &str| {
+            let mut job = test_job(path, Vec::new());
+            job.id = id.to_string();
+            job
+        };
+        let jobs = vec![
+            job("job-1", "/media/season1/episode.mkv"),
+            job("job-2", "/media/season2/episode.mkv"),
+            job("job-3", "/media/season2/other.mkv"),
+        ];
+
+        let validations = validate_job_list(&jobs, &settings);
+        let ids: Vec<&str> = validations.iter().map(|v| v.job_id.as_str()).collect();
+        assert_eq!(ids, vec!["job-1", "job-2"]);
+        assert_eq!(
+            validations[0].errors,
+            vec![format!(
+                "Output {} is also written by job job-2",
+                Path::new("/out").join("episode.mkv").to_string_lossy()
+            )]
+        );
+        assert!(validations[1].errors[0].ends_with("also written by job job-1"));
 
-    let mut mux_state = state.mux_state.lock().unwrap();
-    mux_state.queue = request.jobs;
-    mux_state.settings = Some(request.settings);
-    mux_state.stop = false;
-    mux_state.pause = false;
+        let previews = preview_jobs(
+            &state,
+            MuxStartRequest {
+                settings: settings.clone(),
+                jobs: jobs.clone(),
+            },
+        );
+        assert!(previews[1]
+            .warnings
+            .iter()
+            .any(|warning| warning.ends_with("also written by job job-1")));
+        assert!(!previews[2]
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("also written by")));
 
-    if mux_state.running {
-        return Ok(());
+        // Destination next to each source: the outputs no longer collide
+        settings.destination_dir = String::new();
+        assert!(validate_job_list(&jobs, &settings).is_empty());
     }
-    mux_state.running = true;
 
-    let app_handle = app.clone();
-    let state_clone = state.inner().clone();
-    thread::spawn(move || run_mux_queue(app_handle, state_clone));
+    #[test]
+    fn external_audio_delay_scope_picks_file_or_track() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut job = test_job("/media/show.mkv", vec![test_track("0", "video", "und")]);
+        let mut audio = test_external("/media/show.dub.mka", "audio");
+        audio.included_track_ids = Some(vec![1, 2]);
+        audio.track_id = Some(2);
+        audio.delay = Some(0.5);
+        job.audios.push(audio);
+        let syncs = |job: &MuxJobRequest| {
+            let args = build_mkvmerge_command(job, &test_settings(), Path::new("/o.mkv"), &state);
+            args.windows(2)
+                .filter(|pair| pair[0] == "--sync")
+                .map(|pair| pair[1].clone())
+                .collect::<Vec<_>>()
+        };
 
-    Ok(())
-}
+        // Each muxed track is its own input of the file, so `-1` shifts all of them
+        assert_eq!(syncs(&job), vec!["-1:500", "-1:500"]);
 
-#[tauri::command]
-fn preview_mux(
-    state: State<AppState>,
-    request: MuxStartRequest,
-) -> Result<Vec<MuxPreviewResult>, String> {
-    let settings = request.settings;
-    let mut results = Vec::new();
+        job.audios[0].delay_scope = DelayScope::Track;
+        assert_eq!(syncs(&job), vec!["2:500"]);
+        job.audios[0].track_id = None;
+        assert_eq!(syncs(&job), vec!["1:500"]);
 
-    for job in request.jobs {
-        let (output_path, _final_path, _overwrite) = get_output_paths(&job, &settings);
-        let command_args = build_mkvmerge_command(&job, &settings, &output_path, &state);
-        let command_line = join_mkvmerge_command(&command_args);
-        let mut warnings = Vec::new();
+        // A track's own delay wins under either scope
+        job.audios[0].track_overrides =
+            serde_json::from_value(json!({ "2": { "delay": -0.25 } })).unwrap();
+        assert_eq!(syncs(&job), vec!["1:500", "2:-250"]);
+        job.audios[0].delay_scope = DelayScope::File;
+        assert_eq!(syncs(&job), vec!["-1:500", "2:-250"]);
+    }
 
-        if !Path::new(&job.video.path).exists() {
-            warnings.push(format!("Video file missing: {}", job.video.path));
-        }
-        for audio in &job.audios {
-            if !Path::new(&audio.path).exists() {
-                warnings.push(format!("Audio file missing: {}", audio.path));
-            }
-        }
-        for subtitle in &job.subtitles {
-            if !Path::new(&subtitle.path).exists() {
-                warnings.push(format!("Subtitle file missing: {}", subtitle.path));
-            }
-        }
-        for chapter in &job.chapters {
-            if !Path::new(&chapter.path).exists() {
-                warnings.push(format!("Chapter file missing: {}", chapter.path));
-            }
-        }
-        for attachment in &job.attachments {
-            if !Path::new(&attachment.path).exists() {
-                warnings.push(format!("Attachment file missing: {}", attachment.path));
-            }
+    #[test]
+    fn supported_languages_include_the_common_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let languages = supported_languages(&state);
+        assert!(!languages.is_empty());
+        for (code, name) in [("eng", "English"), ("jpn", "Japanese")] {
+            assert!(
+                languages
+                    .iter()
+                    .any(|language| language.code == code && language.name == name),
+                "{code} missing"
+            );
         }
 
-        let plan = MuxPreviewPlan {
-            video: job.video.path.clone(),
-            output: output_path.to_string_lossy().to_string(),
-            audios: job.audios.clone(),
-            subtitles: job.subtitles.clone(),
-            chapters: job.chapters.clone(),
-            attachments: job.attachments.clone(),
-        };
+        let listed = " English language name | ISO 639-3 code | ISO 639-2 code | ISO 639-1 code\n\
+                      ----------------------+----------------+----------------+---------------\n\
+                      English               | eng            | eng            | en\n\
+                      Ghotuo                | aaa            |                |\n\
+                      Japanese              | jpn            | jpn            | ja\n";
+        let parsed = parse_mkvmerge_languages(listed);
+        assert_eq!(
+            parsed.iter().map(|l| l.code.as_str()).collect::<Vec<_>>(),
+            vec!["eng", "jpn"]
+        );
 
-        results.push(MuxPreviewResult {
-            job_id: job.id,
-            command: command_line,
-            warnings,
-            plan,
-        });
+        let state = test_state(dir.path());
+        state.mkvmerge_languages.set(parsed.clone()).unwrap();
+        assert_eq!(supported_languages(&state), parsed);
     }
 
-    Ok(results)
-}
-
-#[tauri::command]
-fn pause_muxing(state: State<AppState>) -> Result<(), String> {
-    let mut mux_state = state.mux_state.lock().unwrap();
-    mux_state.pause = true;
-    Ok(())
-}
-
-#[tauri::command]
-fn resume_muxing(state: State<AppState>) -> Result<(), String> {
-    let mut mux_state = state.mux_state.lock().unwrap();
-    mux_state.pause = false;
-    Ok(())
-}
+    #[test]
+    fn crc_is_skipped_for_outputs_over_the_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = test_settings();
+        settings.add_crc = true;
+        settings.crc_max_size_bytes = Some(1024);
+        let large = dir.path().join("large.mkv");
+        let small = dir.path().join("small.mkv");
+        fs::write(&large, vec![7u8; 4096]).unwrap();
+        fs::write(&small, vec![7u8; 512]).unwrap();
+
+        let large_output = apply_crc_naming(&large, &settings, &mut |_| {}, &|| false);
+        assert_eq!(large_output, large);
+        assert!(crc_size_limit_exceeded(&large, &settings));
+
+        let small_output = apply_crc_naming(&small, &settings, &mut |_| {}, &|| false);
+        assert!(
+            crc_from_file_name(&small_output).is_some(),
+            "{small_output:?}"
+        );
+        assert!(!crc_size_limit_exceeded(&small_output, &settings));
 
-#[tauri::command]
-fn stop_muxing(state: State<AppState>) -> Result<(), String> {
-    let mut mux_state = state.mux_state.lock().unwrap();
-    mux_state.stop = true;
-    for (_, handle) in mux_state.children.drain() {
-        if let Ok(mut child) = handle.lock() {
-            let _ = child.kill();
-        }
+        settings.crc_max_size_bytes = None;
+        assert!(!crc_size_limit_exceeded(&large, &settings));
     }
-    Ok(())
-}
 
-#[tauri::command]
-fn open_log_file(app: AppHandle, state: State<AppState>) -> Result<(), String> {
-    if !state.paths.log_path.exists() {
-        File::create(&state.paths.log_path)
-            .map_err(|e| format!("Failed to create log file: {e}"))?;
+    #[test]
+    fn track_sizes_follow_bitrate_and_duration() {
+        let mut video_track = test_track("0", "video", "und");
+        video_track.bitrate = Some(6_000_000);
+        let mut audio_track = test_track("1", "audio", "jpn");
+        audio_track.bitrate = Some(1_600_000);
+        let subtitle_track = test_track("2", "subtitle", "eng");
+        let mut video = test_job(
+            "/media/show.mkv",
+            vec![video_track, audio_track, subtitle_track],
+        )
+        .video;
+        video.size = 1_000_000_000;
+        video.duration_seconds = Some(1000.0);
+
+        let breakdown = track_size_breakdown(&video);
+        let estimates: Vec<Option<u64>> = breakdown
+            .tracks
+            .iter()
+            .map(|track| track.estimated_bytes)
+            .collect();
+        assert_eq!(estimates, vec![Some(750_000_000), Some(200_000_000), None]);
+        assert_eq!(breakdown.tracks[0].fraction, Some(0.75));
+        assert_eq!(breakdown.tracks[1].fraction, Some(0.2));
+        assert_eq!(breakdown.remainder_bytes, 50_000_000);
+
+        video.duration_seconds = Some(2000.0);
+        let overshoot = track_size_breakdown(&video);
+        assert_eq!(overshoot.remainder_bytes, 0);
     }
-    let path_string = state.paths.log_path.to_string_lossy().to_string();
-    if tauri::api::shell::open(&app.shell_scope(), path_string.clone(), None).is_ok() {
-        return Ok(());
+
+    #[test]
+    fn resume_runs_only_jobs_without_finished_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.overwrite_source = false;
+        settings.destination_dir = dir.path().to_string_lossy().to_string();
+        let jobs: Vec<MuxJobRequest> = ["ep01", "ep02", "ep03"]
+            .iter()
+            .map(|name| {
+                let mut job = test_job(&format!("/media/{name}.mkv"), Vec::new());
+                job.id = name.to_string();
+                job.video.name = format!("{name}.mkv");
+                job
+            })
+            .collect();
+        let outputs: Vec<PathBuf> = jobs
+            .iter()
+            .map(|job| get_output_paths(job, &settings).1)
+            .collect();
+        fs::write(&outputs[0], b"muxed").unwrap();
+        record_finished_outputs(&state, &outputs[..1]);
+        // A crash mid-mux leaves a partial file that was never recorded as finished
+        fs::write(&outputs[2], b"partial").unwrap();
+
+        let request = MuxStartRequest {
+            settings: settings.clone(),
+            jobs: jobs.clone(),
+        };
+        let recorded = read_finished_outputs(&state.paths.finished_outputs_path);
+        let (remaining, finished) = split_resumable(request, &recorded);
+        assert_eq!(finished, vec![("ep01".to_string(), outputs[0].clone())]);
+        let run: Vec<&str> = remaining.jobs.iter().map(|job| job.id.as_str()).collect();
+        assert_eq!(run, vec!["ep02", "ep03"]);
+
+        // With CRC naming the tag is unknown, so a tagged name still counts
+        settings.add_crc = true;
+        let tagged = file_name_with_crc(&outputs[1], "0A1B2C3D");
+        fs::write(&tagged, b"muxed").unwrap();
+        record_finished_outputs(&state, std::slice::from_ref(&tagged));
+        let recorded = read_finished_outputs(&state.paths.finished_outputs_path);
+        let request = MuxStartRequest {
+            settings: settings.clone(),
+            jobs: jobs.clone(),
+        };
+        let (remaining, finished) = split_resumable(request, &recorded);
+        let skipped: Vec<&str> = finished.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(skipped, vec!["ep01", "ep02"]);
+        assert_eq!(finished[1].1, tagged);
+        assert_eq!(remaining.jobs.len(), 1);
+
+        // A finished output rewritten since (e.g. by a later crashed run) no longer counts
+        fs::write(&outputs[0], b"muxed again, partly").unwrap();
+        let (_, finished) = split_resumable(MuxStartRequest { settings, jobs }, &recorded);
+        let skipped: Vec<&str> = finished.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(skipped, vec!["ep02"]);
+
+        // Recording prunes outputs deleted since
+        let removed = comparable_path(&tagged).to_string_lossy().to_string();
+        fs::remove_file(&tagged).unwrap();
+        record_finished_outputs(&state, &outputs[..1]);
+        let pruned = read_finished_outputs(&state.paths.finished_outputs_path);
+        assert!(!pruned.contains_key(&removed));
+        assert_eq!(pruned.len(), 1);
     }
 
-    #[cfg(target_os = "macos")]
-    {
-        let status = Command::new("open")
-            .arg(&path_string)
-            .status()
-            .map_err(|e| format!("Failed to open log file: {e}"))?;
-        if status.success() {
-            return Ok(());
+    #[test]
+    fn temp_outputs_use_the_configured_separator() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("show.mkv");
+        fs::write(&source, b"source").unwrap();
+        let job = test_job(&source.to_string_lossy(), Vec::new());
+        let mut settings = test_settings();
+        settings.temp_separator = ".tmp".to_string();
+
+        let (output_path, final_path, overwrite_mode) = get_output_paths(&job, &settings);
+        assert!(overwrite_mode);
+        let temp_name = output_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert!(temp_name.starts_with("show.tmp") && !temp_name.contains('#'));
+        assert_eq!(
+            temp_output_final_path(&output_path, ".tmp"),
+            Some(final_path.clone())
+        );
+
+        // Leftovers of an earlier run are matched by the same separator
+        let orphan = dir.path().join("show.tmp1700000000.mkv");
+        let other_separator = dir.path().join("show#1700000000.mkv");
+        let other_video = dir.path().join("other.tmp1700000000.mkv");
+        let numbered = dir.path().join("show.tmp001.mkv");
+        for path in [&orphan, &other_separator, &other_video, &numbered] {
+            fs::write(path, b"partial").unwrap();
         }
+        assert_eq!(
+            orphan_temp_outputs(&final_path, &settings),
+            vec![orphan.clone()]
+        );
+
+        let state = test_state(dir.path());
+        let preview = preview_job_result(&state, job, &settings);
+        assert!(preview
+            .warnings
+            .iter()
+            .any(|warning| warning.starts_with("Leftover temp output")));
+        assert!(orphan.exists() && numbered.exists());
+
+        settings.temp_separator = "".to_string();
+        assert_eq!(settings.temp_separator(), "#");
+        assert_eq!(
+            orphan_temp_outputs(&final_path, &settings),
+            vec![other_separator]
+        );
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        let status = Command::new("cmd")
-            .args(["/C", "start", "", &path_string])
-            .status()
-            .map_err(|e| format!("Failed to open log file: {e}"))?;
-        if status.success() {
-            return Ok(());
-        }
+    #[test]
+    fn replacing_one_embedded_font_keeps_the_other() {
+        let info = json!({
+            "attachments": [
+                { "id": 1, "file_name": "Main.ttf", "size": 2048, "content_type": "font/ttf" },
+                { "id": 2, "file_name": "Sign.otf", "size": 1024, "content_type": "font/otf" }
+            ]
+        });
+        let embedded = embedded_attachments(&info);
+        assert_eq!(
+            embedded,
+            vec![
+                EmbeddedAttachment {
+                    id: 1,
+                    name: "Main.ttf".to_string()
+                },
+                EmbeddedAttachment {
+                    id: 2,
+                    name: "Sign.otf".to_string()
+                }
+            ]
+        );
+
+        // Main.ttf is replaced, Sign.otf stays
+        let new_fonts = vec![("/fonts/main.TTF".to_string(), None)];
+        assert_eq!(
+            old_attachment_args(Some(&embedded), &new_fonts, false),
+            vec!["--attachments", "2"]
+        );
+        assert!(old_attachment_args(Some(&embedded), &[], false).is_empty());
+        let both = vec![
+            ("/fonts/Main.ttf".to_string(), None),
+            ("/fonts/Sign.otf".to_string(), None),
+        ];
+        assert_eq!(
+            old_attachment_args(Some(&embedded), &both, false),
+            vec!["--no-attachments"]
+        );
+        assert_eq!(
+            old_attachment_args(None, &new_fonts, false),
+            vec!["--no-attachments"]
+        );
+        // The poster takes the place of every embedded cover, whatever its extension
+        let mut with_cover = embedded.clone();
+        with_cover.push(EmbeddedAttachment {
+            id: 3,
+            name: "cover.jpg".to_string(),
+        });
+        with_cover.push(EmbeddedAttachment {
+            id: 4,
+            name: "Cover.PNG".to_string(),
+        });
+        assert_eq!(
+            old_attachment_args(Some(&with_cover), &new_fonts, true),
+            vec!["--attachments", "2"]
+        );
+        assert_eq!(
+            old_attachment_args(Some(&with_cover), &new_fonts, false),
+            vec!["--attachments", "2,3,4"]
+        );
+
+        // The builder uses the listing probed before muxing
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        settings.discard_old_attachments = true;
+        settings.discard_replaced_attachments_only = true;
+        let mut job = test_job("/media/show.mkv", Vec::new());
+        job.attachments
+            .push(test_external("/fonts/main.TTF", "attachment"));
+        job.probed.embedded_attachments = Some(embedded);
+        let args = build_mkvmerge_command(&job, &settings, Path::new("/out/show.mkv"), &state);
+        assert!(args
+            .windows(2)
+            .any(|pair| pair[0] == "--attachments" && pair[1] == "2"));
     }
 
-    #[cfg(target_os = "linux")]
-    {
-        let status = Command::new("xdg-open")
-            .arg(&path_string)
-            .status()
-            .map_err(|e| format!("Failed to open log file: {e}"))?;
-        if status.success() {
-            return Ok(());
-        }
+    #[test]
+    fn log_lines_start_with_a_utc_timestamp() {
+        assert_eq!(
+            utc_timestamp(Duration::from_millis(951_825_723_045)),
+            "2000-02-29T12:02:03.045Z"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let mut settings = test_settings();
+        state.paths.use_settings_for_log(&settings);
+        write_log_line(&state.paths, "Job job-1 started").unwrap();
+        settings.timestamped_logs = false;
+        state.paths.use_settings_for_log(&settings);
+        write_log_line(&state.paths, "Job job-1 finished").unwrap();
+
+        let log = fs::read_to_string(&state.paths.log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        let (stamp, line) = lines[0].split_once(' ').unwrap();
+        assert_eq!(line, "Job job-1 started");
+        let (date, time) = stamp.split_once('T').unwrap();
+        let date: Vec<u32> = date.split('-').map(|part| part.parse().unwrap()).collect();
+        assert!(date[0] >= 2024 && (1..=12).contains(&date[1]) && (1..=31).contains(&date[2]));
+        let time: Vec<f64> = time
+            .strip_suffix('Z')
+            .unwrap()
+            .split(':')
+            .map(|part| part.parse().unwrap())
+            .collect();
+        assert!(time[0] < 24.0 && time[1] < 60.0 && time[2] < 60.0);
+        assert_eq!(lines[1], "Job job-1 finished");
     }
 
-    Err("Failed to open log file".to_string())
-}
+    #[test]
+    fn batch_analysis_has_every_section_for_a_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let source = dir.path().join("show.mkv");
+        fs::write(&source, b"source").unwrap();
+        let mut job = test_job(
+            &source.to_string_lossy(),
+            vec![
+                test_track("0", "video", "und"),
+                test_track("1", "audio", "jpn"),
+            ],
+        );
+        job.video.size = 6;
+        let mut settings = test_settings();
+        settings.use_mkvpropedit = true;
+        settings.output_container = OutputContainer::Mp4;
+
+        let analysis = analyze_jobs(
+            &state,
+            MuxStartRequest {
+                settings,
+                jobs: vec![job],
+            },
+            &UsageStats::default(),
+        );
+        let job = &analysis.jobs[0];
+        assert!(job.preview.command.starts_with("ffmpeg"));
+        assert_eq!(job.track_order.len(), 2);
+        assert_eq!(job.preview.required_bytes, 6);
+        assert!(job.preview.space_ok);
+        assert!(!job.fast_mux);
+        assert_eq!(
+            job.fast_mux_blocker.as_deref(),
+            Some("MP4 outputs are written by ffmpeg")
+        );
+        assert_eq!(analysis.estimate.fast_mux_jobs, 0);
+
+        let value = serde_json::to_value(&analysis).unwrap();
+        for key in [
+            "jobId",
+            "command",
+            "warnings",
+            "plan",
+            "trackActions",
+            "spaceOk",
+            "requiredBytes",
+            "trackOrder",
+            "errors",
+            "fastMux",
+            "fastMuxBlocker",
+        ] {
+            assert!(value["jobs"][0].get(key).is_some(), "missing {key}");
+        }
+        assert!(value["estimate"].get("seconds").is_some());
+    }
 
-fn main() {
-    tauri::Builder::default()
-        .setup(|app| {
-            let app_data_dir = tauri::api::path::app_data_dir(&app.config())
-                .ok_or("Failed to resolve app data directory")?;
-            ensure_dir(&app_data_dir)?;
-            let paths = AppPaths {
-                app_data_dir: app_data_dir.clone(),
-                options_path: app_data_dir.join("setting.json"),
-                log_path: app_data_dir.join("muxing_log_file.txt"),
-            };
-            let state = AppState {
-                paths,
-                mux_state: Arc::new(Mutex::new(MuxState::default())),
-            };
-            app.manage(state);
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![
-            get_app_paths,
-            load_options,
-            save_options,
-            scan_media,
-            inspect_paths,
-            inspect_paths_stream,
-            start_muxing,
-            preview_mux,
-            pause_muxing,
-            resume_muxing,
-            stop_muxing,
-            open_log_file,
-            session::save_session,
-            session::load_session,
-            session::clear_session,
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    #[test]
+    fn probe_failure_keeps_stderr_reason() {
+        let error = parse_probe_output(
+            "mediainfo",
+            false,
+            b"Segmentation fault (core dumped",
+            b"warning: odd header\nerror: unable to read file\n",
+        )
+        .unwrap_err();
+        assert_eq!(error, "mediainfo: error: unable to read file");
+
+        let error = parse_probe_output("mkvmerge", true, b"not json", b"").unwrap_err();
+        assert_eq!(error, "mkvmerge: returned non-JSON output");
+
+        let (mkvmerge, mediainfo, probe_error) =
+            split_probe_results(Err(error), Ok(Some(json!({ "media": {} }))));
+        assert!(mkvmerge.is_none());
+        assert!(mediainfo.is_some());
+        assert_eq!(
+            probe_error.as_deref(),
+            Some("mkvmerge: returned non-JSON output")
+        );
+    }
 }
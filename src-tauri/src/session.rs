@@ -2,7 +2,7 @@
 /// Handles atomic save/load/clear of session state to prevent data loss.
 use serde_json::Value;
 use std::fs;
-use std::io::Write;
+use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::AppHandle;
@@ -35,9 +35,6 @@ pub fn save_session_data(app: &AppHandle, mut state: Value) -> Result<(), String
         obj.insert("timestamp".to_string(), Value::Number(timestamp.into()));
     }
 
-    let json = serde_json::to_string(&state)
-        .map_err(|e| format!("Failed to serialize session: {e}"))?;
-
     let temp_path = session_temp_path(app);
     let final_path = session_path(app);
 
@@ -47,13 +44,7 @@ pub fn save_session_data(app: &AppHandle, mut state: Value) -> Result<(), String
     }
 
     // Write to temp file
-    let mut file = fs::File::create(&temp_path)
-        .map_err(|e| format!("Failed to create temp session file: {e}"))?;
-    file.write_all(json.as_bytes())
-        .map_err(|e| format!("Failed to write session data: {e}"))?;
-    file.flush()
-        .map_err(|e| format!("Failed to flush session file: {e}"))?;
-    drop(file);
+    write_session_file(&temp_path, &state)?;
 
     // Verify temp file is valid JSON
     verify_session_file(&temp_path)?;
@@ -72,11 +63,9 @@ pub fn load_session_data(app: &AppHandle) -> Result<Option<Value>, String> {
         return Ok(None);
     }
 
-    let content = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read session file: {e}"))?;
-
-    match serde_json::from_str::<Value>(&content) {
+    match read_session_file(&path) {
         Ok(value) => Ok(Some(value)),
+        Err(e) if e.is_io() => Err(format!("Failed to read session file: {e}")),
         Err(e) => {
             // Corrupt session — delete and return None
             let _ = fs::remove_file(&path);
@@ -99,12 +88,35 @@ pub fn clear_session_data(app: &AppHandle) -> Result<(), String> {
     Ok(())
 }
 
+/// Streams the session to disk, so a queue of thousands of jobs is never held as one
+/// big string.
+fn write_session_file(path: &Path, state: &Value) -> Result<(), String> {
+    let file =
+        fs::File::create(path).map_err(|e| format!("Failed to create temp session file: {e}"))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, state)
+        .map_err(|e| format!("Failed to write session data: {e}"))?;
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush session file: {e}"))
+}
+
+/// Parses the session straight from the file. Open and read failures come back as
+/// I/O errors (`is_io`), anything else means the content is corrupt.
+fn read_session_file(path: &Path) -> serde_json::Result<Value> {
+    let file = fs::File::open(path).map_err(serde_json::Error::io)?;
+    serde_json::from_reader(BufReader::new(file))
+}
+
 /// Verify that a file contains valid JSON with a version field.
 fn verify_session_file(path: &Path) -> Result<(), String> {
-    let content = fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read temp session for verification: {e}"))?;
-    let value: Value = serde_json::from_str(&content)
-        .map_err(|e| format!("Temp session file is not valid JSON: {e}"))?;
+    let value = read_session_file(path).map_err(|e| {
+        if e.is_io() {
+            format!("Failed to read temp session for verification: {e}")
+        } else {
+            format!("Temp session file is not valid JSON: {e}")
+        }
+    })?;
     if value.get("version").is_none() {
         return Err("Session file missing version field".to_string());
     }
@@ -127,3 +139,39 @@ pub fn load_session(app: AppHandle) -> Result<Option<Value>, String> {
 pub fn clear_session(app: AppHandle) -> Result<(), String> {
     clear_session_data(&app)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn large_queue_round_trips_through_streaming_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_FILE_NAME);
+        let jobs: Vec<Value> = (0..5_000)
+            .map(|index| {
+                json!({
+                    "id": format!("job-{index}"),
+                    "videoFile": {
+                        "path": format!("/media/show/episode {index:04}.mkv"),
+                        "tracks": []
+                    },
+                    "status": "queued",
+                    "progress": 0
+                })
+            })
+            .collect();
+        let state = json!({ "version": SESSION_VERSION, "jobs": jobs });
+
+        write_session_file(&path, &state).unwrap();
+        verify_session_file(&path).unwrap();
+        assert_eq!(read_session_file(&path).unwrap(), state);
+
+        fs::write(&path, "{\"jobs\": [").unwrap();
+        assert!(!read_session_file(&path).unwrap_err().is_io());
+        assert!(read_session_file(&dir.path().join("missing.json"))
+            .unwrap_err()
+            .is_io());
+    }
+}
@@ -222,6 +222,13 @@ const WorkspacePage = () => {
       .then((data) => {
         if (!mounted) return;
         setOptions(data);
+        if (data.Load_Error) {
+          toast({
+            title: "Options could not be loaded",
+            description: `${data.Load_Error}. Using default options for this session.`,
+            variant: "destructive",
+          });
+        }
         const preset = data.Presets[data.FavoritePresetId] || data.Presets[0];
         setActivePreset(preset);
         setIsDarkMode(Boolean(data.Dark_Mode));
@@ -232,6 +239,7 @@ const WorkspacePage = () => {
         }));
         setMuxSettings((prev) => ({
           ...prev,
+          ...(preset.Default_Mux_Settings ?? {}),
           destinationDir: preset.Default_Destination_Directory || "",
         }));
       })
@@ -336,7 +344,7 @@ const WorkspacePage = () => {
               Math.round((elapsed * (100 - payload.progress)) / payload.progress),
             );
           }
-          if (status === "completed") {
+          if (status === "completed" || status === "finalizing") {
             etaSeconds = 0;
           }
           return {
@@ -367,7 +375,8 @@ const WorkspacePage = () => {
       const isTerminal =
         payload.status === "completed" ||
         payload.status === "error" ||
-        payload.status === "stopped";
+        payload.status === "stopped" ||
+        payload.status === "skipped";
 
       if (isTerminal) {
         bufferedProgress.delete(payload.job_id);
@@ -570,8 +579,10 @@ const WorkspacePage = () => {
       chapterFiles,
       attachmentFiles,
       perVideoExternal,
+      presetName: activePreset?.Preset_Name,
     });
   }, [
+    activePreset,
     attachmentFiles,
     audioFilesByTrack,
     chapterFiles,
@@ -723,6 +734,7 @@ const WorkspacePage = () => {
       }
 
       const rules: string[] = [];
+      if (job.preset_name) rules.push(`Built from preset: ${job.preset_name}`);
       if (muxSettings.discardOldChapters) rules.push("Remove existing chapters from source");
       if (muxSettings.discardOldAttachments) rules.push("Remove existing attachments from source");
       if (muxSettings.removeGlobalTags) rules.push("Remove global tags from source");
@@ -884,7 +896,7 @@ const WorkspacePage = () => {
     stopMuxing();
     setJobs((prev) =>
       prev.map((job) =>
-        job.status === "processing" || job.status === "queued"
+        job.status === "processing" || job.status === "finalizing" || job.status === "queued"
           ? { ...job, status: "stopped", errorMessage: "Stopped by user." }
           : job,
       ),
@@ -920,6 +932,7 @@ const WorkspacePage = () => {
     }));
     setMuxSettings((prev) => ({
       ...prev,
+      ...(preset.Default_Mux_Settings ?? {}),
       destinationDir: preset.Default_Destination_Directory || prev.destinationDir,
     }));
   }, []);
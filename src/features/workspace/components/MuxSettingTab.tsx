@@ -103,10 +103,11 @@ export function MuxSettingTab({
   const removeGlobalTags = muxSettings.removeGlobalTags;
   const fileCount = jobs.length > 0 ? jobs.length : videoFiles.length;
   const autoParallelJobs = Math.min(fileCount, 12);
-  const isProcessing = jobs.some((job) => job.status === 'processing');
+  const isProcessing = jobs.some((job) => job.status === 'processing' || job.status === 'finalizing');
   const hasJobs = jobs.length > 0;
   const completedJobs = jobs.filter((job) => job.status === 'completed').length;
   const stoppedJobs = jobs.filter((job) => job.status === 'stopped').length;
+  const skippedJobs = jobs.filter((job) => job.status === 'skipped').length;
   const statusLabel = isProcessing ? "Running" : stoppedJobs > 0 ? "Stopped" : "Idle";
   const warningCount = useMemo(
     () => Object.values(previewResults).reduce((acc, result) => acc + result.warnings.length, 0),
@@ -116,7 +117,7 @@ export function MuxSettingTab({
   const overallProgress = useMemo(() => {
     if (!hasJobs) return 0;
     const sum = jobs.reduce((acc, job) => {
-      if (job.status === 'completed') return acc + 100;
+      if (job.status === 'completed' || job.status === 'skipped' || job.status === 'finalizing') return acc + 100;
       if (job.status === 'processing') return acc + job.progress;
       return acc;
     }, 0);
@@ -634,7 +635,8 @@ export function MuxSettingTab({
                       job.status === 'completed' && "bg-success/20 text-success",
                       job.status === 'error' && "bg-destructive/20 text-destructive",
                       job.status === 'stopped' && "bg-warning/20 text-warning",
-                      job.status === 'processing' && "bg-primary/20 text-primary",
+                      job.status === 'skipped' && "bg-muted text-muted-foreground",
+                      (job.status === 'processing' || job.status === 'finalizing') && "bg-primary/20 text-primary",
                       job.status === 'queued' && "bg-muted text-muted-foreground"
                     )}>
                       {job.status === 'queued' ? 'Queued' : job.status === 'stopped' ? 'Stopped' : job.status}
@@ -652,6 +654,14 @@ export function MuxSettingTab({
                           <span>ETA {formatEta(job.etaSeconds)}</span>
                         </div>
                       </div>
+                    ) : job.status === 'finalizing' ? (
+                      <div className="flex flex-col gap-1 w-full">
+                        <Progress value={job.progress} className="h-1.5" />
+                        <div className="flex items-center justify-between text-[10px] text-muted-foreground font-mono">
+                          <span>{job.progress}%</span>
+                          <span>Finalizing</span>
+                        </div>
+                      </div>
                     ) : job.status === 'completed' ? (
                       <div className="flex flex-col gap-1 w-full">
                         <Progress value={100} className="h-1.5" />
@@ -662,6 +672,8 @@ export function MuxSettingTab({
                       </div>
                     ) : job.status === 'stopped' ? (
                       <div className="w-full text-[10px] text-warning text-center font-medium">Stopped</div>
+                    ) : job.status === 'skipped' ? (
+                      <div className="w-full text-[10px] text-muted-foreground text-center font-medium">Skipped</div>
                     ) : null}
                   </div>
                   <div className="px-3 text-xs text-center text-muted-foreground font-mono flex items-center justify-center">
@@ -687,7 +699,7 @@ export function MuxSettingTab({
           View Log
         </Button>
         <div className="text-xs text-muted-foreground">
-          {statusLabel} • {jobs.length} queued • {completedJobs} completed
+          {statusLabel} • {jobs.length} queued • {completedJobs} completed{skippedJobs > 0 ? ` • ${skippedJobs} skipped` : ""}
         </div>
       </div>
 
@@ -14,6 +14,7 @@ interface BuildMuxJobRequestsInput {
   chapterFiles: ExternalFile[];
   attachmentFiles: ExternalFile[];
   perVideoExternal: Record<string, PerVideoExternalFiles>;
+  presetName?: string;
 }
 
 const normalizeName = (value: string) =>
@@ -111,6 +112,7 @@ export function buildMuxJobRequests({
   chapterFiles,
   attachmentFiles,
   perVideoExternal,
+  presetName,
 }: BuildMuxJobRequestsInput) {
   const { byId, resolve } = buildStrictVideoMatcher(videoFiles);
 
@@ -171,6 +173,7 @@ export function buildMuxJobRequests({
         ),
         chapters: chapterMap.get(video.id) || [],
         attachments: attachmentMap.get(video.id) || [],
+        preset_name: presetName,
       },
     ];
   });
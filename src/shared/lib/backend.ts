@@ -7,14 +7,49 @@ export interface AppPaths {
   app_data_dir: string;
   options_path: string;
   log_path: string;
+  stats_path: string;
 }
 
+export interface UsageStats {
+  jobsCompleted: number;
+  bytesProcessed: number;
+  bytesSaved: number;
+  remuxedBytes: number;
+  remuxMs: number;
+}
+
+export interface BatchTimeEstimate {
+  seconds: number;
+  bytesPerSecond: number;
+  fromHistory: boolean;
+  fastMuxJobs: number;
+}
+
+export type DurationSource = "auto" | "mkvmerge" | "mediainfo";
+export type MetadataLevel = "nameSizeOnly" | "basic" | "full";
+
 export interface ScanRequest {
   folder: string;
   extensions: string[];
   recursive: boolean;
   type: "video" | "audio" | "subtitle" | "chapter" | "attachment";
   include_tracks: boolean;
+  modified_since?: number;
+  duration_source?: DurationSource;
+  metadata_level?: MetadataLevel;
+  skip_trackless?: boolean;
+  verify_magic?: boolean;
+  max_concurrent_probes?: number;
+  require_languages?: string[];
+  require_language_types?: ("video" | "audio" | "subtitle")[];
+}
+
+export interface ScanSummary {
+  items: (VideoFile | ExternalFile)[];
+  matched: number;
+  skippedOlder: number;
+  skippedTrackless: number;
+  skippedMissingLanguage: number;
 }
 
 export interface MuxJobRequest {
@@ -24,6 +59,18 @@ export interface MuxJobRequest {
   subtitles: ExternalFile[];
   chapters: ExternalFile[];
   attachments: ExternalFile[];
+  output_name?: string;
+  preset_name?: string;
+  destination_dir?: string;
+  append_sources?: string[];
+  abort_on_errors?: boolean | null;
+  poster_image?: string | null;
+  keep_track_name_patterns?: string[] | null;
+}
+
+export interface FavoriteDefaults {
+  audioLanguages: string[];
+  subtitleLanguages: string[];
 }
 
 export interface MuxStartRequest {
@@ -38,6 +85,8 @@ export interface MuxPreviewPlan {
   subtitles: ExternalFile[];
   chapters: ExternalFile[];
   attachments: ExternalFile[];
+  willOverwriteSource: boolean;
+  tempPath?: string | null;
 }
 
 export interface MuxPreviewResult {
@@ -45,12 +94,69 @@ export interface MuxPreviewResult {
   command: string;
   warnings: string[];
   plan: MuxPreviewPlan;
+  trackActions: TrackActionSummary[];
+  spaceOk: boolean;
+  requiredBytes: number;
+  availableBytes?: number | null;
+}
+
+export interface TrackActionSummary {
+  type: "video" | "audio" | "subtitle";
+  kept: number;
+  removed: number;
+  disabled: number;
+}
+
+export interface JobValidation {
+  jobId: string;
+  errors: string[];
+  warnings: string[];
+}
+
+export interface OutputAttachment {
+  name: string;
+  size: number;
+}
+
+export interface OutputDescription {
+  tracks: VideoFile["tracks"];
+  attachments: OutputAttachment[];
+  chapterCount: number;
+}
+
+export interface TrackOrderEntry {
+  sourceFileIndex: number;
+  trackId: number;
+  type: "video" | "audio" | "subtitle";
+  language?: string | null;
+  name?: string | null;
+}
+
+export interface RenameItem {
+  path: string;
+  newName: string;
+}
+
+export interface RenameResult {
+  path: string;
+  newPath?: string | null;
+  error?: string | null;
+}
+
+export interface DependencyStatus {
+  tool: string;
+  found: boolean;
+  version?: string | null;
+  path?: string | null;
+  installHint?: string | null;
 }
 
 export interface InspectRequest {
   paths: string[];
   type: "video" | "audio" | "subtitle" | "chapter" | "attachment";
   include_tracks: boolean;
+  duration_source?: DurationSource;
+  metadata_level?: MetadataLevel;
 }
 
 export interface InspectStreamRequest {
@@ -59,6 +165,34 @@ export interface InspectStreamRequest {
   type: "video" | "audio" | "subtitle" | "chapter" | "attachment";
   include_tracks: boolean;
   batch_size?: number;
+  duration_source?: DurationSource;
+  metadata_level?: MetadataLevel;
+}
+
+export interface SelfTestStep {
+  name: string;
+  passed: boolean;
+  elapsedMs: number;
+  detail?: string | null;
+}
+
+export interface SelfTestReport {
+  passed: boolean;
+  elapsedMs: number;
+  steps: SelfTestStep[];
+}
+
+export interface MuxPlanEvent {
+  job_id: string;
+  args: string[];
+  output_path: string;
+  track_order: TrackOrderEntry[];
+}
+
+export interface MuxLowSpaceEvent {
+  available_bytes: number;
+  threshold_bytes: number;
+  paused: boolean;
 }
 
 export interface InspectStreamChunkEvent {
@@ -78,13 +212,83 @@ export interface InspectStreamErrorEvent {
   message: string;
 }
 
+export interface LastBatchSettings {
+  settings: MuxSettings;
+  jobCount: number;
+}
+
+export interface XmlIssue {
+  line: number;
+  column: number;
+  message: string;
+}
+
+export interface XmlValidation {
+  errors: XmlIssue[];
+  formatted?: string | null;
+}
+
+export interface CommandDiff {
+  added: string[];
+  removed: string[];
+  reordered: string[];
+}
+
+export interface LanguageEntry {
+  code: string;
+  name: string;
+}
+
+export interface TrackSizeEstimate {
+  trackId: string;
+  type: string;
+  language?: string | null;
+  bitrate?: number | null;
+  estimatedBytes?: number | null;
+  fraction?: number | null;
+}
+
+export interface TrackSizeBreakdown {
+  fileSize: number;
+  durationSeconds?: number | null;
+  tracks: TrackSizeEstimate[];
+  remainderBytes: number;
+}
+
+export interface ResumeSummary {
+  skipped: string[];
+  run: string[];
+}
+
+export interface DestinationCheck {
+  writable: boolean;
+  freeBytes?: number | null;
+  error?: string | null;
+}
+
+export interface JobAnalysis extends MuxPreviewResult {
+  trackOrder: TrackOrderEntry[];
+  errors: string[];
+  fastMux: boolean;
+  fastMuxBlocker?: string | null;
+}
+
+export interface BatchAnalysis {
+  jobs: JobAnalysis[];
+  estimate: BatchTimeEstimate;
+}
+
+export type JobPhase = "processing" | "finalizing" | "completed" | "error" | "stopped" | "skipped";
+
 export interface MuxProgressEvent {
   job_id: string;
-  status: "queued" | "processing" | "completed" | "error";
+  status: JobPhase;
   progress: number;
   message?: string;
   size_after?: number;
   error_message?: string;
+  error_detail?: string;
+  elapsed_ms?: number;
 }
 
 export async function getAppPaths() {
@@ -95,6 +299,14 @@ export async function loadOptions() {
   return invoke<OptionsData>("load_options");
 }
 
+export async function relocatePresetPaths(presetId: number, oldRoot: string, newRoot: string) {
+  return invoke<string[]>("relocate_preset_paths", { presetId, oldRoot, newRoot });
+}
+
+export async function readOptionsOnly() {
+  return invoke<OptionsData>("read_options_only");
+}
+
 export async function saveOptions(options: OptionsData) {
   return invoke<void>("save_options", { options });
 }
@@ -103,6 +315,10 @@ export async function scanMedia(request: ScanRequest) {
   return invoke<(VideoFile | ExternalFile)[]>("scan_media", { request });
 }
 
+export async function scanMediaWithSummary(request: ScanRequest) {
+  return invoke<ScanSummary>("scan_media_with_summary", { request });
+}
+
 export async function inspectPaths(request: InspectRequest) {
   return invoke<(VideoFile | ExternalFile)[]>("inspect_paths", { request });
 }
@@ -115,10 +331,38 @@ export async function startMuxing(request: MuxStartRequest) {
   return invoke<void>("start_muxing", { request });
 }
 
+export async function resumeBatch(request: MuxStartRequest) {
+  return invoke<ResumeSummary>("resume_batch", { request });
+}
+
+export async function checkDestinationWritable(dir: string) {
+  return invoke<DestinationCheck>("check_destination_writable", { dir });
+}
+
 export async function previewMux(request: MuxStartRequest) {
   return invoke<MuxPreviewResult[]>("preview_mux", { request });
 }
 
+export async function previewJob(job: MuxJobRequest, settings: MuxSettings) {
+  return invoke<MuxPreviewResult>("preview_job", { job, settings });
+}
+
+export async function analyzeBatch(request: MuxStartRequest) {
+  return invoke<BatchAnalysis>("analyze_batch", { request });
+}
+
+export async function validateJobs(request: MuxStartRequest) {
+  return invoke<JobValidation[]>("validate_jobs", { request });
+}
+
+export async function describeOutput(path: string) {
+  return invoke<OutputDescription>("describe_output", { path });
+}
+
+export async function resolveTrackOrder(job: MuxJobRequest, settings: MuxSettings) {
+  return invoke<TrackOrderEntry[]>("resolve_track_order", { job, settings });
+}
+
 export async function pauseMuxing() {
   return invoke<void>("pause_muxing");
 }
@@ -131,6 +375,118 @@ export async function stopMuxing() {
   return invoke<void>("stop_muxing");
 }
 
+export async function stopKeepOutputs() {
+  return invoke<void>("stop_keep_outputs");
+}
+
+export async function resetMuxState() {
+  return invoke<void>("reset_mux_state");
+}
+
+export async function runSingleJob(job: MuxJobRequest, settings: MuxSettings) {
+  return invoke<string>("run_single_job", { job, settings });
+}
+
+export async function buildJobsFromPreset(presetId: number) {
+  return invoke<MuxStartRequest>("build_jobs_from_preset", { presetId });
+}
+
+export async function buildDefaultsFromFavorites(presetId: number) {
+  return invoke<FavoriteDefaults>("build_defaults_from_favorites", { presetId });
+}
+
+export async function groupSplitParts(files: VideoFile[]) {
+  return invoke<{ jobs: MuxJobRequest[]; ungrouped: VideoFile[] }>("group_split_parts", { files });
+}
+
+export async function findOrphanExternals(videos: VideoFile[], externals: ExternalFile[]) {
+  return invoke<ExternalFile[]>("find_orphan_externals", { videos, externals });
+}
+
+export async function matchByFolder(root: string) {
+  return invoke<MuxJobRequest[]>("match_by_folder", { root });
+}
+
+export async function compactSavedQueue() {
+  return invoke<number>("compact_saved_queue");
+}
+
+export async function getLastBatchSettings() {
+  return invoke<LastBatchSettings | null>("get_last_batch_settings");
+}
+
+export async function diffCommands(a: string, b: string) {
+  return invoke<CommandDiff>("diff_commands", { a, b });
+}
+
+export async function getLastJobCommand(jobId: string) {
+  return invoke<string | null>("get_last_job_command", { jobId });
+}
+
+export async function estimateTrackSizes(path: string) {
+  return invoke<TrackSizeBreakdown>("estimate_track_sizes", { path });
+}
+
+export async function runSelfTest() {
+  return invoke<SelfTestReport>("run_self_test");
+}
+
+export async function invalidateProbeCache(paths: string[]) {
+  return invoke<number>("invalidate_probe_cache", { paths });
+}
+
+export async function clearProbeCache() {
+  return invoke<number>("clear_probe_cache");
+}
+
+export async function getSupportedLanguages() {
+  return invoke<LanguageEntry[]>("get_supported_languages");
+}
+
+export async function checkDependencies() {
+  return invoke<DependencyStatus[]>("check_dependencies");
+}
+
+export async function getStats() {
+  return invoke<UsageStats>("get_stats");
+}
+
+export async function estimateBatchTime(jobs: MuxJobRequest[], settings: MuxSettings) {
+  return invoke<BatchTimeEstimate>("estimate_batch_time", { jobs, settings });
+}
+
+export async function resetStats() {
+  return invoke<void>("reset_stats");
+}
+
+export async function tailLog(lines: number) {
+  return invoke<string[]>("tail_log", { lines });
+}
+
+export async function setIdSeed(seed: number | null) {
+  return invoke<void>("set_id_seed", { seed });
+}
+
+export async function previewRename(paths: string[], template: string) {
+  return invoke<RenameItem[]>("preview_rename", { paths, template });
+}
+
+export async function renameFiles(items: RenameItem[]) {
+  return invoke<RenameResult[]>("rename_files", { items });
+}
+
+export async function previewCleanTitle(path: string, template: string, substitute?: string) {
+  return invoke<string | null>("preview_clean_title", { path, template, substitute });
+}
+
+export async function convertChapters(path: string, to: "ogm" | "xml") {
+  return invoke<string>("convert_chapters", { path, to });
+}
+
+export async function validateXmlFile(path: string, kind: "chapters" | "tags") {
+  return invoke<XmlValidation>("validate_xml_file", { path, kind });
+}
+
 export async function openLogFile() {
   return invoke<void>("open_log_file");
 }
@@ -159,6 +515,38 @@ export function listenMuxLog(handler: (payload: { job_id: string; line: string }
   return listen<{ job_id: string; line: string }>("mux-log", (event) => handler(event.payload));
 }
 
+export function listenMuxAborted(handler: (payload: { reason: string }) => void) {
+  return listen<{ reason: string }>("mux-aborted", (event) => handler(event.payload));
+}
+
+export function listenMuxComplete(
+  handler: (payload: { processed: number; not_run: number }) => void,
+) {
+  return listen<{ processed: number; not_run: number }>("mux-complete", (event) =>
+    handler(event.payload),
+  );
+}
+
+export function listenMuxResume(handler: (payload: ResumeSummary) => void) {
+  return listen<ResumeSummary>("mux-resume", (event) => handler(event.payload));
+}
+
+export function listenMuxReset(handler: () => void) {
+  return listen<null>("mux-reset", () => handler());
+}
+
+export function listenMuxResetFailed(handler: (reason: string) => void) {
+  return listen<string>("mux-reset-failed", (event) => handler(event.payload));
+}
+
+export function listenMuxLowSpace(handler: (payload: MuxLowSpaceEvent) => void) {
+  return listen<MuxLowSpaceEvent>("mux-low-space", (event) => handler(event.payload));
+}
+
+export function listenMuxPlan(handler: (payload: MuxPlanEvent) => void) {
+  return listen<MuxPlanEvent>("mux-plan", (event) => handler(event.payload));
+}
+
 export function listenInspectPathsStreamChunk(
   handler: (payload: InspectStreamChunkEvent) => void,
 ) {
@@ -4,20 +4,28 @@ export interface VideoFile {
   path: string;
   size: number;
   duration?: string;
+  durationSeconds?: number | null;
   fps?: number;
   status: 'pending' | 'processing' | 'completed' | 'error';
   tracks: Track[];
+  probeError?: string;
+  probeWarning?: string | null;
+  warning?: string;
+  sources?: Record<string, string>;
+  dolbyVisionProfile?: number;
 }
 
 export interface Track {
   id: string;
   type: 'video' | 'audio' | 'subtitle' | 'chapter';
   codec?: string;
+  codecId?: string;
   language?: string;
   name?: string;
   isDefault?: boolean;
   isForced?: boolean;
   bitrate?: number; // Bitrate in bits per second
+  delay?: number; // Seconds, applied with --sync
   action?: 'keep' | 'remove' | 'modify';
   originalName?: string;
   originalLanguage?: string;
@@ -30,24 +38,34 @@ export interface ExternalFile {
   name: string;
   path: string;
   type: 'audio' | 'subtitle' | 'chapter' | 'attachment';
-  source?: 'bulk' | 'per-file';
+  source?: 'bulk' | 'per-file' | 'video' | 'video-audio';
   language?: string;
   trackName?: string;
   delay?: number;
+  delayScope?: 'file' | 'track';
   isDefault?: boolean;
   isForced?: boolean;
   matchedVideoId?: string;
   size?: number;
   bitrate?: number;
   duration?: string;
+  durationSeconds?: number | null;
   trackId?: number;
   tracks?: Track[];
   includedTrackIds?: number[];
+  firstTrackOnly?: boolean;
   includeSubtitles?: boolean;
   includedSubtitleTrackIds?: number[];
+  subtitleFlags?: { isDefault?: boolean; isForced?: boolean; language?: string } | null;
   trackOverrides?: Record<number, { language?: string; delay?: number; trackName?: string }>;
+  mimeType?: string | null;
+  defaultEdition?: boolean | null;
+  ordered?: boolean | null;
   muxAfter?: string;
   isManuallyEdited?: boolean;
+  probeError?: string;
+  probeWarning?: string | null;
+  sources?: Record<string, string>;
 }
 
 export interface OutputSettings {
@@ -59,7 +77,7 @@ export interface OutputSettings {
 export interface MuxJob {
   id: string;
   videoFile: VideoFile;
-  status: 'queued' | 'processing' | 'completed' | 'error' | 'stopped';
+  status: 'queued' | 'processing' | 'finalizing' | 'completed' | 'error' | 'stopped' | 'skipped';
   progress: number;
   errorMessage?: string;
   sizeBefore?: number;
@@ -68,6 +86,9 @@ export interface MuxJob {
   etaSeconds?: number;
   previewCommand?: string;
   previewWarnings?: string[];
+  outputName?: string;
+  presetName?: string;
+  destinationDir?: string;
 }
 
 export interface Preset {
@@ -86,6 +107,7 @@ export interface Preset {
   Default_Destination_Directory: string;
   Default_Favorite_Subtitle_Languages: string[];
   Default_Favorite_Audio_Languages: string[];
+  Default_Mux_Settings?: Partial<MuxSettings> | null;
 }
 
 export interface OptionsData {
@@ -95,6 +117,8 @@ export interface OptionsData {
   Attachment_Expert_Mode_Info_Message_Show: boolean;
   Choose_Preset_On_Startup: boolean;
   Show_Session_Recovery_Dialog?: boolean;
+  Extension_Groups?: Record<string, string[]>;
+  Load_Error?: string;
 }
 
 export interface MuxSettings {
@@ -109,14 +133,79 @@ export interface MuxSettings {
   onlyKeepSubtitlesEnabled: boolean;
   onlyKeepAudioLanguages: string[];
   onlyKeepSubtitleLanguages: string[];
+  keepAudioCodecs?: string[];
+  keepSubtitleCodecs?: string[];
   discardOldChapters: boolean;
   discardOldAttachments: boolean;
+  discardReplacedAttachmentsOnly?: boolean;
   allowDuplicateAttachments: boolean;
   attachmentsExpertMode: boolean;
   removeGlobalTags: boolean;
   makeAudioDefaultLanguage?: string;
   makeSubtitleDefaultLanguage?: string;
+  audioDefaultLanguagePriority?: string[];
+  subtitleDefaultLanguagePriority?: string[];
+  makeAudioDefaultPosition?: number | null;
+  makeSubtitleDefaultPosition?: number | null;
   useMkvpropedit: boolean;
+  guiMode?: boolean;
+  split?: { chapters: string } | null;
+  noDefaultSubtitles?: boolean;
+  noDefaultAudio?: boolean;
+  keepLogDestination?: string | null;
+  languageRemap?: Record<string, string>;
+  verifyInputChecksums?: boolean;
+  stripTrackNames?: boolean | Array<"video" | "audio" | "subtitle">;
+  clearImportedTrackNames?: boolean;
+  removeTrackTags?: boolean;
+  skipExisting?: boolean;
+  batchTrackOverrides?: BatchTrackOverride[];
+  outputExtension?: string | null;
+  verifyOutput?: boolean;
+  abortOn?: JobErrorKind[];
+  postMuxCommand?: string | null;
+  postMuxCommandMustSucceed?: boolean;
+  maxTotalOutputBytes?: number | null;
+  sortAttachments?: boolean;
+  dryRun?: boolean;
+  crcBufferBytes?: number;
+  crcSequentialRead?: boolean;
+  crcOnTempOutput?: boolean;
+  titleParser?: string | null;
+  lowSpaceThresholdBytes?: number | null;
+  resumeOnSpaceRecovered?: boolean;
+  outputContainer?: 'mkv' | 'mp4';
+  muxingApp?: string | null;
+  writeNfoSidecar?: boolean;
+  undefinedLanguagePolicy?: 'leave' | 'drop' | { setTo: string };
+  chapterAutoOffset?: boolean;
+  commentTag?: string | null;
+  sanitizeFilenames?: boolean;
+  sanitizeSubstitute?: string;
+  blockSourceTrackIds?: number[];
+  smoothProgress?: boolean;
+  keepTrackNamePatterns?: string[];
+  stopAfterFirst?: boolean;
+  maxFilenameBytes?: number | null;
+  crcMaxSizeBytes?: number | null;
+  tempSeparator?: string;
+  timestampedLogs?: boolean;
+}
+
+export type JobErrorKind =
+  | 'destination'
+  | 'diskSpace'
+  | 'outputCollision'
+  | 'sourceCorrupt'
+  | 'toolMissing'
+  | 'muxFailed';
+
+export interface BatchTrackOverride {
+  matchLanguage: string;
+  trackType: 'video' | 'audio' | 'subtitle';
+  setName?: string;
+  setDefault?: boolean;
+  setForced?: boolean;
 }
 
 export type NavigationSection = 
@@ -135,6 +224,8 @@ export interface MuxPreviewPlan {
   subtitles: ExternalFile[];
   chapters: ExternalFile[];
   attachments: ExternalFile[];
+  willOverwriteSource: boolean;
+  tempPath?: string | null;
 }
 
 export interface MuxPreviewResult {
@@ -142,4 +233,15 @@ export interface MuxPreviewResult {
   command: string;
   warnings: string[];
   plan: MuxPreviewPlan;
+  trackActions: TrackActionSummary[];
+  spaceOk: boolean;
+  requiredBytes: number;
+  availableBytes?: number | null;
+}
+
+export interface TrackActionSummary {
+  type: 'video' | 'audio' | 'subtitle';
+  kept: number;
+  removed: number;
+  disabled: number;
 }